@@ -0,0 +1,179 @@
+//! Anchoring blobs on an Ethereum chain: a transaction whose calldata carries a blob's root and
+//! versioned hashes, sent through a node's JSON-RPC endpoint, so an availability run can point
+//! at a block. The node signs (`eth_sendTransaction`), so the endpoint must hold an unlocked
+//! account, as dev chains such as anvil or `geth --dev` do.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::error::AnchorError;
+
+/// Largest JSON-RPC response read, in bytes.
+const MAX_RESPONSE_LEN: u64 = 4 * 1024 * 1024;
+
+/// A plain `http://host[:port][/path]` endpoint: a JSON-RPC node or a webhook.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RpcEndpoint {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl FromStr for RpcEndpoint {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, String> {
+        if url.starts_with("https://") {
+            return Err("https is not supported; use a plain http endpoint".into());
+        }
+        let rest = url.strip_prefix("http://").ok_or_else(|| format!("expected http://host:port, got {:?}", url))?;
+        let (authority, path) = rest.find('/').map_or((rest, "/"), |at| (&rest[..at], &rest[at..]));
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| format!("bad port in {:?}", url))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("no host in {:?}", url));
+        }
+        Ok(Self { host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+impl fmt::Display for RpcEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+
+/// What a mined transaction's receipt says.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Receipt {
+    pub block: u64,
+    /// Whether the transaction executed; a reverted one is still mined
+    pub success: bool,
+}
+
+/// A JSON-RPC client over one HTTP request per call.
+pub struct EthRpc {
+    endpoint: RpcEndpoint,
+    next_id: AtomicU64,
+}
+
+impl EthRpc {
+    pub fn new(endpoint: RpcEndpoint) -> Self {
+        Self { endpoint, next_id: AtomicU64::new(1) }
+    }
+
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, AnchorError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let body = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string();
+        let response = post_json(&self.endpoint, &body).await?;
+
+        #[derive(Deserialize)]
+        struct RpcError {
+            code: i64,
+            message: String,
+        }
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(default)]
+            result: Value,
+            error: Option<RpcError>,
+        }
+        let response: Response = serde_json::from_slice(&response).map_err(|e| AnchorError::Malformed(e.to_string()))?;
+        match response.error {
+            Some(e) => Err(AnchorError::Rpc { method: method.to_string(), code: e.code, message: e.message }),
+            None => Ok(response.result),
+        }
+    }
+
+    /// The endpoint's first account, which signs when no sender is given.
+    pub async fn first_account(&self) -> Result<String, AnchorError> {
+        let accounts = self.call("eth_accounts", json!([])).await?;
+        accounts.get(0).and_then(Value::as_str).map(str::to_string)
+            .ok_or(AnchorError::NoAccount)
+    }
+
+    /// Sends a zero-value transaction carrying `data`, and returns its hash.
+    pub async fn send_transaction(&self, from: &str, to: &str, data: &[u8]) -> Result<String, AnchorError> {
+        let tx = json!({ "from": from, "to": to, "value": "0x0", "data": format!("0x{}", hex::encode(data)) });
+        let hash = self.call("eth_sendTransaction", json!([tx])).await?;
+        hash.as_str().map(str::to_string).ok_or_else(|| AnchorError::Malformed(format!("transaction hash {}", hash)))
+    }
+
+    /// The receipt of `tx`, or `None` while it is not mined.
+    pub async fn receipt(&self, tx: &str) -> Result<Option<Receipt>, AnchorError> {
+        let receipt = self.call("eth_getTransactionReceipt", json!([tx])).await?;
+        if receipt.is_null() {
+            return Ok(None);
+        }
+        let quantity = |field: &str| receipt.get(field).and_then(Value::as_str).and_then(|q| u64::from_str_radix(q.trim_start_matches("0x"), 16).ok());
+        let block = quantity("blockNumber").ok_or_else(|| AnchorError::Malformed("receipt without a block number".into()))?;
+        // Receipts from before Byzantium carry a state root instead of a status.
+        Ok(Some(Receipt { block, success: quantity("status").is_none_or(|s| s == 1) }))
+    }
+}
+
+/// Calldata anchoring one blob: the optional 4-byte function selector, then the root and each
+/// versioned hash as 32-byte words. That is also the ABI encoding of a call with one `bytes32`
+/// argument per word, so a test contract can take them as such.
+pub fn anchor_calldata(selector: Option<[u8; 4]>, root: &str, versioned_hashes: &[String]) -> Result<Vec<u8>, AnchorError> {
+    let mut data = selector.map(Vec::from).unwrap_or_default();
+    for word in std::iter::once(root).chain(versioned_hashes.iter().map(String::as_str)) {
+        let bytes = hex::decode(word.trim_start_matches("0x")).ok().filter(|b| b.len() == 32)
+            .ok_or_else(|| AnchorError::Malformed(format!("{} is not a 32-byte hex word", word)))?;
+        data.extend(bytes);
+    }
+    Ok(data)
+}
+
+/// One HTTP/1.1 POST of a JSON body, returning the response body. Any 2xx status is success,
+/// so webhooks answering `204 No Content` pass too.
+pub async fn post_json(endpoint: &RpcEndpoint, body: &str) -> Result<Vec<u8>, AnchorError> {
+    let mut stream = TcpStream::connect((endpoint.host.as_str(), endpoint.port)).await?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        endpoint.path, endpoint.host, endpoint.port, body.len(), body
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    (&mut stream).take(MAX_RESPONSE_LEN).read_to_end(&mut response).await?;
+
+    let at = response.windows(4).position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| AnchorError::Http("response without a header end".into()))?;
+    let head = String::from_utf8_lossy(&response[..at]).to_string();
+    let body = response.split_off(at + 4);
+    let status = head.lines().next().unwrap_or_default();
+    if !status.split_whitespace().nth(1).is_some_and(|code| code.len() == 3 && code.starts_with('2')) {
+        return Err(AnchorError::Http(status.to_string()));
+    }
+    let chunked = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    if chunked { dechunk(&body) } else { Ok(body) }
+}
+
+/// Joins an HTTP chunked body.
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>, AnchorError> {
+    let bad = || AnchorError::Http("malformed chunked body".into());
+    let mut out = Vec::new();
+    loop {
+        let at = body.windows(2).position(|w| w == b"\r\n").ok_or_else(bad)?;
+        let size = std::str::from_utf8(&body[..at]).ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next().unwrap_or_default().trim(), 16).ok())
+            .ok_or_else(bad)?;
+        body = &body[at + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        out.extend_from_slice(body.get(..size).ok_or_else(bad)?);
+        body = body.get(size + 2..).ok_or_else(bad)?;
+    }
+}
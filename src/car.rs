@@ -0,0 +1,548 @@
+//! Content-addressed archives (CAR v1), the format IPFS tooling imports and exports: a DAG-CBOR
+//! header naming the root CIDs, then each block prefixed by its CID. Only what blobs need is
+//! supported: CIDv1 with a SHA-256 multihash, raw and DAG-CBOR blocks.
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::CarError;
+use crate::protocol::is_digest;
+
+/// Multicodec of a block that is plain bytes.
+pub const CODEC_RAW: u64 = 0x55;
+/// Multicodec of a DAG-CBOR block.
+pub const CODEC_DAG_CBOR: u64 = 0x71;
+/// Multihash code of SHA-256.
+const MULTIHASH_SHA256: u64 = 0x12;
+/// CBOR tag marking a CID inside DAG-CBOR.
+const TAG_CID: u64 = 42;
+
+/// Largest header a reader accepts, in bytes.
+const MAX_HEADER_LEN: u64 = 1024 * 1024;
+/// Largest block section (CID and data) a reader accepts, in bytes.
+const MAX_SECTION_LEN: u64 = 1 << 30;
+/// Deepest nesting of CBOR arrays and maps a reader follows.
+const MAX_CBOR_DEPTH: usize = 16;
+
+/// A version 1 content identifier: what a block holds (`codec`) and the SHA-256 of its bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cid {
+    pub codec: u64,
+    pub digest: [u8; 32],
+}
+
+impl Cid {
+    pub fn of(codec: u64, data: &[u8]) -> Self {
+        Self { codec, digest: Sha256::digest(data).into() }
+    }
+
+    /// Whether `data` is the block this CID names.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        Sha256::digest(data)[..] == self.digest
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(36);
+        put_varint(&mut out, 1);
+        put_varint(&mut out, self.codec);
+        put_varint(&mut out, MULTIHASH_SHA256);
+        put_varint(&mut out, 32);
+        out.extend_from_slice(&self.digest);
+        out
+    }
+
+    /// Parses a binary CID from the front of `bytes`, returning it and the bytes it took.
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), CarError> {
+        let mut at = 0;
+        let mut next = || varint_at(bytes, &mut at);
+        let (version, codec, hash, len) = (next()?, next()?, next()?, next()?);
+        if version != 1 {
+            return Err(CarError::Malformed(format!("CID version {} (only v1 is supported)", version)));
+        }
+        if hash != MULTIHASH_SHA256 || len != 32 {
+            return Err(CarError::Malformed(format!("multihash 0x{:x} of {} bytes (only sha2-256 is supported)", hash, len)));
+        }
+        let digest = bytes.get(at..at + 32).ok_or_else(|| CarError::Malformed("CID truncated".into()))?;
+        Ok((Self { codec, digest: digest.try_into().expect("32 bytes") }, at + 32))
+    }
+}
+
+/// The multibase form IPFS tools print: `b` and the base32 of the binary CID.
+impl fmt::Display for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+        let mut text = String::from("b");
+        let (mut buffer, mut bits) = (0u32, 0);
+        for byte in self.to_bytes() {
+            buffer = (buffer << 8) | byte as u32;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                text.push(ALPHABET[(buffer >> bits) as usize & 31] as char);
+            }
+        }
+        if bits > 0 {
+            text.push(ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+        }
+        f.write_str(&text)
+    }
+}
+
+/// The DAG-CBOR node at the top of one blob in an archive: the blob's root, and links to its
+/// manifest and to its shard files in index order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobNode {
+    /// Hex root the shards were encoded under
+    pub root: String,
+    pub manifest: Option<Cid>,
+    pub shards: Vec<Cid>,
+}
+
+impl BlobNode {
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut fields = vec![
+            ("root", Cbor::Text(self.root.clone())),
+            ("shards", Cbor::Array(self.shards.iter().map(|c| Cbor::Link(*c)).collect())),
+        ];
+        if let Some(manifest) = self.manifest {
+            fields.push(("manifest", Cbor::Link(manifest)));
+        }
+        Cbor::map(fields).encode()
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, CarError> {
+        let node = Cbor::decode(bytes)?;
+        let root = match node.field("root") {
+            // Importers name files after the root, so it must be a digest and nothing else.
+            Some(Cbor::Text(root)) if is_digest(root) => root.clone(),
+            Some(Cbor::Text(root)) => return Err(CarError::Malformed(format!("blob node root {:?} is not a digest", root))),
+            _ => return Err(CarError::Malformed("blob node without a root".into())),
+        };
+        let shards = match node.field("shards") {
+            Some(Cbor::Array(items)) => items.iter().map(|item| match item {
+                Cbor::Link(cid) => Ok(*cid),
+                _ => Err(CarError::Malformed("shard entry is not a link".into())),
+            }).collect::<Result<_, _>>()?,
+            _ => return Err(CarError::Malformed("blob node without shards".into())),
+        };
+        let manifest = match node.field("manifest") {
+            Some(Cbor::Link(cid)) => Some(*cid),
+            None => None,
+            Some(_) => return Err(CarError::Malformed("manifest is not a link".into())),
+        };
+        Ok(Self { root, manifest, shards })
+    }
+}
+
+/// Writes a CAR v1 archive: the header on creation, then one block per [`CarWriter::block`].
+pub struct CarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> CarWriter<W> {
+    pub fn new(mut out: W, roots: &[Cid]) -> Result<Self, CarError> {
+        let header = Cbor::map(vec![
+            ("roots", Cbor::Array(roots.iter().map(|c| Cbor::Link(*c)).collect())),
+            ("version", Cbor::Int(1)),
+        ]).encode();
+        let mut prefix = Vec::new();
+        put_varint(&mut prefix, header.len() as u64);
+        out.write_all(&prefix)?;
+        out.write_all(&header)?;
+        Ok(Self { out })
+    }
+
+    pub fn block(&mut self, cid: &Cid, data: &[u8]) -> Result<(), CarError> {
+        let cid = cid.to_bytes();
+        let mut prefix = Vec::new();
+        put_varint(&mut prefix, (cid.len() + data.len()) as u64);
+        self.out.write_all(&prefix)?;
+        self.out.write_all(&cid)?;
+        self.out.write_all(data)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, CarError> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads a CAR v1 archive: the roots on creation, then its blocks as an iterator. Every block
+/// is checked against its CID, so a corrupted archive fails instead of yielding bad data.
+pub struct CarReader<R: Read> {
+    input: R,
+    roots: Vec<Cid>,
+}
+
+impl<R: Read> CarReader<R> {
+    pub fn new(mut input: R) -> Result<Self, CarError> {
+        let len = read_varint(&mut input)?.ok_or_else(|| CarError::Malformed("empty archive".into()))?;
+        if len > MAX_HEADER_LEN {
+            return Err(CarError::Malformed(format!("{} byte header", len)));
+        }
+        let mut header = vec![0; len as usize];
+        input.read_exact(&mut header)?;
+        let header = Cbor::decode(&header)?;
+        if header.field("version") != Some(&Cbor::Int(1)) {
+            return Err(CarError::Malformed("not a CAR v1 header".into()));
+        }
+        let roots = match header.field("roots") {
+            Some(Cbor::Array(items)) => items.iter().map(|item| match item {
+                Cbor::Link(cid) => Ok(*cid),
+                _ => Err(CarError::Malformed("root is not a link".into())),
+            }).collect::<Result<_, _>>()?,
+            _ => return Err(CarError::Malformed("header without roots".into())),
+        };
+        Ok(Self { input, roots })
+    }
+
+    pub fn roots(&self) -> &[Cid] {
+        &self.roots
+    }
+
+    fn next_block(&mut self) -> Result<Option<(Cid, Vec<u8>)>, CarError> {
+        let Some(len) = read_varint(&mut self.input)? else { return Ok(None) };
+        if len > MAX_SECTION_LEN {
+            return Err(CarError::Malformed(format!("{} byte block", len)));
+        }
+        let mut section = vec![0; len as usize];
+        self.input.read_exact(&mut section)?;
+        let (cid, at) = Cid::from_bytes(&section)?;
+        let data = section.split_off(at);
+        if !cid.matches(&data) {
+            return Err(CarError::BadBlock { cid: cid.to_string() });
+        }
+        Ok(Some((cid, data)))
+    }
+}
+
+impl<R: Read> Iterator for CarReader<R> {
+    type Item = Result<(Cid, Vec<u8>), CarError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_block().transpose()
+    }
+}
+
+/// The subset of DAG-CBOR that headers and blob nodes use.
+#[derive(Clone, Debug, PartialEq)]
+enum Cbor {
+    Int(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    /// Text keys in DAG-CBOR's canonical order: shorter first, then bytewise
+    Map(Vec<(String, Cbor)>),
+    Link(Cid),
+}
+
+impl Cbor {
+    fn map(fields: Vec<(&str, Cbor)>) -> Self {
+        let mut fields: Vec<(String, Cbor)> = fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+        fields.sort_by(|(a, _), (b, _)| (a.len(), a).cmp(&(b.len(), b)));
+        Cbor::Map(fields)
+    }
+
+    fn field(&self, key: &str) -> Option<&Cbor> {
+        match self {
+            Cbor::Map(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Cbor::Int(n) => put_head(out, 0, *n),
+            Cbor::Bytes(bytes) => {
+                put_head(out, 2, bytes.len() as u64);
+                out.extend_from_slice(bytes);
+            }
+            Cbor::Text(text) => {
+                put_head(out, 3, text.len() as u64);
+                out.extend_from_slice(text.as_bytes());
+            }
+            Cbor::Array(items) => {
+                put_head(out, 4, items.len() as u64);
+                items.iter().for_each(|item| item.encode_into(out));
+            }
+            Cbor::Map(fields) => {
+                put_head(out, 5, fields.len() as u64);
+                for (key, value) in fields {
+                    Cbor::Text(key.clone()).encode_into(out);
+                    value.encode_into(out);
+                }
+            }
+            // A link is the binary CID behind a zero byte (the identity multibase), tagged 42.
+            Cbor::Link(cid) => {
+                put_head(out, 6, TAG_CID);
+                Cbor::Bytes([&[0][..], &cid.to_bytes()].concat()).encode_into(out);
+            }
+        }
+    }
+
+    /// Decodes exactly one item filling `bytes`.
+    fn decode(bytes: &[u8]) -> Result<Self, CarError> {
+        let mut at = 0;
+        let item = Self::decode_at(bytes, &mut at, 0)?;
+        if at != bytes.len() {
+            return Err(CarError::Malformed(format!("{} trailing bytes after CBOR item", bytes.len() - at)));
+        }
+        Ok(item)
+    }
+
+    fn decode_at(bytes: &[u8], at: &mut usize, depth: usize) -> Result<Self, CarError> {
+        if depth > MAX_CBOR_DEPTH {
+            return Err(CarError::Malformed("CBOR nested too deeply".into()));
+        }
+        let truncated = || CarError::Malformed("CBOR truncated".into());
+        let initial = *bytes.get(*at).ok_or_else(truncated)?;
+        *at += 1;
+        let (major, info) = (initial >> 5, initial & 31);
+        let arg = match info {
+            0..=23 => info as u64,
+            24..=27 => {
+                let width = 1 << (info - 24);
+                let raw = bytes.get(*at..*at + width).ok_or_else(truncated)?;
+                *at += width;
+                raw.iter().fold(0u64, |n, b| (n << 8) | *b as u64)
+            }
+            _ => return Err(CarError::Malformed("indefinite-length CBOR is not DAG-CBOR".into())),
+        };
+        // Every item takes at least a byte, so no honest length exceeds what is left.
+        let remaining = (bytes.len() - *at) as u64;
+        let mut take = |len: u64| -> Result<&[u8], CarError> {
+            if len > remaining {
+                return Err(truncated());
+            }
+            let slice = &bytes[*at..*at + len as usize];
+            *at += len as usize;
+            Ok(slice)
+        };
+        Ok(match major {
+            0 => Cbor::Int(arg),
+            2 => Cbor::Bytes(take(arg)?.to_vec()),
+            3 => Cbor::Text(String::from_utf8(take(arg)?.to_vec()).map_err(|_| CarError::Malformed("CBOR text is not UTF-8".into()))?),
+            4 => {
+                if arg > remaining {
+                    return Err(truncated());
+                }
+                Cbor::Array((0..arg).map(|_| Self::decode_at(bytes, at, depth + 1)).collect::<Result<_, _>>()?)
+            }
+            5 => {
+                if arg > remaining {
+                    return Err(truncated());
+                }
+                let mut fields = Vec::with_capacity(arg as usize);
+                for _ in 0..arg {
+                    let Cbor::Text(key) = Self::decode_at(bytes, at, depth + 1)? else {
+                        return Err(CarError::Malformed("CBOR map key is not text".into()));
+                    };
+                    fields.push((key, Self::decode_at(bytes, at, depth + 1)?));
+                }
+                Cbor::Map(fields)
+            }
+            6 if arg == TAG_CID => match Self::decode_at(bytes, at, depth + 1)? {
+                Cbor::Bytes(raw) if raw.first() == Some(&0) => {
+                    let (cid, len) = Cid::from_bytes(&raw[1..])?;
+                    if len != raw.len() - 1 {
+                        return Err(CarError::Malformed("trailing bytes after CID".into()));
+                    }
+                    Cbor::Link(cid)
+                }
+                _ => return Err(CarError::Malformed("tag 42 without a binary CID".into())),
+            },
+            _ => return Err(CarError::Malformed(format!("unsupported CBOR item (major type {}, argument {})", major, arg))),
+        })
+    }
+}
+
+/// A CBOR item head: the major type and its argument in the shortest form.
+fn put_head(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend([major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((arg as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(arg.to_be_bytes());
+        }
+    }
+}
+
+/// Unsigned LEB128, as multiformats and CAR section lengths use.
+fn put_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn varint_at(bytes: &[u8], at: &mut usize) -> Result<u64, CarError> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*at).ok_or_else(|| CarError::Malformed("varint truncated".into()))?;
+        *at += 1;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(CarError::Malformed("varint too long".into()))
+}
+
+/// Reads a varint from a stream; `None` when the stream ends before its first byte.
+fn read_varint(input: &mut impl Read) -> Result<Option<u64>, CarError> {
+    let mut n = 0u64;
+    for (i, shift) in (0..64).step_by(7).enumerate() {
+        let mut byte = [0u8];
+        if input.read(&mut byte)? == 0 {
+            return match i {
+                0 => Ok(None),
+                _ => Err(CarError::Malformed("varint truncated".into())),
+            };
+        }
+        n |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(n));
+        }
+    }
+    Err(CarError::Malformed("varint too long".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(roots: &[Cid], blocks: &[(Cid, &[u8])]) -> Vec<u8> {
+        let mut car = CarWriter::new(Vec::new(), roots).unwrap();
+        for (cid, data) in blocks {
+            car.block(cid, data).unwrap();
+        }
+        car.finish().unwrap()
+    }
+
+    #[test]
+    fn cid_prints_as_ipfs_does() {
+        assert_eq!(Cid::of(CODEC_RAW, b"").to_string(), "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku");
+    }
+
+    #[test]
+    fn cid_round_trips() {
+        let cid = Cid::of(CODEC_DAG_CBOR, b"node");
+        let mut bytes = cid.to_bytes();
+        bytes.push(0xff);
+        assert_eq!(Cid::from_bytes(&bytes).unwrap(), (cid, bytes.len() - 1));
+    }
+
+    #[test]
+    fn unsupported_or_truncated_cids_are_refused() {
+        let bytes = Cid::of(CODEC_RAW, b"x").to_bytes();
+        let mut v0 = bytes.clone();
+        v0[0] = 0;
+        assert!(matches!(Cid::from_bytes(&v0), Err(CarError::Malformed(_))));
+        assert!(matches!(Cid::from_bytes(&bytes[..bytes.len() - 1]), Err(CarError::Malformed(_))));
+        assert!(matches!(Cid::from_bytes(&[0x80; 12]), Err(CarError::Malformed(_))));
+    }
+
+    #[test]
+    fn archive_round_trips() {
+        let shard = b"shard bytes".as_slice();
+        let node = BlobNode { root: "ab".repeat(32), manifest: None, shards: vec![Cid::of(CODEC_RAW, shard)] };
+        let cbor = node.to_cbor();
+        let root = Cid::of(CODEC_DAG_CBOR, &cbor);
+        let bytes = archive(&[root], &[(root, &cbor), (node.shards[0], shard)]);
+
+        let mut reader = CarReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.roots(), &[root]);
+        let (cid, data) = reader.next().unwrap().unwrap();
+        assert_eq!((cid, BlobNode::from_cbor(&data).unwrap()), (root, node));
+        assert_eq!(reader.next().unwrap().unwrap(), (Cid::of(CODEC_RAW, shard), shard.to_vec()));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn corrupted_block_fails_its_cid() {
+        let cid = Cid::of(CODEC_RAW, b"genuine");
+        let mut bytes = archive(&[cid], &[(cid, b"genuine")]);
+        *bytes.last_mut().unwrap() ^= 1;
+        let mut reader = CarReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(reader.next(), Some(Err(CarError::BadBlock { .. }))));
+    }
+
+    #[test]
+    fn truncated_archive_is_an_error() {
+        let cid = Cid::of(CODEC_RAW, b"genuine");
+        let bytes = archive(&[cid], &[(cid, b"genuine")]);
+        let mut reader = CarReader::new(&bytes[..bytes.len() - 3]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(CarError::Io(_)))));
+        assert!(matches!(CarReader::new(&bytes[..3]), Err(CarError::Io(_))));
+        assert!(matches!(CarReader::new(&[][..]), Err(CarError::Malformed(_))));
+    }
+
+    #[test]
+    fn oversized_lengths_are_refused_before_allocating() {
+        let mut header = Vec::new();
+        put_varint(&mut header, MAX_HEADER_LEN + 1);
+        assert!(matches!(CarReader::new(header.as_slice()), Err(CarError::Malformed(_))));
+
+        let mut bytes = archive(&[], &[]);
+        put_varint(&mut bytes, MAX_SECTION_LEN + 1);
+        let mut reader = CarReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(reader.next(), Some(Err(CarError::Malformed(_)))));
+    }
+
+    #[test]
+    fn hostile_cbor_is_refused() {
+        // An array claiming 2^32 items in a handful of bytes.
+        assert!(matches!(Cbor::decode(&[0x9a, 0xff, 0xff, 0xff, 0xff]), Err(CarError::Malformed(_))));
+        // Indefinite length, nesting past the limit, a non-text map key, trailing bytes.
+        assert!(matches!(Cbor::decode(&[0x9f, 0xff]), Err(CarError::Malformed(_))));
+        let mut nested = vec![0x81; MAX_CBOR_DEPTH + 1];
+        nested.push(0x00);
+        assert!(matches!(Cbor::decode(&nested), Err(CarError::Malformed(m)) if m.contains("deeply")));
+        nested.remove(0);
+        assert!(Cbor::decode(&nested).is_ok());
+        assert!(matches!(Cbor::decode(&[0xa1, 0x01, 0x01]), Err(CarError::Malformed(_))));
+        assert!(matches!(Cbor::decode(&[0x01, 0x01]), Err(CarError::Malformed(_))));
+        // A header that is valid CBOR but not a CAR v1 header.
+        let mut bytes = Vec::new();
+        let header = Cbor::map(vec![("version", Cbor::Int(2)), ("roots", Cbor::Array(vec![]))]).encode();
+        put_varint(&mut bytes, header.len() as u64);
+        bytes.extend(header);
+        assert!(matches!(CarReader::new(bytes.as_slice()), Err(CarError::Malformed(_))));
+    }
+
+    #[test]
+    fn blob_node_needs_links() {
+        let node = Cbor::map(vec![("root", Cbor::Text("ab".repeat(32))), ("shards", Cbor::Array(vec![Cbor::Int(1)]))]).encode();
+        assert!(matches!(BlobNode::from_cbor(&node), Err(CarError::Malformed(_))));
+    }
+
+    #[test]
+    fn blob_node_root_must_be_a_digest() {
+        let node = |root: &str| Cbor::map(vec![("root", Cbor::Text(root.into())), ("shards", Cbor::Array(Vec::new()))]).encode();
+        let root = "ab".repeat(32);
+        assert_eq!(BlobNode::from_cbor(&node(&root)).unwrap().root, root);
+        for bad in ["../../x", "ab", &root.to_uppercase(), &format!("{}é", &root[..62])] {
+            assert!(matches!(BlobNode::from_cbor(&node(bad)), Err(CarError::Malformed(_))), "{:?}", bad);
+        }
+    }
+}
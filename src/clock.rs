@@ -0,0 +1,139 @@
+//! Time as a dependency. Everything that measures or waits asks a [`Clock`], so the same code runs
+//! against the wall clock or against a [`SimulatedClock`] that only moves when told to, which
+//! makes timings and deadlines reproducible without real waits.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+
+pub trait Clock: Send + Sync {
+    /// Monotonic time since the clock's origin; only differences are meaningful.
+    fn now(&self) -> Duration;
+
+    /// Time since the Unix epoch, for timestamps that leave the process.
+    fn unix(&self) -> Duration;
+
+    /// Completes once `duration` has passed on this clock.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    fn elapsed(&self, since: Duration) -> Duration {
+        self.now().saturating_sub(since)
+    }
+}
+
+/// The wall clock, with tokio timers for sleeping.
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { origin: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn unix(&self) -> Duration {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        tokio::time::sleep(duration).boxed()
+    }
+}
+
+/// Virtual time that stands still until [`advance`](Self::advance)d. Sleepers wake in deadline
+/// order (ties in the order they went to sleep), so a run is the same every time. Clones share
+/// one timeline.
+#[derive(Clone, Default)]
+pub struct SimulatedClock {
+    state: Arc<Mutex<SimState>>,
+}
+
+#[derive(Default)]
+struct SimState {
+    now: Duration,
+    unix_start: Duration,
+    /// Pending sleepers by (deadline, arrival)
+    sleepers: BTreeMap<(Duration, u64), oneshot::Sender<()>>,
+    arrivals: u64,
+}
+
+impl SimulatedClock {
+    /// A clock at time zero whose Unix time starts at `unix_start`.
+    pub fn new(unix_start: Duration) -> Self {
+        let state = SimState { unix_start, ..SimState::default() };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let now = self.now();
+        self.advance_to(now + by);
+    }
+
+    /// Moves time forward to `time` (never backwards), waking every sleeper due by then.
+    pub fn advance_to(&self, time: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now = state.now.max(time);
+        let now = state.now;
+        while let Some(entry) = state.sleepers.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let _ = entry.remove().send(());
+        }
+    }
+
+    /// Deadline of the earliest pending sleeper.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.state.lock().unwrap().sleepers.keys().next().map(|(deadline, _)| *deadline)
+    }
+
+    /// Jumps to the next deadline and wakes whoever is due. `false` if nobody is sleeping.
+    pub fn advance_to_next(&self) -> bool {
+        match self.next_deadline() {
+            Some(deadline) => {
+                self.advance_to(deadline);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    fn unix(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        state.unix_start + state.now
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        if duration.is_zero() {
+            return futures::future::ready(()).boxed();
+        }
+        let (tx, rx) = oneshot::channel();
+        let mut state = self.state.lock().unwrap();
+        let key = (state.now + duration, state.arrivals);
+        state.arrivals += 1;
+        state.sleepers.insert(key, tx);
+        rx.map(|_| ()).boxed()
+    }
+}
@@ -0,0 +1,510 @@
+//! Reed-Solomon coding over GF(2^8): encoding a blob into its extended shard set, and
+//! regenerating individual shards from any k others.
+//!
+//! [`DasEncoder`] and [`DasDecoder`] are the embeddable entry points: they take any RS(k, m)
+//! code and handle padding, striping, shard commitments and reconstruction, with no CLI or
+//! networking attached.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use reed_solomon_erasure::galois_8::{self, ReedSolomon};
+use sha2::{Digest, Sha256};
+
+use crate::error::ReconstructError;
+use crate::protocol::{Encoding, Layout, Striping};
+
+pub fn calculate_sha256(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hex::encode(hasher.finalize())
+}
+
+/// Binary SHA-256 Merkle root over hex-encoded leaf hashes (odd nodes are paired with themselves).
+pub fn merkle_root(leaves: &[String]) -> String {
+    let mut level: Vec<Vec<u8>> = leaves.iter().map(|h| hex::decode(h).unwrap_or_default()).collect();
+    if level.is_empty() { return calculate_sha256(&[]); }
+    while level.len() > 1 {
+        level = level.chunks(2).map(|pair| {
+            let mut hasher = Sha256::new();
+            hasher.update(&pair[0]);
+            hasher.update(pair.get(1).unwrap_or(&pair[0]));
+            hasher.finalize().to_vec()
+        }).collect();
+    }
+    hex::encode(&level[0])
+}
+
+/// Zero-pads `data` to a non-zero multiple of k, so that every shard holds at least one byte.
+pub fn pad_data(data: &[u8], k: usize) -> Vec<u8> {
+    let mut padded = data.to_vec();
+    padded.resize(data.len().div_ceil(k).max(1) * k, 0);
+    padded
+}
+
+/// The GF(2^8) arithmetic this build codes with: `simd` when built with the `simd-accel`
+/// feature on a target reed-solomon-erasure has SIMD kernels for, `portable` otherwise.
+pub const GALOIS_BACKEND: &str = if cfg!(all(
+    feature = "simd-accel",
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+    not(target_env = "msvc"),
+    not(any(target_os = "android", target_os = "ios")),
+)) {
+    "simd"
+} else {
+    "portable"
+};
+
+/// Shards a GF(2^8) Reed-Solomon code can have.
+pub const MAX_SHARDS: usize = 256;
+
+/// Checks that k data and m parity shards make a code [`DasEncoder`] can use in both
+/// encodings: at least one of each, and 2k + m shards within [`MAX_SHARDS`], since the
+/// non-systematic encoding takes the parity half of an RS(k, k + (k + m)) code. The error
+/// names the nearest valid pair.
+pub fn check_params(k: usize, m: usize) -> Result<(), ReconstructError> {
+    let reason = if k == 0 {
+        "k must be at least 1".to_string()
+    } else if m == 0 {
+        "m must be at least 1".to_string()
+    } else if 2 * k + m > MAX_SHARDS {
+        format!("the non-systematic encoding needs 2k + m = {} shards, over the {} of GF(2^8)", 2 * k + m, MAX_SHARDS)
+    } else {
+        return Ok(());
+    };
+    let (suggested_k, suggested_m) = suggest_params(k, m);
+    Err(ReconstructError::InvalidParams { k, m, reason, suggested_k, suggested_m })
+}
+
+/// The valid (k, m) closest to the given one: zeros become 1, and a code too large for the
+/// field shrinks to fit while keeping roughly its rate.
+pub fn suggest_params(k: usize, m: usize) -> (usize, usize) {
+    let (k, m) = (k.max(1), m.max(1));
+    let total = 2 * k + m;
+    if total <= MAX_SHARDS {
+        return (k, m);
+    }
+    // Rounding both down keeps 2k + m within the field, with room for m to round up from 0.
+    ((k * MAX_SHARDS / total).max(1), (m * MAX_SHARDS / total).max(1))
+}
+
+/// Encodes blobs into an RS(k, m) extended shard set under one [`Layout`].
+#[derive(Copy, Clone, Debug)]
+pub struct DasEncoder {
+    k: usize,
+    m: usize,
+    layout: Layout,
+    align: usize,
+}
+
+impl DasEncoder {
+    /// Fails unless both encodings of RS(k, m) are available; see [`check_params`].
+    pub fn new(k: usize, m: usize) -> Result<Self, ReconstructError> {
+        check_params(k, m)?;
+        Ok(Self { k, m, layout: Layout::default(), align: 1 })
+    }
+
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Pads further so that `shard_len` is a multiple of `bytes`, e.g. whole field elements
+    /// for a commitment that packs shards into them.
+    pub fn shard_align(mut self, bytes: usize) -> Self {
+        self.align = bytes.max(1);
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.layout.encoding = encoding;
+        self
+    }
+
+    pub fn striping(mut self, striping: Striping) -> Self {
+        self.layout.striping = striping;
+        self
+    }
+
+    /// Pads `data` to a non-zero multiple of k (times the shard alignment), stripes it and
+    /// extends it to k + m shards.
+    pub fn encode(&self, data: &[u8]) -> EncodedBlob {
+        let (k, n) = (self.k, self.k + self.m);
+        let offset = parity_offset(k, self.layout.encoding);
+        let padded = pad_data(data, k * self.align);
+        let mut shards: Vec<Vec<u8>> = stripe(k, &padded, self.layout.striping);
+        let shard_len = shards[0].len();
+        shards.resize(offset + n, vec![0; shard_len]);
+        let r = ReedSolomon::new(k, offset + self.m).expect("code checked in DasEncoder::new");
+        r.encode(&mut shards).expect("shards are k + m of equal length");
+        shards.drain(..offset);
+
+        let shard_hashes: Vec<String> = shards.iter().map(|s| calculate_sha256(s)).collect();
+        EncodedBlob {
+            k,
+            m: self.m,
+            layout: self.layout,
+            original_len: data.len(),
+            shard_len,
+            root: merkle_root(&shard_hashes),
+            checksum: calculate_sha256(data),
+            shard_hashes,
+            shards,
+        }
+    }
+}
+
+/// One encoded blob: its shards in codeword order, plus the commitments receivers check.
+#[derive(Clone, Debug)]
+pub struct EncodedBlob {
+    pub k: usize,
+    pub m: usize,
+    pub layout: Layout,
+    pub original_len: usize,
+    pub shard_len: usize,
+    pub shards: Vec<Vec<u8>>,
+    pub shard_hashes: Vec<String>,
+    /// Merkle root over `shard_hashes`.
+    pub root: String,
+    /// SHA-256 of the original (unpadded) blob.
+    pub checksum: String,
+}
+
+impl EncodedBlob {
+    /// A decoder with this blob's code, layout and lengths.
+    pub fn decoder(&self) -> DasDecoder {
+        DasDecoder::new(self.k, self.m)
+            .expect("code checked by the encoder")
+            .layout(self.layout)
+            .original_len(self.original_len)
+            .shard_len(self.shard_len)
+    }
+}
+
+/// Collects shards of one blob and rebuilds it, or any single shard, from any k of them.
+#[derive(Clone, Debug)]
+pub struct DasDecoder {
+    k: usize,
+    m: usize,
+    layout: Layout,
+    original_len: Option<usize>,
+    shard_len: Option<usize>,
+    received: HashMap<usize, Vec<u8>>,
+}
+
+impl DasDecoder {
+    pub fn new(k: usize, m: usize) -> Result<Self, ReconstructError> {
+        check_params(k, m)?;
+        Ok(Self { k, m, layout: Layout::default(), original_len: None, shard_len: None, received: HashMap::new() })
+    }
+
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Length of the unpadded blob; without it `decode` returns the padded data.
+    pub fn original_len(mut self, len: usize) -> Self {
+        self.original_len = Some(len);
+        self
+    }
+
+    /// Expected shard length; otherwise the first shard pushed sets it.
+    pub fn shard_len(mut self, len: usize) -> Self {
+        self.shard_len = Some(len);
+        self
+    }
+
+    /// Adds shard `index` (codeword order). A shard already held is replaced.
+    pub fn push(&mut self, index: usize, data: Vec<u8>) -> Result<(), ReconstructError> {
+        let n = self.k + self.m;
+        if index >= n {
+            return Err(ReconstructError::IndexOutOfRange { index, n });
+        }
+        let expected = *self.shard_len.get_or_insert(data.len());
+        if data.len() != expected {
+            return Err(ReconstructError::ShardLength { index, len: data.len(), expected });
+        }
+        self.received.insert(index, data);
+        Ok(())
+    }
+
+    pub fn shard(&self, index: usize) -> Option<&[u8]> {
+        self.received.get(&index).map(Vec::as_slice)
+    }
+
+    /// Codeword indices of the shards held, in no particular order.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.received.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.received.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.received.is_empty()
+    }
+
+    /// Whether enough shards are held to reconstruct.
+    pub fn is_ready(&self) -> bool {
+        self.received.len() >= self.k
+    }
+
+    /// Regenerates shard `index` as a GF(2^8) linear combination of any k held shards,
+    /// without decoding the blob.
+    pub fn repair(&self, index: usize) -> Result<Vec<u8>, ReconstructError> {
+        let n = self.k + self.m;
+        if index >= n {
+            return Err(ReconstructError::IndexOutOfRange { index, n });
+        }
+        if let Some(shard) = self.received.get(&index) {
+            return Ok(shard.clone());
+        }
+        self.regenerate_row(parity_offset(self.k, self.layout.encoding) + index)
+    }
+
+    /// The full extended shard set, regenerating any index that was not received.
+    pub fn extended_shards(&self) -> Result<Vec<Vec<u8>>, ReconstructError> {
+        (0..self.k + self.m).map(|i| self.repair(i)).collect()
+    }
+
+    /// Data shard `j` (row `j` of the full code), computed only if it was not received.
+    pub fn data_shard(&self, j: usize) -> Result<Cow<'_, [u8]>, ReconstructError> {
+        match self.received.get(&j).filter(|_| self.layout.encoding == Encoding::Systematic) {
+            Some(shard) => Ok(Cow::Borrowed(shard)),
+            None => Ok(Cow::Owned(self.regenerate_row(j)?)),
+        }
+    }
+
+    /// Position in the padded blob of the first padding byte (past `original_len`) that is not
+    /// zero. Encoders pad with zeros, so anything else means the shards carry bytes the blob's
+    /// checksum never covered. `None` when the padding is clean or `original_len` is unknown.
+    pub fn nonzero_padding(&self) -> Result<Option<usize>, ReconstructError> {
+        let Some(len) = self.original_len else { return Ok(None) };
+        let shards: Vec<Cow<'_, [u8]>> = (0..self.k).map(|j| self.data_shard(j)).collect::<Result<_, _>>()?;
+        let shard_len = shards[0].len();
+        Ok((len..self.k * shard_len).find(|&pos| {
+            let (j, row) = match self.layout.striping {
+                Striping::Contiguous => (pos / shard_len, pos % shard_len),
+                Striping::Interleaved => (pos % self.k, pos / self.k),
+            };
+            shards[j][row] != 0
+        }))
+    }
+
+    /// Reassembles the blob, truncated to `original_len` when it is known.
+    pub fn decode(&self) -> Result<Vec<u8>, ReconstructError> {
+        let shards: Vec<Vec<u8>> = (0..self.k).map(|j| self.data_shard(j).map(Cow::into_owned)).collect::<Result<_, _>>()?;
+        let mut data = match self.layout.striping {
+            Striping::Contiguous => shards.concat(),
+            Striping::Interleaved => {
+                let shard_len = shards[0].len();
+                (0..shard_len).flat_map(|r| shards.iter().map(move |s| s[r])).collect()
+            }
+        };
+        if let Some(len) = self.original_len {
+            data.truncate(len);
+        }
+        Ok(data)
+    }
+
+    /// Computes row `row` of the full code (the k data rows first, then the transmitted shards)
+    /// from any k held shards.
+    fn regenerate_row(&self, row: usize) -> Result<Vec<u8>, ReconstructError> {
+        let k = self.k;
+        let mut sources: Vec<usize> = self.received.keys().copied().collect();
+        sources.sort_unstable();
+        sources.truncate(k);
+        if sources.len() < k {
+            return Err(ReconstructError::NotEnoughShards { have: sources.len(), need: k });
+        }
+        let available = &self.received;
+        let shard_len = available[&sources[0]].len();
+        if sources.iter().any(|i| available[i].len() != shard_len) {
+            return Err(ReconstructError::MixedShardLengths);
+        }
+
+        let offset = parity_offset(k, self.layout.encoding);
+        let generator = generator_matrix(k, self.m, offset)?;
+        let sub: Vec<Vec<u8>> = sources.iter().map(|i| generator[offset + i].clone()).collect();
+        let inverse = gf_invert(sub).ok_or(ReconstructError::Singular)?;
+        // coefficients = G[row] * A^-1
+        let row = &generator[row];
+        let coeffs: Vec<u8> = (0..k)
+            .map(|j| (0..k).fold(0, |acc, t| galois_8::add(acc, galois_8::mul(row[t], inverse[t][j]))))
+            .collect();
+
+        let mut out = vec![0u8; shard_len];
+        for (c, i) in coeffs.iter().zip(&sources) {
+            galois_8::mul_slice_xor(*c, &available[i], &mut out);
+        }
+        Ok(out)
+    }
+}
+
+/// Non-systematic shards are the parity half of an RS(k, k + n) code: any k of them
+/// still decode, but none equals a slice of the input.
+fn parity_offset(k: usize, encoding: Encoding) -> usize {
+    match encoding {
+        Encoding::Systematic => 0,
+        Encoding::NonSystematic => k,
+    }
+}
+
+/// Rows of the full code's generator matrix, recovered by encoding unit vectors.
+fn generator_matrix(k: usize, m: usize, offset: usize) -> Result<Vec<Vec<u8>>, ReconstructError> {
+    let n = offset + k + m;
+    let r = ReedSolomon::new(k, n - k)?;
+    let mut rows = vec![vec![0u8; k]; n];
+    for j in 0..k {
+        let mut unit = vec![vec![0u8; 1]; n];
+        unit[j][0] = 1;
+        r.encode(&mut unit)?;
+        for (row, shard) in rows.iter_mut().zip(&unit) {
+            row[j] = shard[0];
+        }
+    }
+    Ok(rows)
+}
+
+/// Gauss-Jordan inversion over GF(2^8) (where subtraction is addition).
+fn gf_invert(mut m: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    let n = m.len();
+    let mut inv: Vec<Vec<u8>> = (0..n).map(|i| (0..n).map(|j| u8::from(i == j)).collect()).collect();
+    for col in 0..n {
+        let pivot = (col..n).find(|&r| m[r][col] != 0)?;
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+        let scale = m[col][col];
+        for j in 0..n {
+            m[col][j] = galois_8::div(m[col][j], scale);
+            inv[col][j] = galois_8::div(inv[col][j], scale);
+        }
+        for r in (0..n).filter(|&r| r != col) {
+            let factor = m[r][col];
+            if factor == 0 { continue; }
+            for j in 0..n {
+                m[r][j] = galois_8::add(m[r][j], galois_8::mul(factor, m[col][j]));
+                inv[r][j] = galois_8::add(inv[r][j], galois_8::mul(factor, inv[col][j]));
+            }
+        }
+    }
+    Some(inv)
+}
+
+/// Splits padded data into the k data shards.
+fn stripe(k: usize, padded: &[u8], striping: Striping) -> Vec<Vec<u8>> {
+    let shard_len = padded.len() / k;
+    match striping {
+        Striping::Contiguous => (0..k).map(|i| padded[i * shard_len..(i + 1) * shard_len].to_vec()).collect(),
+        Striping::Interleaved => (0..k)
+            .map(|i| padded.iter().skip(i).step_by(k).copied().collect())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUTS: [(Encoding, Striping); 4] = [
+        (Encoding::Systematic, Striping::Contiguous),
+        (Encoding::Systematic, Striping::Interleaved),
+        (Encoding::NonSystematic, Striping::Contiguous),
+        (Encoding::NonSystematic, Striping::Interleaved),
+    ];
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn any_k_shards_decode_in_every_layout() {
+        let data = data(1001);
+        for (encoding, striping) in LAYOUTS {
+            let blob = DasEncoder::new(4, 4).unwrap().encoding(encoding).striping(striping).encode(&data);
+            assert_eq!(blob.shards.len(), 8);
+            assert_eq!(blob.checksum, calculate_sha256(&data));
+            for subset in [[0, 1, 2, 3], [4, 5, 6, 7], [1, 3, 5, 7], [0, 2, 6, 7]] {
+                let mut decoder = blob.decoder();
+                for i in subset {
+                    decoder.push(i, blob.shards[i].clone()).unwrap();
+                }
+                assert_eq!(decoder.decode().unwrap(), data, "{:?} {:?} from {:?}", encoding, striping, subset);
+                for (i, shard) in blob.shards.iter().enumerate() {
+                    assert_eq!(&decoder.repair(i).unwrap(), shard);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn systematic_data_is_verbatim_and_non_systematic_is_not() {
+        let data = data(64);
+        let blob = DasEncoder::new(4, 2).unwrap().encode(&data);
+        assert_eq!(blob.shards[..4].concat(), data);
+        let blob = DasEncoder::new(4, 2).unwrap().encoding(Encoding::NonSystematic).encode(&data);
+        assert!(blob.shards.iter().all(|s| !data.windows(s.len()).any(|w| w == s.as_slice())));
+    }
+
+    #[test]
+    fn empty_blobs_pad_to_one_byte_shards() {
+        let blob = DasEncoder::new(4, 4).unwrap().encode(&[]);
+        assert_eq!((blob.shard_len, blob.original_len), (1, 0));
+        assert_eq!(blob.decoder().decode().unwrap_err().to_string(), "need 4 shards to repair, have 0");
+    }
+
+    #[test]
+    fn shards_pad_to_whole_field_elements() {
+        let blob = DasEncoder::new(4, 4).unwrap().shard_align(32).encode(&data(33));
+        assert_eq!(blob.shard_len, 32);
+        assert_eq!(blob.shards.iter().map(Vec::len).collect::<Vec<_>>(), [32; 8]);
+    }
+
+    #[test]
+    fn decoder_refuses_bad_shards() {
+        let blob = DasEncoder::new(4, 4).unwrap().encode(&data(100));
+        let mut decoder = blob.decoder();
+        assert!(matches!(decoder.push(8, blob.shards[0].clone()), Err(ReconstructError::IndexOutOfRange { index: 8, n: 8 })));
+        assert!(matches!(decoder.push(0, vec![0; 3]), Err(ReconstructError::ShardLength { index: 0, len: 3, .. })));
+        for i in 0..3 {
+            decoder.push(i, blob.shards[i].clone()).unwrap();
+        }
+        assert!(!decoder.is_ready());
+        assert!(matches!(decoder.decode(), Err(ReconstructError::NotEnoughShards { have: 3, need: 4 })));
+    }
+
+    #[test]
+    fn nonzero_padding_is_found() {
+        let blob = DasEncoder::new(4, 4).unwrap().striping(Striping::Interleaved).encode(&data(10));
+        let mut decoder = blob.decoder();
+        for i in 0..4 {
+            decoder.push(i, blob.shards[i].clone()).unwrap();
+        }
+        assert_eq!(decoder.nonzero_padding().unwrap(), None);
+        // Byte 11 of the padded blob is row 2 of shard 3 when interleaved over 4.
+        let mut shard = blob.shards[3].clone();
+        shard[2] = 1;
+        decoder.push(3, shard).unwrap();
+        assert_eq!(decoder.nonzero_padding().unwrap(), Some(11));
+    }
+
+    #[test]
+    fn params_are_checked_with_a_suggestion() {
+        assert!(check_params(4, 4).is_ok());
+        assert!(matches!(check_params(0, 4), Err(ReconstructError::InvalidParams { suggested_k: 1, suggested_m: 4, .. })));
+        let Err(ReconstructError::InvalidParams { suggested_k, suggested_m, .. }) = check_params(128, 128) else { panic!("over the field") };
+        assert!(2 * suggested_k + suggested_m <= MAX_SHARDS);
+        assert_eq!(suggest_params(200, 0), (127, 1));
+    }
+
+    #[test]
+    fn merkle_root_pairs_odd_nodes_with_themselves() {
+        let leaves: Vec<String> = ["a", "b", "c"].iter().map(|l| calculate_sha256(l.as_bytes())).collect();
+        let pair = |a: &str, b: &str| calculate_sha256(&[hex::decode(a).unwrap(), hex::decode(b).unwrap()].concat());
+        let expected = pair(&pair(&leaves[0], &leaves[1]), &pair(&leaves[2], &leaves[2]));
+        assert_eq!(merkle_root(&leaves), expected);
+        assert_eq!(merkle_root(&[]), calculate_sha256(&[]));
+    }
+}
@@ -0,0 +1,238 @@
+//! Commitment schemes a proposer can bind a blob to. The chosen scheme travels in the manifest as
+//! a tagged [`BlobCommitment`], so validators check shards with whatever the proposer picked and
+//! schemes can be compared under identical network conditions.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::coding::{calculate_sha256, merkle_root, EncodedBlob};
+use crate::error::CommitmentError;
+use crate::kzg;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommitmentScheme {
+    /// SHA-256 of the whole file only; shards are unchecked until the blob is rebuilt
+    Sha256,
+    /// SHA-256 per shard under a Merkle root
+    #[default]
+    Merkle,
+    /// One KZG commitment per shard (BLS12-381)
+    Kzg,
+}
+
+impl CommitmentScheme {
+    pub fn commitment(self) -> &'static dyn Commitment {
+        match self {
+            CommitmentScheme::Sha256 => &Sha256Commitment,
+            CommitmentScheme::Merkle => &MerkleCommitment,
+            CommitmentScheme::Kzg => &KzgCommitment,
+        }
+    }
+
+    /// What every shard length must be a multiple of under this scheme.
+    pub fn shard_align(self) -> usize {
+        match self {
+            CommitmentScheme::Kzg => kzg::BYTES_PER_FIELD_ELEMENT,
+            _ => 1,
+        }
+    }
+}
+
+/// How a KZG blob's shards map onto field elements. Every shard is a whole number of them, so
+/// each byte is committed to and none is implied: cutting a shard's trailing zeros, or adding
+/// some, changes what it commits to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldLayout {
+    /// Shard bytes packed into each 32-byte field element
+    pub bytes_per_element: usize,
+    pub elements_per_shard: usize,
+    /// Zero bytes after the blob in the k data shards, up to the last element boundary
+    pub padding: usize,
+}
+
+impl FieldLayout {
+    pub fn new(blob: &EncodedBlob) -> Self {
+        Self {
+            bytes_per_element: kzg::BYTES_PER_FIELD_ELEMENT,
+            elements_per_shard: blob.shard_len / kzg::BYTES_PER_FIELD_ELEMENT,
+            padding: blob.k * blob.shard_len - blob.original_len,
+        }
+    }
+}
+
+/// The wire form of a commitment, tagged by `scheme`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "scheme", rename_all = "kebab-case")]
+pub enum BlobCommitment {
+    /// Relies on the manifest's whole-file `checksum`.
+    Sha256,
+    /// SHA-256 of each shard; the manifest's `root` is their Merkle root.
+    Merkle { shard_hashes: Vec<String> },
+    /// Compressed G1 points, hex-encoded, one per shard, with an opening proof per shard that
+    /// lets a sampler check a shard without recomputing its commitment. Manifests from before
+    /// proofs carry none.
+    Kzg {
+        commitments: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        proofs: Vec<String>,
+    },
+}
+
+impl BlobCommitment {
+    pub fn scheme(&self) -> CommitmentScheme {
+        match self {
+            BlobCommitment::Sha256 => CommitmentScheme::Sha256,
+            BlobCommitment::Merkle { .. } => CommitmentScheme::Merkle,
+            BlobCommitment::Kzg { .. } => CommitmentScheme::Kzg,
+        }
+    }
+
+    /// EIP-4844 versioned hashes (`0x`-prefixed hex), one per shard in codeword order. Only KZG
+    /// commitments have them; every other scheme gives none.
+    pub fn versioned_hashes(&self) -> Vec<String> {
+        let BlobCommitment::Kzg { commitments, .. } = self else { return Vec::new() };
+        commitments.iter()
+            .filter_map(|c| hex::decode(c).ok())
+            .map(|bytes| format!("0x{}", hex::encode(kzg::versioned_hash(&bytes))))
+            .collect()
+    }
+
+    /// Checks shard `index` (codeword order). `None` if the scheme cannot check single shards.
+    pub fn verify_shard(&self, index: usize, shard: &[u8]) -> Option<bool> {
+        self.scheme().commitment().verify_shard(self, index, shard)
+    }
+
+    /// Checks `(index, shard)` pairs together; `true` only if every one passes.
+    pub fn verify_shards(&self, shards: &[(usize, &[u8])]) -> Option<bool> {
+        self.scheme().commitment().verify_shards(self, shards)
+    }
+}
+
+pub trait Commitment: Send + Sync {
+    fn scheme(&self) -> CommitmentScheme;
+
+    fn commit(&self, blob: &EncodedBlob) -> Result<BlobCommitment, CommitmentError>;
+
+    /// Checks one shard against `commitment`; `None` when the scheme has no per-shard check.
+    fn verify_shard(&self, commitment: &BlobCommitment, index: usize, shard: &[u8]) -> Option<bool>;
+
+    /// Checks several shards at once. One by one unless the scheme has a cheaper batch check.
+    fn verify_shards(&self, commitment: &BlobCommitment, shards: &[(usize, &[u8])]) -> Option<bool> {
+        shards.iter().try_fold(true, |ok, &(index, shard)| Some(ok && self.verify_shard(commitment, index, shard)?))
+    }
+}
+
+pub struct Sha256Commitment;
+
+impl Commitment for Sha256Commitment {
+    fn scheme(&self) -> CommitmentScheme {
+        CommitmentScheme::Sha256
+    }
+
+    fn commit(&self, _blob: &EncodedBlob) -> Result<BlobCommitment, CommitmentError> {
+        Ok(BlobCommitment::Sha256)
+    }
+
+    fn verify_shard(&self, _commitment: &BlobCommitment, _index: usize, _shard: &[u8]) -> Option<bool> {
+        None
+    }
+}
+
+pub struct MerkleCommitment;
+
+impl MerkleCommitment {
+    /// The Merkle root a commitment's shard hashes produce, to compare against the manifest root.
+    pub fn root(commitment: &BlobCommitment) -> Option<String> {
+        match commitment {
+            BlobCommitment::Merkle { shard_hashes } => Some(merkle_root(shard_hashes)),
+            _ => None,
+        }
+    }
+}
+
+impl Commitment for MerkleCommitment {
+    fn scheme(&self) -> CommitmentScheme {
+        CommitmentScheme::Merkle
+    }
+
+    fn commit(&self, blob: &EncodedBlob) -> Result<BlobCommitment, CommitmentError> {
+        Ok(BlobCommitment::Merkle { shard_hashes: blob.shard_hashes.clone() })
+    }
+
+    fn verify_shard(&self, commitment: &BlobCommitment, index: usize, shard: &[u8]) -> Option<bool> {
+        let BlobCommitment::Merkle { shard_hashes } = commitment else { return Some(false) };
+        Some(shard_hashes.get(index).is_some_and(|h| *h == calculate_sha256(shard)))
+    }
+}
+
+/// Commits to each shard as a polynomial whose coefficients are its packed field elements, and
+/// opens it at a point derived from the commitment and the shard. A shard with a proof is checked
+/// by one evaluation and two pairings; without one, by recomputing its commitment.
+pub struct KzgCommitment;
+
+impl KzgCommitment {
+    pub fn commit_shard(shard: &[u8]) -> Result<String, CommitmentError> {
+        let coeffs = kzg::field_elements(shard);
+        let setup = kzg::setup(coeffs.len());
+        Ok(kzg::encode_point(&setup.commit(&coeffs)?))
+    }
+
+    /// The shard's commitment and its opening proof, both hex-encoded.
+    pub fn commit_and_prove(shard: &[u8]) -> Result<(String, String), CommitmentError> {
+        let coeffs = kzg::field_elements(shard);
+        let setup = kzg::setup(coeffs.len());
+        let commitment = setup.commit(&coeffs)?;
+        let proof = setup.open(&coeffs, kzg::challenge(&commitment, shard))?;
+        Ok((kzg::encode_point(&commitment), kzg::encode_point(&proof)))
+    }
+
+    /// Whether `proof` opens `commitment` to `shard` at the shard's challenge point.
+    pub fn verify_proof(commitment: &str, shard: &[u8], proof: &str) -> bool {
+        Self::opening(commitment, shard, proof)
+            .is_some_and(|o| kzg::setup(0).verify_opening(&o.commitment, o.z, o.y, &o.proof))
+    }
+
+    /// The opening a shard's proof claims, or `None` if a point does not decode.
+    fn opening(commitment: &str, shard: &[u8], proof: &str) -> Option<kzg::Opening> {
+        let (commitment, proof) = (kzg::decode_point(commitment).ok()?, kzg::decode_point(proof).ok()?);
+        let z = kzg::challenge(&commitment, shard);
+        Some(kzg::Opening { commitment, z, y: kzg::evaluate(&kzg::field_elements(shard), z), proof })
+    }
+}
+
+impl Commitment for KzgCommitment {
+    fn scheme(&self) -> CommitmentScheme {
+        CommitmentScheme::Kzg
+    }
+
+    /// Fails unless the shards are whole field elements (`DasEncoder::shard_align`).
+    fn commit(&self, blob: &EncodedBlob) -> Result<BlobCommitment, CommitmentError> {
+        if !blob.shard_len.is_multiple_of(kzg::BYTES_PER_FIELD_ELEMENT) {
+            return Err(CommitmentError::Unaligned { shard_len: blob.shard_len, bytes_per_element: kzg::BYTES_PER_FIELD_ELEMENT });
+        }
+        let (commitments, proofs) = blob.shards.iter().map(|s| Self::commit_and_prove(s)).collect::<Result<_, _>>()?;
+        Ok(BlobCommitment::Kzg { commitments, proofs })
+    }
+
+    /// With proofs, one batched pairing check for all shards; without, one recomputation each.
+    fn verify_shards(&self, commitment: &BlobCommitment, shards: &[(usize, &[u8])]) -> Option<bool> {
+        let BlobCommitment::Kzg { commitments, proofs } = commitment else { return Some(false) };
+        if proofs.is_empty() {
+            return Some(shards.iter().all(|&(index, shard)| self.verify_shard(commitment, index, shard) == Some(true)));
+        }
+        let openings: Option<Vec<_>> = shards.iter()
+            .map(|&(index, shard)| Self::opening(commitments.get(index)?, shard, proofs.get(index)?))
+            .collect();
+        Some(openings.is_some_and(|o| kzg::setup(0).verify_openings(&o)))
+    }
+
+    fn verify_shard(&self, commitment: &BlobCommitment, index: usize, shard: &[u8]) -> Option<bool> {
+        let BlobCommitment::Kzg { commitments, proofs } = commitment else { return Some(false) };
+        let Some(expected) = commitments.get(index) else { return Some(false) };
+        Some(match proofs.get(index) {
+            Some(proof) => Self::verify_proof(expected, shard, proof),
+            None => Self::commit_shard(shard).is_ok_and(|c| c == *expected),
+        })
+    }
+}
@@ -0,0 +1,271 @@
+//! Signed envelopes around every frame after the handshake. The handshake authenticates the
+//! peer once; envelopes authenticate each message, so a frame an on-path attacker rewrote,
+//! injected, replayed or dropped is caught at that frame instead of passing as the peer's.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+use futures::{ready, Sink, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EnvelopeError, TransportError};
+use crate::handshake::{Identity, PeerHandshake};
+use crate::transport::Connection;
+
+/// One frame as it travels once the session is up.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Envelope {
+    /// Sender's pubkey, hex
+    pub from: String,
+    /// The sender's frames on this connection, counted from 0
+    pub seq: u64,
+    /// The frame itself, exactly as it would travel unwrapped
+    pub payload: String,
+    /// Ed25519 signature by `from` over the session, `seq` and `payload`, hex
+    pub sig: String,
+}
+
+impl Envelope {
+    pub fn seal(id: &Identity, session: &[u8; 32], seq: u64, payload: String) -> Self {
+        let sig = id.key.sign(&signing_bytes(session, seq, &payload));
+        Self { from: hex::encode(id.public.as_bytes()), seq, payload, sig: hex::encode(sig.to_bytes()) }
+    }
+
+    /// Checks that `peer` sealed this envelope for `session`. Says nothing about its order.
+    pub fn verify(&self, peer: &VerifyingKey, session: &[u8; 32]) -> Result<(), EnvelopeError> {
+        if self.from != hex::encode(peer.as_bytes()) {
+            return Err(EnvelopeError::WrongSender { from: self.from.clone() });
+        }
+        let sig: [u8; 64] = hex::decode(&self.sig).ok().and_then(|b| b.try_into().ok()).ok_or(EnvelopeError::BadSignature)?;
+        peer.verify(&signing_bytes(session, self.seq, &self.payload), &Signature::from_bytes(&sig))
+            .map_err(|_| EnvelopeError::BadSignature)
+    }
+}
+
+/// The session ID keeps an envelope from being replayed into another session between the same
+/// two nodes, where its sequence number could be fresh.
+fn signing_bytes(session: &[u8; 32], seq: u64, payload: &str) -> Vec<u8> {
+    let mut bytes = b"das-envelope:v1:".to_vec();
+    bytes.extend_from_slice(session);
+    bytes.extend_from_slice(&seq.to_be_bytes());
+    bytes.extend_from_slice(payload.as_bytes());
+    bytes
+}
+
+/// Seals every frame written in an [`Envelope`] and opens every frame read, passing on only
+/// payloads the handshake's peer signed for this session, in its send order. A rejected frame
+/// is reported as [`TransportError::Envelope`] and the connection stays usable. After a gap,
+/// the frame that revealed it follows the error, since it is genuine.
+pub struct SignedConnection<C> {
+    inner: C,
+    id: Identity,
+    peer: VerifyingKey,
+    session: [u8; 32],
+    /// The peer's `max_frame`, which sealed frames must fit
+    limit: usize,
+    next_send: u64,
+    next_recv: u64,
+    /// A genuine frame held back while the gap before it is reported
+    held: Option<String>,
+    /// Every envelope sealed or opened, in order, when kept
+    transcript: Option<Vec<Envelope>>,
+}
+
+impl<C: Connection> SignedConnection<C> {
+    pub fn new(inner: C, id: &Identity, handshake: &PeerHandshake) -> Self {
+        Self {
+            inner,
+            id: id.clone(),
+            peer: handshake.key,
+            session: handshake.session,
+            limit: handshake.max_frame,
+            next_send: 0,
+            next_recv: 0,
+            held: None,
+            transcript: None,
+        }
+    }
+
+    /// Keeps every envelope sent and every genuine, in-sequence one received, so what each side
+    /// signed can be shown to a third party later.
+    pub fn keep_transcript(mut self) -> Self {
+        self.transcript = Some(Vec::new());
+        self
+    }
+
+    /// The envelopes kept so far; empty unless [`keep_transcript`](Self::keep_transcript).
+    pub fn transcript(&self) -> &[Envelope] {
+        self.transcript.as_deref().unwrap_or_default()
+    }
+
+    /// The wrapped connection.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    fn open(&mut self, line: &str) -> Result<String, EnvelopeError> {
+        let envelope: Envelope = serde_json::from_str(line).map_err(|e| EnvelopeError::Malformed(e.to_string()))?;
+        envelope.verify(&self.peer, &self.session)?;
+        let expected = self.next_recv;
+        if envelope.seq < expected {
+            return Err(EnvelopeError::Replayed { seq: envelope.seq, expected });
+        }
+        // Taking `u64::MAX` would wrap the next expected seq to 0 and let every frame replay.
+        let next = envelope.seq.checked_add(1).ok_or(EnvelopeError::SeqExhausted { seq: envelope.seq })?;
+        // Only envelopes the sequence check lets through, so a replay can't pad the transcript.
+        if let Some(transcript) = &mut self.transcript {
+            transcript.push(envelope.clone());
+        }
+        self.next_recv = next;
+        if envelope.seq > expected {
+            self.held = Some(envelope.payload);
+            return Err(EnvelopeError::Gap { seq: envelope.seq, expected });
+        }
+        Ok(envelope.payload)
+    }
+}
+
+impl<C: Connection> Connection for SignedConnection<C> {
+    fn peer_addr(&self) -> String {
+        self.inner.peer_addr()
+    }
+}
+
+impl<C: Connection> Stream for SignedConnection<C> {
+    type Item = Result<String, TransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(payload) = self.held.take() {
+            return Poll::Ready(Some(Ok(payload)));
+        }
+        let frame = ready!(self.inner.poll_next_unpin(cx));
+        Poll::Ready(frame.map(|frame| frame.and_then(|line| Ok(self.open(&line)?))))
+    }
+}
+
+impl<C: Connection> Sink<String> for SignedConnection<C> {
+    type Error = TransportError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: String) -> Result<(), TransportError> {
+        let envelope = Envelope::seal(&self.id, &self.session, self.next_send, frame);
+        let line = serde_json::to_string(&envelope).expect("envelope serializes");
+        // The payload alone may fit while the envelope around it does not.
+        if line.len() > self.limit {
+            return Err(TransportError::FrameTooLarge { len: line.len(), limit: self.limit });
+        }
+        self.next_send += 1;
+        if let Some(transcript) = &mut self.transcript {
+            transcript.push(envelope);
+        }
+        Pin::new(&mut self.inner).start_send(line)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{Compression, Listener, MemoryConnection, MemoryNetwork, Transport};
+
+    const SESSION: [u8; 32] = [7; 32];
+
+    /// The peer's identity, the raw end it writes envelopes to, and our signed end reading them.
+    async fn pair() -> (Identity, MemoryConnection, SignedConnection<MemoryConnection>) {
+        let net = MemoryNetwork::new();
+        let mut listener = net.transport("b", usize::MAX).listen("b").await.unwrap();
+        let raw = net.transport("a", usize::MAX).connect("b").await.unwrap();
+        let peer = Identity::generate();
+        let handshake = PeerHandshake {
+            key: peer.public,
+            max_frame: usize::MAX,
+            compression: Compression::None,
+            clock_offset_ms: 0,
+            session: SESSION,
+            record: None,
+        };
+        let signed = SignedConnection::new(listener.accept().await.unwrap(), &Identity::generate(), &handshake).keep_transcript();
+        (peer, raw, signed)
+    }
+
+    fn sealed(id: &Identity, seq: u64, payload: &str) -> String {
+        serde_json::to_string(&Envelope::seal(id, &SESSION, seq, payload.into())).unwrap()
+    }
+
+    #[test]
+    fn seal_verifies_only_for_its_signer_and_session() {
+        let id = Identity::generate();
+        let envelope = Envelope::seal(&id, &SESSION, 3, "frame".into());
+        assert!(envelope.verify(&id.public, &SESSION).is_ok());
+        assert!(matches!(envelope.verify(&id.public, &[8; 32]), Err(EnvelopeError::BadSignature)));
+        assert!(matches!(envelope.verify(&Identity::generate().public, &SESSION), Err(EnvelopeError::WrongSender { .. })));
+        let moved = Envelope { seq: 4, ..envelope.clone() };
+        assert!(matches!(moved.verify(&id.public, &SESSION), Err(EnvelopeError::BadSignature)));
+        let rewritten = Envelope { payload: "other".into(), ..envelope };
+        assert!(matches!(rewritten.verify(&id.public, &SESSION), Err(EnvelopeError::BadSignature)));
+    }
+
+    #[tokio::test]
+    async fn frames_pass_in_order_and_replays_are_refused() {
+        let (peer, mut raw, mut signed) = pair().await;
+        for frame in [sealed(&peer, 0, "a"), sealed(&peer, 1, "b"), sealed(&peer, 1, "b"), sealed(&peer, 0, "a")] {
+            raw.send_frame(frame, usize::MAX).await.unwrap();
+        }
+        assert_eq!(signed.next().await.unwrap().unwrap(), "a");
+        assert_eq!(signed.next().await.unwrap().unwrap(), "b");
+        for expected_seq in [1, 0] {
+            match signed.next().await.unwrap() {
+                Err(TransportError::Envelope(EnvelopeError::Replayed { seq, expected: 2 })) => assert_eq!(seq, expected_seq),
+                other => panic!("expected a replay, got {:?}", other),
+            }
+        }
+        // Replays don't reach the transcript.
+        assert_eq!(signed.transcript().iter().map(|e| e.seq).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[tokio::test]
+    async fn a_gap_is_reported_before_the_frame_that_revealed_it() {
+        let (peer, mut raw, mut signed) = pair().await;
+        raw.send_frame(sealed(&peer, 2, "c"), usize::MAX).await.unwrap();
+        raw.send_frame(sealed(&peer, 3, "d"), usize::MAX).await.unwrap();
+        assert!(matches!(signed.next().await.unwrap(), Err(TransportError::Envelope(EnvelopeError::Gap { seq: 2, expected: 0 }))));
+        assert_eq!(signed.next().await.unwrap().unwrap(), "c");
+        assert_eq!(signed.next().await.unwrap().unwrap(), "d");
+    }
+
+    #[tokio::test]
+    async fn the_last_seq_cannot_wrap_the_count() {
+        let (peer, mut raw, mut signed) = pair().await;
+        raw.send_frame(sealed(&peer, u64::MAX, "last"), usize::MAX).await.unwrap();
+        raw.send_frame(sealed(&peer, 0, "again"), usize::MAX).await.unwrap();
+        assert!(matches!(signed.next().await.unwrap(), Err(TransportError::Envelope(EnvelopeError::SeqExhausted { seq: u64::MAX }))));
+        // Refused without moving the count, so seq 0 is still the next one.
+        assert_eq!(signed.next().await.unwrap().unwrap(), "again");
+        assert!(signed.transcript().iter().all(|e| e.seq != u64::MAX));
+    }
+
+    #[tokio::test]
+    async fn forged_and_malformed_envelopes_are_refused() {
+        let (peer, mut raw, mut signed) = pair().await;
+        let forged = Envelope { payload: "forged".into(), ..Envelope::seal(&peer, &SESSION, 0, "real".into()) };
+        raw.send_frame(serde_json::to_string(&forged).unwrap(), usize::MAX).await.unwrap();
+        raw.send_frame(sealed(&Identity::generate(), 0, "stranger"), usize::MAX).await.unwrap();
+        raw.send_frame("{\"seq\":0}".into(), usize::MAX).await.unwrap();
+        raw.send_frame(sealed(&peer, 0, "real"), usize::MAX).await.unwrap();
+        assert!(matches!(signed.next().await.unwrap(), Err(TransportError::Envelope(EnvelopeError::BadSignature))));
+        assert!(matches!(signed.next().await.unwrap(), Err(TransportError::Envelope(EnvelopeError::WrongSender { .. }))));
+        assert!(matches!(signed.next().await.unwrap(), Err(TransportError::Envelope(EnvelopeError::Malformed(_)))));
+        assert_eq!(signed.next().await.unwrap().unwrap(), "real");
+    }
+}
@@ -0,0 +1,272 @@
+//! Typed failures for the library layer. Each module reports its own kind; [`Error`] wraps them
+//! so callers can either match on a specific kind or propagate one type.
+
+use thiserror::Error;
+use tokio_util::codec::LinesCodecError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Handshake(#[from] HandshakeError),
+    #[error(transparent)]
+    Codec(#[from] CodecError),
+    #[error(transparent)]
+    Reconstruct(#[from] ReconstructError),
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+    #[error(transparent)]
+    Commitment(#[from] CommitmentError),
+    #[error(transparent)]
+    Matrix(#[from] MatrixError),
+    #[error(transparent)]
+    Record(#[from] RecordError),
+    #[error(transparent)]
+    Car(#[from] CarError),
+    #[error(transparent)]
+    Anchor(#[from] AnchorError),
+    #[error(transparent)]
+    Sidecar(#[from] SidecarError),
+}
+
+/// The peer could not be authenticated.
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("peer closed before handshake")]
+    PeerClosed,
+    #[error("expected handshake, got another message")]
+    UnexpectedMessage,
+    /// The peer connected but did not complete the handshake in time.
+    #[error("no handshake within {secs} s")]
+    Timeout { secs: u64 },
+    /// Authenticated, but as a different node than the one asked for.
+    #[error("peer is {got}, not the requested {expected}")]
+    UnexpectedPeer { expected: String, got: String },
+    /// The peer's signed timestamp is further from our clock than the allowed skew.
+    #[error("peer clock is {offset_ms} ms off ours, beyond the {max_ms} ms allowed skew")]
+    ClockSkew { offset_ms: i64, max_ms: u64 },
+    #[error("bad handshake signature: {0}")]
+    BadSignature(#[from] ed25519_dalek::SignatureError),
+    /// A node record that does not verify, or that names another node than the handshake.
+    #[error("bad node record: {0}")]
+    BadRecord(#[from] RecordError),
+    #[error(transparent)]
+    Codec(#[from] CodecError),
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+}
+
+/// A frame that could not be parsed, or that failed validation.
+#[derive(Debug, Error)]
+pub enum CodecError {
+    /// Not JSON, or not a known message shape.
+    #[error("malformed frame: {0}")]
+    Malformed(String),
+    /// Well-formed, but a field is out of range or unsafe to use.
+    #[error("invalid {field}: {reason}")]
+    InvalidField { field: &'static str, reason: String },
+}
+
+/// Shards could not be turned back into data.
+#[derive(Debug, Error)]
+pub enum ReconstructError {
+    #[error("shard index {index} out of range (n={n})")]
+    IndexOutOfRange { index: usize, n: usize },
+    #[error("need {need} shards to repair, have {have}")]
+    NotEnoughShards { have: usize, need: usize },
+    #[error("shard {index} is {len} bytes, expected {expected}")]
+    ShardLength { index: usize, len: usize, expected: usize },
+    #[error("source shards differ in length")]
+    MixedShardLengths,
+    #[error("source shards are not independent")]
+    Singular,
+    /// k and m that make no code both encodings can use.
+    #[error("no RS code with k = {k}, m = {m}: {reason}; try k = {suggested_k}, m = {suggested_m}")]
+    InvalidParams { k: usize, m: usize, reason: String, suggested_k: usize, suggested_m: usize },
+    #[error("reed-solomon: {0}")]
+    Coding(#[from] reed_solomon_erasure::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The connection failed underneath the protocol.
+#[derive(Debug, Error)]
+pub enum TransportError {
+    #[error("{len} byte frame exceeds the peer's max_frame of {limit} bytes")]
+    FrameTooLarge { len: usize, limit: usize },
+    /// A received frame was over this node's own limit.
+    #[error("received a frame over the {limit} byte max_frame")]
+    OversizeFrame { limit: usize },
+    #[error("connection closed")]
+    Closed,
+    /// The SOCKS5 proxy refused or botched the connection to the target.
+    #[error("SOCKS5 proxy: {0}")]
+    Socks(String),
+    /// The relay could not put us through to the node, or dropped its registration.
+    #[error("relay: {0}")]
+    Relay(String),
+    /// A frame that did not inflate with the negotiated codec.
+    #[error("undecodable {codec} frame: {reason}")]
+    Decompress { codec: &'static str, reason: String },
+    #[error(transparent)]
+    Envelope(#[from] EnvelopeError),
+    #[error(transparent)]
+    Lines(#[from] LinesCodecError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A frame whose envelope does not show it came from the session's peer, in order.
+#[derive(Debug, Error)]
+pub enum EnvelopeError {
+    #[error("malformed envelope: {0}")]
+    Malformed(String),
+    #[error("envelope from {from}, not the session's peer")]
+    WrongSender { from: String },
+    #[error("bad envelope signature")]
+    BadSignature,
+    #[error("envelope seq {seq} already received (next is {expected})")]
+    Replayed { seq: u64, expected: u64 },
+    /// Frames `expected..seq` never arrived; the frame at `seq` itself is genuine.
+    #[error("envelope seq {seq} arrived while {expected} was next; the frames between are missing")]
+    Gap { seq: u64, expected: u64 },
+    /// A seq no later frame could follow, which would wrap the count back to 0.
+    #[error("envelope seq {seq} is the last there is; no frame can follow it")]
+    SeqExhausted { seq: u64 },
+}
+
+/// A node record that does not parse or verify, or a peers file holding one.
+#[derive(Debug, Error)]
+pub enum RecordError {
+    #[error("malformed record: {0}")]
+    Malformed(String),
+    #[error("bad record signature: {0}")]
+    BadSignature(#[from] ed25519_dalek::SignatureError),
+    /// Signed by its key, but presented by a different node.
+    #[error("record of {record}, presented by {peer}")]
+    WrongNode { record: String, peer: String },
+    #[error("line {line}: {source}")]
+    Line { line: usize, source: Box<RecordError> },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A CAR archive that does not parse, or a block that does not match its CID.
+#[derive(Debug, Error)]
+pub enum CarError {
+    #[error("malformed archive: {0}")]
+    Malformed(String),
+    #[error("block {cid} does not match its CID")]
+    BadBlock { cid: String },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A JSON-RPC call to an Ethereum endpoint that failed, or that the node refused.
+#[derive(Debug, Error)]
+pub enum AnchorError {
+    #[error("HTTP: {0}")]
+    Http(String),
+    #[error("{method} failed ({code}): {message}")]
+    Rpc { method: String, code: i64, message: String },
+    #[error("malformed response: {0}")]
+    Malformed(String),
+    #[error("the endpoint holds no account to send from")]
+    NoAccount,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A blob sidecar that does not parse, or that fails a check.
+#[derive(Debug, Error)]
+pub enum SidecarError {
+    #[error("{len} bytes is not a whole number of {} byte sidecars", crate::sidecar::SIDECAR_LEN)]
+    Length { len: usize },
+    /// A 32-byte element at or above the BLS12-381 scalar modulus.
+    #[error("blob element {element} is not a canonical field element")]
+    NonCanonical { element: usize },
+    /// The commitment does not hash up to the header's body root.
+    #[error("commitment {index} is not included in the block body")]
+    Inclusion { index: u64 },
+}
+
+/// A blob or shard could not be committed to.
+#[derive(Debug, Error)]
+pub enum CommitmentError {
+    #[error("{needed} field elements exceed the KZG setup of {size} powers")]
+    SetupTooSmall { needed: usize, size: usize },
+    #[error("not a compressed G1 point: {0}")]
+    InvalidPoint(String),
+    #[error("trusted setup: {0}")]
+    InvalidSetup(String),
+    /// A shard that does not fill a whole number of field elements.
+    #[error("{shard_len} byte shards are not whole {bytes_per_element} byte field elements")]
+    Unaligned { shard_len: usize, bytes_per_element: usize },
+}
+
+/// A block matrix that could not be built, or a payload that is not a well-formed one.
+#[derive(Debug, Error)]
+pub enum MatrixError {
+    #[error("a block matrix holds 1 to {max} blobs, not {count}")]
+    BlobCount { count: usize, max: usize },
+    #[error("not a block matrix")]
+    NotAMatrix,
+    #[error("unsupported block matrix version {0}")]
+    Version(u8),
+    #[error("malformed block matrix: {0}")]
+    Malformed(String),
+    /// The parity rows are not the column code of the data rows.
+    #[error("column {column} does not match its column code")]
+    BadExtension { column: usize },
+    #[error("reed-solomon: {0}")]
+    Coding(#[from] reed_solomon_erasure::Error),
+    #[error(transparent)]
+    Reconstruct(#[from] ReconstructError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variants_name_what_went_wrong() {
+        assert_eq!(HandshakeError::Timeout { secs: 5 }.to_string(), "no handshake within 5 s");
+        assert_eq!(CodecError::InvalidField { field: "root", reason: "too short".into() }.to_string(), "invalid root: too short");
+        assert_eq!(CodecError::Malformed("eof".into()).to_string(), "malformed frame: eof");
+        assert_eq!(ReconstructError::NotEnoughShards { have: 3, need: 4 }.to_string(), "need 4 shards to repair, have 3");
+        assert_eq!(ReconstructError::ShardLength { index: 2, len: 7, expected: 8 }.to_string(), "shard 2 is 7 bytes, expected 8");
+        assert_eq!(TransportError::FrameTooLarge { len: 10, limit: 5 }.to_string(), "10 byte frame exceeds the peer's max_frame of 5 bytes");
+    }
+
+    #[test]
+    fn kinds_convert_into_the_crate_error_and_keep_their_message() {
+        let codec = CodecError::Malformed("eof".into());
+        let message = codec.to_string();
+        let err = Error::from(codec);
+        assert!(matches!(err, Error::Codec(CodecError::Malformed(_))));
+        assert_eq!(err.to_string(), message);
+
+        assert!(matches!(Error::from(TransportError::Closed), Error::Transport(TransportError::Closed)));
+        assert!(matches!(Error::from(ReconstructError::Singular), Error::Reconstruct(ReconstructError::Singular)));
+        assert!(matches!(Error::from(HandshakeError::PeerClosed), Error::Handshake(HandshakeError::PeerClosed)));
+    }
+
+    #[test]
+    fn handshake_errors_wrap_the_layers_below() {
+        let err = HandshakeError::from(TransportError::Closed);
+        assert!(matches!(err, HandshakeError::Transport(TransportError::Closed)));
+        assert_eq!(err.to_string(), "connection closed");
+        let err = HandshakeError::from(CodecError::InvalidField { field: "sig", reason: "expected 64 bytes".into() });
+        assert_eq!(err.to_string(), "invalid sig: expected 64 bytes");
+    }
+
+    #[test]
+    fn io_errors_pass_through_unchanged() {
+        let err = ReconstructError::from(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "short shard"));
+        assert_eq!(err.to_string(), "short shard");
+        let err = Error::from(TransportError::from(std::io::Error::other("reset")));
+        assert_eq!(err.to_string(), "reset");
+        assert!(matches!(err, Error::Transport(TransportError::Io(ref e)) if e.kind() == std::io::ErrorKind::Other));
+    }
+}
@@ -0,0 +1,44 @@
+//! What data would cost on Ethereum under EIP-4844 blob gas rules, to put the prototype's
+//! transfers in economic terms.
+
+use serde::Serialize;
+
+use crate::kzg::BYTES_PER_FIELD_ELEMENT;
+
+pub const FIELD_ELEMENTS_PER_BLOB: usize = 4096;
+/// Payload bytes one blob carries when packed the way [`crate::kzg::field_elements`] packs
+/// them: 31 bytes per 32-byte field element.
+pub const USABLE_BYTES_PER_BLOB: usize = FIELD_ELEMENTS_PER_BLOB * BYTES_PER_FIELD_ELEMENT;
+/// Blob gas charged per blob, whatever it holds.
+pub const GAS_PER_BLOB: u64 = 1 << 17;
+/// The floor on the blob base fee, in wei.
+pub const MIN_BASE_FEE_PER_BLOB_GAS: u64 = 1;
+
+const WEI_PER_GWEI: f64 = 1e9;
+const WEI_PER_ETH: f64 = 1e18;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct BlobFee {
+    pub blobs: usize,
+    pub blob_gas: u64,
+    pub base_fee_wei: u64,
+    pub fee_wei: u128,
+}
+
+impl BlobFee {
+    /// Cost of posting `payload_len` bytes as blobs at `base_fee_wei` per unit of blob gas.
+    /// Empty payloads still take a blob.
+    pub fn estimate(payload_len: usize, base_fee_wei: u64) -> Self {
+        let blobs = payload_len.div_ceil(USABLE_BYTES_PER_BLOB).max(1);
+        let blob_gas = blobs as u64 * GAS_PER_BLOB;
+        Self { blobs, blob_gas, base_fee_wei, fee_wei: blob_gas as u128 * base_fee_wei as u128 }
+    }
+
+    pub fn fee_gwei(&self) -> f64 {
+        self.fee_wei as f64 / WEI_PER_GWEI
+    }
+
+    pub fn fee_eth(&self) -> f64 {
+        self.fee_wei as f64 / WEI_PER_ETH
+    }
+}
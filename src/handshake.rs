@@ -0,0 +1,145 @@
+//! Node identity and the signed handshake that opens every session.
+
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+
+use crate::clock::Clock;
+use crate::error::HandshakeError;
+use crate::error::RecordError;
+use crate::protocol::{decode_message, P2PMessage};
+use crate::record::NodeRecord;
+use crate::transport::{Compression, Connection};
+
+#[derive(Clone)]
+pub struct Identity {
+    pub key: Arc<SigningKey>,
+    pub public: VerifyingKey,
+    /// Presented in every handshake, when the node has one
+    pub record: Option<Arc<NodeRecord>>,
+}
+impl Identity {
+    pub fn generate() -> Self {
+        let mut csprng = OsRng;
+        let key = SigningKey::generate(&mut csprng);
+        Self::from_key(key)
+    }
+
+    pub fn from_key(key: SigningKey) -> Self {
+        let public = VerifyingKey::from(&key);
+        Self { public, key: Arc::new(key), record: None }
+    }
+
+    /// Presents `record`, which should be signed by this identity, in handshakes from here on.
+    pub fn with_record(mut self, record: NodeRecord) -> Self {
+        self.record = Some(Arc::new(record));
+        self
+    }
+
+    /// Loads a hex-encoded Ed25519 seed, generating and saving a fresh one if the file is missing.
+    /// A new key file is readable by its owner only.
+    pub fn load_or_create(path: &str) -> std::io::Result<Self> {
+        if let Ok(text) = std::fs::read_to_string(path) {
+            let seed: [u8; 32] = hex::decode(text.trim())
+                .ok()
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Key file {} must hold a 32-byte hex seed", path)))?;
+            return Ok(Self::from_key(SigningKey::from_bytes(&seed)));
+        }
+        let id = Self::generate();
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)?.write_all(hex::encode(id.key.to_bytes()).as_bytes())?;
+        Ok(id)
+    }
+}
+
+/// What a successful handshake established about the peer.
+#[derive(Debug, Clone)]
+pub struct PeerHandshake {
+    pub key: VerifyingKey,
+    /// Frame limit the peer advertised; unlimited for peers that send none
+    pub max_frame: usize,
+    /// Compression both sides will use from here on
+    pub compression: Compression,
+    /// The peer's signed timestamp minus our clock when it arrived, in milliseconds. Includes
+    /// the one-way delay, so it overstates a peer that is ahead by roughly half the RTT.
+    pub clock_offset_ms: i64,
+    /// SHA-256 of both handshake signatures in byte order, the same on both sides and unique to
+    /// this session; envelopes sign it
+    pub session: [u8; 32],
+    /// The peer's node record, checked against its key, if it presented one
+    pub record: Option<NodeRecord>,
+}
+
+/// What a handshake signs: its timestamp, frame limit and codec offer, each length-prefixed
+/// after a domain tag, so a middlebox can't strip the offer or lower the limit unnoticed.
+fn signing_bytes(ts: u64, max_frame: Option<usize>, compression: &[String]) -> Vec<u8> {
+    let mut bytes = b"das-handshake-v2".to_vec();
+    bytes.extend_from_slice(&ts.to_be_bytes());
+    match max_frame {
+        Some(limit) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&(limit as u64).to_be_bytes());
+        }
+        None => bytes.push(0),
+    }
+    bytes.extend_from_slice(&(compression.len() as u32).to_be_bytes());
+    for name in compression {
+        bytes.extend_from_slice(&(name.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+    bytes
+}
+
+/// Exchanges signed handshakes, advertising our `max_frame` and the codecs in `compression`.
+/// Each side signs its Unix time in milliseconds from `clock`; a peer whose timestamp is more
+/// than `max_skew` away from ours is rejected, which also bounds how long a captured handshake
+/// can be replayed. A node record from the peer must verify and be its own.
+pub async fn perform_handshake<C: Connection>(conn: &mut C, id: &Identity, max_frame: usize, compression: &[Compression], clock: &dyn Clock, max_skew: Duration) -> Result<PeerHandshake, HandshakeError> {
+    let ts = clock.unix().as_millis() as u64;
+    let offer: Vec<String> = compression.iter().filter(|c| **c != Compression::None).map(|c| c.name().to_string()).collect();
+    let sig = id.key.sign(&signing_bytes(ts, Some(max_frame), &offer));
+    let msg = P2PMessage::Handshake { pubkey: id.public.as_bytes().to_vec(), sig: sig.to_bytes().to_vec(), ts, max_frame: Some(max_frame), compression: offer,
+        record: id.record.as_ref().map(|r| r.to_string()) };
+    let json = serde_json::to_string(&msg).expect("handshake serializes");
+    conn.send_frame(json, usize::MAX).await?;
+
+    let line = conn.recv_frame().await.ok_or(HandshakeError::PeerClosed)??;
+    match decode_message(&line)? {
+        P2PMessage::Handshake { pubkey, sig: their_sig, ts, max_frame, compression: theirs, record } => {
+            // Lengths were checked by `decode_message`.
+            let pubkey: [u8; 32] = pubkey.try_into().expect("validated pubkey length");
+            let their_sig: [u8; 64] = their_sig.try_into().expect("validated signature length");
+            let peer = VerifyingKey::from_bytes(&pubkey)?;
+            peer.verify(&signing_bytes(ts, max_frame, &theirs), &Signature::from_bytes(&their_sig))?;
+            let (lo, hi) = if sig.to_bytes() <= their_sig { (sig.to_bytes(), their_sig) } else { (their_sig, sig.to_bytes()) };
+            let session = Sha256::new().chain_update(lo).chain_update(hi).finalize().into();
+            // `validate` caps `ts`, but the difference is still taken wide so no timestamp can wrap it.
+            let offset = i128::from(ts) - clock.unix().as_millis() as i128;
+            let clock_offset_ms = offset.clamp(i64::MIN.into(), i64::MAX.into()) as i64;
+            let max_ms = max_skew.as_millis() as u64;
+            if offset.unsigned_abs() > u128::from(max_ms) {
+                return Err(HandshakeError::ClockSkew { offset_ms: clock_offset_ms, max_ms });
+            }
+            let record = match record {
+                Some(text) => {
+                    let record: NodeRecord = text.parse()?;
+                    if !record.pubkey.eq_ignore_ascii_case(&hex::encode(pubkey)) {
+                        return Err(RecordError::WrongNode { record: record.pubkey, peer: hex::encode(pubkey) }.into());
+                    }
+                    Some(record)
+                }
+                None => None,
+            };
+            Ok(PeerHandshake { key: peer, max_frame: max_frame.unwrap_or(usize::MAX), compression: Compression::negotiate(compression, &theirs), clock_offset_ms, session, record })
+        }
+        _ => Err(HandshakeError::UnexpectedMessage),
+    }
+}
@@ -0,0 +1,292 @@
+//! KZG polynomial commitments over BLS12-381. Data is packed into field elements, which become
+//! the coefficients of a polynomial committed against a structured reference string.
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
+use rand::RngCore;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::error::CommitmentError;
+
+/// Bytes packed into each field element. 31 bytes always fit below the BLS12-381 scalar
+/// modulus, so the packing is lossless for arbitrary data.
+pub const BYTES_PER_FIELD_ELEMENT: usize = 31;
+
+/// First byte of an EIP-4844 versioned hash for a KZG commitment.
+pub const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+/// A 128-power setup in the ceremony file layout, derived from the same public tau as
+/// [`KzgSetup::insecure`]; small enough to embed, and only fit for tests.
+const MINIMAL_SETUP: &str = include_str!("trusted_setup_minimal.txt");
+
+/// A claimed evaluation: `commitment` opens to `y` at `z`, shown by `proof`.
+pub struct Opening {
+    pub commitment: G1Affine,
+    pub z: Scalar,
+    pub y: Scalar,
+    pub proof: G1Affine,
+}
+
+/// Structured reference string: `[tau^i]G1` for each coefficient, and `[tau]G2` for openings.
+pub struct KzgSetup {
+    pub g1_powers: Vec<G1Affine>,
+    pub g2_tau: G2Affine,
+}
+
+impl KzgSetup {
+    /// Derives a setup of `size` powers from a publicly known tau. Anyone can forge openings
+    /// against it, so it is only fit for measuring cost, not for security claims.
+    pub fn insecure(size: usize) -> Self {
+        let tau = insecure_tau();
+        let table = FixedBase::new(G1Projective::generator());
+        let mut powers = Vec::with_capacity(size);
+        let mut t = Scalar::one();
+        for _ in 0..size {
+            powers.push(table.mul(&t));
+            t *= tau;
+        }
+        let mut g1_powers = vec![G1Affine::identity(); size];
+        G1Projective::batch_normalize(&powers, &mut g1_powers);
+        Self { g1_powers, g2_tau: G2Affine::from(G2Projective::generator() * tau) }
+    }
+
+    /// Parses the KZG ceremony's `trusted_setup.txt` layout: the G1 and G2 point counts, the
+    /// G1 points in Lagrange form, the G2 powers, then the G1 powers. Commitments here are to
+    /// coefficients, so files from before the G1 powers were appended are refused.
+    pub fn parse(text: &str) -> Result<Self, CommitmentError> {
+        let invalid = CommitmentError::InvalidSetup;
+        let tokens: Vec<&str> = text.split_whitespace().map(|t| t.strip_prefix("0x").unwrap_or(t)).collect();
+        let count = |i: usize, what: &str| -> Result<usize, CommitmentError> {
+            tokens.get(i).and_then(|t| t.parse().ok()).ok_or_else(|| invalid(format!("expected the number of {} points", what)))
+        };
+        let (n_g1, n_g2) = (count(0, "G1")?, count(1, "G2")?);
+        let tokens = &tokens[2..];
+        if n_g2 < 2 {
+            return Err(invalid(format!("{} G2 points, need at least [1] and [tau]", n_g2)));
+        }
+        if tokens.len() == n_g1 + n_g2 {
+            return Err(invalid("no G1 powers after the G2 points (an older file with Lagrange points only)".into()));
+        }
+        if tokens.len() != 2 * n_g1 + n_g2 {
+            return Err(invalid(format!("expected {} points, found {}", 2 * n_g1 + n_g2, tokens.len())));
+        }
+        if let Some(bad) = tokens[..n_g1].iter().find(|t| t.len() != 96 || hex::decode(t).is_err()) {
+            return Err(invalid(format!("bad Lagrange point {}", bad)));
+        }
+        let g2 = |text: &str| -> Result<G2Affine, CommitmentError> {
+            let bytes: [u8; 96] = hex::decode(text).ok().and_then(|b| b.try_into().ok()).ok_or_else(|| invalid(format!("bad G2 point {}", text)))?;
+            Option::from(G2Affine::from_compressed(&bytes)).ok_or_else(|| invalid(format!("bad G2 point {}", text)))
+        };
+        let g2_tau = g2(tokens[n_g1 + 1])?;
+        let g1_powers = tokens[n_g1 + n_g2..].iter().map(|t| decode_point(t)).collect::<Result<Vec<_>, _>>()?;
+        if g1_powers[0] != G1Affine::generator() || g2(tokens[n_g1])? != G2Affine::generator() {
+            return Err(invalid("powers do not start at the generators".into()));
+        }
+        // Both sides must be powers of the same tau: e([tau]G1, G2) == e(G1, [tau]G2).
+        if g1_powers.len() > 1 && pairing(&g1_powers[1], &G2Affine::generator()) != pairing(&G1Affine::generator(), &g2_tau) {
+            return Err(invalid("G1 and G2 powers come from different secrets".into()));
+        }
+        Ok(Self { g1_powers, g2_tau })
+    }
+
+    pub fn load(path: &str) -> Result<Self, CommitmentError> {
+        let text = std::fs::read_to_string(path).map_err(|e| CommitmentError::InvalidSetup(format!("{}: {}", path, e)))?;
+        Self::parse(&text)
+    }
+
+    /// The embedded 128-power test setup.
+    pub fn minimal() -> Self {
+        Self::parse(MINIMAL_SETUP).expect("embedded setup parses")
+    }
+
+    pub fn size(&self) -> usize {
+        self.g1_powers.len()
+    }
+
+    /// Commits to the polynomial with the given coefficients.
+    pub fn commit(&self, coeffs: &[Scalar]) -> Result<G1Affine, CommitmentError> {
+        if coeffs.len() > self.size() {
+            return Err(CommitmentError::SetupTooSmall { needed: coeffs.len(), size: self.size() });
+        }
+        Ok(G1Affine::from(msm(&self.g1_powers[..coeffs.len()], coeffs)))
+    }
+
+    /// Proof that the committed polynomial takes the value `evaluate(coeffs, z)` at `z`: a
+    /// commitment to the quotient `(p(X) - p(z)) / (X - z)`.
+    pub fn open(&self, coeffs: &[Scalar], z: Scalar) -> Result<G1Affine, CommitmentError> {
+        // Synthetic division, top coefficient down; the remainder left over is p(z).
+        let mut quotient = vec![Scalar::zero(); coeffs.len().saturating_sub(1)];
+        let mut acc = Scalar::zero();
+        for i in (1..coeffs.len()).rev() {
+            acc = coeffs[i] + z * acc;
+            quotient[i - 1] = acc;
+        }
+        self.commit(&quotient)
+    }
+
+    /// Checks an opening with two pairings: `e(C - [y]G1, G2) == e(proof, [tau - z]G2)`.
+    pub fn verify_opening(&self, commitment: &G1Affine, z: Scalar, y: Scalar, proof: &G1Affine) -> bool {
+        let lhs = G1Affine::from(G1Projective::from(commitment) - G1Projective::generator() * y);
+        let shifted = G2Affine::from(G2Projective::from(self.g2_tau) - G2Projective::generator() * z);
+        pairing(&lhs, &G2Affine::generator()) == pairing(proof, &shifted)
+    }
+
+    /// Checks many openings with two pairings in all. Each check rearranges to
+    /// `e(C - [y]G1 + [z]proof, G2) == e(proof, [tau]G2)`, and a random linear combination of
+    /// them holds for a false opening only with probability about 1/r.
+    pub fn verify_openings(&self, openings: &[Opening]) -> bool {
+        let mut rng = rand::thread_rng();
+        let weights: Vec<Scalar> = openings.iter().map(|_| {
+            let mut bytes = [0u8; 64];
+            rng.fill_bytes(&mut bytes);
+            Scalar::from_bytes_wide(&bytes)
+        }).collect();
+        let proofs: Vec<G1Affine> = openings.iter().map(|o| o.proof).collect();
+        let mut points: Vec<G1Affine> = openings.iter().map(|o| o.commitment).chain(proofs.iter().copied()).collect();
+        let mut scalars: Vec<Scalar> = weights.iter().copied()
+            .chain(openings.iter().zip(&weights).map(|(o, r)| o.z * r))
+            .collect();
+        points.push(G1Affine::generator());
+        scalars.push(-openings.iter().zip(&weights).fold(Scalar::zero(), |acc, (o, r)| acc + o.y * r));
+        let lhs = G1Affine::from(msm(&points, &scalars));
+        let rhs = G1Affine::from(msm(&proofs, &weights));
+        pairing(&lhs, &G2Affine::generator()) == pairing(&rhs, &self.g2_tau)
+    }
+}
+
+/// Evaluates the polynomial with the given coefficients at `z` (Horner's rule).
+pub fn evaluate(coeffs: &[Scalar], z: Scalar) -> Scalar {
+    coeffs.iter().rev().fold(Scalar::zero(), |acc, c| acc * z + c)
+}
+
+/// The Fiat-Shamir point a shard is opened at, bound to its commitment and its bytes so the
+/// prover cannot choose it. Mirrors how EIP-4844 derives the point for a blob proof.
+pub fn challenge(commitment: &G1Affine, data: &[u8]) -> Scalar {
+    let digest = Sha512::new()
+        .chain_update(b"eth-das-prototype shard challenge")
+        .chain_update(commitment.to_compressed())
+        .chain_update(data)
+        .finalize();
+    Scalar::from_bytes_wide(&digest.into())
+}
+
+fn insecure_tau() -> Scalar {
+    let digest = Sha256::digest(b"eth-das-prototype insecure kzg setup");
+    Scalar::from_bytes_wide(&[digest.as_slice(), digest.as_slice()].concat().try_into().expect("64 bytes"))
+}
+
+static INSTALLED: OnceLock<Arc<KzgSetup>> = OnceLock::new();
+
+/// Makes `setup` the one every commitment in this process uses, in place of the insecure one.
+/// Only the first call takes effect.
+pub fn install_setup(setup: KzgSetup) -> Arc<KzgSetup> {
+    INSTALLED.get_or_init(|| Arc::new(setup)).clone()
+}
+
+/// The installed setup if there is one; otherwise the insecure setup with at least `min_size`
+/// powers. An installed setup never grows, so commitments past its size fail.
+pub fn setup(min_size: usize) -> Arc<KzgSetup> {
+    INSTALLED.get().cloned().unwrap_or_else(|| insecure_setup(min_size))
+}
+
+/// The process-wide insecure setup, grown (and cached) to at least `min_size` powers.
+pub fn insecure_setup(min_size: usize) -> Arc<KzgSetup> {
+    static SETUP: OnceLock<Mutex<Arc<KzgSetup>>> = OnceLock::new();
+    let mut setup = SETUP.get_or_init(|| Mutex::new(Arc::new(KzgSetup::insecure(0)))).lock().unwrap();
+    if setup.size() < min_size {
+        *setup = Arc::new(KzgSetup::insecure(min_size.next_power_of_two()));
+    }
+    setup.clone()
+}
+
+/// Packs `data` into field elements, `BYTES_PER_FIELD_ELEMENT` bytes each (the last zero-padded).
+pub fn field_elements(data: &[u8]) -> Vec<Scalar> {
+    data.chunks(BYTES_PER_FIELD_ELEMENT)
+        .map(|chunk| {
+            let mut bytes = [0u8; 32];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            Scalar::from_bytes(&bytes).expect("31 bytes are below the modulus")
+        })
+        .collect()
+}
+
+pub fn encode_point(point: &G1Affine) -> String {
+    hex::encode(point.to_compressed())
+}
+
+pub fn decode_point(text: &str) -> Result<G1Affine, CommitmentError> {
+    let bytes: [u8; 48] = hex::decode(text).ok().and_then(|b| b.try_into().ok())
+        .ok_or_else(|| CommitmentError::InvalidPoint(text.to_string()))?;
+    Option::from(G1Affine::from_compressed(&bytes)).ok_or_else(|| CommitmentError::InvalidPoint(text.to_string()))
+}
+
+/// The EIP-4844 versioned hash of a compressed commitment: `0x01 || sha256(commitment)[1..]`,
+/// which is how the execution layer refers to a blob.
+pub fn versioned_hash(commitment: &[u8]) -> [u8; 32] {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    hash
+}
+
+/// Multiples `d * 256^j * base` for every byte position `j` and byte value `d`, so multiplying
+/// the base by any scalar takes 32 additions instead of a full double-and-add.
+struct FixedBase {
+    table: Vec<[G1Affine; 256]>,
+}
+
+impl FixedBase {
+    fn new(base: G1Projective) -> Self {
+        let mut table = Vec::with_capacity(32);
+        let mut step = base;
+        for _ in 0..32 {
+            let mut row = [G1Projective::identity(); 256];
+            for d in 1..256 {
+                row[d] = row[d - 1] + step;
+            }
+            let mut affine = [G1Affine::identity(); 256];
+            G1Projective::batch_normalize(&row, &mut affine);
+            table.push(affine);
+            step = row[255] + step;
+        }
+        Self { table }
+    }
+
+    fn mul(&self, scalar: &Scalar) -> G1Projective {
+        scalar.to_bytes().iter().zip(&self.table).fold(G1Projective::identity(), |acc, (&d, row)| acc + row[d as usize])
+    }
+}
+
+/// Multi-scalar multiplication by Pippenger's bucket method: far fewer group additions than
+/// one scalar multiplication per point once there are more than a handful of terms.
+fn msm(points: &[G1Affine], scalars: &[Scalar]) -> G1Projective {
+    let n = points.len().min(scalars.len());
+    let c = if n < 32 { 3 } else { (n as f64).ln().ceil() as usize };
+    let bytes: Vec<[u8; 32]> = scalars[..n].iter().map(Scalar::to_bytes).collect();
+    let digit = |b: &[u8; 32], window: usize| {
+        (0..c).map(|j| window * c + j).filter(|&i| i < 256)
+            .fold(0usize, |acc, i| acc | ((((b[i / 8] >> (i % 8)) & 1) as usize) << (i - window * c)))
+    };
+
+    let mut acc = G1Projective::identity();
+    for window in (0..256usize.div_ceil(c)).rev() {
+        for _ in 0..c {
+            acc = acc.double();
+        }
+        let mut buckets = vec![G1Projective::identity(); (1 << c) - 1];
+        for (point, b) in points.iter().zip(&bytes) {
+            let d = digit(b, window);
+            if d != 0 {
+                buckets[d - 1] += point;
+            }
+        }
+        // sum_d d * bucket[d], as a running sum from the top bucket down
+        let (mut running, mut sum) = (G1Projective::identity(), G1Projective::identity());
+        for bucket in buckets.iter().rev() {
+            running += bucket;
+            sum += running;
+        }
+        acc += sum;
+    }
+    acc
+}
@@ -0,0 +1,29 @@
+//! Data availability sampling over erasure-coded blobs: the coding, wire protocol, and
+//! reconstruction pieces behind the `eth-das-prototype` binary, usable on their own.
+
+pub mod anchor;
+pub mod car;
+pub mod clock;
+pub mod coding;
+pub mod commitment;
+pub mod envelope;
+pub mod error;
+pub mod fees;
+pub mod handshake;
+pub mod kzg;
+pub mod matrix;
+pub mod protocol;
+pub mod reconstruct;
+pub mod record;
+pub mod sampling;
+pub mod sidecar;
+pub mod sim;
+pub mod subnets;
+pub mod transport;
+
+pub use error::{AnchorError, CarError, CodecError, CommitmentError, EnvelopeError, Error, HandshakeError, MatrixError, ReconstructError, RecordError, Result, SidecarError, TransportError};
+
+// RESEARCH CONSTANTS (EIP-4844 Simulation)
+pub const DATA_SHARDS: usize = 4;   // k
+pub const PARITY_SHARDS: usize = 2; // m
+pub const TOTAL_SHARDS: usize = DATA_SHARDS + PARITY_SHARDS;
@@ -1,49 +1,64 @@
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand, ValueEnum};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use colored::*;
-use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+use eth_das_prototype::anchor::{anchor_calldata, post_json, EthRpc, RpcEndpoint};
+use eth_das_prototype::car::{BlobNode, CarReader, CarWriter, Cid, CODEC_DAG_CBOR, CODEC_RAW};
+use eth_das_prototype::clock::{Clock, SystemClock};
+use eth_das_prototype::coding::{calculate_sha256, check_params, merkle_root, DasDecoder, DasEncoder, GALOIS_BACKEND};
+use eth_das_prototype::commitment::{BlobCommitment, CommitmentScheme};
+use eth_das_prototype::fees::{BlobFee, MIN_BASE_FEE_PER_BLOB_GAS};
+use eth_das_prototype::envelope::{Envelope, SignedConnection};
+use eth_das_prototype::handshake::{perform_handshake, Identity, PeerHandshake};
+use eth_das_prototype::kzg::{self, KzgSetup};
+use eth_das_prototype::matrix::{decode_matrix, encode_matrix, matrix_shape, per_blob_cost, MatrixCells, MatrixDecodeReport, MatrixDecoder, MatrixShape, SampleUnit, SamplingCost};
+use eth_das_prototype::protocol::{decode_frame, decode_message, encode_frame, is_digest, is_safe_name, Encoding, Layout, Manifest, P2PMessage, Striping, BARE_STREAM, DEFAULT_MAX_FRAME, MAX_GOSSIP_TTL};
+use eth_das_prototype::protocol::{bitfield_indices, custody_digest, encode_bitfield, message_id};
+use eth_das_prototype::reconstruct::StreamingDecoder;
+use eth_das_prototype::record::{merge_peers_file, read_peers_file, NodeRecord};
+use eth_das_prototype::sidecar::BlobSidecar;
+use eth_das_prototype::sampling::{Sample, Sampler, SamplingArgs, SamplingKind};
+use eth_das_prototype::sim::{SimConfig, Simulation};
+use eth_das_prototype::subnets::{column_subnet, node_subnets, subnet_columns};
+use eth_das_prototype::transport::{send_frame, CompressedConnection, Compression, Connection, FaultyConnection, Faults, Listener, SendRate, Socks5Proxy, TcpConnection, TcpFrameListener, TcpTransport, Transport};
+use eth_das_prototype::transport::{read_relay_message, registration_bytes, RelayMessage, RelayTransport};
+use eth_das_prototype::{AnchorError, CodecError, HandshakeError, TransportError};
+use eth_das_prototype::{DATA_SHARDS, PARITY_SHARDS, TOTAL_SHARDS};
 use futures::{SinkExt, StreamExt};
-use rand::rngs::OsRng;
 use rand::seq::SliceRandom;
-use reed_solomon_erasure::galois_8::ReedSolomon;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{Read, Write};
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
-use tokio::net::{TcpListener, TcpStream};
-use tokio_util::codec::{Framed, LinesCodec};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::net::TcpListener;
 
-// RESEARCH CONSTANTS (EIP-4844 Simulation)
-const DATA_SHARDS: usize = 4;   // k
-const PARITY_SHARDS: usize = 2; // m
-const TOTAL_SHARDS: usize = DATA_SHARDS + PARITY_SHARDS;
+// OUTPUT
+// `--quiet` silences all interactive output; only the final summary line is printed.
+static QUIET: AtomicBool = AtomicBool::new(false);
 
-// NETWORK PROTOCOL
-#[derive(Serialize, Deserialize, Debug, Clone)]
-enum P2PMessage {
-    Handshake {
-        pubkey: Vec<u8>,
-        sig: Vec<u8>,
-        ts: u64,
-    },
-    NaiveTransfer {
-        filename: String,
-        data: Vec<u8>,
-        checksum: String,
-    },
-    DasShard {
-        filename: String,
-        original_len: usize,
-        index: usize,
-        data: Vec<u8>,
-        full_file_checksum: String,
-    },
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) { println!($($arg)*); }
+    };
+}
+
+macro_rules! say_inline {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            print!($($arg)*);
+            let _ = std::io::stdout().flush();
+        }
+    };
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 enum ResearchMode {
     /// Legacy: Full Block Download
     Naive,
@@ -53,260 +68,8395 @@ enum ResearchMode {
     DasSample 
 }
 
-// IDENTITY LAYER
-#[derive(Clone)]
-struct Identity {
-    key: Arc<SigningKey>,
-    public: VerifyingKey,
+// TELEMETRY
+/// Final metrics of a proposer run, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug, Clone)]
+struct TransferSummary {
+    role: &'static str,
+    mode: ResearchMode,
+    file: String,
+    payload_bytes: usize,
+    wire_bytes: usize,
+    latency_ms: f64,
+    throughput_mb_s: f64,
+    /// Positive: bandwidth saved vs. payload size. Negative: wire overhead.
+    savings_pct: f64,
+    /// What the payload would cost posted as EIP-4844 blobs
+    blob_fee: BlobFee,
+    /// `--send-rate`, when shards were paced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    send_rate: Option<String>,
+    /// The codec the handshake settled on, when frames were compressed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compression: Option<CompressionSummary>,
+    /// With `--matrix`: the block's shape and what sampling it costs, against blob by blob
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matrix: Option<MatrixSummary>,
+    /// With `--anchor-rpc`: each blob's anchor transaction
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    anchors: Vec<AnchorSummary>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct MatrixSummary {
+    #[serde(flatten)]
+    shape: MatrixShape,
+    target_confidence: f64,
+    /// Blob by blob first, then the matrix by cells, rows and columns
+    costs: Vec<SamplingCost>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct CompressionSummary {
+    codec: Compression,
+    /// Bytes actually written, against `wire_bytes` of uncompressed frames
+    wire_bytes: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Verdict {
+    Verified,
+    Corrupted,
+    Reconstructed,
+    Sampled,
+    Incomplete,
+    Unavailable,
 }
-impl Identity {
-    fn new() -> Self {
-        let mut csprng = OsRng;
-        let key = SigningKey::generate(&mut csprng);
-        let public = VerifyingKey::from(&key);
-        Self { public, key: Arc::new(key) }
+
+impl Verdict {
+    fn exit_status(self) -> ExitStatus {
+        match self {
+            Verdict::Verified | Verdict::Reconstructed | Verdict::Sampled => ExitStatus::Success,
+            Verdict::Corrupted => ExitStatus::ChecksumMismatch,
+            Verdict::Incomplete => ExitStatus::TransferIncomplete,
+            Verdict::Unavailable => ExitStatus::SamplingUnavailable,
+        }
     }
 }
 
-// CLI
-#[derive(Parser)]
-struct Cli {
-    #[command(subcommand)]
-    command: Commands,
+/// Process exit codes for experiment scripts. `1` stays the generic error exit and `2` is
+/// used by clap for usage errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitStatus {
+    Success = 0,
+    AuthFailure = 3,
+    TransferIncomplete = 4,
+    ChecksumMismatch = 5,
+    SamplingUnavailable = 6,
+    /// `sample --deadline` passed without the root found available
+    DeclaredUnavailable = 7,
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    Listen {
-        #[arg(short, long, default_value_t = 8080)]
-        port: u16,
-    },
-    Send {
-        #[arg(short, long, default_value_t = 8080)]
-        port: u16,
-        #[arg(short, long)]
-        peer: String,
-        #[arg(short, long)]
-        file: String,
-        #[arg(short, long, value_enum)]
-        mode: ResearchMode,
-    },
+#[derive(Serialize, Debug, Clone)]
+struct FileOutcome {
+    file: String,
+    root: String,
+    shards: usize,
+    verdict: Verdict,
+    /// From the first shard to the k-th (full-node transfers only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    threshold_ms: Option<f64>,
+    /// Time spent decoding and hashing once the threshold was reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reconstruct_ms: Option<f64>,
+    /// From header publication (the manifest) to this node being sure the blob is available:
+    /// the k-th shard for a full node, a passing sample verdict otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence_latency_ms: Option<f64>,
+    /// From header publication to the blob rebuilt and checked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reconstruction_latency_ms: Option<f64>,
+    /// Shards each peer supplied toward a full-node verdict, by pubkey
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    senders: BTreeMap<String, usize>,
 }
 
-// HELPER FUNCTIONS
-fn calculate_sha256(data: &[u8]) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    hex::encode(hasher.finalize())
+/// One line of `listen --transfer-log`: a finished (or failed) transfer and who sent it.
+#[derive(Serialize)]
+struct TransferRecord<'a> {
+    /// Unix seconds
+    logged_at: u64,
+    sender: &'a str,
+    addr: String,
+    #[serde(flatten)]
+    tags: &'a RunTags,
+    #[serde(flatten)]
+    outcome: &'a FileOutcome,
+}
+
+/// Per-connection result on the validator, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug)]
+struct SessionSummary {
+    role: &'static str,
+    /// The receiving validator, when one process runs several
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node: Option<String>,
+    peer: String,
+    bytes_received: usize,
+    /// Shards dropped because the peer's unverified-byte quota was full
+    quota_drops: usize,
+    /// Frames rejected as malformed or invalid, each answered with `ProtocolError`
+    protocol_errors: usize,
+    files: Vec<FileOutcome>,
+    /// Per root, what arrived over this connection
+    roots: Vec<RootSummary>,
+    /// Invariants the peer broke (`--strict`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    violations: Vec<Violation>,
 }
 
-fn pad_data(data: &[u8], k: usize) -> Vec<u8> {
-    let mut padded = data.to_vec();
-    let remainder = padded.len() % k;
-    if remainder != 0 {
-        let padding = k - remainder;
-        padded.extend(std::iter::repeat(0).take(padding));
+/// An invariant a peer broke under `listen --strict`: which field, where, and who sent it.
+#[derive(Serialize, Debug, Clone)]
+struct Violation {
+    peer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root: Option<String>,
+    /// Wire index of the offending shard
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<usize>,
+    field: &'static str,
+    reason: String,
+}
+
+impl Violation {
+    fn new(peer: &str, root: Option<&str>, index: Option<usize>, field: &'static str, reason: impl Into<String>) -> Self {
+        Self { peer: peer.to_string(), root: root.map(str::to_string), index, field, reason: reason.into() }
+    }
+
+    fn from_codec(peer: &str, root: Option<&str>, index: Option<usize>, error: CodecError) -> Self {
+        match error {
+            CodecError::InvalidField { field, reason } => Self::new(peer, root, index, field, reason),
+            CodecError::Malformed(reason) => Self::new(peer, root, index, "frame", reason),
+        }
+    }
+
+    /// Prints and logs the violation, and keeps it for the session summary.
+    fn report(self, violations: &mut Vec<Violation>) {
+        say!("\n{} {}", "❌ Strict:".red(), self);
+        log_event(Event::Violation { violation: self.clone() });
+        violations.push(self);
     }
-    padded
 }
 
-fn format_bytes(n: usize) -> String {
-    if n < 1024 { return format!("{} B", n); }
-    if n < 1024 * 1024 { return format!("{:.2} KB", n as f64 / 1024.0); }
-    format!("{:.2} MB", n as f64 / 1024.0 / 1024.0)
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid {} from {}…", self.field, self.peer.get(..16).unwrap_or(&self.peer))?;
+        if let Some(root) = &self.root {
+            write!(f, ", root {}…", root.get(..16).unwrap_or(root))?;
+        }
+        if let Some(index) = self.index {
+            write!(f, ", wire index {}", index)?;
+        }
+        write!(f, ": {}", self.reason)
+    }
 }
 
-// MAIN
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Cli::parse();
-    let id = Identity::new();
-    
-    println!("\n{}", "=== Ethereum DAS Research Prototype ===".bold().white().on_blue());
+/// What one validator session received for one root, reported when the connection ends.
+#[derive(Serialize, Debug, Clone)]
+struct RootSummary {
+    root: String,
+    file: String,
+    /// Shard frames that arrived, duplicates and rejects included
+    shards_received: usize,
+    /// Shards that passed a per-shard check against the manifest's commitment
+    shards_verified: usize,
+    /// Frames for a wire index that had already arrived in this session
+    duplicates: usize,
+    rejected: usize,
+    /// Wire bytes of every frame for the root, the manifest included
+    bytes: usize,
+    /// `None` when the session ended with only a manifest for the root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verdict: Option<Verdict>,
+    /// From the root's first frame to its last
+    elapsed_ms: f64,
+    #[serde(skip)]
+    accepted: usize,
+    #[serde(skip)]
+    seen: Vec<usize>,
+    #[serde(skip)]
+    first_at: Duration,
+    #[serde(skip)]
+    last_at: Duration,
+}
 
-    match args.command {
-        Commands::Listen { port } => run_validator(port, id).await?,
-        Commands::Send { port: _, peer, file, mode } => run_proposer(peer, file, mode, id).await?,
+impl RootSummary {
+    /// The entry for `root`, created on its first frame; every call counts as a frame arriving now.
+    fn tally<'a>(roots: &'a mut Vec<RootSummary>, root: &str, file: &str) -> &'a mut RootSummary {
+        let now = clock().now();
+        let pos = roots.iter().position(|r| r.root == root).unwrap_or_else(|| {
+            roots.push(RootSummary {
+                root: root.to_string(),
+                file: file.to_string(),
+                shards_received: 0,
+                shards_verified: 0,
+                duplicates: 0,
+                rejected: 0,
+                bytes: 0,
+                verdict: None,
+                elapsed_ms: 0.0,
+                accepted: 0,
+                seen: Vec::new(),
+                first_at: now,
+                last_at: now,
+            });
+            roots.len() - 1
+        });
+        roots[pos].last_at = now;
+        &mut roots[pos]
+    }
+}
+
+/// Prints a run's summary as one JSON line in `--quiet` mode, and records it in `--results`.
+fn print_summary<T: Serialize>(summary: &T) -> Result<()> {
+    record_run(summary)?;
+    if QUIET.load(Ordering::Relaxed) {
+        println!("{}", serde_json::to_string(summary)?);
     }
     Ok(())
 }
 
-// VALIDATOR (RECEIVER)
-async fn run_validator(port: u16, id: Identity) -> Result<()> {
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
-    println!("{} Listening on :{}", "➜ Validator:".green().bold(), port);
-    
-    let shard_buffer: Arc<Mutex<HashMap<String, HashMap<usize, Vec<u8>>>>> = Arc::new(Mutex::new(HashMap::new()));
+// LOCKS
+/// `Mutex::lock` that shrugs off poisoning. Supervised tasks and worker jobs are restarted
+/// after a panic, so a panic while one held a lock must not make every later `lock` panic too;
+/// the guarded state is used as the panic left it.
+trait Locked<T> {
+    fn locked(&self) -> std::sync::MutexGuard<'_, T>;
+}
 
-    while let Ok((socket, addr)) = listener.accept().await {
-        println!("\n{} Connection from {}", "➜ Network:".blue().bold(), addr);
-        let mut framed = Framed::new(socket, LinesCodec::new());
-        let buffer_ref = shard_buffer.clone();
-        
-        if let Err(_) = perform_handshake(&mut framed, &id).await {
-             println!("{}", "❌ Auth Failed".red());
-             continue;
+impl<T> Locked<T> for Mutex<T> {
+    fn locked(&self) -> std::sync::MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+// CLOCK
+// Every timing and wait goes through this clock; the wall clock unless a simulation installs its own.
+static CLOCK: OnceLock<Arc<dyn Clock>> = OnceLock::new();
+
+fn clock() -> &'static Arc<dyn Clock> {
+    CLOCK.get_or_init(|| Arc::new(SystemClock::new()))
+}
+
+/// Default for `--max-clock-skew`, in seconds.
+const DEFAULT_MAX_CLOCK_SKEW: u64 = 30;
+// How far a peer's handshake timestamp may be from this clock; set from `--max-clock-skew`.
+static MAX_CLOCK_SKEW: OnceLock<Duration> = OnceLock::new();
+
+/// [`perform_handshake`] against this process's clock and `--max-clock-skew`.
+async fn handshake<C: Connection>(conn: &mut C, id: &Identity, max_frame: usize, compression: &[Compression]) -> Result<PeerHandshake, HandshakeError> {
+    let max_skew = *MAX_CLOCK_SKEW.get_or_init(|| Duration::from_secs(DEFAULT_MAX_CLOCK_SKEW));
+    perform_handshake(conn, id, max_frame, compression, clock().as_ref(), max_skew).await
+}
+
+/// A connection past the handshake: frames are sealed in signed envelopes, then compressed.
+type PeerConnection<C> = SignedConnection<CompressedConnection<C>>;
+
+fn peer_connection<C: Connection>(conn: C, id: &Identity, hs: &PeerHandshake, max_frame: usize) -> PeerConnection<C> {
+    SignedConnection::new(CompressedConnection::new(conn, hs.compression, max_frame), id, hs)
+}
+
+/// The offset that got a handshake rejected, for its event.
+fn skew_offset(e: &HandshakeError) -> Option<i64> {
+    match e {
+        HandshakeError::ClockSkew { offset_ms, .. } => Some(*offset_ms),
+        _ => None,
+    }
+}
+
+// EVENT LOG
+// `--event-log <file>` records every protocol event as a timestamped JSON line, from both roles.
+static EVENT_LOG: OnceLock<(String, Mutex<RotatingLog>)> = OnceLock::new();
+
+/// When logs roll over, from the global `--log-*` flags; unset, they grow without bound.
+#[derive(Args, Clone, Copy, Debug, Default)]
+#[command(about = None, long_about = None)]
+struct Rotation {
+    /// Roll the event log and transfer logs over once they reach this many bytes
+    #[arg(long, global = true, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    log_max_bytes: Option<u64>,
+    /// Roll the event log and transfer logs over once they have been written to for this many
+    /// seconds
+    #[arg(long, global = true, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    log_max_age: Option<u64>,
+    /// Rolled-over logs to keep, as `<log>.1` (newest) to `<log>.N`; older ones are deleted
+    #[arg(long, global = true, default_value_t = 5)]
+    log_keep: usize,
+}
+
+static ROTATION: OnceLock<Rotation> = OnceLock::new();
+
+/// An append-only log that rolls over under [`ROTATION`]: the full file becomes `<path>.1`,
+/// `<path>.1` becomes `<path>.2` and so on, and `<path>` starts empty. Whole lines are written
+/// in one call, so a line never straddles two files.
+struct RotatingLog {
+    path: String,
+    file: File,
+    len: u64,
+    /// Unix seconds since which this file has been written
+    opened_at: u64,
+}
+
+impl RotatingLog {
+    fn open(path: &str) -> Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("Cannot open {}", path))?;
+        let len = file.metadata()?.len();
+        Ok(Self { path: path.to_string(), file, len, opened_at: unix_now() })
+    }
+
+    fn append(&mut self, lines: &[u8]) -> std::io::Result<()> {
+        let rotation = ROTATION.get().copied().unwrap_or_default();
+        let full = rotation.log_max_bytes.is_some_and(|max| self.len > 0 && self.len + lines.len() as u64 > max);
+        let old = rotation.log_max_age.is_some_and(|max| unix_now().saturating_sub(self.opened_at) >= max);
+        if full || old {
+            self.rotate(rotation.log_keep)?;
         }
-        println!("{}", "✓ Session Secured (Ed25519)".green());
-        
-        let mut bytes_rec = 0;
-        
-        while let Some(Ok(line)) = framed.next().await {
-            if line.trim().is_empty() { continue; }
-            bytes_rec += line.len(); 
-            let msg: P2PMessage = serde_json::from_str(&line)?;
-            
-            match msg {
-                P2PMessage::NaiveTransfer { filename, data, checksum } => {
-                    println!("{}", "➜ Receiving Full Blob (Naive)...".yellow());
-                    if calculate_sha256(&data) == checksum {
-                        println!("{}", "✓ Integrity Verified".green());
-                        let mut f = File::create(format!("recv_{}", filename))?;
-                        f.write_all(&data)?;
-                    } else { println!("{}", "❌ Corrupted".red()); }
-                }
-                P2PMessage::DasShard { filename, original_len, index, data, full_file_checksum } => {
-                    let mut lock = buffer_ref.lock().unwrap();
-                    let map = lock.entry(filename.clone()).or_insert(HashMap::new());
-                    map.insert(index, data);
-                    
-                    print!("\rDownloading Shards: {}/{} (k={})", map.len(), TOTAL_SHARDS, DATA_SHARDS);
-                    std::io::stdout().flush().unwrap();
-                    
-                    // Try Reconstruct
-                    if map.len() >= DATA_SHARDS {
-                        let r = ReedSolomon::new(DATA_SHARDS, PARITY_SHARDS).unwrap();
-                        let mut shards = vec![None; TOTAL_SHARDS];
-                        for (idx, d) in map.iter() { shards[*idx] = Some(d.clone()); }
-                        
-                        if let Ok(_) = r.reconstruct(&mut shards) {
-                            println!("\n{}", "➜ Threshold Reached. Reconstructing...".yellow());
-                            let mut reconstructed = Vec::new();
-                            for i in 0..DATA_SHARDS {
-                                if let Some(s) = &shards[i] { reconstructed.extend_from_slice(s); }
-                            }
-                            if reconstructed.len() >= original_len {
-                                reconstructed.truncate(original_len);
-                                if calculate_sha256(&reconstructed) == full_file_checksum {
-                                    println!("{}", "✓ RECONSTRUCTION SUCCESSFUL".green().bold());
-                                    let mut f = File::create(format!("reconstructed_{}", filename))?;
-                                    f.write_all(&reconstructed)?;
-                                }
-                            }
-                            map.clear(); // Reset
-                        }
+        self.file.write_all(lines)?;
+        self.len += lines.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self, keep: usize) -> std::io::Result<()> {
+        let rolled = |i: usize| format!("{}.{}", self.path, i);
+        match keep {
+            0 => std::fs::remove_file(&self.path)?,
+            keep => {
+                for i in (1..keep).rev() {
+                    if std::path::Path::new(&rolled(i)).exists() {
+                        std::fs::rename(rolled(i), rolled(i + 1))?;
                     }
                 }
-                _ => {}
+                std::fs::rename(&self.path, rolled(1))?;
             }
         }
-        
-        // Check for Light Client Success
-        let lock = buffer_ref.lock().unwrap();
-        for (filename, map) in lock.iter() {
-            if !map.is_empty() && map.len() < DATA_SHARDS {
-                println!("\n\n{}", "=== Light Client Validation ===".bold().blue());
-                println!("File: {}", filename);
-                println!("Sampled {} random shards.", map.len());
-                println!("{}", "✓ Data Availability Verified (>99% prob)".green());
-                println!("Simulated Bandwidth: {}", format_bytes(bytes_rec).cyan());
+        self.file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.len = 0;
+        self.opened_at = unix_now();
+        Ok(())
+    }
+}
+
+tokio::task_local! {
+    // The validator a task works for when one process runs several (`listen --port 8080-8089`).
+    // Its events are logged under that node rather than the process key.
+    static NODE: String;
+}
+
+/// Background work the process waits for before it exits: alert posts, which give up after
+/// [`ANCHOR_RPC_TIMEOUT`], and `--on-reconstruct` commands.
+static IN_FLIGHT: Mutex<Vec<tokio::task::JoinHandle<()>>> = Mutex::new(Vec::new());
+
+fn track_in_flight(task: tokio::task::JoinHandle<()>) {
+    let mut in_flight = IN_FLIGHT.locked();
+    in_flight.retain(|task| !task.is_finished());
+    in_flight.push(task);
+}
+
+async fn flush_in_flight() {
+    let in_flight = std::mem::take(&mut *IN_FLIGHT.locked());
+    for task in in_flight {
+        let _ = task.await;
+    }
+}
+
+/// `tokio::spawn` that keeps the spawning task's [`NODE`].
+fn spawn_in_node<F>(task: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match NODE.try_with(|node| node.clone()) {
+        Ok(node) => tokio::spawn(NODE.scope(node, task)),
+        Err(_) => tokio::spawn(task),
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    /// `clock_offset_ms` is the peer's clock minus ours, when known
+    Handshake { peer: String, ok: bool, #[serde(skip_serializing_if = "Option::is_none")] clock_offset_ms: Option<i64> },
+    BlobSent { peer: String, file: String, root: String, bytes: usize },
+    ManifestSent { peer: String, root: String, scheme: CommitmentScheme, #[serde(skip_serializing_if = "Option::is_none")] msg_id: Option<String> },
+    ManifestReceived { peer: String, root: String, scheme: CommitmentScheme, valid: bool, #[serde(skip_serializing_if = "Option::is_none")] msg_id: Option<String> },
+    ShardSent { peer: String, root: String, index: usize, bytes: usize, #[serde(skip_serializing_if = "Option::is_none")] msg_id: Option<String>, #[serde(skip_serializing_if = "Option::is_none")] trace: Option<String> },
+    ShardWithheld { peer: String, root: String, index: usize },
+    ShardReceived { peer: String, root: String, index: usize, bytes: usize, accepted: bool, #[serde(skip_serializing_if = "Option::is_none")] reason: Option<String>, #[serde(skip_serializing_if = "Option::is_none")] ttl: Option<u8>, #[serde(skip_serializing_if = "Option::is_none")] msg_id: Option<String>, #[serde(skip_serializing_if = "Option::is_none")] trace: Option<String> },
+    /// A received shard passed on to a `--gossip-peers` validator, with the TTL it left with
+    ShardRelayed { peer: String, root: String, index: usize, ttl: u8, #[serde(skip_serializing_if = "Option::is_none")] msg_id: Option<String>, #[serde(skip_serializing_if = "Option::is_none")] trace: Option<String> },
+    /// One custody challenge put to an `--audit-peers` validator; `reason` says why it failed
+    CustodyAudit { peer: String, root: String, index: usize, passed: bool, #[serde(skip_serializing_if = "Option::is_none")] reason: Option<String> },
+    /// A peer's `HaveShards` handled: shards held on each side, and how many were requested
+    Sync { peer: String, root: String, ours: usize, theirs: usize, pulled: usize },
+    TransferEndSent { peer: String, root: String, shards_sent: usize, #[serde(skip_serializing_if = "Option::is_none")] msg_id: Option<String> },
+    /// A proposer run that completed, with its final metrics
+    Transfer { peer: String, #[serde(flatten)] summary: TransferSummary },
+    SampleRequest { peer: String, root: String, index: usize },
+    SampleResponse { peer: String, root: String, index: usize, found: bool },
+    /// A light client's confidence after an answered sample, against the smallest withholding
+    /// that leaves the root unrecoverable
+    Confidence { peer: String, root: String, index: usize, answered: usize, confidence: f64 },
+    /// A light client's signed declaration that a root is unavailable, made at `sample --deadline`
+    NegativeAttestation { peer: String, failed: usize, unanswered: usize, #[serde(flatten)] attestation: Attestation },
+    /// A light client's finished sampling of a root
+    Sampled { peer: String, root: String, #[serde(flatten)] run: SamplingRun },
+    CanServe { peer: String, root: String, indices: usize },
+    /// A verdict on one transfer: verified, reconstructed, sampled, unavailable, incomplete...
+    Outcome { peer: String, #[serde(flatten)] outcome: FileOutcome },
+    /// A broken invariant under `listen --strict`
+    Violation { #[serde(flatten)] violation: Violation },
+    Attestation { root: String, path: String },
+    /// Evidence of a root found unavailable, written by `sample --evidence-dir`
+    Evidence { root: String, path: String },
+    /// An `--on-reconstruct` command run on a rebuilt blob; `code` is its exit status
+    ReconstructHook { root: String, path: String, #[serde(skip_serializing_if = "Option::is_none")] code: Option<i32>, ms: f64, #[serde(skip_serializing_if = "Option::is_none")] error: Option<String> },
+    ProtocolError { peer: String, reason: String },
+    /// What a finished session delivered for one root
+    SessionSummary { peer: String, #[serde(flatten)] summary: RootSummary },
+    /// A validator task that stopped, failed or panicked and is being restarted
+    TaskDied { task: String, reason: String, restarts: usize },
+    /// A newer node record than any held, presented by the peer in its handshake
+    PeerRecord { peer: String, seq: u64, addrs: Vec<String>, custody: Vec<usize>, caps: Vec<String> },
+    /// A peer banned for its score; it is refused and not dialled from then on
+    PeerBanned { peer: String, reason: String },
+    /// A blob's root posted on chain by `send --anchor-rpc`; `block` once mined
+    Anchored { root: String, tx: String, #[serde(skip_serializing_if = "Option::is_none")] block: Option<u64> },
+}
+
+#[derive(Serialize)]
+struct EventLine<'a> {
+    /// Unix milliseconds
+    ts_ms: u64,
+    node: &'a str,
+    #[serde(flatten)]
+    tags: &'a RunTags,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// `--experiment-id` and `--tag`s, stamped on every record a run writes so a campaign's
+/// records can be filtered and grouped downstream.
+#[derive(Serialize, Debug, Default)]
+struct RunTags {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    experiment: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+}
+
+static RUN_TAGS: OnceLock<RunTags> = OnceLock::new();
+
+fn run_tags() -> &'static RunTags {
+    RUN_TAGS.get_or_init(RunTags::default)
+}
+
+fn open_event_log(path: &str, id: &Identity) -> Result<()> {
+    let _ = EVENT_LOG.set((hex::encode(id.public.as_bytes()), Mutex::new(RotatingLog::open(path)?)));
+    Ok(())
+}
+
+/// [`message_id`] of a frame for its event, computed only while events are being logged.
+fn event_msg_id(frame: &str) -> Option<String> {
+    EVENT_LOG.get().map(|_| message_id(frame))
+}
+
+/// Appends one event if `--event-log` is set; logging failures never disturb the protocol.
+fn log_event(event: Event) {
+    raise_alert(&event);
+    let Some((process, file)) = EVENT_LOG.get() else { return };
+    let ts_ms = clock().unix().as_millis() as u64;
+    let node = NODE.try_with(|node| node.clone()).ok();
+    let node = node.as_deref().unwrap_or(process);
+    if let Ok(mut line) = serde_json::to_string(&EventLine { ts_ms, node, tags: run_tags(), event: &event }) {
+        line.push('\n');
+        let _ = file.locked().append(line.as_bytes());
+    }
+}
+
+// ALERTING
+// `--alert-webhook <url>` POSTs the events worth paging for, so an unattended run reports its
+// failures as they happen.
+static ALERT_WEBHOOK: OnceLock<(String, RpcEndpoint)> = OnceLock::new();
+static ALERTS: Mutex<Option<AlertLimits>> = Mutex::new(None);
+/// Posts pending at once; alerts raised while this many are still going out are dropped.
+static ALERT_POSTS: tokio::sync::Semaphore = tokio::sync::Semaphore::const_new(ALERT_QUEUE);
+const ALERT_QUEUE: usize = 32;
+/// Alerts one peer's events may raise per [`ALERT_WINDOW`]. Roots and failures are the peer's
+/// to make up, so this keeps a hostile peer from flooding the webhook.
+const ALERTS_PER_PEER: usize = 5;
+const ALERT_WINDOW: Duration = Duration::from_secs(60);
+/// How long a posted alert suppresses a repeat for its root, and how many are remembered.
+const ALERT_DEDUP_TTL: Duration = Duration::from_secs(3600);
+const ALERT_DEDUP_MAX: usize = 4096;
+
+/// What bounds the alerts posted: those already sent, so a failing root pages once per kind,
+/// and each peer's count in its current window. Both forget old entries.
+#[derive(Default)]
+struct AlertLimits {
+    /// When each alert was posted, by kind and root
+    sent: HashMap<(&'static str, String), Duration>,
+    /// Start of each peer's current window, and the alerts raised in it
+    peers: HashMap<String, (Duration, usize)>,
+}
+
+impl AlertLimits {
+    /// Whether an alert of `kind` for `root`, raised by `peer`'s events, goes out now: `false`
+    /// for a repeat, or why a limit holds it back. An alert let through counts against both.
+    fn admit(&mut self, kind: &'static str, root: &str, peer: &str, now: Duration) -> Result<bool, &'static str> {
+        if self.sent.len() >= ALERT_DEDUP_MAX {
+            self.sent.retain(|_, at| now.saturating_sub(*at) < ALERT_DEDUP_TTL);
+            self.peers.retain(|_, (start, _)| now.saturating_sub(*start) < ALERT_WINDOW);
+        }
+        let key = (kind, root.to_string());
+        if self.sent.get(&key).is_some_and(|at| now.saturating_sub(*at) < ALERT_DEDUP_TTL) {
+            return Ok(false);
+        }
+        if self.sent.len() >= ALERT_DEDUP_MAX {
+            return Err("too many recent alerts");
+        }
+        let (start, count) = self.peers.entry(peer.to_string()).or_insert((now, 0));
+        if now.saturating_sub(*start) >= ALERT_WINDOW {
+            (*start, *count) = (now, 0);
+        }
+        if *count >= ALERTS_PER_PEER {
+            return Err("peer over its alert rate");
+        }
+        *count += 1;
+        self.sent.insert(key, now);
+        Ok(true)
+    }
+}
+
+/// Why a shard that fails its manifest commitment is rejected, in its `shard_received` event.
+const COMMITMENT_MISMATCH: &str = "manifest commitment mismatch";
+
+/// One webhook POST: the event's log line under the kind of alert it raised.
+#[derive(Serialize)]
+struct AlertPayload<'a> {
+    alert: &'static str,
+    #[serde(flatten)]
+    line: EventLine<'a>,
+}
+
+impl Event {
+    /// The alert this event raises and the root it concerns, for the failures an unattended
+    /// run should page for: a rebuild that fails or can't be made, corrupted data, and a
+    /// sampling deadline missed.
+    /// Also returns the peer whose frames led to it, which the alert is rate-limited by.
+    fn alert(&self) -> Option<(&'static str, &str, &str)> {
+        match self {
+            Event::Outcome { peer, outcome } => match outcome.verdict {
+                Verdict::Corrupted if outcome.reconstruct_ms.is_none() => Some(("corruption_detected", &outcome.root, peer)),
+                Verdict::Corrupted | Verdict::Incomplete => Some(("reconstruction_failed", &outcome.root, peer)),
+                _ => None,
+            },
+            Event::ShardReceived { peer, root, reason: Some(reason), .. } if reason == COMMITMENT_MISMATCH => Some(("corruption_detected", root, peer)),
+            Event::NegativeAttestation { peer, attestation, .. } => Some(("deadline_missed", &attestation.root, peer)),
+            _ => None,
+        }
+    }
+}
+
+/// Posts `event` to `--alert-webhook` if it raises an alert not yet sent for its root, within
+/// its peer's rate and while fewer than [`ALERT_QUEUE`] posts are pending. The post runs in the
+/// background and a failed one is only reported: alerting never changes a verdict.
+fn raise_alert(event: &Event) {
+    let Some((process, webhook)) = ALERT_WEBHOOK.get() else { return };
+    let Some((alert, root, peer)) = event.alert() else { return };
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else { return };
+    match ALERTS.locked().get_or_insert_default().admit(alert, root, peer, clock().now()) {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(reason) => {
+            say!("\n{} {} for {}: {}", "➜ Alert Suppressed:".yellow(), alert, root, reason);
+            return;
+        }
+    }
+    let Ok(permit) = ALERT_POSTS.try_acquire() else {
+        say!("\n{} {} for {}: {} posts already pending", "❌ Alert Dropped:".red(), alert, root, ALERT_QUEUE);
+        return;
+    };
+    let ts_ms = clock().unix().as_millis() as u64;
+    let node = NODE.try_with(|node| node.clone()).ok();
+    let node = node.as_deref().unwrap_or(process);
+    let Ok(body) = serde_json::to_string(&AlertPayload { alert, line: EventLine { ts_ms, node, tags: run_tags(), event } }) else { return };
+    let root = root.to_string();
+    track_in_flight(runtime.spawn(async move {
+        match rpc_call(post_json(webhook, &body)).await {
+            Ok(_) => say!("\n{} {} for {}", "➜ Alert Sent:".blue().bold(), alert, root),
+            Err(e) => say!("\n{} {} for {} to {}: {:#}", "❌ Alert Failed:".red(), alert, root, webhook, e),
+        }
+        drop(permit);
+    }));
+}
+
+// RECONSTRUCT HOOK
+/// Runs `--on-reconstruct` on a rebuilt blob in the background. The command goes through
+/// `sh -c` with the file's path and root appended as arguments, so it may be a pipeline. Under
+/// `--quiet` its standard output is dropped, to keep stdout to the summary line.
+fn run_reconstruct_hook(command: &str, path: &str, root: &str) -> tokio::task::JoinHandle<()> {
+    let mut hook = tokio::process::Command::new("sh");
+    hook.arg("-c").arg(format!("{} \"$@\"", command)).arg("sh").arg(path).arg(root)
+        .stdin(std::process::Stdio::null());
+    if QUIET.load(Ordering::Relaxed) {
+        hook.stdout(std::process::Stdio::null());
+    }
+    let (path, root) = (path.to_string(), root.to_string());
+    spawn_in_node(async move {
+        let start = clock().now();
+        let (code, error) = match hook.status().await {
+            Ok(status) if status.success() => {
+                say!("{} {}", "✓ Hook Finished:".green(), path);
+                (status.code(), None)
+            }
+            Ok(status) => {
+                say!("{} {}: {}", "❌ Hook Failed:".red(), path, status);
+                (status.code(), Some(status.to_string()))
+            }
+            Err(e) => {
+                say!("{} {}: {}", "❌ Hook Failed:".red(), path, e);
+                (None, Some(e.to_string()))
             }
+        };
+        let ms = clock().elapsed(start).as_secs_f64() * 1000.0;
+        log_event(Event::ReconstructHook { root, path, code, ms, error });
+    })
+}
+
+// ATTESTATION
+/// Signed statement that a node verified availability of a root, or declared it unavailable;
+/// checkable with the node's pubkey.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Attestation {
+    root: String,
+    filename: String,
+    verdict: Verdict,
+    samples: usize,
+    /// Unix seconds
+    verified_at: u64,
+    validator: String,
+    signature: String,
+}
+
+impl Attestation {
+    fn new(id: &Identity, outcome: &FileOutcome) -> Self {
+        let verified_at = unix_now();
+        let mut att = Self {
+            root: outcome.root.clone(),
+            filename: outcome.file.clone(),
+            verdict: outcome.verdict,
+            samples: outcome.shards,
+            verified_at,
+            validator: hex::encode(id.public.as_bytes()),
+            signature: String::new(),
+        };
+        att.signature = hex::encode(id.key.sign(&att.signing_bytes()).to_bytes());
+        att
+    }
+
+    /// A domain tag, then the text fields length-prefixed and the numbers as fixed-width
+    /// integers, so no filename can make two attestations sign the same bytes.
+    fn signing_bytes(&self) -> Vec<u8> {
+        let verdict = serde_json::to_string(&self.verdict).unwrap_or_default();
+        let mut bytes = b"das-attestation-v2".to_vec();
+        for field in [&self.root, &self.filename, &verdict] {
+            bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(field.as_bytes());
         }
+        bytes.extend_from_slice(&(self.samples as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.verified_at.to_be_bytes());
+        bytes
+    }
+
+    fn verify(&self) -> Result<VerifyingKey> {
+        let pubkey: [u8; 32] = hex::decode(&self.validator)?
+            .try_into()
+            .map_err(|_| anyhow!("Bad validator pubkey length"))?;
+        let sig: [u8; 64] = hex::decode(&self.signature)?
+            .try_into()
+            .map_err(|_| anyhow!("Bad signature length"))?;
+        let key = VerifyingKey::from_bytes(&pubkey)?;
+        key.verify(&self.signing_bytes(), &Signature::from_bytes(&sig))?;
+        Ok(key)
     }
-    Ok(())
 }
 
-// PROPOSER (SENDER)
-async fn run_proposer(peer: String, filepath: String, mode: ResearchMode, id: Identity) -> Result<()> {
-    let mut file = File::open(&filepath).context("File not found")?;
-    let mut data = Vec::new();
-    file.read_to_end(&mut data)?;
-    
-    let filename = std::path::Path::new(&filepath).file_name().unwrap().to_str().unwrap().to_string();
-    let checksum = calculate_sha256(&data);
-    let fsize = data.len();
+fn write_attestation(dir: &str, id: &Identity, outcome: &FileOutcome) -> Result<String> {
+    std::fs::create_dir_all(dir)?;
+    let path = format!("{}/{}.attestation.json", dir, outcome.root);
+    std::fs::write(&path, serde_json::to_string_pretty(&Attestation::new(id, outcome))?)?;
+    Ok(path)
+}
 
-    println!("Target: {}", peer);
-    println!("Payload: {} ({})", filename, format_bytes(fsize));
-    println!("Strategy: {:?}", mode);
-    
-    let socket = TcpStream::connect(peer).await.context("Connection Failed")?;
-    let mut framed = Framed::new(socket, LinesCodec::new());
-    
-    perform_handshake(&mut framed, &id).await?;
-    
-    let start = Instant::now();
-    let mut wire_bytes = 0;
+fn run_verify_attestation(path: &str, expected: Option<String>) -> Result<ExitStatus> {
+    let att: Attestation = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let result = att.verify().and_then(|key| match expected {
+        Some(ref pk) if *pk != hex::encode(key.as_bytes()) => bail!("Signed by {}, expected {}", att.validator, pk),
+        _ => Ok(()),
+    });
+    match result {
+        Ok(()) => {
+            say!("{} root {} ({:?}, {} samples) by {}", "✓ Valid Attestation:".green(), att.root, att.verdict, att.samples, att.validator);
+            Ok(ExitStatus::Success)
+        }
+        Err(e) => {
+            say!("{} {}", "❌ Invalid Attestation:".red(), e);
+            Ok(ExitStatus::AuthFailure)
+        }
+    }
+}
 
-    match mode {
-        ResearchMode::Naive => {
-            let msg = P2PMessage::NaiveTransfer { filename, data, checksum };
-            let json = serde_json::to_string(&msg)?;
-            wire_bytes += json.len();
-            framed.send(json).await?;
+// STORAGE
+const SHARD_MAGIC: &[u8; 4] = b"DASS";
+const SHARD_FORMAT_VERSION: u8 = 2;
+/// magic(4) | version(1) | flags(1) | root(32) | index(2) | k(2) | m(2) | original_len(8) | data_len(4)
+/// Version 1 files lack the flags byte and are read with the default layout.
+const SHARD_HEADER_LEN: usize = 4 + 1 + 1 + 32 + 2 + 2 + 2 + 8 + 4;
+const FLAG_NON_SYSTEMATIC: u8 = 0b0000_0001;
+const FLAG_INTERLEAVED: u8 = 0b0000_0010;
+
+/// A shard as stored on disk: a fixed big-endian header followed by the shard bytes, so the
+/// file alone says which blob it belongs to and how to decode it.
+#[derive(Debug, Clone)]
+struct ShardFile {
+    root: String,
+    index: usize,
+    k: usize,
+    m: usize,
+    layout: Layout,
+    original_len: usize,
+    data: Vec<u8>,
+}
+
+impl ShardFile {
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.layout.encoding == Encoding::NonSystematic { flags |= FLAG_NON_SYSTEMATIC; }
+        if self.layout.striping == Striping::Interleaved { flags |= FLAG_INTERLEAVED; }
+        flags
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let root: [u8; 32] = hex::decode(&self.root)?
+            .try_into()
+            .map_err(|_| anyhow!("Root must be 32 bytes"))?;
+        let mut out = Vec::with_capacity(SHARD_HEADER_LEN + self.data.len());
+        out.extend_from_slice(SHARD_MAGIC);
+        out.push(SHARD_FORMAT_VERSION);
+        out.push(self.flags());
+        out.extend_from_slice(&root);
+        out.extend_from_slice(&u16::try_from(self.index)?.to_be_bytes());
+        out.extend_from_slice(&u16::try_from(self.k)?.to_be_bytes());
+        out.extend_from_slice(&u16::try_from(self.m)?.to_be_bytes());
+        out.extend_from_slice(&(self.original_len as u64).to_be_bytes());
+        out.extend_from_slice(&u32::try_from(self.data.len())?.to_be_bytes());
+        out.extend_from_slice(&self.data);
+        Ok(out)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 5 || &bytes[..4] != SHARD_MAGIC {
+            bail!("Not a shard file");
         }
-        ResearchMode::DasFull | ResearchMode::DasSample => {
-            let shards = encode_shards(&data);
-            let count = if mode == ResearchMode::DasSample { 2 } else { DATA_SHARDS }; // Sample 2 or Send k
-            
-            // Shuffle for sampling
-            let mut indices: Vec<usize> = (0..TOTAL_SHARDS).collect();
-            indices.shuffle(&mut rand::thread_rng());
+        let (flags, at) = match bytes[4] {
+            1 => (0, 5),
+            2 if bytes.len() > 5 => (bytes[5], 6),
+            v => bail!("Unsupported shard format version {}", v),
+        };
+        let header_len = at + 50;
+        if bytes.len() < header_len {
+            bail!("Shard header truncated");
+        }
+        let u16_at = |off: usize| u16::from_be_bytes([bytes[at + off], bytes[at + off + 1]]) as usize;
+        let original_len = u64::from_be_bytes(bytes[at + 38..at + 46].try_into()?) as usize;
+        let data_len = u32::from_be_bytes(bytes[at + 46..at + 50].try_into()?) as usize;
+        if bytes.len() != header_len + data_len {
+            bail!("Shard file truncated: header says {} data bytes, found {}", data_len, bytes.len() - header_len);
+        }
+        let layout = Layout {
+            encoding: if flags & FLAG_NON_SYSTEMATIC != 0 { Encoding::NonSystematic } else { Encoding::Systematic },
+            striping: if flags & FLAG_INTERLEAVED != 0 { Striping::Interleaved } else { Striping::Contiguous },
+            // Shard files are stored by shard index, so the wire permutation is not needed.
+            permutation_seed: None,
+        };
+        Ok(Self {
+            root: hex::encode(&bytes[at..at + 32]),
+            index: u16_at(32),
+            k: u16_at(34),
+            m: u16_at(36),
+            layout,
+            original_len,
+            data: bytes[header_len..].to_vec(),
+        })
+    }
 
-            for &i in indices.iter().take(count) {
-                 let msg = P2PMessage::DasShard {
-                    filename: filename.clone(),
-                    original_len: fsize,
-                    index: i,
-                    data: shards[i].clone(),
-                    full_file_checksum: checksum.clone(),
-                };
-                let json = serde_json::to_string(&msg)?;
-                wire_bytes += json.len();
-                framed.send(json).await?;
+    fn write(&self, dir: &str) -> Result<String> {
+        std::fs::create_dir_all(dir)?;
+        let path = format!("{}/{}_{}.shard", dir, self.root, self.index);
+        std::fs::write(&path, self.to_bytes()?)?;
+        Ok(path)
+    }
+
+    fn read(path: &str) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?).with_context(|| format!("Reading {}", path))
+    }
+}
+
+/// Where a name in the blob store currently points.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IndexEntry {
+    hash: String,
+    /// Root the blob was received under (the checksum itself for naive transfers); empty in
+    /// indexes written before roots were recorded
+    #[serde(default)]
+    root: String,
+    size: u64,
+    /// Unix seconds of the last store under this name
+    stored_at: u64,
+}
+
+/// Content-addressed store for received blobs: `<dir>/blobs/<sha256>`, plus `<dir>/index.json`
+/// mapping filenames to hashes. Repeated runs of the same payload share one blob.
+#[derive(Clone)]
+struct BlobStore {
+    dir: String,
+    /// Held across each read-modify-write of the index, since sessions and reconstruction
+    /// workers commit concurrently
+    index_lock: Arc<Mutex<()>>,
+}
+
+impl BlobStore {
+    const INDEX: &'static str = "index.json";
+
+    fn open(dir: &str) -> Result<Self> {
+        std::fs::create_dir_all(format!("{}/blobs", dir))?;
+        std::fs::create_dir_all(format!("{}/tmp", dir))?;
+        Ok(Self { dir: dir.to_string(), index_lock: Arc::default() })
+    }
+
+    fn blob_path(&self, hash: &str) -> String {
+        format!("{}/blobs/{}", self.dir, hash)
+    }
+
+    /// Writes and removes a file in `tmp/`, the way every received blob starts out.
+    fn probe(&self) -> Result<()> {
+        let path = self.staging_path(".probe")?;
+        std::fs::write(&path, b"ok")?;
+        Ok(std::fs::remove_file(path)?)
+    }
+
+    /// Scratch location for a blob still being written; moved into place by `commit`. Keys
+    /// come from peers, so one that isn't a bare file name is refused rather than joined on.
+    fn staging_path(&self, key: &str) -> Result<String> {
+        if !is_safe_name(key) {
+            bail!("unsafe staging key {:?}", key);
+        }
+        Ok(format!("{}/tmp/{}", self.dir, key))
+    }
+
+    /// Where the shards of `root` are buffered while its transfer is open.
+    fn transfer_path(&self, root: &str) -> Result<String> {
+        if !is_digest(root) {
+            bail!("root {:?} is not a hex SHA-256 digest", root);
+        }
+        self.staging_path(root)
+    }
+
+    fn index(&self) -> Result<std::collections::BTreeMap<String, IndexEntry>> {
+        match std::fs::read_to_string(format!("{}/{}", self.dir, Self::INDEX)) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_index(&self, index: &std::collections::BTreeMap<String, IndexEntry>) -> Result<()> {
+        let tmp = self.staging_path(Self::INDEX)?;
+        std::fs::write(&tmp, serde_json::to_string_pretty(index)?)?;
+        std::fs::rename(tmp, format!("{}/{}", self.dir, Self::INDEX))?;
+        Ok(())
+    }
+
+    /// Moves a verified staged file to its content address and points `name` at it.
+    fn commit(&self, name: &str, hash: &str, root: &str, staged: &str) -> Result<String> {
+        if !is_safe_name(name) {
+            bail!("unsafe file name {:?}", name);
+        }
+        if !is_digest(hash) {
+            bail!("checksum {:?} is not a hex SHA-256 digest", hash);
+        }
+        if !is_digest(root) {
+            bail!("root {:?} is not a hex SHA-256 digest", root);
+        }
+        let path = self.blob_path(hash);
+        if std::path::Path::new(&path).exists() {
+            std::fs::remove_file(staged)?;
+            say!("{} {}… already stored", "➜ Dedup:".blue().bold(), &hash[..16]);
+        } else {
+            std::fs::rename(staged, &path)?;
+        }
+        let _index = self.index_lock.locked();
+        let mut index = self.index()?;
+        let size = std::fs::metadata(&path)?.len();
+        index.insert(name.to_string(), IndexEntry { hash: hash.to_string(), root: root.to_string(), size, stored_at: unix_now() });
+        self.write_index(&index)?;
+        Ok(path)
+    }
+
+    fn put(&self, name: &str, hash: &str, data: &[u8]) -> Result<String> {
+        if !is_digest(hash) {
+            bail!("checksum {:?} is not a hex SHA-256 digest", hash);
+        }
+        let staged = self.staging_path(hash)?;
+        std::fs::write(&staged, data)?;
+        self.commit(name, hash, hash, &staged)
+    }
+
+    /// Unpacks a stored `.pack` or `.matrix` blob under `<dir>/unpacked/<name without suffix>/`.
+    /// A matrix whose column code does not check out stays packed, since the blob itself was
+    /// what the proposer committed to.
+    fn extract(&self, name: &str, path: &str) -> Result<()> {
+        if !is_safe_name(name) {
+            bail!("unsafe file name {:?}", name);
+        }
+        if let Some(stem) = name.strip_suffix(MATRIX_SUFFIX) {
+            if let Err(e) = extract_matrix(path, &format!("{}/unpacked/{}", self.dir, stem)) {
+                say!("{} {}", "❌ Bad Matrix:".red(), e);
             }
+            return Ok(());
         }
+        let stem = name.strip_suffix(PACK_SUFFIX).unwrap_or(name);
+        extract_pack(path, &format!("{}/unpacked/{}", self.dir, stem))
     }
-    
-    let duration = start.elapsed();
-    let mb_s = (wire_bytes as f64 / 1024.0 / 1024.0) / duration.as_secs_f64();
-    
-    println!("\n{}", "=== Performance Metrics ===".bold().white().on_blue());
-    println!("{:<15} : {:?}", "Mode", mode);
-    println!("{:<15} : {:.2?}", "Latency", duration);
-    println!("{:<15} : {:.2} MB/s", "Throughput", mb_s);
-    println!("{:<15} : {}", "Total Wire", format_bytes(wire_bytes));
-    
-    if wire_bytes < fsize {
-        let savings = ((fsize as f64 - wire_bytes as f64) / fsize as f64) * 100.0;
-        println!("{:<15} : {}", "Efficiency", format!("{:.2}% Saved", savings).green().bold());
-    } else {
-        let overhead = ((wire_bytes as f64 / fsize as f64) - 1.0) * 100.0;
-        println!("{:<15} : {}", "Overhead", format!("{:.2}%", overhead).red());
+
+    /// Where `extract` or `join_windows` put what they made of the blob stored as `name`, if
+    /// it is a bundle or a window.
+    fn unpacked_path(&self, name: &str) -> Option<String> {
+        let stem = match window_of(name) {
+            Some((file, _, _)) => file,
+            None => name.strip_suffix(MATRIX_SUFFIX).or_else(|| name.strip_suffix(PACK_SUFFIX))?,
+        };
+        Some(format!("{}/unpacked/{}", self.dir, stem))
     }
 
-    // Wait for buffer flush before exit
-    tokio::time::sleep(std::time::Duration::from_millis(500)).await; 
-    
-    Ok(())
+    /// Once every one of the `count` windows of `file` is stored, joins them in order into
+    /// `<dir>/unpacked/<file>`, copying one window at a time. Each window was checked against its
+    /// own root; the joined file has no check of its own.
+    fn join_windows(&self, file: &str, count: usize) -> Result<()> {
+        if !is_safe_name(file) {
+            bail!("unsafe file name {:?}", file);
+        }
+        let index = self.index()?;
+        let Some(hashes) = (1..=count).map(|i| index.get(&window_name(file, i, count)).map(|e| e.hash.clone())).collect::<Option<Vec<_>>>() else {
+            return Ok(());
+        };
+        let dir = format!("{}/unpacked", self.dir);
+        std::fs::create_dir_all(&dir)?;
+        let staged = self.staging_path(&format!("{}.joining", file))?;
+        let mut out = File::create(&staged)?;
+        for hash in &hashes {
+            std::io::copy(&mut File::open(self.blob_path(hash))?, &mut out)?;
+        }
+        let path = format!("{}/{}", dir, file);
+        std::fs::rename(&staged, &path)?;
+        say!("{} {} windows into {}", "➜ Joined:".blue().bold(), count, path);
+        Ok(())
+    }
 }
 
-fn encode_shards(data: &[u8]) -> Vec<Vec<u8>> {
-    let padded = pad_data(data, DATA_SHARDS);
-    let shard_len = padded.len() / DATA_SHARDS;
-    let mut shards: Vec<Vec<u8>> = vec![vec![0; shard_len]; TOTAL_SHARDS];
-    for i in 0..DATA_SHARDS {
-        shards[i] = padded[i*shard_len..(i+1)*shard_len].to_vec();
+/// Encodes a file offline, writing every shard plus the manifest into `out_dir`; a file over
+/// `window` bytes window by window, as one blob each.
+fn run_encode(filepath: &str, out_dir: &str, layout: Layout, scheme: CommitmentScheme, window: usize, id: &Identity) -> Result<ExitStatus> {
+    match Payload::load(filepath, window)? {
+        Payload::Whole(filename, data) => {
+            encode_blob(&filename, &data, out_dir, layout, scheme, id)?;
+        }
+        Payload::Windowed { name, path, len, window } => {
+            for w in Windows::open(&path, &name, len, window)? {
+                let (filename, data) = w?;
+                say!("{} {} ({})", "➜ Window:".blue().bold(), filename, format_bytes(data.len()));
+                encode_blob(&filename, &data, out_dir, layout, scheme, id)?;
+            }
+        }
     }
-    let r = ReedSolomon::new(DATA_SHARDS, PARITY_SHARDS).unwrap();
-    r.encode(&mut shards).unwrap();
-    shards
+    Ok(ExitStatus::Success)
 }
 
-async fn perform_handshake(framed: &mut Framed<TcpStream, LinesCodec>, id: &Identity) -> Result<()> {
-    let ts: u64 = 1000;
-    let sig = id.key.sign(&ts.to_be_bytes());
-    let msg = P2PMessage::Handshake { pubkey: id.public.as_bytes().to_vec(), sig: sig.to_bytes().to_vec(), ts };
-    framed.send(serde_json::to_string(&msg)?).await?;
+/// Writes one blob's shards and manifest into `out_dir`, and returns its root.
+fn encode_blob(filename: &str, data: &[u8], out_dir: &str, layout: Layout, scheme: CommitmentScheme, id: &Identity) -> Result<String> {
+    let blob = DasEncoder::new(DATA_SHARDS, PARITY_SHARDS)?.layout(layout).shard_align(scheme.shard_align()).encode(data);
+    let manifest = Manifest::new(filename, &blob, scheme.commitment(), &id.public)?;
+    for (index, shard) in blob.shards.into_iter().enumerate() {
+        let file = ShardFile { root: manifest.root.clone(), index, k: blob.k, m: blob.m, layout, original_len: blob.original_len, data: shard };
+        let path = file.write(out_dir)?;
+        say!("{} {}", "➜ Shard:".blue().bold(), path);
+    }
+    let path = format!("{}/{}.manifest.json", out_dir, manifest.root);
+    std::fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+    say!("{} {}", "➜ Manifest:".blue().bold(), path);
+    show_versioned_hashes(&manifest.commitment);
+    Ok(manifest.root)
+}
+
+/// Result of an `import-sidecars` run, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug)]
+struct SidecarsSummary {
+    role: &'static str,
+    sidecars: Vec<ImportedSidecar>,
+}
+
+#[derive(Serialize, Debug)]
+struct ImportedSidecar {
+    file: String,
+    slot: u64,
+    proposer_index: u64,
+    block_root: String,
+    index: u64,
+    versioned_hash: String,
+    /// The raw blob, written for `send -f`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blob: Option<String>,
+    /// Root the blob was encoded under
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Reads SSZ blob sidecars, as consensus clients serve them, and runs each blob that passes the
+/// checks through `encode`: the raw blob goes to `<out_dir>/<slot>_<index>.blob`, next to its
+/// shards and manifest.
+fn run_import_sidecars(files: &[String], out_dir: &str, layout: Layout, scheme: CommitmentScheme, id: &Identity) -> Result<ExitStatus> {
+    let mut summary = SidecarsSummary { role: "import-sidecars", sidecars: Vec::new() };
+    std::fs::create_dir_all(out_dir)?;
+    for file in files {
+        let sidecars = BlobSidecar::read_list(&std::fs::read(file)?).with_context(|| format!("Reading {}", file))?;
+        say!("{} {} ({} sidecars)", "➜ Sidecars:".blue().bold(), file, sidecars.len());
+        for sidecar in sidecars {
+            let header = &sidecar.header;
+            let mut imported = ImportedSidecar {
+                file: file.clone(),
+                slot: header.slot,
+                proposer_index: header.proposer_index,
+                block_root: format!("0x{}", hex::encode(header.root())),
+                index: sidecar.index,
+                versioned_hash: format!("0x{}", hex::encode(sidecar.versioned_hash())),
+                blob: None,
+                root: None,
+                error: None,
+            };
+            say!("\nSlot {} (proposer {}, block {}…), blob {}", header.slot, header.proposer_index, &imported.block_root[..18], sidecar.index);
+            say!("  versioned hash {}", imported.versioned_hash);
+            if let Err(e) = sidecar.verify_inclusion().and_then(|_| sidecar.check_canonical()) {
+                say!("{} {}", "❌ Rejected:".red(), e);
+                imported.error = Some(e.to_string());
+                summary.sidecars.push(imported);
+                continue;
+            }
+            say!("{}", "✓ Included in the block body, canonical field elements".green());
+            let name = format!("{}_{}.blob", header.slot, sidecar.index);
+            let path = format!("{}/{}", out_dir, name);
+            std::fs::write(&path, &sidecar.blob)?;
+            say!("{} {}", "➜ Blob:".blue().bold(), path);
+            imported.root = Some(encode_blob(&name, &sidecar.blob, out_dir, layout, scheme, id)?);
+            imported.blob = Some(path);
+            summary.sidecars.push(imported);
+        }
+    }
+    let rejected = summary.sidecars.iter().filter(|s| s.error.is_some()).count();
+    say!("\n{} {} of {} sidecars into {}", "✓ Imported:".green().bold(), summary.sidecars.len() - rejected, summary.sidecars.len(), out_dir);
+    print_summary(&summary)?;
+    Ok(if rejected > 0 { ExitStatus::ChecksumMismatch } else { ExitStatus::Success })
+}
+
+/// Lists a KZG commitment's versioned hashes, to match against execution-layer blob references.
+fn show_versioned_hashes(commitment: &BlobCommitment) {
+    for (index, hash) in commitment.versioned_hashes().iter().enumerate() {
+        say!("  versioned hash [{}] {}", index, hash);
+    }
+}
+
+/// Rebuilds one missing shard file in `dir` from the shard files already there.
+fn run_repair(dir: &str, index: usize, root: Option<String>) -> Result<ExitStatus> {
+    let mut shards: Vec<ShardFile> = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "shard") {
+            shards.push(ShardFile::read(&path.to_string_lossy())?);
+        }
+    }
+    let mut roots: Vec<&String> = shards.iter().map(|s| &s.root).collect();
+    roots.sort();
+    roots.dedup();
+    let root = match root {
+        Some(r) => r,
+        None if roots.len() == 1 => roots[0].clone(),
+        None => bail!("{} roots found in {}; pick one with --root", roots.len(), dir),
+    };
+    shards.retain(|s| s.root == root);
+    let Some(template) = shards.first().cloned() else { bail!("No shards for root {} in {}", root, dir) };
+    let mut decoder = DasDecoder::new(template.k, template.m)?.layout(template.layout);
+    for shard in shards {
+        // Shards of one root but another code or layout would decode to garbage, not fail.
+        if (shard.k, shard.m, shard.layout, shard.original_len) != (template.k, template.m, template.layout, template.original_len) {
+            bail!("Shards {} and {} of {} disagree on k, m, layout or original length; not repairing from a mixed set",
+                template.index, shard.index, root);
+        }
+        decoder.push(shard.index, shard.data)?;
+    }
+
+    let start = clock().now();
+    let data = decoder.repair(index)?;
+    let elapsed = clock().elapsed(start);
+
+    let manifest_path = format!("{}/{}.manifest.json", dir, root);
+    if let Ok(text) = std::fs::read_to_string(&manifest_path) {
+        let manifest: Manifest = serde_json::from_str(&text)?;
+        match manifest.verify_shard(index, &data) {
+            Some(false) => {
+                say!("{} shard {} does not match {}", "❌ Repair Failed:".red(), index, manifest_path);
+                return Ok(ExitStatus::ChecksumMismatch);
+            }
+            Some(true) => say!("{}", "✓ Matches manifest commitment".green()),
+            None => say!("{} {:?} commitments cannot check single shards", "➜ Unchecked:".yellow(), manifest.commitment.scheme()),
+        }
+    }
+    let path = ShardFile { index, data, ..template }.write(dir)?;
+    say!("{} {} in {:.2?}", "✓ Repaired:".green().bold(), path, elapsed);
+    Ok(ExitStatus::Success)
+}
+
+/// Result of an `export-car` or `import-car` run, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug)]
+struct CarSummary {
+    role: &'static str,
+    archive: String,
+    blobs: Vec<CarBlob>,
+}
+
+#[derive(Serialize, Debug)]
+struct CarBlob {
+    root: String,
+    /// CID of the blob's DAG-CBOR node, one of the archive's roots
+    cid: String,
+    shards: usize,
+    /// Shards that failed the manifest's commitment or belong to another root (import only)
+    rejected: usize,
+    manifest: bool,
+}
+
+/// Writes the shard files and manifests in `dir` into a CAR archive at `out`, as one DAG-CBOR
+/// node per root linking its manifest and shards. Files are read twice, once to address them and
+/// once to copy them, so no blob is held in memory whole.
+fn run_export_car(dir: &str, out: &str, root: Option<String>) -> Result<ExitStatus> {
+    let mut shards: BTreeMap<String, BTreeMap<usize, (String, Cid)>> = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "shard") { continue; }
+        let path = path.to_string_lossy().to_string();
+        let bytes = std::fs::read(&path)?;
+        let shard = ShardFile::from_bytes(&bytes).with_context(|| format!("Reading {}", path))?;
+        if root.as_ref().is_none_or(|r| *r == shard.root) {
+            shards.entry(shard.root).or_default().entry(shard.index).or_insert((path, Cid::of(CODEC_RAW, &bytes)));
+        }
+    }
+    if shards.is_empty() {
+        bail!("No shards{} in {}", root.map(|r| format!(" for root {}", r)).unwrap_or_default(), dir);
+    }
+
+    let mut blobs = Vec::new();
+    for (root, indices) in &shards {
+        let manifest_path = format!("{}/{}.manifest.json", dir, root);
+        let manifest = std::path::Path::new(&manifest_path).exists().then(|| -> Result<_> {
+            let bytes = std::fs::read(&manifest_path)?;
+            Ok((Cid::of(CODEC_RAW, &bytes), bytes))
+        }).transpose()?;
+        let node = BlobNode { root: root.clone(), manifest: manifest.as_ref().map(|(cid, _)| *cid), shards: indices.values().map(|(_, cid)| *cid).collect() };
+        let bytes = node.to_cbor();
+        blobs.push((Cid::of(CODEC_DAG_CBOR, &bytes), bytes, manifest));
+    }
+
+    let roots: Vec<Cid> = blobs.iter().map(|(cid, _, _)| *cid).collect();
+    let mut car = CarWriter::new(std::io::BufWriter::new(File::create(out)?), &roots)?;
+    let mut summary = CarSummary { role: "export-car", archive: out.to_string(), blobs: Vec::new() };
+    for ((root, indices), (cid, node, manifest)) in shards.iter().zip(blobs) {
+        car.block(&cid, &node)?;
+        if let Some((cid, bytes)) = &manifest {
+            car.block(cid, bytes)?;
+        }
+        for (path, cid) in indices.values() {
+            let bytes = std::fs::read(path)?;
+            if !cid.matches(&bytes) {
+                bail!("{} changed during export", path);
+            }
+            car.block(cid, &bytes)?;
+        }
+        say!("{} {}… {} shards{} as {}", "➜ Blob:".blue().bold(), root.get(..16).unwrap_or(root), indices.len(),
+            if manifest.is_some() { " and manifest" } else { ", no manifest" }, cid);
+        summary.blobs.push(CarBlob { root: root.clone(), cid: cid.to_string(), shards: indices.len(), rejected: 0, manifest: manifest.is_some() });
+    }
+    car.finish()?;
+    say!("{} {} blobs to {} ({})", "✓ Exported:".green().bold(), summary.blobs.len(), out,
+        format_bytes(std::fs::metadata(out)?.len() as usize));
+    print_summary(&summary)?;
+    Ok(ExitStatus::Success)
+}
+
+/// Writes the shard files and manifests of every blob in the CAR archive `file` into `out_dir`.
+/// Blocks are checked against their CIDs as they are read, and shards against the blob's
+/// manifest when it has one, so an archive from an untrusted store cannot plant bad shards.
+fn run_import_car(file: &str, out_dir: &str) -> Result<ExitStatus> {
+    let car = CarReader::new(std::io::BufReader::new(File::open(file)?)).with_context(|| format!("Reading {}", file))?;
+    let roots = car.roots().to_vec();
+    let blocks: HashMap<Cid, Vec<u8>> = car.collect::<Result<_, _>>().with_context(|| format!("Reading {}", file))?;
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut summary = CarSummary { role: "import-car", archive: file.to_string(), blobs: Vec::new() };
+    let mut status = ExitStatus::Success;
+    for cid in roots {
+        let node = match blocks.get(&cid) {
+            Some(bytes) if cid.codec == CODEC_DAG_CBOR => BlobNode::from_cbor(bytes).with_context(|| format!("Blob node {}", cid))?,
+            Some(_) => bail!("Root {} is not a DAG-CBOR blob node", cid),
+            None => bail!("Root block {} is missing from {}", cid, file),
+        };
+        let manifest = match node.manifest.map(|m| blocks.get(&m).ok_or_else(|| anyhow!("Manifest block {} is missing", m))).transpose()? {
+            Some(bytes) => {
+                let manifest: Manifest = serde_json::from_slice(bytes).context("Invalid manifest")?;
+                manifest.validate().context("Invalid manifest")?;
+                if manifest.root != node.root || !manifest.root_matches() {
+                    say!("{} {} does not match its commitment", "❌ Bad Manifest:".red(), node.root.get(..16).unwrap_or(&node.root));
+                    summary.blobs.push(CarBlob { root: node.root, cid: cid.to_string(), shards: 0, rejected: node.shards.len(), manifest: false });
+                    status = ExitStatus::ChecksumMismatch;
+                    continue;
+                }
+                std::fs::write(format!("{}/{}.manifest.json", out_dir, node.root), bytes)?;
+                Some(manifest)
+            }
+            None => None,
+        };
+
+        let mut blob = CarBlob { root: node.root.clone(), cid: cid.to_string(), shards: 0, rejected: 0, manifest: manifest.is_some() };
+        let mut k = manifest.as_ref().map(|m| m.k);
+        for link in &node.shards {
+            let Some(bytes) = blocks.get(link) else {
+                say!("{} shard block {} is not in the archive", "➜ Missing:".yellow(), link);
+                continue;
+            };
+            let shard = ShardFile::from_bytes(bytes).with_context(|| format!("Shard block {}", link))?;
+            let fails = shard.root != node.root || manifest.as_ref().is_some_and(|m| m.verify_shard(shard.index, &shard.data) == Some(false));
+            if fails {
+                say!("{} {}… index {} does not match {}", "❌ Bad Shard:".red(), node.root.get(..16).unwrap_or(&node.root), shard.index,
+                    if manifest.is_some() { "the manifest" } else { "its blob" });
+                blob.rejected += 1;
+                continue;
+            }
+            k.get_or_insert(shard.k);
+            shard.write(out_dir)?;
+            blob.shards += 1;
+        }
+        let k = k.unwrap_or(DATA_SHARDS);
+        say!("{} {}… {} shards{} ({} needed to rebuild)", "➜ Blob:".blue().bold(), node.root.get(..16).unwrap_or(&node.root), blob.shards,
+            if blob.manifest { " and manifest" } else { "" }, k);
+        if blob.rejected > 0 {
+            status = ExitStatus::ChecksumMismatch;
+        } else if blob.shards < k && status == ExitStatus::Success {
+            status = ExitStatus::TransferIncomplete;
+        }
+        summary.blobs.push(blob);
+    }
+    say!("{} {} blobs into {}", "✓ Imported:".green().bold(), summary.blobs.len(), out_dir);
+    print_summary(&summary)?;
+    Ok(status)
+}
+
+#[derive(Serialize, Default)]
+struct GcSummary {
+    role: &'static str,
+    blobs_removed: usize,
+    shards_removed: usize,
+    other_removed: usize,
+    reclaimed_bytes: u64,
+}
+
+impl GcSummary {
+    fn remove(&mut self, path: &std::path::Path) -> Result<()> {
+        let meta = std::fs::metadata(path)?;
+        if meta.is_dir() {
+            self.reclaimed_bytes += dir_size(path)?;
+            std::fs::remove_dir_all(path)?;
+        } else {
+            self.reclaimed_bytes += meta.len();
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytes in the files under `path`, at any depth. Symlinks count as themselves, not followed.
+fn dir_size(path: &std::path::Path) -> Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        total += if meta.is_dir() { dir_size(&entry.path())? } else { meta.len() };
+    }
+    Ok(total)
+}
+
+/// Removes store entries and shard files that are older than `keep_days` and whose root
+/// is not listed in `keep_roots`. With only `--keep-roots`, everything unlisted goes.
+fn run_gc(data_dir: &str, shard_dir: Option<&str>, keep_days: Option<u64>, keep_roots: Option<&str>) -> Result<ExitStatus> {
+    let keep: std::collections::HashSet<String> = match keep_roots {
+        Some(path) => std::fs::read_to_string(path)?
+            .lines()
+            .map(|l| l.trim().to_lowercase())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect(),
+        None => Default::default(),
+    };
+    let cutoff = keep_days.map(|d| unix_now().saturating_sub(d * 86_400));
+    let expired = |stored_at: u64, roots: &[&str]| {
+        !roots.iter().any(|r| keep.contains(*r)) && cutoff.is_none_or(|c| stored_at < c)
+    };
+    let mtime = |path: &std::path::Path| -> Result<u64> {
+        let modified = std::fs::metadata(path)?.modified()?;
+        Ok(modified.duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+    };
+    let mut summary = GcSummary { role: "gc", ..Default::default() };
+
+    if std::path::Path::new(data_dir).is_dir() {
+        let store = BlobStore::open(data_dir)?;
+        let mut index = store.index()?;
+        let before = index.len();
+        let dropped: Vec<String> = index.iter()
+            .filter(|(_, e)| expired(e.stored_at, &[&e.root, &e.hash]))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &dropped {
+            index.remove(name);
+            // Extracted packs and matrices, and a joined file once any of its windows goes.
+            if let Some(unpacked) = store.unpacked_path(name) && std::path::Path::new(&unpacked).exists() {
+                summary.remove(std::path::Path::new(&unpacked))?;
+                summary.other_removed += 1;
+            }
+        }
+        if index.len() != before {
+            store.write_index(&index)?;
+        }
+        // A blob may be shared by several names; it goes only when no name points at it.
+        let live: std::collections::HashSet<&str> = index.values().map(|e| e.hash.as_str()).collect();
+        for entry in std::fs::read_dir(format!("{}/blobs", data_dir))? {
+            let path = entry?.path();
+            let hash = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            if !live.contains(hash.as_str()) && expired(mtime(&path)?, &[&hash]) {
+                summary.remove(&path)?;
+                summary.blobs_removed += 1;
+            }
+        }
+        // Leftovers from interrupted transfers, staged under their root (`<root>`,
+        // `<root>.speculative-…`), so a kept root's still go nowhere.
+        for entry in std::fs::read_dir(format!("{}/tmp", data_dir))? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let root = name.split('.').next().unwrap_or_default();
+            if expired(mtime(&path)?, &[root]) {
+                summary.remove(&path)?;
+                summary.other_removed += 1;
+            }
+        }
+    }
+
+    if let Some(dir) = shard_dir {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "shard") { continue; }
+            let Ok(shard) = ShardFile::read(&path.to_string_lossy()) else { continue };
+            if expired(mtime(&path)?, &[&shard.root]) {
+                summary.remove(&path)?;
+                summary.shards_removed += 1;
+            }
+        }
+    }
+
+    say!("{} {} blobs, {} shards, {} other entries", "➜ GC removed:".blue().bold(),
+        summary.blobs_removed, summary.shards_removed, summary.other_removed);
+    say!("Reclaimed: {}", format_bytes(summary.reclaimed_bytes as usize).cyan());
+    print_summary(&summary)?;
+    Ok(ExitStatus::Success)
+}
+
+fn run_inspect(paths: &[String]) -> Result<ExitStatus> {
+    for path in paths {
+        let shard = ShardFile::read(path)?;
+        say!("{}", path.bold());
+        say!("  {:<13}: {}", "Root", shard.root);
+        say!("  {:<13}: {} of {} (k={}, m={})", "Index", shard.index, shard.k + shard.m, shard.k, shard.m);
+        say!("  {:<13}: {:?}, {:?}", "Layout", shard.layout.encoding, shard.layout.striping);
+        say!("  {:<13}: {}", "Original Len", format_bytes(shard.original_len));
+        say!("  {:<13}: {} ({})", "Data", format_bytes(shard.data.len()), calculate_sha256(&shard.data));
+    }
+    Ok(ExitStatus::Success)
+}
+
+// TEST VECTORS
+/// Names the built-in setup in `gen-vectors` output when no `--trusted-setup` is given.
+const INSECURE_SETUP: &str = "insecure: tau = SHA-256(\"eth-das-prototype insecure kzg setup\") taken twice as 64 bytes and reduced";
+
+/// How `gen-vectors` derives input data from a seed, stated in the output for other
+/// implementations.
+const VECTOR_INPUT_DERIVATION: &str = "sha256(seed as u64 big-endian || counter as u64 big-endian) for counter = 0, 1, ..., concatenated and cut to size";
+
+#[derive(Serialize, Debug)]
+struct VectorFile {
+    generator: String,
+    input_derivation: &'static str,
+    /// The setup KZG commitments and proofs are made against
+    kzg_setup: String,
+    vectors: Vec<TestVector>,
+}
+
+#[derive(Serialize, Debug)]
+struct TestVector {
+    name: String,
+    seed: u64,
+    size: usize,
+    k: usize,
+    m: usize,
+    layout: Layout,
+    scheme: CommitmentScheme,
+    /// Hex input bytes
+    input: String,
+    encoded: EncodedVector,
+    commitment: BlobCommitment,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    versioned_hashes: Vec<String>,
+    /// Shards to check against `commitment`, with the expected verdict; tampered ones must fail
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checks: Vec<ShardCheck>,
+    /// Shard subsets that must decode back to the input
+    reconstruct: Vec<ReconstructCase>,
+}
+
+#[derive(Serialize, Debug)]
+struct EncodedVector {
+    shard_len: usize,
+    /// Hex shards in codeword order: data shards first when systematic
+    shards: Vec<String>,
+    shard_hashes: Vec<String>,
+    root: String,
+    checksum: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ShardCheck {
+    index: usize,
+    shard: String,
+    valid: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct ReconstructCase {
+    indices: Vec<usize>,
+    /// SHA-256 of the decoded output, which is the input's
+    checksum: String,
+}
+
+#[derive(Serialize, Debug)]
+struct VectorsSummary {
+    role: &'static str,
+    out: String,
+    vectors: usize,
+}
+
+/// The input `gen-vectors` derives from `seed`, per [`VECTOR_INPUT_DERIVATION`].
+fn vector_input(seed: u64, size: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(size + 32);
+    let mut counter = 0u64;
+    while data.len() < size {
+        data.extend(Sha256::digest([seed.to_be_bytes(), counter.to_be_bytes()].concat()));
+        counter += 1;
+    }
+    data.truncate(size);
+    data
+}
+
+/// Encodes, commits and decodes one input, checking every result against the prototype's own
+/// verifiers before it is written down.
+fn test_vector(seed: u64, size: usize, k: usize, m: usize, layout: Layout, scheme: CommitmentScheme) -> Result<TestVector> {
+    let name = format!("seed{}-size{}-k{}-m{}-{:?}-{:?}-{:?}", seed, size, k, m, layout.encoding, layout.striping, scheme).to_lowercase();
+    let data = vector_input(seed, size);
+    let blob = DasEncoder::new(k, m)?.layout(layout).shard_align(scheme.shard_align()).encode(&data);
+    let commitment = scheme.commitment().commit(&blob)?;
+
+    let mut checks = Vec::new();
+    for (index, shard) in blob.shards.iter().enumerate() {
+        let mut tampered = shard.clone();
+        tampered[0] ^= 1;
+        match (commitment.verify_shard(index, shard), commitment.verify_shard(index, &tampered)) {
+            (None, _) => break,
+            (Some(true), Some(false)) => {
+                checks.push(ShardCheck { index, shard: hex::encode(shard), valid: true });
+                checks.push(ShardCheck { index, shard: hex::encode(&tampered), valid: false });
+            }
+            _ => bail!("{}: shard {} does not check against its own commitment", name, index),
+        }
+    }
+
+    // All data shards, the last k (parity first), and a subset fixed by the seed.
+    let n = k + m;
+    let mut subsets = vec![(0..k).collect::<Vec<_>>(), (m..n).collect()];
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut random: Vec<usize> = (0..n).collect();
+    random.shuffle(&mut rng);
+    random.truncate(k);
+    random.sort();
+    if !subsets.contains(&random) {
+        subsets.push(random);
+    }
+    let mut reconstruct = Vec::new();
+    for indices in subsets {
+        let mut decoder = blob.decoder();
+        for &i in &indices {
+            decoder.push(i, blob.shards[i].clone())?;
+        }
+        let output = decoder.decode()?;
+        if output != data {
+            bail!("{}: shards {:?} decode to something other than the input", name, indices);
+        }
+        reconstruct.push(ReconstructCase { indices, checksum: calculate_sha256(&output) });
+    }
+
+    Ok(TestVector {
+        name,
+        seed,
+        size,
+        k,
+        m,
+        layout,
+        scheme,
+        input: hex::encode(&data),
+        encoded: EncodedVector {
+            shard_len: blob.shard_len,
+            shards: blob.shards.iter().map(hex::encode).collect(),
+            shard_hashes: blob.shard_hashes.clone(),
+            root: blob.root.clone(),
+            checksum: blob.checksum.clone(),
+        },
+        versioned_hashes: commitment.versioned_hashes(),
+        commitment,
+        checks,
+        reconstruct,
+    })
+}
+
+/// Writes a test vector for every combination of seed, size, code and scheme to `out`.
+fn run_gen_vectors(args: &GenVectorsArgs, kzg_setup: &str) -> Result<ExitStatus> {
+    let mut vectors = Vec::new();
+    for &seed in &args.seed {
+        for &size in &args.size {
+            for &k in &args.data_shards {
+                for &m in &args.parity_shards {
+                    for &scheme in &args.commitment {
+                        let vector = test_vector(seed, size, k, m, args.layout, scheme)?;
+                        say!("{} {} ({} shards of {})", "➜ Vector:".blue().bold(), vector.name, k + m, format_bytes(vector.encoded.shard_len));
+                        vectors.push(vector);
+                    }
+                }
+            }
+        }
+    }
+    let file = VectorFile {
+        generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        input_derivation: VECTOR_INPUT_DERIVATION,
+        kzg_setup: kzg_setup.to_string(),
+        vectors,
+    };
+    std::fs::write(&args.out, serde_json::to_string_pretty(&file)?)?;
+    say!("{} {} vectors to {}", "✓ Wrote:".green().bold(), file.vectors.len(), args.out);
+    print_summary(&VectorsSummary { role: "gen-vectors", out: args.out.clone(), vectors: file.vectors.len() })?;
+    Ok(ExitStatus::Success)
+}
+
+// BENCHMARK
+/// Result of `bench`, printed as a single JSON line in `--quiet` mode. Runs of builds with and
+/// without `simd-accel`, under different `--experiment-id`s, line up in `compare-runs`.
+#[derive(Serialize)]
+struct BenchSummary {
+    role: &'static str,
+    backend: &'static str,
+    k: usize,
+    m: usize,
+    results: Vec<BenchResult>,
+}
+
+/// Throughputs in MB/s of blob bytes.
+#[derive(Serialize)]
+struct BenchResult {
+    size: usize,
+    /// Parity computation alone, the part the GF(2^8) backend speeds up
+    parity_mb_s: f64,
+    /// `DasEncoder::encode`: padding, striping, parity and shard hashes
+    encode_mb_s: f64,
+    /// Decoding from the last k shards, so every parity shard is used
+    decode_mb_s: f64,
+}
+
+fn run_bench(args: &BenchArgs) -> Result<ExitStatus> {
+    let (k, m) = (args.data_shards, args.parity_shards);
+    let encoder = DasEncoder::new(k, m)?.layout(args.layout);
+    let code = reed_solomon_erasure::galois_8::ReedSolomon::new(k, m)?;
+    say!("{} {} GF(2^8), RS({}, {}), {} runs per size", "➜ Backend:".blue().bold(), GALOIS_BACKEND, k, m, args.iterations);
+    say!("{:>12} {:>14} {:>14} {:>14}", "size", "parity", "encode", "decode");
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let mut results = Vec::new();
+    for &size in &args.size {
+        let mut data = vec![0u8; size];
+        rng.fill(&mut data[..]);
+        let mb_s = |elapsed: Duration| (size * args.iterations) as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64().max(1e-9);
+
+        let shard_len = size.div_ceil(k).max(1);
+        let mut shards: Vec<Vec<u8>> = data.chunks(shard_len).map(|c| c.to_vec()).collect();
+        shards.resize(k + m, Vec::new());
+        shards.iter_mut().for_each(|s| s.resize(shard_len, 0));
+        let start = clock().now();
+        for _ in 0..args.iterations {
+            code.encode(&mut shards)?;
+        }
+        let parity_mb_s = mb_s(clock().elapsed(start));
+
+        let start = clock().now();
+        let mut blob = encoder.encode(&data);
+        for _ in 1..args.iterations {
+            blob = encoder.encode(&data);
+        }
+        let encode_mb_s = mb_s(clock().elapsed(start));
+
+        let mut decoder = blob.decoder();
+        for i in m..k + m {
+            decoder.push(i, blob.shards[i].clone())?;
+        }
+        let start = clock().now();
+        let mut decoded = decoder.decode()?;
+        for _ in 1..args.iterations {
+            decoded = decoder.decode()?;
+        }
+        let decode_mb_s = mb_s(clock().elapsed(start));
+        if decoded != data {
+            bail!("{} bytes did not decode back from the last {} shards", size, k);
+        }
+
+        let rate = |mb_s: f64| format!("{:.1} MB/s", mb_s);
+        say!("{:>12} {:>14} {:>14} {:>14}", format_bytes(size), rate(parity_mb_s), rate(encode_mb_s), rate(decode_mb_s));
+        results.push(BenchResult { size, parity_mb_s, encode_mb_s, decode_mb_s });
+    }
+    print_summary(&BenchSummary { role: "bench", backend: GALOIS_BACKEND, k, m, results })?;
+    Ok(ExitStatus::Success)
+}
+
+// SUBNET MAPPING
+/// Result of `mapping`, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize)]
+struct MappingSummary {
+    role: &'static str,
+    columns: usize,
+    column_subnets: usize,
+    custody: usize,
+    subnets: Vec<SubnetMapping>,
+    nodes: Vec<NodeMapping>,
+    /// Subnets none of the given nodes subscribe to
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    uncovered: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct SubnetMapping {
+    subnet: usize,
+    columns: Vec<usize>,
+    nodes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct NodeMapping {
+    node: String,
+    subnets: Vec<usize>,
+    columns: Vec<usize>,
+}
+
+fn run_mapping(column_subnets: usize, custody: usize, nodes: &[String]) -> Result<ExitStatus> {
+    if custody > column_subnets {
+        bail!("--custody {} exceeds the {} column subnets", custody, column_subnets);
+    }
+    let columns = TOTAL_SHARDS;
+    let nodes: Vec<NodeMapping> = nodes.iter().map(|node| {
+        // A hex pubkey is placed by its bytes, anything else by its text.
+        let id = hex::decode(node).unwrap_or_else(|_| node.as_bytes().to_vec());
+        let subnets = node_subnets(&id, column_subnets, custody);
+        let columns = (0..columns).filter(|&c| subnets.contains(&column_subnet(c, column_subnets))).collect();
+        NodeMapping { node: node.clone(), subnets, columns }
+    }).collect();
+    let subnets: Vec<SubnetMapping> = (0..column_subnets).map(|subnet| SubnetMapping {
+        subnet,
+        columns: subnet_columns(subnet, column_subnets, columns),
+        nodes: nodes.iter().filter(|n| n.subnets.contains(&subnet)).map(|n| n.node.clone()).collect(),
+    }).collect();
+    let uncovered = if nodes.is_empty() { Vec::new() } else {
+        subnets.iter().filter(|s| s.nodes.is_empty()).map(|s| s.subnet).collect()
+    };
+    let list = |items: &[usize]| items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+    // Pubkeys are cut down to a recognizable prefix in the tables; the JSON keeps them whole.
+    let short = |node: &String| match node.len() > 16 {
+        true => format!("{}…", &node[..node.floor_char_boundary(16)]),
+        false => node.clone(),
+    };
+
+    say!("{} {} columns over {} subnets, {} per node", "➜ Mapping:".green().bold(), columns, column_subnets, custody);
+    say!("
+{:<8} {:<16} {}", "Subnet", "Columns", "Nodes");
+    for s in &subnets {
+        let columns = if s.columns.is_empty() { "-".to_string() } else { list(&s.columns) };
+        say!("{:<8} {:<16} {}", s.subnet, columns, s.nodes.iter().map(short).collect::<Vec<_>>().join(", "));
+    }
+    if !nodes.is_empty() {
+        say!("
+{:<20} {:<16} {}", "Node", "Subnets", "Columns");
+        for n in &nodes {
+            say!("{:<20} {:<16} {}", short(&n.node), list(&n.subnets), list(&n.columns));
+        }
+    }
+    let empty: Vec<usize> = subnets.iter().filter(|s| s.columns.is_empty()).map(|s| s.subnet).collect();
+    if !empty.is_empty() {
+        say!("{} subnets {} carry no column ({} columns only)", "⚠ Empty:".yellow(), list(&empty), columns);
+    }
+    if !uncovered.is_empty() {
+        say!("{} no node subscribes to subnets {}", "⚠ Uncovered:".yellow(), list(&uncovered));
+    }
+
+    print_summary(&MappingSummary { role: "mapping", columns, column_subnets, custody, subnets, nodes, uncovered })?;
+    Ok(ExitStatus::Success)
+}
+
+// PACKING
+const PACK_MAGIC: &[u8; 4] = b"DASP";
+const PACK_VERSION: u8 = 1;
+const PACK_SUFFIX: &str = ".pack";
+const MATRIX_SUFFIX: &str = ".matrix";
+/// Marks one window of a file over `--window`: `<file>.window-<i>-of-<n>`, counting from 1.
+const WINDOW_MARK: &str = ".window-";
+/// Default for `send --window` and `encode --window`.
+const DEFAULT_WINDOW: usize = 256 * 1024 * 1024;
+
+/// Concatenates many small files into one blob so per-blob overhead is paid once:
+/// magic(4) | version(1) | count(4) | count x [name_len(2) | name | len(8)] | file bytes...
+fn pack_files(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    out.extend_from_slice(PACK_MAGIC);
+    out.push(PACK_VERSION);
+    out.extend_from_slice(&u32::try_from(files.len())?.to_be_bytes());
+    for (name, data) in files {
+        out.extend_from_slice(&u16::try_from(name.len())?.to_be_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&(data.len() as u64).to_be_bytes());
+    }
+    for (_, data) in files {
+        out.extend_from_slice(data);
+    }
+    Ok(out)
+}
+
+/// Splits a pack back into `(name, bytes)` entries. Names are plain file names, never paths.
+fn unpack_files(bytes: &[u8]) -> Result<Vec<(String, &[u8])>> {
+    let mut at: usize = 0;
+    let mut take = |n: usize| -> Result<&[u8]> {
+        let slice = at.checked_add(n).and_then(|end| bytes.get(at..end)).context("Pack truncated")?;
+        at += n;
+        Ok(slice)
+    };
+    if take(4)? != PACK_MAGIC {
+        bail!("Not a pack");
+    }
+    let version = take(1)?[0];
+    if version != PACK_VERSION {
+        bail!("Unsupported pack version {}", version);
+    }
+    let count = u32::from_be_bytes(take(4)?.try_into()?) as usize;
+    let mut index = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let name_len = u16::from_be_bytes(take(2)?.try_into()?) as usize;
+        let name = String::from_utf8(take(name_len)?.to_vec())?;
+        if !is_safe_name(&name) {
+            bail!("Unsafe entry name {:?} in pack", name);
+        }
+        let len = u64::from_be_bytes(take(8)?.try_into()?) as usize;
+        index.push((name, len));
+    }
+    index.into_iter().map(|(name, len)| Ok((name, take(len)?))).collect()
+}
+
+/// Reads a payload for sending: a regular file as-is, or every file in a directory as a pack.
+fn load_payload(path: &str) -> Result<(String, Vec<u8>)> {
+    let p = std::path::Path::new(path);
+    let name = p.file_name().context("Path has no file name")?.to_string_lossy().to_string();
+    if !p.is_dir() {
+        return Ok((name, std::fs::read(p).context("File not found")?));
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(p)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push((entry.file_name().to_string_lossy().to_string(), std::fs::read(entry.path())?));
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    say!("Packed {} files from {}", files.len(), path);
+    Ok((format!("{}{}", name, PACK_SUFFIX), pack_files(&files)?))
+}
+
+/// What one `--file` sends: a payload held whole, or a regular file over `--window`, read one
+/// window at a time so only the window being sent is in memory.
+enum Payload {
+    Whole(String, Vec<u8>),
+    Windowed { name: String, path: String, len: usize, window: usize },
+}
+
+impl Payload {
+    /// Holds `path` whole unless it is a regular file over `window` bytes.
+    fn load(path: &str, window: usize) -> Result<Self> {
+        let p = std::path::Path::new(path);
+        let meta = std::fs::metadata(p).context("File not found")?;
+        if !meta.is_file() || meta.len() <= window as u64 {
+            let (name, data) = load_payload(path)?;
+            return Ok(Payload::Whole(name, data));
+        }
+        let name = p.file_name().context("Path has no file name")?.to_string_lossy().to_string();
+        Ok(Payload::Windowed { name, path: path.to_string(), len: meta.len() as usize, window })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Payload::Whole(_, data) => data.len(),
+            Payload::Windowed { len, .. } => *len,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Payload::Whole(name, _) | Payload::Windowed { name, .. } => name,
+        }
+    }
+}
+
+/// The windows of a file over `--window`, in order, each named for [`window_of`]. The next is
+/// read only when asked for.
+struct Windows {
+    file: File,
+    name: String,
+    len: usize,
+    window: usize,
+    /// Windows read so far
+    read: usize,
+}
+
+impl Windows {
+    fn open(path: &str, name: &str, len: usize, window: usize) -> Result<Self> {
+        Ok(Self { file: File::open(path)?, name: name.to_string(), len, window, read: 0 })
+    }
+
+    fn total(&self) -> usize {
+        self.len.div_ceil(self.window)
+    }
+}
+
+impl Iterator for Windows {
+    type Item = Result<(String, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.read * self.window;
+        if start >= self.len {
+            return None;
+        }
+        self.read += 1;
+        let mut data = vec![0; self.window.min(self.len - start)];
+        if let Err(e) = self.file.read_exact(&mut data) {
+            return Some(Err(e.into()));
+        }
+        Some(Ok((window_name(&self.name, self.read, self.total()), data)))
+    }
+}
+
+fn window_name(name: &str, i: usize, count: usize) -> String {
+    format!("{}{}{:04}-of-{:04}", name, WINDOW_MARK, i, count)
+}
+
+/// The file, window number and window count a window's name encodes.
+fn window_of(name: &str) -> Option<(&str, usize, usize)> {
+    let (file, rest) = name.rsplit_once(WINDOW_MARK)?;
+    let (i, count) = rest.split_once("-of-")?;
+    let (i, count): (usize, usize) = (i.parse().ok()?, count.parse().ok()?);
+    (1..=count).contains(&i).then_some((file, i, count))
+}
+
+/// Whether a stored blob bundles other files, to extract once it is verified.
+fn is_bundle(name: &str) -> bool {
+    name.ends_with(PACK_SUFFIX) || name.ends_with(MATRIX_SUFFIX)
+}
+
+/// Extracts every blob of the block matrix at `path` into `dir`, once its column code checks out.
+fn extract_matrix(path: &str, dir: &str) -> Result<()> {
+    let blobs = decode_matrix(&std::fs::read(path)?)?;
+    if let Some((name, _)) = blobs.iter().find(|(name, _)| !is_safe_name(name)) {
+        bail!("Unsafe blob name {:?} in matrix", name);
+    }
+    std::fs::create_dir_all(dir)?;
+    for (name, data) in &blobs {
+        std::fs::write(format!("{}/{}", dir, name), data)?;
+    }
+    say!("{} {} blobs into {}/", "➜ Unpacked:".blue().bold(), blobs.len(), dir);
+    Ok(())
+}
+
+/// Extracts every file of the pack at `path` into `dir`.
+fn extract_pack(path: &str, dir: &str) -> Result<()> {
+    let bytes = std::fs::read(path)?;
+    let entries = unpack_files(&bytes)?;
+    std::fs::create_dir_all(dir)?;
+    for (name, data) in &entries {
+        std::fs::write(format!("{}/{}", dir, name), data)?;
+    }
+    say!("{} {} files into {}/", "➜ Unpacked:".blue().bold(), entries.len(), dir);
+    Ok(())
+}
+
+// CLI
+#[derive(Parser)]
+struct Cli {
+    /// Suppress interactive output and print one JSON summary line at exit
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Persistent node key (hex seed); created if missing. Defaults to an ephemeral key
+    #[arg(long, global = true)]
+    key_file: Option<String>,
+    /// Append every protocol event as a timestamped JSON line to this file
+    #[arg(long, global = true)]
+    event_log: Option<String>,
+    /// POST a JSON alert to this plain `http://` URL when a rebuild fails, corruption is detected
+    /// or a sampling deadline is missed; once per root and kind
+    #[arg(long, global = true)]
+    alert_webhook: Option<RpcEndpoint>,
+    /// KZG ceremony file (`trusted_setup.txt` layout) to commit and verify with, or `minimal`
+    /// for the embedded test setup. Without one, KZG uses an insecure setup with a public secret
+    #[arg(long, global = true)]
+    trusted_setup: Option<String>,
+    /// Reject peers whose signed handshake time is more than this many seconds from our clock
+    #[arg(long, global = true, default_value_t = DEFAULT_MAX_CLOCK_SKEW)]
+    max_clock_skew: u64,
+    /// Append each run's summary and its metrics as a JSON line to this results file, for
+    /// `compare-runs`
+    #[arg(long, global = true)]
+    results: Option<String>,
+    /// Experiment this run belongs to, such as a code or parameter variant; stamped on every
+    /// event, transfer-log and `--results` record
+    #[arg(long, global = true, alias = "experiment")]
+    experiment_id: Option<String>,
+    /// `key=value` metadata stamped next to the experiment ID (repeatable)
+    #[arg(long = "tag", global = true)]
+    tags: Vec<Tag>,
+    #[command(flatten)]
+    rotation: Rotation,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    Listen(ListenArgs),
+    Send(SendArgs),
+    /// Light client: query a serving node for shards of a root and judge availability
+    Sample(SampleArgs),
+    /// Retrieve a blob: request shards from serving nodes until k pass, rebuild it, check it and
+    /// write it to disk
+    Fetch(FetchArgs),
+    /// Erasure-code a file into self-describing shard files, without networking
+    Encode {
+        #[arg(short, long)]
+        file: String,
+        #[arg(short, long)]
+        out_dir: String,
+        #[command(flatten)]
+        layout: Layout,
+        /// Commitment scheme written into the manifest
+        #[arg(long, value_enum, default_value_t = CommitmentScheme::Merkle)]
+        commitment: CommitmentScheme,
+        /// Bytes per window: a file over this is encoded as independent blobs of one window
+        /// each, with a manifest apiece
+        #[arg(long, default_value_t = DEFAULT_WINDOW, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        window: usize,
+    },
+    /// Read SSZ blob sidecars dumped by consensus clients, check them, and encode each blob into
+    /// shard files as `encode` does
+    ImportSidecars {
+        /// SSZ file of one `BlobSidecar` or a list of them, as the beacon API serves them
+        #[arg(short, long, required = true)]
+        file: Vec<String>,
+        #[arg(short, long)]
+        out_dir: String,
+        #[command(flatten)]
+        layout: Layout,
+        /// Commitment scheme written into the manifests
+        #[arg(long, value_enum, default_value_t = CommitmentScheme::Merkle)]
+        commitment: CommitmentScheme,
+    },
+    /// Bundle every file in a directory into one pack blob
+    Pack {
+        #[arg(short, long)]
+        dir: String,
+        #[arg(short, long)]
+        out: String,
+    },
+    /// Extract a pack or block matrix blob into `<file without .pack or .matrix>/`
+    Unpack {
+        #[arg(short, long)]
+        file: String,
+    },
+    /// Regenerate one missing shard file from any k shard files in a directory
+    Repair {
+        #[arg(short, long)]
+        dir: String,
+        /// Shard index to regenerate
+        #[arg(short, long)]
+        index: usize,
+        /// Root to repair when the directory holds shards of several blobs
+        #[arg(long)]
+        root: Option<String>,
+    },
+    /// Bundle shard files and manifests, as `encode` writes them, into a CAR archive for
+    /// IPFS-compatible tooling
+    ExportCar {
+        #[arg(short, long)]
+        dir: String,
+        #[arg(short, long)]
+        out: String,
+        /// Export only this root; every root in the directory by default
+        #[arg(long)]
+        root: Option<String>,
+    },
+    /// Unpack a CAR archive from `export-car` into shard files and manifests, checking every
+    /// shard on the way
+    ImportCar {
+        #[arg(short, long)]
+        file: String,
+        #[arg(short, long)]
+        out_dir: String,
+    },
+    /// Remove expired blobs and shard files and report the space reclaimed
+    #[command(group(clap::ArgGroup::new("policy").required(true).multiple(true)))]
+    Gc {
+        #[arg(long, default_value = "das_store")]
+        data_dir: String,
+        /// Also collect shard files written with `listen --shard-dir`
+        #[arg(long)]
+        shard_dir: Option<String>,
+        /// Keep anything stored within the last N days
+        #[arg(long, group = "policy")]
+        keep_days: Option<u64>,
+        /// File of roots (one hex root per line) to keep regardless of age
+        #[arg(long, group = "policy")]
+        keep_roots: Option<String>,
+    },
+    /// Show the peer scores, history and bans a validator keeps in its `--data-dir`, and edit
+    /// the bans
+    Peers {
+        #[arg(long, default_value = "das_store")]
+        data_dir: String,
+        /// List every peer's score and history, lowest score first
+        #[arg(long)]
+        show_scores: bool,
+        /// Ban this hex pubkey (repeatable); edit bans while the validator is stopped
+        #[arg(long, value_delimiter = ',')]
+        ban: Vec<String>,
+        /// Reason recorded with `--ban`
+        #[arg(long, requires = "ban", default_value = "manual")]
+        reason: String,
+        /// Lift the ban on this hex pubkey (repeatable)
+        #[arg(long, value_delimiter = ',')]
+        unban: Vec<String>,
+    },
+    /// Forward traffic between a proposer and a validator, logging and optionally mutating it
+    Proxy(ProxyArgs),
+    /// Rendezvous point for nodes that can't accept connections (NAT): validators register with
+    /// `listen --relay`, and `send`/`sample --relay` reach them by pubkey through it
+    Relay(RelayArgs),
+    /// Re-run a recorded validator session (`listen --record`) offline
+    Replay {
+        #[arg(short, long)]
+        file: String,
+        /// Store what the replay receives and rebuilds here instead of in a fresh directory under
+        /// the system temp dir; `--data-dir` is never written to
+        #[arg(long)]
+        store: Option<String>,
+        #[command(flatten)]
+        listen: ListenArgs,
+    },
+    /// Print the header of one or more shard files
+    Inspect {
+        files: Vec<String>,
+    },
+    /// Print which columns (wire indices) each column subnet carries, and which subnets the
+    /// given nodes subscribe to, for planning PeerDAS-style runs
+    Mapping {
+        /// Subnets the columns are spread over, column `i` on subnet `i % N`
+        #[arg(long, default_value_t = 2, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        column_subnets: usize,
+        /// Subnets each node subscribes to, picked from its ID
+        #[arg(long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        custody: usize,
+        /// Node IDs to place: hex pubkeys, or any other names
+        #[arg(long, value_delimiter = ',')]
+        nodes: Vec<String>,
+    },
+    /// Run the in-process network simulator and report, per slot, what the network decided
+    Simulate {
+        /// JSON file with the simulation settings (same names as the flags); replaces the flags
+        #[arg(long)]
+        topology: Option<String>,
+        /// Rerun the same network and seed with this sampling strategy and compare the two
+        #[arg(long, value_enum)]
+        compare: Option<SamplingKind>,
+        #[command(flatten)]
+        config: SimConfig,
+    },
+    /// Aggregate an `--event-log` file over many transfers: success rate, bandwidth savings per
+    /// mode and failure reasons
+    Report {
+        #[arg(long)]
+        from: String,
+    },
+    /// Compare the experiments in a `--results` file metric by metric against the first, and
+    /// flag regressions and improvements
+    CompareRuns {
+        #[arg(long)]
+        from: String,
+        /// Experiments to compare, the baseline first; defaults to every experiment in the file
+        /// in the order they first appear
+        #[arg(long, value_delimiter = ',')]
+        experiments: Vec<String>,
+        /// Only compare runs whose summary has this role (proposer, validator, light-client, ...)
+        #[arg(long)]
+        role: Option<String>,
+        /// Group runs by the value of this `--tag` instead of by experiment ID
+        #[arg(long)]
+        group_by: Option<String>,
+        /// Changes of at most this many percent count as unchanged
+        #[arg(long, default_value_t = 5.0)]
+        threshold: f64,
+    },
+    /// Print each metric's change from A to B, with Welch's t-test over the trials on each side
+    DiffMetrics {
+        /// Metrics file (a `--results` file or `--quiet` summaries), or an experiment in `--from`
+        a: String,
+        b: String,
+        /// Results file to look run IDs up in
+        #[arg(long)]
+        from: Option<String>,
+        /// Only compare runs whose summary has this role (proposer, validator, light-client, ...)
+        #[arg(long)]
+        role: Option<String>,
+    },
+    /// Check the signature on an attestation file
+    VerifyAttestation {
+        #[arg(short, long)]
+        file: String,
+        /// Require the attestation to be signed by this hex pubkey
+        #[arg(long)]
+        pubkey: Option<String>,
+    },
+    /// Write deterministic encode, commit, proof and reconstruct test vectors as JSON, for other
+    /// implementations to check themselves against
+    GenVectors(GenVectorsArgs),
+    /// Measure encode and reconstruct throughput with this build's GF(2^8) backend
+    Bench(BenchArgs),
+    /// Create and inspect signed node records (`enr:...`), to share peers as single strings
+    Enr {
+        #[command(subcommand)]
+        action: EnrAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum EnrAction {
+    /// Sign a node record with `--key-file` and print its text form
+    Encode {
+        /// Address (`host:port`) the node takes connections on, preferred first (repeatable)
+        #[arg(long = "addr", value_delimiter = ',', required = true)]
+        addrs: Vec<String>,
+        /// Column subnets the node custodies, picked from its ID over `--column-subnets` as
+        /// `mapping` does. Defaults to every column
+        #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        custody: Option<usize>,
+        /// Subnets the columns are spread over, column `i` on subnet `i % N`
+        #[arg(long, default_value_t = 2, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        column_subnets: usize,
+        /// Capabilities to list (repeatable); defaults to what `listen` advertises
+        #[arg(long = "cap", value_delimiter = ',')]
+        caps: Vec<String>,
+        /// Sequence number; defaults to now in Unix milliseconds, so it replaces older records
+        #[arg(long)]
+        seq: Option<u64>,
+        /// Also add the record to this peers file
+        #[arg(long)]
+        peers_file: Option<String>,
+    },
+    /// Check node records and print what they hold
+    Decode {
+        /// Records in text form
+        records: Vec<String>,
+        /// Also check every record in this peers file
+        #[arg(long)]
+        from: Option<String>,
+    },
+}
+
+#[derive(Args, Clone)]
+struct ListenArgs {
+    /// Port to listen on, or a range like `8080-8089`; repeat for more. Each port runs a
+    /// validator of its own in this process
+    #[arg(short, long, default_value = "8080")]
+    port: Vec<PortRange>,
+    /// Exit after the first session, with its verdict as the exit code
+    #[arg(long)]
+    once: bool,
+    /// Write a signed attestation for every root whose availability was verified
+    #[arg(long)]
+    attest_dir: Option<String>,
+    /// Persist every accepted shard as a self-describing shard file
+    #[arg(long)]
+    shard_dir: Option<String>,
+    /// Content-addressed store for verified blobs (`blobs/<sha256>` plus `index.json`)
+    #[arg(long, default_value = "das_store")]
+    data_dir: String,
+    /// Largest frame (JSON line, bytes) to accept; advertised to peers in the handshake
+    #[arg(long, default_value_t = DEFAULT_MAX_FRAME)]
+    max_frame: usize,
+    /// Frame compression to offer in the handshake; each session uses the best codec both
+    /// peers offer (zstd, then lz4), or none
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "zstd,lz4")]
+    compression: Vec<Compression>,
+    /// Bytes of not-yet-verified shards one peer (by pubkey) may hold in the buffer
+    #[arg(long, default_value_t = DEFAULT_PEER_QUOTA)]
+    peer_quota: usize,
+    /// Buffer the shards of blobs over this many padded bytes in temporary files next to the
+    /// staging output instead of memory, and rebuild them with streamed reads
+    #[arg(long)]
+    spill_above: Option<usize>,
+    /// Every this many seconds, checkpoint the transfers still being buffered (roots, shards,
+    /// senders) to `<data-dir>/checkpoint/`, and resume from the last checkpoint on start
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    checkpoint_interval: Option<u64>,
+    /// Drop the buffered shards of a root that no session is sending, such as one restored from
+    /// a checkpoint, once they are this many seconds old
+    #[arg(long, default_value_t = DEFAULT_ABANDON_AFTER, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    abandon_after: u64,
+    /// Frames (or shard files) that may queue between pipeline stages before the socket stalls
+    #[arg(long, default_value_t = DEFAULT_PIPELINE_DEPTH)]
+    pipeline_depth: usize,
+    /// Append one JSON line per finished or failed transfer (sender, root, timings, verdict)
+    #[arg(long)]
+    transfer_log: Option<String>,
+    /// Record every received frame of each session to `<dir>/*.session.jsonl` for `replay`
+    #[arg(long)]
+    record: Option<String>,
+    /// Serve `/status` (JSON) and `/metrics` (Prometheus text) over HTTP on this port
+    #[arg(long)]
+    status_port: Option<u16>,
+    /// Check every invariant a frame can break (fields agreeing with the manifest, index range,
+    /// shard length, zero padding, commitment) and reject violations with a diagnostic naming
+    /// the field and peer, instead of skipping or tolerating them
+    #[arg(long)]
+    strict: bool,
+    /// Sessions served at once; further peers queue after their handshake until a slot frees
+    #[arg(long, default_value_t = 4, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_transfers: usize,
+    /// Seconds a new connection has to complete its handshake before it is dropped
+    #[arg(long, default_value_t = DEFAULT_HANDSHAKE_TIMEOUT, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    handshake_timeout: u64,
+    /// Seconds a session may go without a frame from its peer before it is closed
+    #[arg(long, default_value_t = DEFAULT_IDLE_TIMEOUT, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    idle_timeout: u64,
+    /// Worker threads shared by all sessions. Each takes the oldest job of the most urgent kind
+    /// waiting: shard verification, then reconstruction, then answers to sample requests
+    #[arg(long, default_value_t = 6, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    workers: usize,
+    /// Of `--workers`, how many may verify shards at once. Together with
+    /// `--reconstruct-workers` below `--workers`, some are always free to answer samplers
+    #[arg(long, default_value_t = 3, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    verify_workers: usize,
+    /// Of `--workers`, how many may reconstruct at once. Blobs reaching threshold beyond that
+    /// wait for a worker while their sessions keep reading
+    #[arg(long, default_value_t = 2, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    reconstruct_workers: usize,
+    /// Start rebuilding a root as soon as k of its shards have arrived, before they are
+    /// verified, and keep the result if it hashes back to the root; a failed attempt is retried
+    /// with another k of the shards that arrived. Holds a copy of each shard until the root settles
+    #[arg(long)]
+    speculative: bool,
+    /// Shell command to run on each blob rebuilt from shards, with its stored path and root
+    /// appended as arguments, e.g. to diff it against the original or feed it to analysis
+    #[arg(long)]
+    on_reconstruct: Option<String>,
+    /// Also act as a light client: periodically sample every root these peers serve
+    #[arg(long, value_delimiter = ',')]
+    sample_peers: Vec<String>,
+    /// Seconds between sampling passes over `--sample-peers`
+    #[arg(long, default_value_t = 12, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    sample_interval: u64,
+    /// How roots from `--sample-peers` are sampled
+    #[command(flatten)]
+    sampling: SamplingArgs,
+    /// Re-gossip newly received shards that still have TTL left to these validators, one hop less
+    #[arg(long, value_delimiter = ',')]
+    gossip_peers: Vec<String>,
+    /// Relay each shard to at most this many of `--gossip-peers`, picked at random per shard.
+    /// Defaults to all of them
+    #[arg(long, requires = "gossip_peers", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    gossip_fanout: Option<usize>,
+    /// Seconds a gossiped shard is remembered after it is accepted; copies arriving meanwhile
+    /// are dropped without being verified or relayed again
+    #[arg(long, default_value_t = 120, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    gossip_seen_ttl: u64,
+    /// Periodically compare which shards these validators hold with ours, and exchange the
+    /// missing ones both ways (anti-entropy)
+    #[arg(long, value_delimiter = ',')]
+    sync_peers: Vec<String>,
+    /// Seconds between anti-entropy passes over `--sync-peers`
+    #[arg(long, default_value_t = 30, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    sync_interval: u64,
+    /// Periodically challenge these validators to prove they still hold shards of roots they
+    /// list and we hold too (proof of custody); failures lower their peer score
+    #[arg(long, value_delimiter = ',')]
+    audit_peers: Vec<String>,
+    /// Seconds between audit passes over `--audit-peers`
+    #[arg(long, default_value_t = 60, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    audit_interval: u64,
+    /// Shards of each root challenged per audit pass
+    #[arg(long, default_value_t = 2, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    audit_challenges: usize,
+    /// Throw away this fraction of every rebuilt blob's shards while still listing the root
+    /// (adversarial lazy custody)
+    #[arg(long, default_value_t = 0.0)]
+    lazy_custody: f64,
+    /// Also take sessions put through by this relay (`host:port`), registered under our pubkey,
+    /// for a node that others can't connect to directly
+    #[arg(long)]
+    relay: Option<String>,
+    /// Addresses (`host:port`) to advertise in this node's signed record, preferred first.
+    /// Defaults to `127.0.0.1:<port>`
+    #[arg(long, value_delimiter = ',')]
+    advertise: Vec<String>,
+    /// Column subnets to advertise custody of in the record, picked from the node's ID over
+    /// `--column-subnets` as `mapping` does. Defaults to every column
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    custody: Option<usize>,
+    /// Node records of known peers, one `enr:...` per line: read on start, and updated with every
+    /// newer record a peer presents. Peers in the `--*-peers` lists may then be given by pubkey
+    #[arg(long)]
+    peers_file: Option<String>,
+    /// Ban a peer once its score falls to this (say -30). Bans, scores and peer history are
+    /// kept in `<data-dir>/peers.json` across restarts; banned peers are refused and not dialled
+    #[arg(long, allow_negative_numbers = true)]
+    ban_score: Option<i64>,
+}
+
+/// One `listen --port` value: a single port, or an inclusive range like `8080-8089`.
+#[derive(Copy, Clone, Debug)]
+struct PortRange {
+    first: u16,
+    last: u16,
+}
+
+impl std::str::FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let port = |s: &str| s.trim().parse::<u16>().map_err(|_| format!("expected a port or a range like 8080-8089, got {:?}", spec));
+        let (first, last) = match spec.split_once('-') {
+            Some((first, last)) => (port(first)?, port(last)?),
+            None => (port(spec)?, port(spec)?),
+        };
+        if first > last {
+            return Err(format!("port range {} runs backwards", spec));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+/// One `--tag key=value`.
+#[derive(Clone, Debug)]
+struct Tag {
+    key: String,
+    value: String,
+}
+
+impl std::str::FromStr for Tag {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        match spec.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => Ok(Self { key: key.trim().to_string(), value: value.to_string() }),
+            _ => Err(format!("expected key=value, got {:?}", spec)),
+        }
+    }
+}
+
+impl ListenArgs {
+    /// Every port `--port` names, in order, without repeats.
+    fn ports(&self) -> Vec<u16> {
+        let mut ports: Vec<u16> = Vec::new();
+        for port in self.port.iter().flat_map(|range| range.first..=range.last) {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        ports
+    }
+
+    /// Arguments for the `i`-th of several validators in one process, the one on `port`. It
+    /// gets its own store and output paths under a `<port>` subdirectory (`.<port>` for the
+    /// transfer log), status port `--status-port + i`, and drops itself from its peer lists.
+    fn for_port(&self, i: usize, port: u16) -> Result<Self> {
+        let subdir = |dir: &String| format!("{}/{}", dir, port);
+        let others = |peers: &[String]| peers.iter().filter(|addr| !is_own_addr(addr, port)).cloned().collect();
+        Ok(Self {
+            port: vec![PortRange { first: port, last: port }],
+            data_dir: subdir(&self.data_dir),
+            attest_dir: self.attest_dir.as_ref().map(subdir),
+            shard_dir: self.shard_dir.as_ref().map(subdir),
+            record: self.record.as_ref().map(subdir),
+            transfer_log: self.transfer_log.as_ref().map(|path| format!("{}.{}", path, port)),
+            status_port: match self.status_port {
+                Some(first) => Some(u16::try_from(i).ok().and_then(|i| first.checked_add(i)).context("--status-port range overflows")?),
+                None => None,
+            },
+            sample_peers: others(&self.sample_peers),
+            gossip_peers: others(&self.gossip_peers),
+            sync_peers: others(&self.sync_peers),
+            audit_peers: others(&self.audit_peers),
+            ..self.clone()
+        })
+    }
+}
+
+/// Whether `addr` is this host's own `port`, so a validator can be handed the full peer list
+/// of a multi-port process.
+fn is_own_addr(addr: &str, port: u16) -> bool {
+    addr.rsplit_once(':').is_some_and(|(host, p)| {
+        p.parse() == Ok(port) && matches!(host, "127.0.0.1" | "localhost" | "0.0.0.0" | "[::1]")
+    })
+}
+
+#[derive(Args)]
+struct SendArgs {
+    /// Port to answer sample requests on after dissemination (`--serve-for`)
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+    #[arg(short, long)]
+    peer: String,
+    /// File to send, or a directory whose files are sent together as one pack blob. Repeat to
+    /// send several blobs at once, each on its own stream of the one connection
+    #[arg(short, long, required = true)]
+    file: Vec<String>,
+    #[arg(short, long, value_enum)]
+    mode: ResearchMode,
+    /// Largest frame (JSON line, bytes) to accept; advertised to peers in the handshake
+    #[arg(long, default_value_t = DEFAULT_MAX_FRAME)]
+    max_frame: usize,
+    /// Frame compression to offer in the handshake; each session uses the best codec both
+    /// peers offer (zstd, then lz4), or none
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "zstd,lz4")]
+    compression: Vec<Compression>,
+    /// Where to write the transfer manifest (DAS modes). Defaults to `<file>.manifest.json`
+    #[arg(long)]
+    manifest: Option<String>,
+    #[command(flatten)]
+    layout: Layout,
+    /// Map shard indices to wire indices with a fresh random permutation per blob
+    #[arg(long)]
+    permute: bool,
+    /// Make `--permute` reproducible; mixed with the blob checksum so each blob still differs
+    #[arg(long, requires = "permute")]
+    permutation_seed: Option<u64>,
+    /// Wire indices the proposer refuses to send (adversarial selective withholding)
+    #[arg(long, value_delimiter = ',')]
+    withhold: Vec<usize>,
+    /// With `--matrix`, withhold only these rows of the `--withhold` columns from cell and row
+    /// samples; whole-column requests for those columns are still refused
+    #[arg(long, value_delimiter = ',', requires = "matrix")]
+    withhold_rows: Vec<usize>,
+    /// Commitment scheme sent in the manifest (DAS modes)
+    #[arg(long, value_enum, default_value_t = CommitmentScheme::Merkle)]
+    commitment: CommitmentScheme,
+    /// Which shards `das-sample` sends: the first round of this strategy
+    #[command(flatten)]
+    sampling: SamplingArgs,
+    /// After sending, answer `GetShard`/`GetRoots` for the published root on `--port` for this
+    /// many seconds (DAS modes). 0 exits right away
+    #[arg(long, default_value_t = 0)]
+    serve_for: u64,
+    /// While serving, answer at most this many distinct indices, first come first served, and
+    /// refuse the rest (adversarial selective disclosure)
+    #[arg(long, requires = "serve_for")]
+    disclose_limit: Option<usize>,
+    /// Misbehave on the wire after the handshake, to exercise the receiver's idempotence and
+    /// ordering assumptions
+    #[command(flatten)]
+    faults: Faults,
+    /// Blob base fee (wei per unit of blob gas) to price the payload at
+    #[arg(long, default_value_t = MIN_BASE_FEE_PER_BLOB_GAS)]
+    blob_base_fee: u64,
+    /// Pace shards at a fixed rate instead of bursting them (DAS modes): `N/s` shards per
+    /// second, or `NKB/s` / `NMB/s` on the wire
+    #[arg(long)]
+    send_rate: Option<SendRate>,
+    /// Reach `--peer` through this SOCKS5 proxy (`socks5://host:port`); the peer's host name is
+    /// resolved by the proxy, so Tor `.onion` addresses work
+    #[arg(long)]
+    proxy: Option<Socks5Proxy>,
+    /// Reach `--peer` through this relay (`host:port`) instead of directly. `--peer` is then the
+    /// node's hex pubkey, as it registered with `listen --relay`, and its handshake must prove it
+    #[arg(long)]
+    relay: Option<String>,
+    /// Let validators re-gossip each shard to their `--gossip-peers`, up to this many hops
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=MAX_GOSSIP_TTL as i64))]
+    gossip_ttl: Option<u8>,
+    /// Batch every `--file` into one block matrix (DAS modes): each blob is a row, and a column
+    /// code across the blobs doubles the rows, so each shard is a column covering every blob
+    #[arg(long)]
+    matrix: bool,
+    /// Confidence a light client should reach when `--matrix` reports sampling costs
+    #[arg(long, default_value_t = 0.99, requires = "matrix")]
+    target_confidence: f64,
+    /// Bytes per window: a file over this is sent as independent blobs of one window each, read
+    /// as they go, so memory stays bounded whatever the file's size
+    #[arg(long, default_value_t = DEFAULT_WINDOW, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    window: usize,
+    #[command(flatten)]
+    anchoring: Anchoring,
+}
+
+/// Where `send` anchors the blobs it delivered, when `--anchor-rpc` is given.
+#[derive(Args, Clone, Debug)]
+#[command(about = None, long_about = None)]
+struct Anchoring {
+    /// After the transfer, post each blob's root and versioned hashes (KZG) as calldata through
+    /// this Ethereum JSON-RPC endpoint (`http://host:port`), one transaction per blob. The node
+    /// signs, so it must hold an unlocked account, as dev chains do
+    #[arg(long)]
+    anchor_rpc: Option<RpcEndpoint>,
+    /// Account to send from; the endpoint's first account by default
+    #[arg(long, requires = "anchor_rpc")]
+    anchor_from: Option<String>,
+    /// Contract to send to, such as a test contract on a devnet; the sender itself by default
+    #[arg(long, requires = "anchor_rpc")]
+    anchor_to: Option<String>,
+    /// Function selector (4 hex bytes) to put before the 32-byte words, so the calldata calls a
+    /// contract function taking one `bytes32` per word
+    #[arg(long, requires = "anchor_rpc", value_parser = parse_selector)]
+    anchor_selector: Option<[u8; 4]>,
+    /// Seconds to wait for each transaction to be mined; 0 moves on once it is sent
+    #[arg(long, default_value_t = 30)]
+    anchor_wait: u64,
+}
+
+#[derive(Args)]
+struct GenVectorsArgs {
+    /// Seeds to derive input data from; one vector per seed, size, code and scheme
+    #[arg(long, value_delimiter = ',', default_value = "0,1")]
+    seed: Vec<u64>,
+    /// Input sizes in bytes
+    #[arg(long, value_delimiter = ',', default_value = "1,1000,10000")]
+    size: Vec<usize>,
+    /// Data shard counts (k)
+    #[arg(short = 'k', long = "k", value_delimiter = ',', default_values_t = [DATA_SHARDS])]
+    data_shards: Vec<usize>,
+    /// Parity shard counts (m)
+    #[arg(short = 'm', long = "m", value_delimiter = ',', default_values_t = [PARITY_SHARDS])]
+    parity_shards: Vec<usize>,
+    #[command(flatten)]
+    layout: Layout,
+    /// Commitment schemes
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "sha256,merkle,kzg")]
+    commitment: Vec<CommitmentScheme>,
+    #[arg(short, long, default_value = "vectors.json")]
+    out: String,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Blob sizes in bytes
+    #[arg(long, value_delimiter = ',', default_value = "131072,1048576,16777216")]
+    size: Vec<usize>,
+    #[arg(short = 'k', long = "k", default_value_t = DATA_SHARDS)]
+    data_shards: usize,
+    #[arg(short = 'm', long = "m", default_value_t = PARITY_SHARDS)]
+    parity_shards: usize,
+    #[command(flatten)]
+    layout: Layout,
+    /// Timed runs per size; throughput is over their total
+    #[arg(long, default_value_t = 5, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    iterations: usize,
+}
+
+#[derive(Args)]
+struct SampleArgs {
+    #[arg(short, long)]
+    peer: String,
+    /// Root to sample; taken from `--manifest` when omitted
+    #[arg(long, required_unless_present = "manifest")]
+    root: Option<String>,
+    /// Manifest to check sampled shards against; without one any returned shard counts
+    #[arg(long)]
+    manifest: Option<String>,
+    /// Largest frame (JSON line, bytes) to accept; advertised to peers in the handshake
+    #[arg(long, default_value_t = DEFAULT_MAX_FRAME)]
+    max_frame: usize,
+    /// Frame compression to offer in the handshake; each session uses the best codec both
+    /// peers offer (zstd, then lz4), or none
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "zstd,lz4")]
+    compression: Vec<Compression>,
+    #[command(flatten)]
+    sampling: SamplingArgs,
+    /// What each sample of a block matrix (`send --matrix`) fetches: one cell, a whole row, or a
+    /// whole column (shard). Cells and rows need the matrix's `--manifest`
+    #[arg(long, value_enum, default_value_t = SampleUnit::Columns)]
+    unit: SampleUnit,
+    /// Light clients to run at once, each with a fresh identity, session and random choices
+    #[arg(long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    clients: usize,
+    /// Reach `--peer` through this SOCKS5 proxy (`socks5://host:port`); the peer's host name is
+    /// resolved by the proxy, so Tor `.onion` addresses work
+    #[arg(long)]
+    proxy: Option<Socks5Proxy>,
+    /// Reach `--peer` through this relay (`host:port`) instead of directly. `--peer` is then the
+    /// node's hex pubkey, as it registered with `listen --relay`, and its handshake must prove it
+    #[arg(long)]
+    relay: Option<String>,
+    /// Seconds from the start by which sampling must be done. Samples still unanswered then
+    /// count as failed, and a root not found available by then is declared unavailable: a signed
+    /// negative attestation is logged and the exit code is 7
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..))]
+    deadline: Option<u64>,
+    /// When the root is found unavailable, write `<root>.evidence.json` here: the manifest, the
+    /// samples, and every frame of the session as each side signed it
+    #[arg(long)]
+    evidence_dir: Option<String>,
+}
+
+#[derive(Args)]
+struct FetchArgs {
+    /// Serving nodes to request shards from, in order, until k have passed (repeatable)
+    #[arg(short, long = "peer", value_delimiter = ',', required = true)]
+    peers: Vec<String>,
+    /// Root to fetch; taken from `--manifest` when omitted
+    #[arg(long, required_unless_present = "manifest")]
+    root: Option<String>,
+    /// Manifest to check every shard against; without one, shards need only agree with the
+    /// first that came back, and the blob with the checksum it carried
+    #[arg(long)]
+    manifest: Option<String>,
+    /// Where to write the blob; defaults to the manifest's file name, or else the root, in the
+    /// current directory. Never a name a peer sent, and never over an existing file
+    #[arg(short, long)]
+    out: Option<String>,
+    /// Largest frame (JSON line, bytes) to accept; advertised to peers in the handshake
+    #[arg(long, default_value_t = DEFAULT_MAX_FRAME)]
+    max_frame: usize,
+    /// Frame compression to offer in the handshake; each session uses the best codec both
+    /// peers offer (zstd, then lz4), or none
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "zstd,lz4")]
+    compression: Vec<Compression>,
+    /// Reach the peers through this SOCKS5 proxy (`socks5://host:port`)
+    #[arg(long)]
+    proxy: Option<Socks5Proxy>,
+    /// Reach the peers through this relay (`host:port`); each peer is then a node's hex pubkey
+    #[arg(long)]
+    relay: Option<String>,
+    /// Shell command to run on the rebuilt blob, with its path and root appended as arguments
+    #[arg(long)]
+    on_reconstruct: Option<String>,
+}
+
+// HELPER FUNCTIONS
+fn unix_now() -> u64 {
+    clock().unix().as_secs()
+}
+
+/// Milliseconds since `published_ms` (Unix milliseconds, as stamped on a manifest).
+fn since_published(published_ms: u64) -> f64 {
+    (clock().unix().as_secs_f64() * 1000.0 - published_ms as f64).max(0.0)
+}
+
+fn format_bytes(n: usize) -> String {
+    if n < 1024 { return format!("{} B", n); }
+    if n < 1024 * 1024 { return format!("{:.2} KB", n as f64 / 1024.0); }
+    format!("{:.2} MB", n as f64 / 1024.0 / 1024.0)
+}
+
+// MAIN
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Cli::parse();
+    QUIET.store(args.quiet, Ordering::Relaxed);
+    let _ = MAX_CLOCK_SKEW.set(Duration::from_secs(args.max_clock_skew));
+    let _ = ROTATION.set(args.rotation);
+    let id = match &args.key_file {
+        Some(path) => Identity::load_or_create(path)?,
+        None => Identity::generate(),
+    };
+    if let Some(path) = &args.event_log {
+        open_event_log(path, &id)?;
+    }
+    if let Some(webhook) = &args.alert_webhook {
+        let _ = ALERT_WEBHOOK.set((hex::encode(id.public.as_bytes()), webhook.clone()));
+    }
+    let _ = RUN_TAGS.set(RunTags {
+        experiment: args.experiment_id.clone(),
+        tags: args.tags.iter().map(|t| (t.key.clone(), t.value.clone())).collect(),
+    });
+    if let Some(path) = &args.results {
+        let _ = RESULTS.set(path.clone());
+    }
+    
+    say!("\n{}", "=== Ethereum DAS Research Prototype ===".bold().white().on_blue());
+    if let Some(path) = &args.trusted_setup {
+        let setup = if path == "minimal" { KzgSetup::minimal() } else { KzgSetup::load(path)? };
+        say!("Trusted setup: {} ({} G1 powers)", path, setup.size());
+        kzg::install_setup(setup);
+    }
+
+    let status = match args.command {
+        Commands::Listen(listen) => run_validator(listen, id, args.key_file.as_deref()).await?,
+        Commands::Send(send) => run_proposer(send, id).await?,
+        Commands::Sample(sample) => run_light_client(sample, id).await?,
+        Commands::Fetch(fetch) => run_fetch(fetch, id).await?,
+        Commands::Proxy(proxy) => run_proxy(proxy).await?,
+        Commands::Relay(relay) => run_relay(relay).await?,
+        Commands::Replay { file, store, listen } => run_replay(&file, store, listen, id).await?,
+        Commands::Encode { file, out_dir, layout, commitment, window } => run_encode(&file, &out_dir, layout, commitment, window, &id)?,
+        Commands::ImportSidecars { file, out_dir, layout, commitment } => run_import_sidecars(&file, &out_dir, layout, commitment, &id)?,
+        Commands::Pack { dir, out } => {
+            std::fs::write(&out, load_payload(&dir)?.1)?;
+            ExitStatus::Success
+        }
+        Commands::Unpack { file } => {
+            match file.strip_suffix(MATRIX_SUFFIX) {
+                Some(dir) => extract_matrix(&file, dir)?,
+                None => extract_pack(&file, file.strip_suffix(PACK_SUFFIX).unwrap_or(&file))?,
+            }
+            ExitStatus::Success
+        }
+        Commands::Repair { dir, index, root } => run_repair(&dir, index, root)?,
+        Commands::ExportCar { dir, out, root } => run_export_car(&dir, &out, root)?,
+        Commands::ImportCar { file, out_dir } => run_import_car(&file, &out_dir)?,
+        Commands::Gc { data_dir, shard_dir, keep_days, keep_roots } => {
+            run_gc(&data_dir, shard_dir.as_deref(), keep_days, keep_roots.as_deref())?
+        }
+        Commands::Peers { data_dir, show_scores, ban, reason, unban } => run_peers(&data_dir, show_scores, ban, &reason, unban)?,
+        Commands::Inspect { files } => run_inspect(&files)?,
+        Commands::Mapping { column_subnets, custody, nodes } => run_mapping(column_subnets, custody, &nodes)?,
+        Commands::VerifyAttestation { file, pubkey } => run_verify_attestation(&file, pubkey)?,
+        Commands::Enr { action: EnrAction::Encode { addrs, custody, column_subnets, caps, seq, peers_file } } => {
+            if args.key_file.is_none() {
+                bail!("enr encode signs with the node's key; give its --key-file");
+            }
+            let caps = if caps.is_empty() { validator_caps(&[Compression::Zstd, Compression::Lz4]) } else { caps };
+            let record = NodeRecord::new(&id, seq.unwrap_or_else(|| clock().unix().as_millis() as u64), addrs, custody_columns(&id, column_subnets, custody), caps);
+            run_enr_encode(record, peers_file.as_deref())?
+        }
+        Commands::Enr { action: EnrAction::Decode { records, from } } => run_enr_decode(records, from.as_deref())?,
+        Commands::Bench(bench) => run_bench(&bench)?,
+        Commands::GenVectors(vectors) => run_gen_vectors(&vectors, args.trusted_setup.as_deref().unwrap_or(INSECURE_SETUP))?,
+        Commands::Report { from } => run_report(&from)?,
+        Commands::CompareRuns { from, experiments, role, group_by, threshold } => run_compare_runs(&from, experiments, role, group_by, threshold)?,
+        Commands::DiffMetrics { a, b, from, role } => run_diff_metrics(&a, &b, from.as_deref(), role)?,
+        Commands::Simulate { topology, compare, config } => run_simulate(topology.as_deref(), compare, config)?,
+    };
+    flush_in_flight().await;
+    if status != ExitStatus::Success {
+        std::process::exit(status as i32);
+    }
+    Ok(())
+}
+
+// VALIDATOR (RECEIVER)
+/// Receive-side state shared across connections, keyed by blob root.
+#[derive(Default)]
+struct ValidatorState {
+    shards: HashMap<String, StreamingDecoder>,
+    manifests: HashMap<String, Manifest>,
+    /// Roots this node has reconstructed and re-encoded, so it can serve every index.
+    serving: HashMap<String, ServedBlob>,
+    /// Bytes of buffered, not-yet-verified shards per peer pubkey.
+    quota_usage: HashMap<String, usize>,
+    /// Lifetime traffic per peer pubkey, for the status interface.
+    peers: HashMap<String, PeerTraffic>,
+    /// Latest verdict on each root sampled from `--sample-peers`.
+    sampled: HashMap<String, Verdict>,
+    /// Gossiped shards accepted recently, so copies arriving over other paths are dropped.
+    seen: SeenCache,
+    /// Relayed shards lost because a gossip peer was down or could not keep up.
+    relay_drops: usize,
+    /// Times each supervised task died and was restarted, by task name.
+    restarts: BTreeMap<String, usize>,
+    /// The newest verified record of each known peer, by pubkey.
+    records: HashMap<String, NodeRecord>,
+    /// Peers refused on handshake and never dialled, by pubkey.
+    banned: BTreeMap<String, Ban>,
+    /// Roots at threshold whose reconstruction is queued or running; shards arriving for them
+    /// meanwhile add nothing.
+    rebuilding: HashSet<String>,
+    /// Sessions with an unfinished transfer of each root. A root none is sending any more
+    /// keeps no buffered shards.
+    transfers: HashMap<String, usize>,
+}
+
+impl ValidatorState {
+    /// One session fewer is sending `root`. Once none is, its buffered shards are dropped and
+    /// their bytes returned to the senders' quotas.
+    fn close_transfer(&mut self, root: &str) {
+        let Some(open) = self.transfers.get_mut(root) else { return };
+        *open -= 1;
+        if *open > 0 {
+            return;
+        }
+        self.transfers.remove(root);
+        if let Some(mut decoder) = self.shards.remove(root) {
+            decoder.discard_output();
+            release_quota(&mut self.quota_usage, &mut decoder);
+        }
+    }
+
+    /// Drops buffered roots that no session is sending and that are at least `age` old, such as
+    /// those restored from a checkpoint whose sender never came back. Returns how many.
+    fn expire_abandoned(&mut self, age: Duration) -> usize {
+        let abandoned: Vec<String> = self.shards.iter()
+            .filter(|(root, decoder)| !self.transfers.contains_key(*root) && decoder.elapsed() >= age)
+            .map(|(root, _)| root.clone())
+            .collect();
+        for root in &abandoned {
+            if let Some(mut decoder) = self.shards.remove(root) {
+                decoder.discard_output();
+                release_quota(&mut self.quota_usage, &mut decoder);
+            }
+        }
+        abandoned.len()
+    }
+
+    /// Wire indices held for `root`: every index once rebuilt, else the shards buffered so far.
+    fn holdings(&self, root: &str) -> Vec<usize> {
+        if let Some(blob) = self.serving.get(root) {
+            return (0..TOTAL_SHARDS).map(|i| blob.layout.wire_index(i)).collect();
+        }
+        match (self.shards.get(root), self.manifests.get(root)) {
+            (Some(decoder), Some(manifest)) => decoder.indices().map(|i| manifest.layout.wire_index(i)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// A held shard's bytes, by wire index.
+    fn shard_data(&self, root: &str, wire_index: usize) -> Option<Cow<'_, [u8]>> {
+        if let Some(blob) = self.serving.get(root) {
+            return blob.shard(wire_index).map(Cow::Borrowed);
+        }
+        let manifest = self.manifests.get(root)?;
+        self.shards.get(root)?.shard(manifest.layout.shard_index(wire_index)?)
+    }
+
+    /// A held shard as a frame. Buffered shards need the manifest for the fields they repeat.
+    fn shard_message(&self, root: &str, wire_index: usize) -> Option<P2PMessage> {
+        if let Some(blob) = self.serving.get(root) {
+            return blob.shard_message(root, wire_index);
+        }
+        let manifest = self.manifests.get(root)?;
+        let data = self.shards.get(root)?.shard(manifest.layout.shard_index(wire_index)?)?;
+        Some(P2PMessage::DasShard {
+            filename: manifest.filename.clone(),
+            root: root.to_string(),
+            layout: manifest.layout,
+            original_len: manifest.original_len,
+            index: wire_index,
+            data: data.into_owned(),
+            full_file_checksum: manifest.checksum.clone(),
+            ttl: None,
+            trace: None,
+        })
+    }
+}
+
+/// Traffic exchanged with one peer over the validator's lifetime.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+struct PeerTraffic {
+    sessions: usize,
+    bytes_received: usize,
+    bytes_sent: usize,
+    shards_received: usize,
+    quota_drops: usize,
+    /// Frames rejected as malformed or invalid
+    protocol_errors: usize,
+    /// Shards this node re-gossiped to the peer
+    shards_relayed: usize,
+    /// Gossiped shards from the peer dropped as already seen
+    gossip_duplicates: usize,
+    /// Shards this node pulled from the peer during anti-entropy
+    shards_synced: usize,
+    /// Shards from the peer that went into a rebuild matching its checksum
+    shards_rebuilt: usize,
+    /// Shards from the peer that went into a rebuild that failed its checksum
+    shards_corrupted: usize,
+    /// Peer's clock minus ours at the latest handshake, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clock_offset_ms: Option<i64>,
+    /// Custody challenges this node put to the peer that it answered correctly
+    custody_passed: usize,
+    /// Custody challenges it answered wrongly, with `NotFound`, or not at all
+    custody_failed: usize,
+    /// Standing from custody audits: `+1` per pass, `-AUDIT_PENALTY` per failure. Below
+    /// `--ban-score`, the peer is banned
+    score: i64,
+}
+
+/// Default for `listen --peer-quota`.
+const DEFAULT_PEER_QUOTA: usize = 64 * 1024 * 1024;
+/// Default for `listen --abandon-after`, in seconds.
+const DEFAULT_ABANDON_AFTER: u64 = 600;
+/// Default for `listen --pipeline-depth`.
+const DEFAULT_PIPELINE_DEPTH: usize = 16;
+const DEFAULT_HANDSHAKE_TIMEOUT: u64 = 10;
+const DEFAULT_IDLE_TIMEOUT: u64 = 120;
+
+/// Returns a decoder's buffered bytes to the quotas of the peers that sent them.
+fn release_quota(usage: &mut HashMap<String, usize>, decoder: &mut StreamingDecoder) {
+    for (peer, bytes) in decoder.charged.drain() {
+        if let Some(used) = usage.get_mut(&peer) {
+            *used = used.saturating_sub(bytes);
+        }
+    }
+}
+
+/// Transfers a session has open, each counted in [`ValidatorState::transfers`] while it is,
+/// and the roots whose rebuild it started. However the session ends, dropping this closes what
+/// is still open and unblocks roots whose rebuild never came back.
+struct SessionTransfers {
+    state: Arc<Mutex<ValidatorState>>,
+    /// (stream, root, filename) of transfers that have no verdict yet
+    pending: Vec<(u32, String, String)>,
+    /// Roots this session put in [`ValidatorState::rebuilding`]
+    rebuilding: HashSet<String>,
+}
+
+impl SessionTransfers {
+    fn new(state: Arc<Mutex<ValidatorState>>) -> Self {
+        Self { state, pending: Vec::new(), rebuilding: HashSet::new() }
+    }
+
+    fn start_rebuild(&mut self, state: &mut ValidatorState, root: &str) {
+        state.rebuilding.insert(root.to_string());
+        self.rebuilding.insert(root.to_string());
+    }
+
+    fn end_rebuild(&mut self, state: &mut ValidatorState, root: &str) {
+        if self.rebuilding.remove(root) {
+            state.rebuilding.remove(root);
+        }
+    }
+
+    fn is_open(&self, stream: u32, root: &str) -> bool {
+        self.pending.iter().any(|(s, r, _)| *s == stream && r == root)
+    }
+
+    fn open(&mut self, state: &mut ValidatorState, stream: u32, root: &str, filename: &str) {
+        if self.is_open(stream, root) {
+            return;
+        }
+        if !self.pending.iter().any(|(_, r, _)| r == root) {
+            *state.transfers.entry(root.to_string()).or_default() += 1;
+        }
+        self.pending.push((stream, root.to_string(), filename.to_string()));
+    }
+
+    /// Ends the transfer of `root` on `stream`, or on every stream; the session stops counting
+    /// as a sender of the root once none of its streams carries it.
+    fn close(&mut self, state: &mut ValidatorState, stream: Option<u32>, root: &str) {
+        let was_open = self.pending.iter().any(|(_, r, _)| r == root);
+        self.pending.retain(|(s, r, _)| !(r == root && stream.is_none_or(|stream| stream == *s)));
+        if was_open && !self.pending.iter().any(|(_, r, _)| r == root) {
+            state.close_transfer(root);
+        }
+    }
+}
+
+impl Drop for SessionTransfers {
+    fn drop(&mut self) {
+        let state = self.state.clone();
+        let mut state = state.locked();
+        while let Some((_, root, _)) = self.pending.first().cloned() {
+            self.close(&mut state, None, &root);
+        }
+        for root in self.rebuilding.drain() {
+            state.rebuilding.remove(&root);
+        }
+    }
+}
+
+/// A received shard on its way through verification, with what the checks found.
+struct VerifiedShard {
+    stream: u32,
+    filename: String,
+    root: String,
+    layout: Layout,
+    original_len: usize,
+    /// Index on the wire, and the shard index it maps to
+    wire: usize,
+    index: usize,
+    data: Vec<u8>,
+    checksum: String,
+    ttl: Option<u8>,
+    trace: Option<String>,
+    msg_id: Option<String>,
+    /// Frame bytes
+    bytes: usize,
+    /// SHA-256 of a gossiped shard, for the seen cache
+    digest: Option<String>,
+    /// The `--strict` checks against the manifest, when run
+    checked: Option<Result<(), CodecError>>,
+    /// Whether the shard opens the manifest's commitment, when the scheme can tell
+    opened: Option<bool>,
+}
+
+/// A root that reached threshold, handed from the receive loop to a reconstruction worker
+/// and back with the worker's results filled in.
+struct Rebuild {
+    /// Peer whose shard reached threshold, and the stream it came on
+    peer: String,
+    stream: u32,
+    filename: String,
+    root: String,
+    layout: Layout,
+    original_len: usize,
+    checksum: String,
+    received: usize,
+    threshold_ms: f64,
+    /// When the manifest was published, and how long after it threshold was reached
+    published: Option<u64>,
+    confidence_latency_ms: Option<f64>,
+    decoder: StreamingDecoder,
+    /// First nonzero padding byte, when `--strict` checked and found one
+    padding: Option<usize>,
+    matched: bool,
+    reconstruct_ms: f64,
+    reconstruction_latency_ms: Option<f64>,
+    /// The full extended set, when it hashes back to the root; never for spilled blobs
+    extended: Option<Vec<Vec<u8>>>,
+    /// Where a matching blob was stored, and why its pack failed to unpack if it did
+    stored: Option<String>,
+    bad_pack: Option<String>,
+}
+
+impl Rebuild {
+    /// Decodes and hashes the blob, then regenerates and hashes the extended set, and stores a
+    /// blob that matched in `store`. Blocking.
+    fn run(mut self, strict: bool, store: &BlobStore) -> Result<Self> {
+        let start = clock().now();
+        // Strict: padding that isn't zero fails the blob even if its checksum matches.
+        self.padding = if strict { self.decoder.nonzero_padding()? } else { None };
+        if self.padding.is_some() {
+            self.decoder.discard_output();
+        }
+        self.matched = self.padding.is_none() && self.decoder.finish(&self.checksum)?;
+        self.reconstruct_ms = clock().elapsed(start).as_secs_f64() * 1000.0;
+        self.reconstruction_latency_ms = self.published.filter(|_| self.matched).map(since_published);
+        if self.matched && !self.decoder.is_spilled() {
+            let extended = self.decoder.extended_shards()?;
+            let hashes: Vec<String> = extended.iter().map(|s| calculate_sha256(s)).collect();
+            self.extended = (merkle_root(&hashes) == self.root).then_some(extended);
+        }
+        if self.matched {
+            let path = store.commit(&self.filename, &self.checksum, &self.root, &self.decoder.path)?;
+            // The blob matched its checksum, but the pack inside can still be malformed; that
+            // fails this file, not the session.
+            if is_bundle(&self.filename) && let Err(e) = store.extract(&self.filename, &path) {
+                self.bad_pack = Some(format!("{:#}", e));
+            } else if let Some((file, _, count)) = window_of(&self.filename) {
+                store.join_windows(file, count)?;
+            }
+            self.stored = Some(path);
+        }
+        Ok(self)
+    }
+
+    /// Drops a rebuild that won't be settled, with its staged output.
+    fn discard(mut self) {
+        self.decoder.discard_output();
+        let _ = std::fs::remove_file(&self.decoder.path);
+    }
+}
+
+/// Rebuilds a session has handed to workers, by root and filename, with the shard subset of a
+/// speculative one.
+type Rebuilds = tokio::task::JoinSet<(String, String, Option<Vec<usize>>, Result<Rebuild>)>;
+
+/// Shards of a root as they arrived on a session, before verification, for `--speculative`
+/// rebuilds. Dropped once the root settles either way, or with the session, which returns the
+/// copies' bytes to the peer's quota.
+struct Speculation {
+    state: Arc<Mutex<ValidatorState>>,
+    /// The session's peer, and the stream the shards came on
+    peer: String,
+    stream: u32,
+    filename: String,
+    layout: Layout,
+    original_len: usize,
+    checksum: String,
+    /// When the first shard arrived, on the clock
+    started: Duration,
+    shards: BTreeMap<usize, Vec<u8>>,
+    /// Bytes of `shards` counted against the peer's quota, as the verified copies are
+    charged: usize,
+    /// Subsets rebuilt so far, and whether one is running
+    tried: Vec<Vec<usize>>,
+    running: bool,
+    /// The verified rebuild's mismatch, held back while another subset may still rebuild the
+    /// root; settled as it stands once none can
+    failed: Option<Rebuild>,
+}
+
+impl Drop for Speculation {
+    fn drop(&mut self) {
+        if let Some(used) = self.state.locked().quota_usage.get_mut(&self.peer) {
+            *used = used.saturating_sub(self.charged);
+        }
+    }
+}
+
+impl Speculation {
+    /// The first k of the arrived shards, by index, that no rebuild has tried yet.
+    fn next_subset(&self) -> Option<Vec<usize>> {
+        let held: Vec<usize> = self.shards.keys().copied().collect();
+        if held.len() < DATA_SHARDS {
+            return None;
+        }
+        let mut pick: Vec<usize> = (0..DATA_SHARDS).collect();
+        loop {
+            let subset: Vec<usize> = pick.iter().map(|&p| held[p]).collect();
+            if !self.tried.contains(&subset) {
+                return Some(subset);
+            }
+            let i = (0..DATA_SHARDS).rev().find(|&i| pick[i] < held.len() - DATA_SHARDS + i)?;
+            pick[i] += 1;
+            for j in i + 1..DATA_SHARDS {
+                pick[j] = pick[j - 1] + 1;
+            }
+        }
+    }
+}
+
+/// The full extended shard set of a reconstructed blob.
+struct ServedBlob {
+    filename: String,
+    layout: Layout,
+    original_len: usize,
+    checksum: String,
+    shards: Vec<Vec<u8>>,
+}
+
+impl ServedBlob {
+    /// The shard at a wire index, unless `--lazy-custody` threw it away.
+    fn shard(&self, wire_index: usize) -> Option<&[u8]> {
+        let shard = &self.shards[self.layout.shard_index(wire_index)?];
+        (!shard.is_empty()).then_some(shard.as_slice())
+    }
+
+    fn shard_message(&self, root: &str, wire_index: usize) -> Option<P2PMessage> {
+        Some(P2PMessage::DasShard {
+            filename: self.filename.clone(),
+            root: root.to_string(),
+            layout: self.layout,
+            original_len: self.original_len,
+            index: wire_index,
+            data: self.shard(wire_index)?.to_vec(),
+            full_file_checksum: self.checksum.clone(),
+            ttl: None,
+            trace: None,
+        })
+    }
+}
+
+/// `len` bytes from `offset` of a held shard as a `Range` answer; `None` past its end.
+fn range_message(root: &str, index: usize, shard: &[u8], offset: usize, len: usize) -> Option<P2PMessage> {
+    let data = shard.get(offset..offset.checked_add(len)?)?;
+    Some(P2PMessage::Range { root: root.to_string(), index, offset, data: data.to_vec() })
+}
+
+/// A running validator, shared by its concurrent sessions.
+struct Validator {
+    args: ListenArgs,
+    id: Identity,
+    store: BlobStore,
+    /// Shared with the validator's worker jobs
+    state: Arc<Mutex<ValidatorState>>,
+    started: Duration,
+    /// One outbox per `--gossip-peers` entry
+    gossip: Vec<GossipPeer>,
+    /// `--transfer-log`, shared by concurrent sessions
+    transfer_log: Option<Mutex<RotatingLog>>,
+    /// Whether the accept loop is taking connections, for `/readyz`
+    listening: AtomicBool,
+    /// `--workers`, shared by concurrent sessions
+    jobs: Arc<WorkQueue>,
+}
+
+/// Runs one validator per `--port`. With several, each has its own identity: a fresh key, or
+/// `<key-file>.<port>` when `--key-file` is given.
+async fn run_validator(args: ListenArgs, id: Identity, key_file: Option<&str>) -> Result<ExitStatus> {
+    if !(0.0..=1.0).contains(&args.lazy_custody) {
+        bail!("--lazy-custody is a fraction between 0 and 1");
+    }
+    let ports = args.ports();
+    if let [port] = ports[..] {
+        return run_node(args, port, id).await;
+    }
+    if args.once {
+        bail!("--once serves a single session; give a single --port");
+    }
+    say!("{} {} validators on ports {}", "➜ Validators:".green().bold(), ports.len(),
+        ports.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", "));
+    let mut nodes = tokio::task::JoinSet::new();
+    for (i, &port) in ports.iter().enumerate() {
+        let id = match key_file {
+            Some(path) => Identity::load_or_create(&format!("{}.{}", path, port))?,
+            None => Identity::generate(),
+        };
+        say!("  :{} {}", port, hex::encode(id.public.as_bytes()));
+        nodes.spawn(NODE.scope(hex::encode(id.public.as_bytes()), run_node(args.for_port(i, port)?, port, id)));
+    }
+    // A node that can't start (its port is taken, say) takes the process down with it.
+    while let Some(node) = nodes.join_next().await {
+        node??;
+    }
+    Ok(ExitStatus::Success)
+}
+
+async fn run_node(args: ListenArgs, port: u16, id: Identity) -> Result<ExitStatus> {
+    let store = BlobStore::open(&args.data_dir)?;
+    let record = node_record(&args, port, &id);
+    say!("{} {}", "➜ Node Record:".green().bold(), record);
+    let id = id.with_record(record);
+    let records = match &args.peers_file {
+        Some(path) => read_peers_file(path).with_context(|| format!("Invalid peers file {}", path))?,
+        None => Vec::new(),
+    };
+    let reputation = Reputation::read(&args.data_dir)?;
+    if !reputation.peers.is_empty() || !reputation.banned.is_empty() {
+        say!("{} {} peers, {} banned", "➜ Reputation:".green().bold(), reputation.peers.len(), reputation.banned.len());
+    }
+    let listener = TcpTransport::new(args.max_frame).listen(&format!("0.0.0.0:{}", port)).await?;
+    say!("{} Listening on :{} (store {}, {} concurrent transfers)", "➜ Validator:".green().bold(),
+        port, args.data_dir, args.max_transfers);
+
+    // Sessions beyond `--max-transfers` are accepted but wait here before their handshake.
+    let slots = Arc::new(tokio::sync::Semaphore::new(args.max_transfers));
+    let once = args.once;
+    let status_port = args.status_port;
+    let (gossip, outboxes): (Vec<_>, Vec<_>) = args.gossip_peers.iter().map(|addr| {
+        let (tx, rx) = tokio::sync::mpsc::channel(args.pipeline_depth);
+        (GossipPeer { addr: addr.clone(), tx }, rx)
+    }).unzip();
+    let state = ValidatorState {
+        seen: SeenCache::new(Duration::from_secs(args.gossip_seen_ttl)),
+        records: records.into_iter().map(|r| (r.pubkey.clone(), r)).collect(),
+        peers: reputation.peers.into_iter().collect(),
+        banned: reputation.banned,
+        ..Default::default()
+    };
+    let transfer_log = args.transfer_log.as_deref().map(RotatingLog::open).transpose()?.map(Mutex::new);
+    let jobs = WorkQueue::start(args.workers, [args.verify_workers, args.reconstruct_workers]);
+    let node = Arc::new(Validator { args, id, store, state: Arc::new(Mutex::new(state)), started: clock().now(), gossip, transfer_log, listening: AtomicBool::new(false), jobs });
+    if let Some(interval) = node.args.checkpoint_interval {
+        node.restore()?;
+        spawn_in_node(node.clone().checkpoint_transfers(interval));
+    }
+    spawn_in_node(node.clone().expire_abandoned());
+    if let Some(port) = status_port {
+        let mut listener = Some(TcpListener::bind(format!("0.0.0.0:{}", port)).await?);
+        say!("{} http://0.0.0.0:{}/status", "➜ Status:".green().bold(), port);
+        let status = node.clone();
+        spawn_in_node(node.clone().supervise("status", move || {
+            let (node, listener) = (status.clone(), listener.take());
+            async move {
+                let listener = match listener {
+                    Some(listener) => listener,
+                    None => TcpListener::bind(format!("0.0.0.0:{}", port)).await?,
+                };
+                serve_status(listener, node).await
+            }
+        }));
+    }
+    if !node.args.sample_peers.is_empty() {
+        say!("{} {} every {}s ({:?})", "➜ Light Client:".green().bold(),
+            node.args.sample_peers.join(", "), node.args.sample_interval, node.args.sampling.kind);
+        spawn_in_node(node.clone().sample_peers());
+    }
+    if !node.args.sync_peers.is_empty() {
+        say!("{} {} every {}s", "➜ Anti-Entropy:".green().bold(), node.args.sync_peers.join(", "), node.args.sync_interval);
+        spawn_in_node(node.clone().sync_peers());
+    }
+    if !node.args.audit_peers.is_empty() {
+        say!("{} {} every {}s ({} per root)", "➜ Custody Audits:".green().bold(), node.args.audit_peers.join(", "),
+            node.args.audit_interval, node.args.audit_challenges);
+        spawn_in_node(node.clone().audit_peers());
+    }
+    if !node.gossip.is_empty() {
+        let fanout = node.args.gossip_fanout.map_or("all".to_string(), |n| n.min(node.gossip.len()).to_string());
+        say!("{} {} (fanout {})", "➜ Gossip:".green().bold(), node.args.gossip_peers.join(", "), fanout);
+        for (peer, outbox) in node.gossip.iter().zip(outboxes) {
+            spawn_in_node(node.clone().gossip_to(peer.addr.clone(), outbox));
+        }
+    }
+
+    // Sessions a relay puts through arrive alongside direct connections.
+    let (relayed_tx, relayed) = tokio::sync::mpsc::channel(1);
+    if let Some(relay) = &node.args.relay {
+        let relay = RelayTransport::new(TcpTransport::new(node.args.max_frame), relay.clone()).signed_by(node.id.key.clone());
+        spawn_in_node(accept_relayed(relay, hex::encode(node.id.public.as_bytes()), relayed_tx));
+    }
+    let relayed = Arc::new(tokio::sync::Mutex::new(relayed));
+
+    if once {
+        let status = node.clone().accept_loop(listener, relayed, slots).await;
+        node.save_reputation()?;
+        return status;
+    }
+    spawn_in_node(node.clone().keep_reputation());
+    // The first run keeps the listener bound above, so a taken port still fails the node;
+    // restarts bind the port again.
+    let mut listener = Some(listener);
+    let accept = node.clone();
+    node.supervise("accept", move || {
+        let (node, listener, relayed, slots) = (accept.clone(), listener.take(), relayed.clone(), slots.clone());
+        async move {
+            let listener = match listener {
+                Some(listener) => listener,
+                None => TcpTransport::new(node.args.max_frame).listen(&format!("0.0.0.0:{}", port)).await?,
+            };
+            node.accept_loop(listener, relayed, slots).await.map(|_| ())
+        }
+    }).await;
+    Ok(ExitStatus::Success)
+}
+
+impl Validator {
+    /// Takes direct and relayed connections and starts a session for each, `--max-transfers` at
+    /// a time. Returns the first session's exit status with `--once`, and otherwise only when
+    /// the listener fails.
+    async fn accept_loop(self: Arc<Self>, mut listener: TcpFrameListener, relayed: Arc<tokio::sync::Mutex<tokio::sync::mpsc::Receiver<TcpConnection>>>,
+        slots: Arc<tokio::sync::Semaphore>) -> Result<ExitStatus> {
+        self.listening.store(true, Ordering::Relaxed);
+        let status = loop {
+            let conn = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(conn) => conn,
+                    Err(e) => break Err(e.into()),
+                },
+                Some(conn) = async { relayed.lock().await.recv().await } => conn,
+            };
+            let addr = conn.peer_addr();
+            say!("\n{} Connection from {}", "➜ Network:".blue().bold(), addr);
+            if self.args.once {
+                break self.serve(conn, None).await;
+            }
+            let (node, slots) = (self.clone(), slots.clone());
+            spawn_in_node(async move {
+                if let Err(e) = node.serve(conn, Some(slots)).await {
+                    say!("{} {}: {}", "❌ Session Error:".red(), addr, e);
+                }
+            });
+        };
+        self.listening.store(false, Ordering::Relaxed);
+        status
+    }
+}
+
+/// How long to wait before registering with a relay again after it was unreachable or dropped us.
+const RELAY_RETRY: Duration = Duration::from_secs(5);
+
+/// Keeps this node registered with `relay` as `node`, handing each session it puts through to
+/// `sessions`.
+async fn accept_relayed(relay: RelayTransport, node: String, sessions: tokio::sync::mpsc::Sender<TcpConnection>) {
+    loop {
+        match relay.listen(&node).await {
+            Ok(mut listener) => {
+                say!("{} registered at {} as {}", "➜ Relay:".green().bold(), relay.relay, node);
+                loop {
+                    match listener.accept().await {
+                        Ok(conn) => if sessions.send(conn).await.is_err() {
+                            return;
+                        },
+                        Err(e) => {
+                            say!("{} {}: {}", "❌ Relay Lost:".red(), relay.relay, e);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => say!("{} {}: {}", "❌ Relay Unreachable:".red(), relay.relay, e),
+        }
+        clock().sleep(RELAY_RETRY).await;
+    }
+}
+
+impl Validator {
+    fn traffic(&self, peer: &str, update: impl FnOnce(&mut PeerTraffic)) {
+        update(self.state.locked().peers.entry(peer.to_string()).or_default());
+    }
+
+    /// The handshake, given up after `--handshake-timeout` so a silent peer can't hold on to it.
+    async fn handshake<C: Connection>(&self, conn: &mut C) -> Result<PeerHandshake, HandshakeError> {
+        let secs = self.args.handshake_timeout;
+        tokio::select! {
+            hs = handshake(conn, &self.id, self.args.max_frame, &self.args.compression) => hs,
+            _ = clock().sleep(Duration::from_secs(secs)) => Err(HandshakeError::Timeout { secs }),
+        }
+    }
+
+    /// Socket-reader stage of a session: feeds the peer's frames to `frames`, recording them
+    /// with `recorder`, until the peer closes, a frame fails, or it stays silent for
+    /// `--idle-timeout`.
+    fn read_frames<S>(&self, mut stream: S, frames: tokio::sync::mpsc::Sender<Result<String, TransportError>>, mut recorder: Option<SessionRecorder>, addr: &str) -> tokio::task::JoinHandle<()>
+    where
+        S: futures::Stream<Item = Result<String, TransportError>> + Unpin + Send + 'static,
+    {
+        let (idle, addr) = (Duration::from_secs(self.args.idle_timeout), addr.to_string());
+        tokio::spawn(async move {
+            loop {
+                let next = tokio::select! {
+                    frame = stream.next() => Some(frame),
+                    _ = clock().sleep(idle) => None,
+                };
+                let frame = match next {
+                    Some(Some(frame)) => frame,
+                    Some(None) => break,
+                    None => {
+                        say!("\n{} {} sent nothing for {} s; closing", "❌ Idle Peer:".red(), addr, idle.as_secs());
+                        break;
+                    }
+                };
+                if let Some(recorder) = &mut recorder { recorder.frame(&frame); }
+                let failed = frame.as_ref().is_err_and(|e| !matches!(e, TransportError::Envelope(_)));
+                if frames.send(frame).await.is_err() || failed { break; }
+            }
+        })
+    }
+
+    /// Runs one peer session to completion; the status reflects its worst verdict. With `slots`,
+    /// an authenticated peer waits for one of them before its session starts, so connections
+    /// that never finish a handshake can't hold a slot.
+    async fn serve<C: Connection>(&self, mut conn: C, slots: Option<Arc<tokio::sync::Semaphore>>) -> Result<ExitStatus> {
+        let (max_frame, pipeline_depth) = (self.args.max_frame, self.args.pipeline_depth);
+        let addr = conn.peer_addr();
+
+        let hs = match self.handshake(&mut conn).await {
+            Ok(hs) => hs,
+            Err(e) => {
+                log_event(Event::Handshake { peer: addr.clone(), ok: false, clock_offset_ms: skew_offset(&e) });
+                say!("{} {}", "❌ Auth Failed:".red(), e);
+                return Ok(ExitStatus::AuthFailure);
+            }
+        };
+        let (peer_id, peer_max_frame) = (hex::encode(hs.key.as_bytes()), hs.max_frame);
+        self.traffic(&peer_id, |t| t.clock_offset_ms = Some(hs.clock_offset_ms));
+        log_event(Event::Handshake { peer: peer_id.clone(), ok: true, clock_offset_ms: Some(hs.clock_offset_ms) });
+        if let Some(ban) = self.ban_of(&peer_id) {
+            say!("{} {} ({})", "❌ Banned Peer Refused:".red(), peer_id, ban.reason);
+            return Ok(ExitStatus::AuthFailure);
+        }
+        self.learn_record(hs.record.as_ref());
+        say!("{}", "✓ Session Secured (Ed25519)".green());
+        if hs.compression != Compression::None {
+            say!("Frames: {}-compressed", hs.compression.name());
+        }
+        let _permit = match slots {
+            Some(slots) => Some(match slots.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    say!("{} {} waiting for a transfer slot", "➜ Queued:".yellow(), addr);
+                    slots.acquire_owned().await.expect("semaphore is never closed")
+                }
+            }),
+            None => None,
+        };
+        let conn = peer_connection(conn, &self.id, &hs, max_frame);
+
+        // Session pipeline: receive (socket reader) -> verify/decode (`session`) -> store (shard
+        // file writer). Both hops are bounded, so a slow disk or decoder stops the socket reads
+        // and the transport pushes back on the sender instead of frames piling up in memory.
+        let (sink, stream) = conn.split();
+        let (frame_tx, frames) = tokio::sync::mpsc::channel(pipeline_depth);
+        let recorder = match &self.args.record {
+            Some(dir) => Some(SessionRecorder::create(dir, &peer_id, &addr, peer_max_frame)?),
+            None => None,
+        };
+        let reader = self.read_frames(stream, frame_tx, recorder, &addr);
+        let status = self.session(&peer_id, peer_max_frame, &addr, frames, sink, HashSet::new()).await;
+        reader.abort();
+        status
+    }
+
+    /// Verify/decode stage of a session: consumes frames until the peer is done, then
+    /// reports verdicts. Fed by the socket reader, or by `replay` from a recording.
+    /// `advertised` lists roots whose holdings the peer was already sent (anti-entropy offers).
+    async fn session<S>(&self, peer_id: &str, peer_max_frame: usize, addr: &str, mut frames: FrameReceiver, mut sink: S, mut advertised: HashSet<String>) -> Result<ExitStatus>
+    where
+        S: futures::Sink<String, Error = TransportError> + Unpin,
+    {
+        let ListenArgs { attest_dir, shard_dir, max_frame, peer_quota, pipeline_depth, strict, .. } = &self.args;
+        let (max_frame, peer_quota, pipeline_depth, strict) = (*max_frame, *peer_quota, *pipeline_depth, *strict);
+        let (id, store) = (&self.id, &self.store);
+        let peer_id = peer_id.to_string();
+        self.traffic(&peer_id, |t| t.sessions += 1);
+
+        let mut store_tx = shard_dir.clone().map(|dir| ShardWriter::start(dir, pipeline_depth));
+        
+        let mut bytes_rec = 0;
+        let mut quota_drops = 0;
+        let mut protocol_errors = 0;
+        // Set once a reply fails. Replies are best effort: frames the peer sent before hanging
+        // up are still read and accounted.
+        let mut unreachable = false;
+        let mut violations: Vec<Violation> = Vec::new();
+        let mut answered = 0;
+        let mut outcomes: Vec<FileOutcome> = Vec::new();
+        let mut roots: Vec<RootSummary> = Vec::new();
+        // Transfers from this session that have no verdict yet
+        let mut transfers = SessionTransfers::new(self.state.clone());
+        // Anti-entropy: (root, wire index) requested from the peer and not yet answered, and
+        // whether the peer has handled all of our offers
+        let mut pulling: HashSet<(String, usize)> = HashSet::new();
+        let mut ending = false;
+        // Roots at threshold, each waiting for or running a reconstruction job
+        let mut rebuilds = Rebuilds::new();
+        // `--speculative`: roots whose shards count toward a rebuild as they arrive
+        let mut speculations: HashMap<String, Speculation> = HashMap::new();
+        // Shards in verification, in arrival order; the next one through; and a frame held back
+        // until they are all taken in. `closed` once the peer's frames have ended.
+        let mut verifying: futures::stream::FuturesOrdered<futures::future::BoxFuture<'static, Result<VerifiedShard>>> = Default::default();
+        let mut verified: Option<VerifiedShard> = None;
+        let mut held: Option<(String, u32, P2PMessage)> = None;
+        let mut closed = false;
+        
+        loop {
+            // Strict violations are answered between frames, wherever in a frame they were found.
+            for violation in &violations[answered..] {
+                let json = serde_json::to_string(&P2PMessage::ProtocolError { reason: format!("strict: {}", violation) })?;
+                if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                    say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                    unreachable = true;
+                }
+            }
+            answered = violations.len();
+            if let Some(shard) = verified.take() {
+                let VerifiedShard { stream, filename, root, layout, original_len, wire, index, data, checksum: full_file_checksum, ttl, trace, msg_id, bytes, digest, checked, opened } = shard;
+                let shard_event = |accepted: bool, reason: Option<String>| log_event(Event::ShardReceived {
+                    peer: peer_id.clone(), root: root.clone(), index: wire, bytes, accepted, reason, ttl, msg_id: msg_id.clone(), trace: trace.clone(),
+                });
+                let rejected = |reason: String| shard_event(false, Some(reason));
+                if strict {
+                    let checked = match checked {
+                        Some(checked) => checked.map_err(|e| Violation::from_codec(&peer_id, Some(&root), Some(wire), e)),
+                        None => Err(Violation::new(&peer_id, Some(&root), Some(wire), "root", "no manifest received to check the shard against")),
+                    };
+                    if let Err(violation) = checked {
+                        rejected(format!("strict: invalid {}", violation.field));
+                        violation.report(&mut violations);
+                        continue;
+                    }
+                }
+                match opened {
+                    Some(false) => {
+                        say!("\n{} index {}", "❌ Shard failed manifest commitment check:".red(), index);
+                        rejected(COMMITMENT_MISMATCH.into());
+                        if strict {
+                            Violation::new(&peer_id, Some(&root), Some(wire), "commitment", "shard does not open the manifest's commitment").report(&mut violations);
+                        }
+                        continue;
+                    }
+                    Some(true) => RootSummary::tally(&mut roots, &root, &filename).shards_verified += 1,
+                    None => {}
+                }
+                let (to_store, relay, rebuild) = {
+                    let mut lock = self.state.locked();
+                    let mut to_store = Vec::new();
+                    let mut relay = None;
+                    let mut rebuild = None;
+                    // Rebuilt earlier in this session, or being rebuilt: a late or repeated
+                    // shard adds nothing, and must not open a fresh transfer that would end
+                    // incomplete. Nor once speculation took over from a failed rebuild. A
+                    // gossiped shard adds nothing either once any session here has rebuilt its root.
+                    if outcomes.iter().any(|o| o.root == root && o.verdict == Verdict::Reconstructed)
+                        || lock.rebuilding.contains(&root)
+                        || speculations.get(&root).is_some_and(|s| s.failed.is_some())
+                        || (ttl.is_some() && lock.serving.contains_key(&root)) {
+                        shard_event(true, None);
+                        RootSummary::tally(&mut roots, &root, &filename).accepted += 1;
+                        continue;
+                    }
+                    let used = lock.quota_usage.get(&peer_id).copied().unwrap_or(0);
+                    if used + data.len() > peer_quota {
+                        quota_drops += 1;
+                        lock.peers.entry(peer_id.clone()).or_default().quota_drops += 1;
+                        say!("\n{} {} of {} used, dropping shard {}", "❌ Peer Quota Full:".red(),
+                            format_bytes(used), format_bytes(peer_quota), index);
+                        rejected("peer quota full".into());
+                        continue;
+                    }
+                    let len = data.len();
+                    let spill = self.args.spill_above.is_some_and(|limit| len * DATA_SHARDS > limit);
+                    let staged = match store.transfer_path(&root) {
+                        Ok(staged) => staged,
+                        Err(e) => {
+                            say!("\n{} {}", "❌ Shard rejected:".red(), e);
+                            rejected(e.to_string());
+                            continue;
+                        }
+                    };
+                    let decoder = lock.shards.entry(root.clone()).or_insert_with(|| {
+                        let decoder = StreamingDecoder::new(staged, layout, original_len, len, clock().clone());
+                        if spill { decoder.spill_to_disk() } else { decoder }
+                    });
+                    let fresh = !decoder.has(index);
+                    if let Err(e) = decoder.push(index, data) {
+                        say!("\n{} {}", "❌ Shard rejected:".red(), e);
+                        rejected(e.to_string());
+                        continue;
+                    }
+                    shard_event(true, None);
+                    RootSummary::tally(&mut roots, &root, &filename).accepted += 1;
+                    if store_tx.is_some() && let Some(data) = decoder.shard(index) {
+                        to_store.push(ShardFile { root: root.clone(), index, k: DATA_SHARDS, m: PARITY_SHARDS, layout, original_len, data: data.into_owned() });
+                    }
+                    if fresh {
+                        decoder.senders.insert(index, peer_id.clone());
+                        *decoder.charged.entry(peer_id.clone()).or_default() += len;
+                        *lock.quota_usage.entry(peer_id.clone()).or_default() += len;
+                        lock.peers.entry(peer_id.clone()).or_default().shards_received += 1;
+                    }
+                    // Relayed once per node, however many peers it arrives from.
+                    let first_seen = digest.as_ref().is_some_and(|d| lock.seen.insert(&root, wire, d));
+                    if let Some(ttl) = ttl.filter(|&t| t > 0 && fresh && first_seen && !self.gossip.is_empty())
+                        && let Some(data) = lock.shards[&root].shard(index) {
+                        relay = Some(Relay {
+                            from: peer_id.clone(),
+                            root: root.clone(),
+                            shard: P2PMessage::DasShard {
+                                filename: filename.clone(), root: root.clone(), layout, original_len, index: wire,
+                                data: data.into_owned(), full_file_checksum: full_file_checksum.clone(), ttl: Some(ttl - 1), trace: trace.clone(),
+                            },
+                        });
+                    }
+                    let received = lock.shards[&root].len();
+                    transfers.open(&mut lock, stream, &root, &filename);
+                    
+                    say_inline!("\rDownloading Shards: {}/{} (k={})", received, TOTAL_SHARDS, DATA_SHARDS);
+                    
+                    // Try Reconstruct
+                    if received >= DATA_SHARDS && let Some(mut decoder) = lock.shards.remove(&root) {
+                        release_quota(&mut lock.quota_usage, &mut decoder);
+                        say!("\n{} {}", "➜ Threshold Reached. Reconstructing".yellow(), filename);
+                        transfers.start_rebuild(&mut lock, &root);
+                        let published = lock.manifests.get(&root).and_then(|m| m.published_ms);
+                        // One rebuild settles the root for every stream carrying it.
+                        transfers.close(&mut lock, None, &root);
+                        rebuild = Some(Rebuild {
+                            peer: peer_id.clone(),
+                            stream,
+                            filename: filename.clone(),
+                            root: root.clone(),
+                            layout,
+                            original_len,
+                            checksum: full_file_checksum.clone(),
+                            received,
+                            threshold_ms: decoder.elapsed().as_secs_f64() * 1000.0,
+                            published,
+                            confidence_latency_ms: published.map(since_published),
+                            decoder,
+                            padding: None,
+                            matched: false,
+                            reconstruct_ms: 0.0,
+                            reconstruction_latency_ms: None,
+                            extended: None,
+                            stored: None,
+                            bad_pack: None,
+                        });
+                    }
+                    (to_store, relay, rebuild)
+                };
+                if let Some(relay) = relay {
+                    self.relay(relay);
+                }
+                if let Some(writer) = &mut store_tx {
+                    for file in to_store {
+                        writer.write(self, file).await?;
+                    }
+                }
+                // Reconstruction and hashing run on a worker, so this loop keeps reading
+                // meanwhile; the verdict comes back through `rebuilds`.
+                if let Some(job) = rebuild {
+                    self.start_rebuild(&mut rebuilds, job, None);
+                }
+                continue;
+            }
+            // Shards still in verification come first; the loop ends once they are taken in.
+            if (closed || (ending && pulling.is_empty())) && verifying.is_empty() && held.is_none() { break; }
+            // A frame other than a shard waits until the shards before it are taken in, since
+            // it may depend on them: a `TransferEnd` counts them, a `GetShard` may ask for one.
+            let (line, stream, msg) = match held.take_if(|_| verifying.is_empty()) {
+                Some(held) => held,
+                None => {
+                    let frame = tokio::select! {
+                        Some(done) = rebuilds.join_next() => {
+                            let done = match done {
+                                Ok(done) => done,
+                                Err(e) => {
+                                    say!("\n{} {}", "❌ Reconstruction Failed:".red(), e);
+                                    continue;
+                                }
+                            };
+                            let Some(rebuilt) = self.take_rebuild(&peer_id, done, &mut outcomes, &mut transfers, &mut speculations, &mut rebuilds) else { continue };
+                            let stream = rebuilt.stream;
+                            let (announce, to_store) = self.settle_rebuild(rebuilt, &mut outcomes, &mut violations, store_tx.is_some());
+                            if let Some(writer) = &mut store_tx {
+                                for file in to_store {
+                                    writer.write(self, file).await?;
+                                }
+                            }
+                            if let Some(msg) = announce {
+                                let json = encode_frame(stream, &msg)?;
+                                self.traffic(&peer_id, |t| t.bytes_sent += json.len());
+                                if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                                    say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                                    unreachable = true;
+                                }
+                            }
+                            continue;
+                        }
+                        Some(shard) = verifying.next() => {
+                            verified = Some(shard?);
+                            continue;
+                        }
+                        frame = frames.recv(), if !closed && held.is_none() && verifying.len() < pipeline_depth => frame,
+                    };
+                    let Some(frame) = frame else {
+                        closed = true;
+                        continue;
+                    };
+                    let line = match frame {
+                        Ok(line) => line,
+                        Err(TransportError::OversizeFrame { .. }) => {
+                            say!("\n{} over {} bytes, closing session", "❌ Oversize Frame:".red(), max_frame);
+                            let reason = format!("frame exceeds max_frame of {} bytes", max_frame);
+                            log_event(Event::ProtocolError { peer: peer_id.clone(), reason: reason.clone() });
+                            protocol_errors += 1;
+                            self.traffic(&peer_id, |t| t.protocol_errors += 1);
+                            let _ = sink.send(serde_json::to_string(&P2PMessage::ProtocolError { reason })?).await;
+                            closed = true;
+                            continue;
+                        }
+                        // Only this frame is suspect; the peer's later frames are still its own.
+                        Err(TransportError::Envelope(e)) => {
+                            say!("\n{} {}", "❌ Rejected Envelope:".red(), e);
+                            let reason = e.to_string();
+                            log_event(Event::ProtocolError { peer: peer_id.clone(), reason: reason.clone() });
+                            protocol_errors += 1;
+                            self.traffic(&peer_id, |t| t.protocol_errors += 1);
+                            let json = serde_json::to_string(&P2PMessage::ProtocolError { reason })?;
+                            if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                                say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                                unreachable = true;
+                            }
+                            continue;
+                        }
+                        Err(e) => {
+                            say!("\n{} {}", "❌ Connection Lost:".red(), e);
+                            closed = true;
+                            continue;
+                        }
+                    };
+                    if line.trim().is_empty() { continue; }
+                    bytes_rec += line.len(); 
+                    self.traffic(&peer_id, |t| t.bytes_received += line.len());
+                    let (stream, msg) = match decode_frame(&line) {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            say!("\n{} {}", "❌ Rejected Frame:".red(), e);
+                            let reason = e.to_string();
+                            log_event(Event::ProtocolError { peer: peer_id.clone(), reason: reason.clone() });
+                            protocol_errors += 1;
+                            self.traffic(&peer_id, |t| t.protocol_errors += 1);
+                            let json = serde_json::to_string(&P2PMessage::ProtocolError { reason })?;
+                            if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                                say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                                unreachable = true;
+                            }
+                            continue;
+                        }
+                    };
+                    if !verifying.is_empty() && !matches!(msg, P2PMessage::DasShard { .. }) {
+                        held = Some((line, stream, msg));
+                        continue;
+                    }
+                    (line, stream, msg)
+                }
+            };
+            match msg {
+                P2PMessage::NaiveTransfer { filename, data, checksum } => {
+                    RootSummary::tally(&mut roots, &checksum, &filename).bytes += line.len();
+                    say!("{}", "➜ Receiving Full Blob (Naive)...".yellow());
+                    let verdict = if calculate_sha256(&data) == checksum {
+                        say!("{}", "✓ Integrity Verified".green());
+                        let path = store.put(&filename, &checksum, &data)?;
+                        say!("Stored at {}", path);
+                        if is_bundle(&filename) && let Err(e) = store.extract(&filename, &path) {
+                            say!("{} {}: {:#}", "❌ Bad Pack:".red(), filename, e);
+                            Verdict::Corrupted
+                        } else {
+                            if let Some((file, _, count)) = window_of(&filename) { store.join_windows(file, count)?; }
+                            Verdict::Verified
+                        }
+                    } else {
+                        say!("{}", "❌ Corrupted".red());
+                        Verdict::Corrupted
+                    };
+                    record_outcome(&mut outcomes, &peer_id, FileOutcome { file: filename, root: checksum, shards: 0, verdict, threshold_ms: None, reconstruct_ms: None, confidence_latency_ms: None, reconstruction_latency_ms: None, senders: BTreeMap::new() });
+                }
+                P2PMessage::Manifest(mut manifest) => {
+                    let valid = manifest.root_matches();
+                    let scheme = manifest.commitment.scheme();
+                    log_event(Event::ManifestReceived { peer: peer_id.clone(), root: manifest.root.clone(), scheme, valid, msg_id: event_msg_id(&line) });
+                    if !valid {
+                        say!("{} {}", "❌ Manifest root mismatch, ignoring:".red(), manifest.filename);
+                        if strict {
+                            Violation::new(&peer_id, Some(&manifest.root), None, "root", "not the Merkle root of the manifest's shard hashes").report(&mut violations);
+                        }
+                        continue;
+                    }
+                    RootSummary::tally(&mut roots, &manifest.root, &manifest.filename).bytes += line.len();
+                    say!("{} {} (root {}…, {:?} commitment)", "➜ Manifest:".blue().bold(), manifest.filename, &manifest.root[..16], scheme);
+                    show_versioned_hashes(&manifest.commitment);
+                    // A manifest without a send time counts from its arrival here.
+                    manifest.published_ms.get_or_insert(clock().unix().as_millis() as u64);
+                    self.state.locked().manifests.insert(manifest.root.clone(), manifest);
+                }
+                P2PMessage::DasShard { filename, root, layout, original_len, index, data, full_file_checksum, ttl, trace } => {
+                    let (wire, bytes) = (index, data.len());
+                    if pulling.remove(&(root.clone(), wire)) {
+                        self.traffic(&peer_id, |t| t.shards_synced += 1);
+                    }
+                    let summary = RootSummary::tally(&mut roots, &root, &filename);
+                    summary.shards_received += 1;
+                    summary.bytes += line.len();
+                    if summary.seen.contains(&wire) {
+                        summary.duplicates += 1;
+                    } else {
+                        summary.seen.push(wire);
+                    }
+                    let msg_id = event_msg_id(&line);
+                    let shard_event = |accepted: bool, reason: Option<String>| log_event(Event::ShardReceived {
+                        peer: peer_id.clone(), root: root.clone(), index: wire, bytes, accepted, reason, ttl, msg_id: msg_id.clone(), trace: trace.clone(),
+                    });
+                    let rejected = |reason: String| shard_event(false, Some(reason));
+                    let Some(index) = layout.shard_index(index) else {
+                        say!("\n{} {}", "❌ Wire index out of range:".red(), index);
+                        rejected("wire index out of range".into());
+                        if strict {
+                            Violation::new(&peer_id, Some(&root), Some(wire), "index", format!("maps to no shard of {}", TOTAL_SHARDS)).report(&mut violations);
+                        }
+                        continue;
+                    };
+                    // A gossiped copy of a shard accepted before goes no further: not re-verified,
+                    // re-stored or relayed again.
+                    let digest = ttl.map(|_| calculate_sha256(&data));
+                    if let Some(digest) = &digest && self.state.locked().seen.contains(&root, wire, digest) {
+                        shard_event(true, None);
+                        RootSummary::tally(&mut roots, &root, &filename).accepted += 1;
+                        self.traffic(&peer_id, |t| t.gossip_duplicates += 1);
+                        continue;
+                    }
+                    // Checked on a worker, outside the state lock and while later frames are read: a
+                    // KZG check recomputes the shard's commitment. The shard is taken in at the top
+                    // of the loop once it and every shard before it are through.
+                    let (manifest, commitment) = {
+                        let state = self.state.locked();
+                        let manifest = state.manifests.get(&root);
+                        (manifest.filter(|_| strict).cloned(), manifest.map(|m| m.commitment.clone()))
+                    };
+                    // `--speculative`: the shard also counts toward a rebuild right away. Gossip
+                    // is left to the verified path, since any number of sessions may carry it.
+                    if self.args.speculative && ttl.is_none()
+                        && self.args.spill_above.is_none_or(|limit| data.len() * DATA_SHARDS <= limit)
+                        && !outcomes.iter().any(|o| o.root == root)
+                        && !speculations.get(&root).is_some_and(|s| s.shards.contains_key(&index)) {
+                        // The copy is unverified bytes like the buffered one, so it is charged
+                        // against the same quota; past it, the shard only takes the verified path.
+                        let used = {
+                            let mut lock = self.state.locked();
+                            let used = lock.quota_usage.entry(peer_id.clone()).or_default();
+                            let before = *used;
+                            if before + data.len() <= peer_quota {
+                                *used += data.len();
+                            }
+                            before
+                        };
+                        if used + data.len() > peer_quota {
+                            say!("\n{} {} of {} used, not speculating on shard {}", "❌ Peer Quota Full:".red(),
+                                format_bytes(used), format_bytes(peer_quota), index);
+                        } else {
+                            let speculation = speculations.entry(root.clone()).or_insert_with(|| Speculation {
+                                state: self.state.clone(), peer: peer_id.clone(), stream, filename: filename.clone(), layout, original_len, checksum: full_file_checksum.clone(),
+                                started: clock().now(), shards: BTreeMap::new(), charged: 0, tried: Vec::new(), running: false, failed: None,
+                            });
+                            speculation.charged += data.len();
+                            speculation.shards.insert(index, data.clone());
+                            self.speculate(&root, speculation, &mut rebuilds);
+                        }
+                    }
+                    let shard = VerifiedShard {
+                        stream, filename, root, layout, original_len, wire, index, data, checksum: full_file_checksum, ttl, trace, msg_id, bytes, digest,
+                        checked: None, opened: None,
+                    };
+                    if manifest.is_none() && commitment.is_none() {
+                        verifying.push_back(Box::pin(std::future::ready(Ok(shard))));
+                    } else {
+                        let jobs = self.jobs.clone();
+                        verifying.push_back(Box::pin(async move {
+                            jobs.run(JobKind::Verify, move || {
+                                let mut shard = shard;
+                                shard.checked = manifest.map(|m| m.check_shard(&shard.filename, &shard.layout, shard.original_len, shard.wire, &shard.data, &shard.checksum));
+                                shard.opened = commitment.and_then(|c| c.verify_shard(shard.index, &shard.data));
+                                shard
+                            }).await
+                        }));
+                    }
+                }
+                P2PMessage::HaveShards { root, bitfield } => {
+                    // Anti-entropy: pull what the peer holds and we lack, and advertise ours back
+                    // once when the peer lacks some of it.
+                    let theirs = bitfield_indices(&bitfield);
+                    let (ours, manifest) = {
+                        let state = self.state.locked();
+                        (state.holdings(&root), state.manifests.get(&root).cloned())
+                    };
+                    let wanted: Vec<usize> = theirs.iter().copied()
+                        .filter(|i| !ours.contains(i) && !pulling.contains(&(root.clone(), *i)))
+                        .collect();
+                    log_event(Event::Sync { peer: peer_id.clone(), root: root.clone(), ours: ours.len(), theirs: theirs.len(), pulled: wanted.len() });
+                    if !wanted.is_empty() {
+                        say!("\n{} {} shard(s) of {}… from {}…", "➜ Sync: pulling".blue().bold(), wanted.len(), root.get(..16).unwrap_or(&root), peer_id.get(..16).unwrap_or(&peer_id));
+                    }
+                    let mut replies = Vec::new();
+                    if ours.iter().any(|i| !theirs.contains(i)) && advertised.insert(root.clone()) {
+                        // A peer holding nothing may not have the manifest either.
+                        if theirs.is_empty() && let Some(manifest) = manifest {
+                            replies.push(P2PMessage::Manifest(manifest));
+                        }
+                        replies.push(P2PMessage::HaveShards { root: root.clone(), bitfield: encode_bitfield(ours) });
+                    }
+                    for index in wanted {
+                        pulling.insert((root.clone(), index));
+                        replies.push(P2PMessage::GetShard { root: root.clone(), index });
+                    }
+                    for msg in replies {
+                        let json = encode_frame(stream, &msg)?;
+                        self.traffic(&peer_id, |t| t.bytes_sent += json.len());
+                        if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                            say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                            unreachable = true;
+                        }
+                    }
+                }
+                P2PMessage::NotFound { root, index } => {
+                    pulling.remove(&(root, index));
+                }
+                // Announcements after a peer's rebuild; nothing to act on here.
+                P2PMessage::CanServe { .. } => {}
+                // Only a node that asked gets `Roots`: at the end of a sync it marks that the
+                // peer has answered every offer. Shards still being pulled are waited for.
+                P2PMessage::Roots { .. } => ending = true,
+                P2PMessage::GetShard { root, index } => {
+                    log_event(Event::SampleRequest { peer: peer_id.clone(), root: root.clone(), index });
+                    let (state, key) = (self.state.clone(), root.clone());
+                    let (found, json) = self.jobs.run(JobKind::Serve, move || {
+                        let reply = state.locked().shard_message(&key, index);
+                        (reply.is_some(), encode_frame(stream, &reply.unwrap_or(P2PMessage::NotFound { root: key, index })))
+                    }).await?;
+                    log_event(Event::SampleResponse { peer: peer_id.clone(), root, index, found });
+                    let json = json?;
+                    self.traffic(&peer_id, |t| t.bytes_sent += json.len());
+                    if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                        say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                        unreachable = true;
+                    }
+                }
+                P2PMessage::GetRange { root, index, offset, len } => {
+                    let state = self.state.clone();
+                    let json = self.jobs.run(JobKind::Serve, move || {
+                        let reply = state.locked().shard_data(&root, index).and_then(|shard| range_message(&root, index, &shard, offset, len));
+                        encode_frame(stream, &reply.unwrap_or(P2PMessage::NotFound { root, index }))
+                    }).await??;
+                    self.traffic(&peer_id, |t| t.bytes_sent += json.len());
+                    if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                        say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                        unreachable = true;
+                    }
+                }
+                P2PMessage::CustodyChallenge { root, index, offset, len, nonce } => {
+                    let state = self.state.clone();
+                    let json = self.jobs.run(JobKind::Serve, move || {
+                        let digest = state.locked().shard_data(&root, index).map(|shard| custody_digest(&nonce, &shard, offset, len));
+                        let reply = match digest {
+                            Some(digest) => P2PMessage::CustodyProof { root, index, nonce, digest },
+                            None => P2PMessage::NotFound { root, index },
+                        };
+                        encode_frame(stream, &reply)
+                    }).await??;
+                    self.traffic(&peer_id, |t| t.bytes_sent += json.len());
+                    if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                        say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                        unreachable = true;
+                    }
+                }
+                P2PMessage::GetRoots {} => {
+                    let mut roots: Vec<String> = self.state.locked().serving.keys().cloned().collect();
+                    roots.sort();
+                    let json = encode_frame(stream, &P2PMessage::Roots { roots })?;
+                    self.traffic(&peer_id, |t| t.bytes_sent += json.len());
+                    if let Err(e) = send_frame(&mut sink, json, peer_max_frame).await && !unreachable {
+                        say!("\n{} {}; still reading what it sent", "❌ Peer Unreachable:".red(), e);
+                        unreachable = true;
+                    }
+                }
+                P2PMessage::TransferEnd { filename, root, shards_sent } => {
+                    if strict && !roots.iter().any(|r| r.root == root) {
+                        Violation::new(&peer_id, Some(&root), None, "root", "TransferEnd for a transfer this session never started").report(&mut violations);
+                        continue;
+                    }
+                    RootSummary::tally(&mut roots, &root, &filename).bytes += line.len();
+                    // Full-node transfers get their verdict at reconstruction time.
+                    if shards_sent >= DATA_SHARDS || !transfers.is_open(stream, &root) { continue; }
+                    // The shards are dropped with the transfer, unless another stream or session
+                    // is still sending the root.
+                    let (received, published) = {
+                        let mut lock = self.state.locked();
+                        let received = lock.shards.get(&root).map_or(0, StreamingDecoder::len);
+                        let published = lock.manifests.get(&root).and_then(|m| m.published_ms);
+                        transfers.close(&mut lock, Some(stream), &root);
+                        (received, published)
+                    };
+
+                    say!("\n\n{}", "=== Light Client Validation ===".bold().blue());
+                    say!("File: {}", filename);
+                    say!("Sampled {}/{} random shards.", received, shards_sent);
+                    let verdict = if received >= shards_sent {
+                        say!("{}", "✓ Data Availability Verified (>99% prob)".green());
+                        Verdict::Sampled
+                    } else {
+                        say!("{}", "❌ Samples Missing: Data Unavailable".red());
+                        Verdict::Unavailable
+                    };
+                    say!("Simulated Bandwidth: {}", format_bytes(bytes_rec).cyan());
+                    let confidence_latency_ms = published.filter(|_| verdict == Verdict::Sampled).map(since_published);
+                    if let Some(ms) = confidence_latency_ms {
+                        say!("Confident {:.1} ms after the header", ms);
+                    }
+                    record_outcome(&mut outcomes, &peer_id, FileOutcome { file: filename, root, shards: received, verdict, threshold_ms: None, reconstruct_ms: None, confidence_latency_ms, reconstruction_latency_ms: None, senders: BTreeMap::new() });
+                }
+                other if strict => {
+                    Violation::new(&peer_id, None, None, "message", format!("{} is not something a validator is sent", other.kind())).report(&mut violations);
+                }
+                _ => {}
+            }
+        }
+
+        // Rebuilds still running settle their transfers before the session's verdicts. The peer
+        // is gone or done, so their announcements are not sent.
+        while let Some(done) = rebuilds.join_next().await {
+            let done = match done {
+                Ok(done) => done,
+                Err(e) => {
+                    say!("\n{} {}", "❌ Reconstruction Failed:".red(), e);
+                    continue;
+                }
+            };
+            let Some(rebuilt) = self.take_rebuild(&peer_id, done, &mut outcomes, &mut transfers, &mut speculations, &mut rebuilds) else { continue };
+            let (_, to_store) = self.settle_rebuild(rebuilt, &mut outcomes, &mut violations, store_tx.is_some());
+            if let Some(writer) = &mut store_tx {
+                for file in to_store {
+                    writer.write(self, file).await?;
+                }
+            }
+        }
+
+        // Speculation ran out of subsets for these; their verified rebuilds' mismatches stand.
+        for failed in speculations.into_values().filter_map(|mut s| s.failed.take()) {
+            self.settle_rebuild(failed, &mut outcomes, &mut violations, false);
+        }
+
+        if let Some(writer) = store_tx {
+            writer.finish(self).await;
+        }
+
+        // Anything still pending was cut off before the sender finished. Its shards go with it,
+        // unless another session is still sending the root.
+        let mut lock = self.state.locked();
+        for (stream, root, filename) in transfers.pending.clone() {
+            let (received, senders) = lock.shards.get(&root).map_or((0, BTreeMap::new()), |d| (d.len(), d.shards_by_sender()));
+            say!("\n{} {} ({} shards)", "❌ Transfer Incomplete:".red(), filename, received);
+            transfers.close(&mut lock, Some(stream), &root);
+            record_outcome(&mut outcomes, &peer_id, FileOutcome { file: filename, root, shards: received, verdict: Verdict::Incomplete, threshold_ms: None, reconstruct_ms: None, confidence_latency_ms: None, reconstruction_latency_ms: None, senders });
+        }
+        drop(lock);
+
+        for summary in &mut roots {
+            summary.verdict = outcomes.iter().rev().find(|o| o.root == summary.root).map(|o| o.verdict);
+            summary.rejected = summary.shards_received - summary.accepted;
+            summary.elapsed_ms = (summary.last_at - summary.first_at).as_secs_f64() * 1000.0;
+            log_event(Event::SessionSummary { peer: peer_id.clone(), summary: summary.clone() });
+        }
+        if protocol_errors > 0 {
+            say!("\n{} {} frame(s) rejected", "❌ Protocol Errors:".red(), protocol_errors);
+        }
+        if !roots.is_empty() {
+            say!("\n{}", "=== Session Summary ===".bold().blue());
+            say!("{:<18} {:<20} {:>5} {:>8} {:>5} {:>8} {:>10} {:>10}  {}", "root", "file", "recv", "verified", "dups", "rejected", "bytes", "elapsed", "verdict");
+            for r in &roots {
+                let verdict = r.verdict.map_or_else(|| "-".to_string(), |v| format!("{:?}", v));
+                say!("{:<18} {:<20} {:>5} {:>8} {:>5} {:>8} {:>10} {:>10}  {}", format!("{}…", r.root.get(..16).unwrap_or(&r.root)), r.file, r.shards_received,
+                    r.shards_verified, r.duplicates, r.rejected, format_bytes(r.bytes), format!("{:.1} ms", r.elapsed_ms), verdict);
+            }
+        }
+
+        if let Some(log) = &self.transfer_log {
+            let mut lines = String::new();
+            for outcome in &outcomes {
+                let record = TransferRecord { logged_at: unix_now(), sender: &peer_id, addr: addr.to_string(), tags: run_tags(), outcome };
+                lines += &serde_json::to_string(&record)?;
+                lines.push('\n');
+            }
+            // One append per session keeps concurrent sessions' lines whole, in one file.
+            log.locked().append(lines.as_bytes())?;
+        }
+
+        if let Some(dir) = attest_dir {
+            for outcome in outcomes.iter().filter(|o| matches!(o.verdict, Verdict::Reconstructed | Verdict::Sampled)) {
+                let path = write_attestation(dir, id, outcome)?;
+                say!("{} {}", "➜ Attestation:".blue().bold(), path);
+                log_event(Event::Attestation { root: outcome.root.clone(), path });
+            }
+        }
+
+        let status = outcomes.iter()
+            .map(|o| o.verdict.exit_status())
+            .find(|s| *s != ExitStatus::Success)
+            .unwrap_or(ExitStatus::Success);
+
+        print_summary(&SessionSummary {
+            role: "validator",
+            node: NODE.try_with(|node| node.clone()).ok(),
+            peer: addr.to_string(),
+            bytes_received: bytes_rec,
+            quota_drops,
+            protocol_errors,
+            files: outcomes,
+            roots,
+            violations,
+        })?;
+        Ok(status)
+    }
+
+    /// Hands `job` to a reconstruction worker; the result comes back through `rebuilds`, with
+    /// `subset` when the rebuild is speculative.
+    fn start_rebuild(&self, rebuilds: &mut Rebuilds, job: Rebuild, subset: Option<Vec<usize>>) {
+        let (jobs, store, root, filename, strict) = (self.jobs.clone(), self.store.clone(), job.root.clone(), job.filename.clone(), self.args.strict);
+        rebuilds.spawn(async move {
+            let rebuilt = jobs.run(JobKind::Reconstruct, move || job.run(strict, &store)).await;
+            (root, filename, subset, rebuilt.and_then(|rebuilt| rebuilt))
+        });
+    }
+
+    /// Starts a speculative rebuild of `root` from the next untried k of its arrived shards,
+    /// unless one is already running or there is no such subset yet.
+    fn speculate(&self, root: &str, speculation: &mut Speculation, rebuilds: &mut Rebuilds) {
+        if speculation.running {
+            return;
+        }
+        while let Some(subset) = speculation.next_subset() {
+            speculation.tried.push(subset.clone());
+            // A staging file per session, so a verified rebuild of the root, or another
+            // session's speculation, can run alongside.
+            let peer = &speculation.peer;
+            let Ok(path) = self.store.staging_path(&format!("{}.speculative-{}", root, peer.get(..16).unwrap_or(peer))) else { return };
+            let shard_len = speculation.shards[&subset[0]].len();
+            let mut decoder = StreamingDecoder::new(path, speculation.layout, speculation.original_len, shard_len, clock().clone());
+            if subset.iter().any(|&i| decoder.push(i, speculation.shards[&i].clone()).is_err()) {
+                continue;
+            }
+            for &i in &subset {
+                decoder.senders.insert(i, peer.clone());
+            }
+            let published = self.state.locked().manifests.get(root).and_then(|m| m.published_ms);
+            say!("\n{} {} from shards {:?}, before they are verified", "➜ Speculating: reconstructing".yellow(), speculation.filename, subset);
+            speculation.running = true;
+            let job = Rebuild {
+                peer: peer.clone(),
+                stream: speculation.stream,
+                filename: speculation.filename.clone(),
+                root: root.to_string(),
+                layout: speculation.layout,
+                original_len: speculation.original_len,
+                checksum: speculation.checksum.clone(),
+                received: subset.len(),
+                threshold_ms: clock().elapsed(speculation.started).as_secs_f64() * 1000.0,
+                published,
+                confidence_latency_ms: published.map(since_published),
+                decoder,
+                padding: None,
+                matched: false,
+                reconstruct_ms: 0.0,
+                reconstruction_latency_ms: None,
+                extended: None,
+                stored: None,
+                bad_pack: None,
+            };
+            self.start_rebuild(rebuilds, job, Some(subset));
+            return;
+        }
+    }
+
+    /// Sorts out a finished rebuild, returning it if it is to be settled. A verified rebuild
+    /// is dropped when a speculative one already reconstructed its root, and a mismatch is held
+    /// back while the root is speculated on. A speculative one is kept only when it hashes back
+    /// to the root, which vouches for every shard it used, and then takes the place of the
+    /// verified transfer; otherwise the next subset is tried. A verified rebuild whose job
+    /// failed is settled here as corrupted, without ending the session.
+    fn take_rebuild(&self, peer: &str, done: (String, String, Option<Vec<usize>>, Result<Rebuild>), outcomes: &mut Vec<FileOutcome>,
+        transfers: &mut SessionTransfers, speculations: &mut HashMap<String, Speculation>, rebuilds: &mut Rebuilds) -> Option<Rebuild> {
+        let (root, filename, subset, rebuilt) = done;
+        let root = root.as_str();
+        let reconstructed = outcomes.iter().any(|o| o.root == root && o.verdict == Verdict::Reconstructed);
+        let Some(subset) = subset else {
+            transfers.end_rebuild(&mut self.state.locked(), root);
+            let rebuilt = match rebuilt {
+                Ok(rebuilt) => rebuilt,
+                Err(e) => {
+                    say!("\n{} {}: {:#}", "❌ Reconstruction Failed:".red(), filename, e);
+                    speculations.remove(root);
+                    if !reconstructed {
+                        record_outcome(outcomes, peer, FileOutcome { file: filename, root: root.to_string(), shards: 0, verdict: Verdict::Corrupted, threshold_ms: None, reconstruct_ms: None, confidence_latency_ms: None, reconstruction_latency_ms: None, senders: BTreeMap::new() });
+                    }
+                    return None;
+                }
+            };
+            if reconstructed {
+                rebuilt.discard();
+                return None;
+            }
+            if !rebuilt.matched && rebuilt.padding.is_none() && let Some(speculation) = speculations.get_mut(root) {
+                say!("\n{} {}; trying other subsets of the shards that arrived", "❌ Checksum Mismatch:".red(), rebuilt.filename);
+                speculation.failed = Some(rebuilt);
+                self.speculate(root, speculation, rebuilds);
+                return None;
+            }
+            speculations.remove(root);
+            return Some(rebuilt);
+        };
+        // The root settled some other way while this ran.
+        let Some(speculation) = speculations.get_mut(root) else {
+            if let Ok(rebuilt) = rebuilt { rebuilt.discard(); }
+            return None;
+        };
+        speculation.running = false;
+        match rebuilt {
+            Ok(rebuilt) if rebuilt.matched && rebuilt.extended.is_some() => {
+                say!("\n{} shards {:?} of {} hash back to the root", "✓ Speculation confirmed:".green(), subset, rebuilt.filename);
+                speculations.remove(root);
+                let mut lock = self.state.locked();
+                transfers.close(&mut lock, None, root);
+                if let Some(mut decoder) = lock.shards.remove(root) {
+                    release_quota(&mut lock.quota_usage, &mut decoder);
+                    decoder.discard_output();
+                }
+                return Some(rebuilt);
+            }
+            Ok(rebuilt) => {
+                say!("\n{} shards {:?} of {} don't hash back to the root", "❌ Speculation failed:".red(), subset, rebuilt.filename);
+                rebuilt.discard();
+            }
+            Err(e) => say!("\n{} shards {:?} of {}: {}", "❌ Speculation failed:".red(), subset, speculation.filename, e),
+        }
+        self.speculate(root, speculation, rebuilds);
+        None
+    }
+
+    /// Settles a root whose worker finished, and stored the blob if it matched: credits its
+    /// senders, and on a match reseeds every index. Only the in-memory maps are touched under
+    /// the state lock. Returns the `CanServe` to announce and the shard files to write.
+    fn settle_rebuild(&self, job: Rebuild, outcomes: &mut Vec<FileOutcome>, violations: &mut Vec<Violation>, keep_shards: bool) -> (Option<P2PMessage>, Vec<ShardFile>) {
+        let Rebuild { peer, filename, root, layout, original_len, checksum, received, threshold_ms, confidence_latency_ms, decoder, padding, reconstruct_ms, reconstruction_latency_ms, extended, stored, bad_pack, .. } = job;
+        let mut announce = None;
+        let mut to_store = Vec::new();
+        if let Some(pos) = padding {
+            Violation::new(&peer, Some(&root), None, "padding", format!("byte {} of the padded blob, past original_len {}, is not zero", pos, original_len))
+                .report(violations);
+        }
+        let verdict = match stored {
+            Some(path) => {
+                say!("\n{} {}", "✓ RECONSTRUCTION SUCCESSFUL".green().bold(), filename);
+                if let Some(ttfb) = decoder.first_byte {
+                    say!("First byte on disk after {:.2?}", ttfb);
+                }
+                if let (Some(confident), Some(rebuilt)) = (confidence_latency_ms, reconstruction_latency_ms) {
+                    say!("Since the header: threshold after {:.1} ms, rebuilt after {:.1} ms", confident, rebuilt);
+                }
+                say!("Stored at {}", path);
+                if let Some(e) = bad_pack {
+                    say!("{} {}: {}", "❌ Bad Pack:".red(), filename, e);
+                    Verdict::Corrupted
+                } else {
+                    if let Some(command) = &self.args.on_reconstruct {
+                        track_in_flight(run_reconstruct_hook(command, &path, &root));
+                    }
+                    Verdict::Reconstructed
+                }
+            }
+            None => {
+                say!("\n{} {}", if padding.is_some() { "❌ Nonzero Padding:" } else { "❌ Checksum Mismatch:" }.red(), filename);
+                Verdict::Corrupted
+            }
+        };
+        let mut lock = self.state.locked();
+        // A rebuild from k shards can't tell which one was bad, so a mismatch
+        // counts against every peer that supplied one.
+        let senders = decoder.shards_by_sender();
+        for (sender, &n) in &senders {
+            let traffic = lock.peers.entry(sender.clone()).or_default();
+            match verdict {
+                Verdict::Reconstructed => traffic.shards_rebuilt += n,
+                _ => traffic.shards_corrupted += n,
+            }
+        }
+        if senders.len() > 1 {
+            let sources: Vec<String> = senders.iter().map(|(sender, n)| format!("{}… ({})", sender.get(..16).unwrap_or(sender), n)).collect();
+            say!("Shards from: {}", sources.join(", "));
+        }
+
+        // Reseed: regenerate the full extended set so every index can be served.
+        // Spilled blobs skip it, since the extended set would be held in memory.
+        if verdict == Verdict::Reconstructed && decoder.is_spilled() {
+            say!("{} shards were spilled to disk; the blob is stored but its shards are not served", "➜ Not reseeded:".blue().bold());
+        } else if let Some(extended) = extended {
+            if keep_shards {
+                for (index, data) in extended.iter().enumerate() {
+                    to_store.push(ShardFile { root: root.clone(), index, k: DATA_SHARDS, m: PARITY_SHARDS, layout, original_len, data: data.clone() });
+                }
+            }
+            say!("{} serving all {} indices", "➜ Reseeded:".blue().bold(), TOTAL_SHARDS);
+            let indices = (0..TOTAL_SHARDS).map(|i| layout.wire_index(i)).collect();
+            log_event(Event::CanServe { peer: peer.clone(), root: root.clone(), indices: TOTAL_SHARDS });
+            announce = Some(P2PMessage::CanServe { root: root.clone(), indices });
+            let mut shards = extended;
+            // A lazy custodian still announces every index, but keeps only some.
+            if self.args.lazy_custody > 0.0 {
+                let mut rng = rand::thread_rng();
+                shards.iter_mut().filter(|_| rng.gen_bool(self.args.lazy_custody)).for_each(Vec::clear);
+            }
+            lock.serving.insert(root.clone(), ServedBlob { filename: filename.clone(), layout, original_len, checksum, shards });
+        }
+        drop(lock);
+        record_outcome(outcomes, &peer, FileOutcome {
+            file: filename,
+            root,
+            shards: received,
+            verdict,
+            threshold_ms: Some(threshold_ms),
+            reconstruct_ms: Some(reconstruct_ms),
+            confidence_latency_ms,
+            reconstruction_latency_ms,
+            senders,
+        });
+        (announce, to_store)
+    }
+}
+
+fn record_outcome(outcomes: &mut Vec<FileOutcome>, peer: &str, outcome: FileOutcome) {
+    log_event(Event::Outcome { peer: peer.to_string(), outcome: outcome.clone() });
+    outcomes.push(outcome);
+}
+
+// WORK QUEUE
+/// What a validator's worker job does, most urgent first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum JobKind {
+    /// Checking a received shard against its manifest and commitment
+    Verify,
+    /// Decoding and hashing a root at threshold
+    Reconstruct,
+    /// Reading and encoding a shard, range or custody proof for a peer
+    Serve,
+}
+
+impl JobKind {
+    const ALL: [JobKind; 3] = [JobKind::Verify, JobKind::Reconstruct, JobKind::Serve];
+
+    fn name(self) -> &'static str {
+        match self {
+            JobKind::Verify => "verify",
+            JobKind::Reconstruct => "reconstruct",
+            JobKind::Serve => "serve",
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Jobs waiting and running, by [`JobKind`].
+#[derive(Default)]
+struct Queues {
+    queued: [std::collections::VecDeque<Job>; 3],
+    running: [usize; 3],
+}
+
+/// A validator's worker threads, shared by its sessions. A free worker takes the oldest job of
+/// the most urgent kind that has one waiting and is under its limit, so shards are verified
+/// ahead of rebuilds and rebuilds run ahead of sample answers. The limits keep the busiest
+/// kinds from taking every worker.
+struct WorkQueue {
+    queues: Mutex<Queues>,
+    ready: std::sync::Condvar,
+    /// Most jobs of each kind running at once
+    limits: [usize; 3],
+}
+
+impl WorkQueue {
+    /// Starts `workers` threads, of which at most `limits` verify and reconstruct at once.
+    fn start(workers: usize, [verify, reconstruct]: [usize; 2]) -> Arc<Self> {
+        let queue = Arc::new(Self {
+            queues: Mutex::default(),
+            ready: std::sync::Condvar::new(),
+            limits: [verify.min(workers), reconstruct.min(workers), workers],
+        });
+        for i in 0..workers {
+            let queue = queue.clone();
+            std::thread::Builder::new().name(format!("das-worker-{}", i)).spawn(move || queue.work())
+                .expect("worker thread starts");
+        }
+        queue
+    }
+
+    fn work(&self) {
+        loop {
+            let (kind, job) = {
+                let mut queues = self.ready.wait_while(self.queues.locked(), |q| self.next(q).is_none()).unwrap_or_else(std::sync::PoisonError::into_inner);
+                let kind = self.next(&queues).expect("woken with a job to run");
+                queues.running[kind as usize] += 1;
+                (kind, queues.queued[kind as usize].pop_front().expect("job queued"))
+            };
+            // A job that panics loses its result, not the worker.
+            let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+            self.queues.locked().running[kind as usize] -= 1;
+            // A kind at its limit may have jobs waiting that this slot now frees.
+            self.ready.notify_all();
+        }
+    }
+
+    /// The most urgent kind with a job waiting and a worker to spare.
+    fn next(&self, queues: &Queues) -> Option<JobKind> {
+        JobKind::ALL.into_iter().find(|&k| !queues.queued[k as usize].is_empty() && queues.running[k as usize] < self.limits[k as usize])
+    }
+
+    /// Queues `f` as a job of `kind` and waits for its result.
+    async fn run<T: Send + 'static>(&self, kind: JobKind, f: impl FnOnce() -> T + Send + 'static) -> Result<T> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.queues.locked().queued[kind as usize].push_back(Box::new(move || {
+            let _ = tx.send(f());
+        }));
+        self.ready.notify_one();
+        rx.await.map_err(|_| anyhow!("{} job panicked", kind.name()))
+    }
+
+    /// Jobs waiting for a worker, by kind.
+    fn waiting(&self) -> BTreeMap<String, usize> {
+        let queues = self.queues.locked();
+        JobKind::ALL.into_iter().map(|k| (k.name().to_string(), queues.queued[k as usize].len())).collect()
+    }
+}
+
+// STATUS
+/// Snapshot served at `/status`.
+#[derive(Serialize)]
+struct NodeStatus {
+    node: String,
+    uptime_s: u64,
+    buffered_roots: usize,
+    serving_roots: usize,
+    /// Roots sampled from `--sample-peers` and found available
+    sampled_roots: usize,
+    /// Shards relayed to `--gossip-peers`, and relays lost on the way
+    shards_relayed: usize,
+    relay_drops: usize,
+    /// Restarts of tasks that died, by task
+    task_restarts: BTreeMap<String, usize>,
+    /// Jobs waiting for a worker, by kind
+    queued_jobs: BTreeMap<String, usize>,
+    /// This node's signed record, in text form
+    #[serde(skip_serializing_if = "Option::is_none")]
+    record: Option<String>,
+    /// Peers whose verified records this node holds
+    known_peers: usize,
+    peers: HashMap<String, PeerTraffic>,
+}
+
+/// Served at `/healthz` and `/readyz`.
+#[derive(Serialize)]
+struct Health {
+    /// Ready for dependent nodes: listening, with a writable store
+    ready: bool,
+    /// The accept loop is taking connections
+    listening: bool,
+    store_writable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store_error: Option<String>,
+    /// Peers that have completed a handshake with this node
+    peers: usize,
+    uptime_s: u64,
+}
+
+impl Validator {
+    fn health(&self) -> Health {
+        let listening = self.listening.load(Ordering::Relaxed);
+        let store_error = self.store.probe().err().map(|e| e.to_string());
+        Health {
+            ready: listening && store_error.is_none(),
+            listening,
+            store_writable: store_error.is_none(),
+            store_error,
+            peers: self.state.locked().peers.len(),
+            uptime_s: clock().elapsed(self.started).as_secs(),
+        }
+    }
+
+    fn status(&self) -> NodeStatus {
+        let state = self.state.locked();
+        NodeStatus {
+            node: hex::encode(self.id.public.as_bytes()),
+            uptime_s: clock().elapsed(self.started).as_secs(),
+            buffered_roots: state.shards.len(),
+            serving_roots: state.serving.len(),
+            sampled_roots: state.sampled.values().filter(|v| **v == Verdict::Sampled).count(),
+            shards_relayed: state.peers.values().map(|t| t.shards_relayed).sum(),
+            relay_drops: state.relay_drops,
+            task_restarts: state.restarts.clone(),
+            queued_jobs: self.jobs.waiting(),
+            record: self.id.record.as_ref().map(|r| r.to_string()),
+            known_peers: state.records.len(),
+            peers: state.peers.clone(),
+        }
+    }
+
+    /// Prometheus text exposition of the same counters.
+    fn metrics(&self) -> String {
+        let status = self.status();
+        let mut out = String::new();
+        out += &format!("das_uptime_seconds {}\n", status.uptime_s);
+        out += &format!("das_buffered_roots {}\n", status.buffered_roots);
+        out += &format!("das_serving_roots {}\n", status.serving_roots);
+        out += &format!("das_sampled_roots {}\n", status.sampled_roots);
+        out += &format!("das_shards_relayed_total {}\n", status.shards_relayed);
+        out += &format!("das_relay_drops_total {}\n", status.relay_drops);
+        out += &format!("das_known_peers {}\n", status.known_peers);
+        for (task, n) in &status.task_restarts {
+            out += &format!("das_task_restarts_total{{task=\"{}\"}} {}\n", task, n);
+        }
+        for (kind, n) in &status.queued_jobs {
+            out += &format!("das_queued_jobs{{kind=\"{}\"}} {}\n", kind, n);
+        }
+        for (peer, t) in &status.peers {
+            out += &format!("das_peer_sessions_total{{peer=\"{}\"}} {}\n", peer, t.sessions);
+            out += &format!("das_peer_bytes_received_total{{peer=\"{}\"}} {}\n", peer, t.bytes_received);
+            out += &format!("das_peer_bytes_sent_total{{peer=\"{}\"}} {}\n", peer, t.bytes_sent);
+            out += &format!("das_peer_shards_received_total{{peer=\"{}\"}} {}\n", peer, t.shards_received);
+            out += &format!("das_peer_quota_drops_total{{peer=\"{}\"}} {}\n", peer, t.quota_drops);
+            out += &format!("das_peer_protocol_errors_total{{peer=\"{}\"}} {}\n", peer, t.protocol_errors);
+            out += &format!("das_peer_shards_relayed_total{{peer=\"{}\"}} {}\n", peer, t.shards_relayed);
+            out += &format!("das_peer_gossip_duplicates_total{{peer=\"{}\"}} {}\n", peer, t.gossip_duplicates);
+            out += &format!("das_peer_shards_synced_total{{peer=\"{}\"}} {}\n", peer, t.shards_synced);
+            out += &format!("das_peer_shards_rebuilt_total{{peer=\"{}\"}} {}\n", peer, t.shards_rebuilt);
+            out += &format!("das_peer_shards_corrupted_total{{peer=\"{}\"}} {}\n", peer, t.shards_corrupted);
+            out += &format!("das_peer_custody_audits_total{{peer=\"{}\",result=\"passed\"}} {}\n", peer, t.custody_passed);
+            out += &format!("das_peer_custody_audits_total{{peer=\"{}\",result=\"failed\"}} {}\n", peer, t.custody_failed);
+            out += &format!("das_peer_score{{peer=\"{}\"}} {}\n", peer, t.score);
+            if let Some(offset) = t.clock_offset_ms {
+                out += &format!("das_peer_clock_offset_ms{{peer=\"{}\"}} {}\n", peer, offset);
+            }
+        }
+        out
+    }
+}
+
+/// Minimal HTTP/1.1 responder: one GET per connection, then close. Returns only when the
+/// listener fails.
+async fn serve_status(listener: TcpListener, node: Arc<Validator>) -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let node = node.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let path = request.split_whitespace().nth(1).unwrap_or("/");
+            let (code, content_type, body) = match path {
+                "/status" => ("200 OK", "application/json", serde_json::to_string_pretty(&node.status()).unwrap_or_default()),
+                "/metrics" => ("200 OK", "text/plain; version=0.0.4", node.metrics()),
+                // Liveness: answering at all is the signal. Readiness also needs the node usable.
+                "/healthz" => ("200 OK", "application/json", serde_json::to_string(&node.health()).unwrap_or_default()),
+                "/readyz" => {
+                    let health = node.health();
+                    (if health.ready { "200 OK" } else { "503 Service Unavailable" }, "application/json", serde_json::to_string(&health).unwrap_or_default())
+                }
+                _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                code, content_type, body.len(), body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+// RECORD & REPLAY
+type FrameReceiver = tokio::sync::mpsc::Receiver<Result<String, TransportError>>;
+
+/// One line of a session recording; the first line is always `Session`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Recorded {
+    Session { peer: String, addr: String, max_frame: usize, recorded_at: u64 },
+    /// A received frame, `t_ms` after the handshake completed
+    Frame { t_ms: u64, line: String },
+    /// A frame over `--max-frame` that ended the session
+    Oversize { t_ms: u64 },
+}
+
+struct SessionRecorder {
+    file: File,
+    started: Duration,
+}
+
+impl SessionRecorder {
+    fn create(dir: &str, peer: &str, addr: &str, max_frame: usize) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let recorded_at = unix_now();
+        let path = format!("{}/{}_{}.session.jsonl", dir, recorded_at, addr.replace([':', '.', '[', ']'], "-"));
+        let mut recorder = Self { file: File::create(&path)?, started: clock().now() };
+        recorder.write(&Recorded::Session { peer: peer.to_string(), addr: addr.to_string(), max_frame, recorded_at });
+        say!("{} {}", "➜ Recording:".blue().bold(), path);
+        Ok(recorder)
+    }
+
+    fn frame(&mut self, frame: &Result<String, TransportError>) {
+        let t_ms = clock().elapsed(self.started).as_millis() as u64;
+        match frame {
+            Ok(line) => self.write(&Recorded::Frame { t_ms, line: line.clone() }),
+            Err(TransportError::OversizeFrame { .. }) => self.write(&Recorded::Oversize { t_ms }),
+            Err(_) => {}
+        }
+    }
+
+    /// Best effort: a failing recording must not take the session down.
+    fn write(&mut self, entry: &Recorded) {
+        if let Ok(mut line) = serde_json::to_string(entry) {
+            line.push('\n');
+            let _ = self.file.write_all(line.as_bytes());
+        }
+    }
+}
+
+/// Feeds a recorded session through a fresh validator, offline and in order, and returns the
+/// exit status the live session would have produced. What it stores goes to `store`, or a
+/// fresh temp directory, so a replay never adds to a live node's `--data-dir`.
+async fn run_replay(file: &str, store: Option<String>, mut args: ListenArgs, id: Identity) -> Result<ExitStatus> {
+    let text = std::fs::read_to_string(file)?;
+    let mut entries = text.lines().filter(|l| !l.trim().is_empty()).map(serde_json::from_str::<Recorded>);
+    let Some(Recorded::Session { peer, addr, max_frame, recorded_at }) = entries.next().transpose()? else {
+        bail!("{} does not start with a session header", file);
+    };
+    say!("{} {} from {} ({}), recorded at {}", "➜ Replay:".blue().bold(), file, addr, peer.get(..16).unwrap_or(&peer), recorded_at);
+
+    args.data_dir = store.unwrap_or_else(|| {
+        format!("{}/das-replay-{}-{}", std::env::temp_dir().display(), clock().unix().as_millis(), std::process::id())
+    });
+    say!("{} {}", "➜ Replay Store:".blue().bold(), args.data_dir);
+    let store = BlobStore::open(&args.data_dir)?;
+    let limit = args.max_frame;
+    // Gossip would reach live peers, so a replay never relays.
+    let state = ValidatorState { seen: SeenCache::new(Duration::from_secs(args.gossip_seen_ttl)), ..Default::default() };
+    let transfer_log = args.transfer_log.as_deref().map(RotatingLog::open).transpose()?.map(Mutex::new);
+    let jobs = WorkQueue::start(args.workers, [args.verify_workers, args.reconstruct_workers]);
+    let node = Validator { args, id, store, state: Arc::new(Mutex::new(state)), started: clock().now(), gossip: Vec::new(), transfer_log, listening: AtomicBool::new(false), jobs };
+    let frames: Vec<_> = entries.collect::<std::result::Result<_, _>>()?;
+    let (tx, rx) = tokio::sync::mpsc::channel(frames.len().max(1));
+    for entry in frames {
+        let frame = match entry {
+            Recorded::Frame { line, .. } => Ok(line),
+            Recorded::Oversize { .. } => Err(TransportError::OversizeFrame { limit }),
+            Recorded::Session { .. } => bail!("Unexpected second session header in {}", file),
+        };
+        tx.send(frame).await?;
+    }
+    drop(tx);
+    // Replies have nowhere to go offline; the replay only exercises the receive path.
+    let sink = futures::sink::drain().sink_map_err(|never| match never {});
+    node.session(&peer, max_frame, &addr, rx, sink, HashSet::new()).await
+}
+
+// CAPTURE PROXY
+#[derive(Args, Clone)]
+struct ProxyArgs {
+    /// Port to accept proposer connections on
+    #[arg(long, default_value_t = 9000)]
+    port: u16,
+    /// Validator to forward to
+    #[arg(long)]
+    upstream: String,
+    /// Append every frame (direction, type, action and the full line) as JSON lines here
+    #[arg(long)]
+    dump: Option<String>,
+    /// Drop `DasShard` frames with these wire indices
+    #[arg(long, value_delimiter = ',')]
+    drop_index: Vec<usize>,
+    /// Flip one byte in `DasShard` frames with these wire indices
+    #[arg(long, value_delimiter = ',')]
+    corrupt_index: Vec<usize>,
+    /// Drop each `DasShard` frame with this probability
+    #[arg(long, default_value_t = 0.0)]
+    drop_prob: f64,
+    #[arg(long, default_value_t = DEFAULT_MAX_FRAME)]
+    max_frame: usize,
+}
+
+/// One line of `proxy --dump`.
+#[derive(Serialize)]
+struct CapturedFrame<'a> {
+    t_ms: u64,
+    conn: usize,
+    /// `up` is proposer -> validator, `down` the reverse
+    dir: &'a str,
+    kind: String,
+    bytes: usize,
+    /// [`message_id`] of the message inside the envelope, matching the event logs
+    msg_id: String,
+    action: &'a str,
+    line: &'a str,
+}
+
+/// The message inside an enveloped frame; the frame itself for the handshake and anything
+/// that isn't an envelope.
+fn frame_payload(line: &str) -> String {
+    serde_json::from_str::<Envelope>(line).map_or_else(|_| line.to_string(), |envelope| envelope.payload)
+}
+
+/// Applies the configured mutation to one frame: returns the action taken and the line to forward.
+fn mutate_frame(args: &ProxyArgs, line: String) -> (&'static str, Option<String>) {
+    // The proxy can't re-seal what it changes, so the receiver catches every mutation at its
+    // envelope: a corrupted shard by its signature, a dropped one by the gap it leaves.
+    let Ok(mut envelope) = serde_json::from_str::<Envelope>(&line) else { return ("forward", Some(line)) };
+    let Ok((stream, P2PMessage::DasShard { filename, root, layout, original_len, index, mut data, full_file_checksum, ttl, trace })) = decode_frame(&envelope.payload) else {
+        return ("forward", Some(line));
+    };
+    if args.drop_index.contains(&index) || (args.drop_prob > 0.0 && rand::thread_rng().gen_bool(args.drop_prob.min(1.0))) {
+        return ("drop", None);
+    }
+    if args.corrupt_index.contains(&index) && !data.is_empty() {
+        data[0] ^= 0xff;
+        let msg = P2PMessage::DasShard { filename, root, layout, original_len, index, data, full_file_checksum, ttl, trace };
+        let Ok(payload) = encode_frame(stream, &msg) else { return ("forward", Some(line)) };
+        envelope.payload = payload;
+        return ("corrupt", serde_json::to_string(&envelope).ok());
+    }
+    ("forward", Some(line))
+}
+
+async fn run_proxy(args: ProxyArgs) -> Result<ExitStatus> {
+    let transport = TcpTransport::new(args.max_frame);
+    let mut listener = transport.listen(&format!("0.0.0.0:{}", args.port)).await?;
+    say!("{} :{} -> {}", "➜ Proxy:".green().bold(), args.port, args.upstream);
+    let dump = match &args.dump {
+        Some(path) => Some(Arc::new(Mutex::new(std::fs::OpenOptions::new().create(true).append(true).open(path)?))),
+        None => None,
+    };
+    let args = Arc::new(args);
+    let started = clock().now();
+    let mut conn = 0;
+    while let Ok(client) = listener.accept().await {
+        conn += 1;
+        let addr = client.peer_addr();
+        let upstream = match transport.connect(&args.upstream).await {
+            Ok(s) => s,
+            Err(e) => {
+                say!("{} {}: {}", "❌ Upstream Unreachable:".red(), args.upstream, e);
+                continue;
+            }
+        };
+        say!("\n{} #{} {} <-> {}", "➜ Proxy:".blue().bold(), conn, addr, args.upstream);
+        let (up_sink, up_stream) = upstream.split();
+        let (down_sink, down_stream) = client.split();
+        // Each handshake signs its codec offer, so the proxy relays both untouched and works out
+        // the codec the peers settle on, to inflate frames before capturing or mutating them.
+        let offers = Arc::new(Mutex::new(Vec::<Vec<String>>::new()));
+        let pump = |dir: &'static str, mut from: futures::stream::SplitStream<TcpConnection>,
+                    mut to: futures::stream::SplitSink<TcpConnection, String>| {
+            let (args, dump, offers) = (args.clone(), dump.clone(), offers.clone());
+            async move {
+                let mut codec: Option<Compression> = None;
+                while let Some(Ok(raw)) = from.next().await {
+                    let bytes = raw.len();
+                    // The first frame each way is the handshake, and each side compresses only
+                    // after reading the other's, so both offers are in by its next frame.
+                    if codec.is_none() && let [a, b] = &offers.locked()[..] {
+                        codec = Some(Compression::settled(a, b));
+                    }
+                    let inflated = codec;
+                    let line = match inflated {
+                        Some(c) => match c.decompress(raw, args.max_frame) {
+                            Ok(line) => line,
+                            Err(e) => {
+                                say!("{} #{} {}: {}", "❌ Undecodable Frame:".red(), conn, dir, e);
+                                break;
+                            }
+                        },
+                        None => {
+                            if let Ok(P2PMessage::Handshake { compression, .. }) = decode_message(&raw) {
+                                offers.locked().push(compression);
+                            }
+                            raw
+                        }
+                    };
+                    let payload = frame_payload(&line);
+                    let kind = serde_json::from_str::<serde_json::Value>(&payload).ok()
+                        .and_then(|v| v.as_object().and_then(|o| o.keys().next().cloned()))
+                        .unwrap_or_else(|| "unparsed".into());
+                    let (action, out) = mutate_frame(&args, line.clone());
+                    say!("  #{} {:<4} {:<14} {:>10} {}", conn, dir, kind, format_bytes(bytes), action);
+                    if let Some(dump) = &dump {
+                        let t_ms = clock().elapsed(started).as_millis() as u64;
+                        let record = CapturedFrame { t_ms, conn, dir, kind, bytes, msg_id: message_id(&payload), action, line: &line };
+                        if let Ok(mut json) = serde_json::to_string(&record) {
+                            json.push('\n');
+                            let _ = dump.locked().write_all(json.as_bytes());
+                        }
+                    }
+                    let out = match (out, inflated) {
+                        (Some(out), Some(c)) => c.compress(&out).ok(),
+                        (out, _) => out,
+                    };
+                    if let Some(out) = out && to.send(out).await.is_err() {
+                        break;
+                    }
+                }
+                let _ = to.close().await;
+            }
+        };
+        let up = pump("up", down_stream, up_sink);
+        let down = pump("down", up_stream, down_sink);
+        tokio::spawn(async move {
+            tokio::join!(up, down);
+        });
+    }
+    Ok(ExitStatus::Success)
+}
+
+// RELAY
+#[derive(Args)]
+struct RelayArgs {
+    /// Port validators register on and clients connect to
+    #[arg(long, default_value_t = 9400)]
+    port: u16,
+    /// Seconds a client waits for the node it asked for to dial back
+    #[arg(long, default_value_t = 10)]
+    accept_timeout: u64,
+    /// Largest frame (JSON line, bytes) to forward; keep it at least the nodes' own `--max-frame`
+    #[arg(long, default_value_t = DEFAULT_MAX_FRAME)]
+    max_frame: usize,
+}
+
+/// Registered nodes, and clients waiting for a node to dial back.
+#[derive(Default)]
+struct RelayState {
+    /// Node pubkey -> its registration and the channel to its control connection
+    nodes: HashMap<String, (u64, tokio::sync::mpsc::UnboundedSender<RelayMessage>)>,
+    /// Ticket -> the client waiting for that ticket's `accept`. Tickets are random, so one
+    /// can't be guessed to take over a client meant for another node
+    waiting: HashMap<u64, tokio::sync::oneshot::Sender<TcpConnection>>,
+    /// Last registration number handed out
+    next: u64,
+}
+
+/// How `send` and `sample` reach `--peer`: by address, possibly through `--proxy`, or by pubkey
+/// through a `--relay`.
+#[derive(Clone)]
+enum Route {
+    Direct(TcpTransport),
+    Relay(RelayTransport),
+}
+
+impl Route {
+    fn new(max_frame: usize, proxy: Option<Socks5Proxy>, relay: Option<String>) -> Self {
+        let tcp = TcpTransport::new(max_frame).via(proxy);
+        match relay {
+            Some(relay) => Self::Relay(RelayTransport::new(tcp, relay)),
+            None => Self::Direct(tcp),
+        }
+    }
+
+    fn max_frame(&self) -> usize {
+        match self {
+            Self::Direct(tcp) => tcp.max_frame,
+            Self::Relay(relay) => relay.tcp.max_frame,
+        }
+    }
+
+    async fn connect(&self, peer: &str) -> Result<TcpConnection, TransportError> {
+        match self {
+            Self::Direct(tcp) => tcp.connect(peer).await,
+            Self::Relay(relay) => relay.connect(peer).await,
+        }
+    }
+
+    /// Authenticates `peer`. Through a relay anyone could have registered under its name, so
+    /// there the handshake must also come from the pubkey `peer` names.
+    async fn handshake(&self, conn: &mut TcpConnection, id: &Identity, peer: &str, compression: &[Compression]) -> Result<PeerHandshake, HandshakeError> {
+        let hs = handshake(conn, id, self.max_frame(), compression).await?;
+        let got = hex::encode(hs.key.as_bytes());
+        if matches!(self, Self::Relay(_)) && !got.eq_ignore_ascii_case(peer) {
+            return Err(HandshakeError::UnexpectedPeer { expected: peer.to_string(), got });
+        }
+        Ok(hs)
+    }
+}
+
+async fn run_relay(args: RelayArgs) -> Result<ExitStatus> {
+    let mut listener = TcpTransport::new(args.max_frame).listen(&format!("0.0.0.0:{}", args.port)).await?;
+    say!("{} :{}", "➜ Relay:".green().bold(), args.port);
+    let state = Arc::new(Mutex::new(RelayState::default()));
+    let accept_timeout = Duration::from_secs(args.accept_timeout);
+    while let Ok(conn) = listener.accept().await {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let addr = conn.peer_addr();
+            if let Err(e) = relay_connection(conn, &state, accept_timeout).await {
+                say!("{} {}: {}", "❌ Relay Error:".red(), addr, e);
+            }
+        });
+    }
+    Ok(ExitStatus::Success)
+}
+
+/// Serves one connection to the relay, by its first control line: a node's registration, a
+/// client to put through, or a node dialing back for a client.
+async fn relay_connection(mut conn: TcpConnection, state: &Mutex<RelayState>, accept_timeout: Duration) -> Result<()> {
+    let addr = conn.peer_addr();
+    let line = |message: RelayMessage| serde_json::to_string(&message).expect("relay message serializes");
+    match read_relay_message(&mut conn).await? {
+        RelayMessage::Register { node } => {
+            // Only the holder of `node`'s key may register as it: it signs a fresh nonce.
+            let key = hex::decode(&node).ok().and_then(|b| <[u8; 32]>::try_from(b).ok()).and_then(|b| VerifyingKey::from_bytes(&b).ok());
+            let Some(key) = key else {
+                conn.send(line(RelayMessage::Error { reason: format!("{} is not a hex pubkey", node) })).await?;
+                return Ok(());
+            };
+            let nonce: [u8; 32] = rand::random();
+            conn.send(line(RelayMessage::Challenge { nonce: hex::encode(nonce) })).await?;
+            let proof = tokio::select! {
+                proof = read_relay_message(&mut conn) => proof.ok(),
+                _ = clock().sleep(accept_timeout) => None,
+            };
+            let sig = match proof {
+                Some(RelayMessage::Proof { sig }) => hex::decode(sig).ok().and_then(|b| <[u8; 64]>::try_from(b).ok()),
+                _ => None,
+            };
+            if sig.is_none_or(|sig| key.verify(&registration_bytes(&node, &nonce), &Signature::from_bytes(&sig)).is_err()) {
+                say!("{} {} at {}: no valid proof of its key", "❌ Registration Refused:".red(), node, addr);
+                conn.send(line(RelayMessage::Error { reason: format!("registration as {} needs a signature by its key", node) })).await?;
+                return Ok(());
+            }
+            let (control, mut tickets) = tokio::sync::mpsc::unbounded_channel();
+            let registration = {
+                let mut state = state.locked();
+                state.next += 1;
+                let registration = state.next;
+                // Proven to be the same key, so a node that re-registers (say after a restart)
+                // replaces its old registration; no other key gets this far.
+                state.nodes.insert(node.clone(), (registration, control));
+                registration
+            };
+            say!("{} {} at {}", "➜ Registered:".green(), node, addr);
+            let served = async {
+                conn.send(line(RelayMessage::Ok { peer: addr.clone() })).await?;
+                loop {
+                    tokio::select! {
+                        ticket = tickets.recv() => match ticket {
+                            Some(ticket) => conn.send(line(ticket)).await?,
+                            None => return Ok(()),
+                        },
+                        frame = conn.next() => if !matches!(frame, Some(Ok(_))) {
+                            return Ok(());
+                        },
+                    }
+                }
+            };
+            let result: Result<(), TransportError> = served.await;
+            let mut state = state.locked();
+            if state.nodes.get(&node).is_some_and(|(current, _)| *current == registration) {
+                state.nodes.remove(&node);
+                say!("{} {}", "➜ Unregistered:".yellow(), node);
+            }
+            Ok(result?)
+        }
+        RelayMessage::Connect { node } => {
+            let waiting = {
+                let mut state = state.locked();
+                let ticket = std::iter::repeat_with(rand::random::<u64>).find(|t| !state.waiting.contains_key(t)).expect("an unused ticket");
+                let control = state.nodes.get(&node).map(|(_, control)| control.clone());
+                control.filter(|control| control.send(RelayMessage::Incoming { ticket, from: addr.clone() }).is_ok()).map(|_| {
+                    let (tx, rx) = tokio::sync::oneshot::channel();
+                    state.waiting.insert(ticket, tx);
+                    (ticket, rx)
+                })
+            };
+            let Some((ticket, rx)) = waiting else {
+                conn.send(line(RelayMessage::Error { reason: format!("node {} is not registered", node) })).await?;
+                return Ok(());
+            };
+            let answered = tokio::select! {
+                upstream = rx => upstream.ok(),
+                _ = clock().sleep(accept_timeout) => None,
+            };
+            let upstream = match answered {
+                Some(upstream) => upstream,
+                None => {
+                    state.locked().waiting.remove(&ticket);
+                    conn.send(line(RelayMessage::Error { reason: format!("node {} did not answer", node) })).await?;
+                    return Ok(());
+                }
+            };
+            conn.send(line(RelayMessage::Ok { peer: node.clone() })).await?;
+            say!("{} #{} {} <-> {}", "➜ Relay:".blue().bold(), ticket, addr, node);
+            let (client_sink, client_stream) = conn.split();
+            let (node_sink, node_stream) = upstream.split();
+            let (up, down) = tokio::join!(client_stream.forward(node_sink), node_stream.forward(client_sink));
+            say!("{} #{} closed", "➜ Relay:".blue().bold(), ticket);
+            up.and(down)?;
+            Ok(())
+        }
+        RelayMessage::Accept { ticket } => {
+            let waiting = state.locked().waiting.remove(&ticket);
+            match waiting {
+                Some(client) => {
+                    let _ = client.send(conn);
+                }
+                None => conn.send(line(RelayMessage::Error { reason: format!("ticket {} expired", ticket) })).await?,
+            }
+            Ok(())
+        }
+        other => {
+            conn.send(line(RelayMessage::Error { reason: format!("expected register, connect or accept, got {:?}", other) })).await?;
+            Ok(())
+        }
+    }
+}
+
+// PROPOSER (SENDER)
+async fn run_proposer(args: SendArgs, id: Identity) -> Result<ExitStatus> {
+    let (peer, mode, max_frame, faults, send_rate) = (&args.peer, args.mode, args.max_frame, args.faults, args.send_rate);
+    if args.file.len() > 1 && args.manifest.is_some() && !args.matrix {
+        bail!("--manifest names one file; with several --file, each manifest goes to <file>.manifest.json");
+    }
+    if args.matrix {
+        if mode == ResearchMode::Naive {
+            bail!("--matrix batches blobs for DAS; use das-full or das-sample");
+        }
+        // Interleaving would scatter each cell over every column.
+        if args.layout.striping != Striping::Contiguous {
+            bail!("--matrix needs contiguous striping, so each data shard is one column");
+        }
+        if !(args.target_confidence > 0.0 && args.target_confidence < 1.0) {
+            bail!("--target-confidence must be between 0 and 1, exclusive");
+        }
+    }
+    let mut payloads = args.file.iter().map(|path| Payload::load(path, args.window)).collect::<Result<Vec<_>>>()?;
+    let fsize: usize = payloads.iter().map(Payload::len).sum();
+
+    say!("Target: {}", peer);
+    for payload in &payloads {
+        match payload {
+            Payload::Windowed { len, window, .. } => say!("Payload: {} ({}, in {} windows of up to {})", payload.name(), format_bytes(*len),
+                len.div_ceil(*window), format_bytes(*window)),
+            _ => say!("Payload: {} ({})", payload.name(), format_bytes(payload.len())),
+        }
+    }
+    let windowed = payloads.iter().any(|p| matches!(p, Payload::Windowed { .. }));
+    if windowed && (mode == ResearchMode::Naive || args.matrix) {
+        bail!("files over --window are sent window by window as DAS blobs; raise --window to send them {}", if args.matrix { "in a matrix" } else { "naively" });
+    }
+    if windowed && args.manifest.is_some() {
+        bail!("--manifest names one blob; a file over --window sends one per window, each to <window>.manifest.json");
+    }
+    let mut matrix = None;
+    if args.matrix {
+        let whole: Vec<(String, Vec<u8>)> = payloads.into_iter().map(|p| match p {
+            Payload::Whole(name, data) => (name, data),
+            Payload::Windowed { .. } => unreachable!("checked above"),
+        }).collect();
+        let lens: Vec<usize> = whole.iter().map(|(_, data)| data.len()).collect();
+        let (shape, data) = encode_matrix(&whole, DATA_SHARDS, args.commitment.shard_align())?;
+        say!("Matrix: {} blobs in {} x {} cells of {} (columns of {})", shape.blobs, shape.rows, TOTAL_SHARDS,
+            format_bytes(shape.cell_len), format_bytes(shape.column_len()));
+        let mut costs = vec![per_blob_cost(&lens, DATA_SHARDS, PARITY_SHARDS, args.target_confidence)];
+        costs.extend([SampleUnit::Cells, SampleUnit::Rows, SampleUnit::Columns].map(|unit| shape.cost(unit, PARITY_SHARDS, args.target_confidence)));
+        payloads = vec![Payload::Whole(format!("block-{}{}", &calculate_sha256(&data)[..8], MATRIX_SUFFIX), data)];
+        matrix = Some(MatrixSummary { shape, target_confidence: args.target_confidence, costs });
+    }
+    say!("Strategy: {:?}", mode);
+    
+    if let Some(proxy) = args.proxy {
+        say!("Via: {}", proxy);
+    }
+    if let Some(relay) = &args.relay {
+        say!("Relay: {}", relay);
+    }
+    let route = Route::new(max_frame, args.proxy, args.relay.clone());
+    let mut conn = route.connect(peer).await.context("Connection Failed")?;
+
+    let hs = match route.handshake(&mut conn, &id, peer, &args.compression).await {
+        Ok(hs) => hs,
+        Err(e) => {
+            log_event(Event::Handshake { peer: peer.clone(), ok: false, clock_offset_ms: skew_offset(&e) });
+            say!("{} {}", "❌ Auth Failed:".red(), e);
+            return Ok(ExitStatus::AuthFailure);
+        }
+    };
+    let (peer_id, peer_max_frame, codec) = (hex::encode(hs.key.as_bytes()), hs.max_frame, hs.compression);
+    log_event(Event::Handshake { peer: peer_id.clone(), ok: true, clock_offset_ms: Some(hs.clock_offset_ms) });
+    // Faults apply before sealing, so the validator sees them as the proposer's own frames.
+    let conn = tokio::sync::Mutex::new(FaultyConnection::new(peer_connection(conn, &id, &hs, max_frame), faults));
+    
+    let start = clock().now();
+    // A single payload keeps the bare stream; several each get a stream of their own and
+    // interleave frame by frame.
+    let multiplexed = payloads.len() > 1;
+    if multiplexed {
+        say!("Streams: {} transfers sharing one connection", payloads.len());
+    }
+    let sends = payloads.into_iter().enumerate().map(|(i, payload)| {
+        let stream = if multiplexed { i as u32 + 1 } else { BARE_STREAM };
+        send_payload(&args, &id, &conn, &peer_id, peer_max_frame, stream, payload)
+    });
+    let Some(sent) = futures::future::try_join_all(sends).await?.into_iter().collect::<Option<Vec<Vec<SentBlob>>>>() else {
+        let conn = conn.into_inner();
+        if conn.severed() {
+            say!("{} dropped the connection mid-transfer, after {} malformed and {} truncated frames", "➜ Chaos:".yellow(),
+                conn.malformed(), conn.truncated());
+        }
+        return Ok(ExitStatus::TransferIncomplete);
+    };
+    let sent: Vec<SentBlob> = sent.into_iter().flatten().collect();
+    let mut conn = conn.into_inner();
+    // Releases frames still held for reordering
+    if let Err(e) = conn.close().await {
+        say!("{} {}", "❌ Transfer Incomplete:".red(), e);
+        return Ok(ExitStatus::TransferIncomplete);
+    }
+    
+    let duration = clock().elapsed(start);
+    let wire_bytes: usize = sent.iter().map(|s| s.wire_bytes).sum();
+    let mb_s = (wire_bytes as f64 / 1024.0 / 1024.0) / duration.as_secs_f64();
+    let savings = ((fsize as f64 - wire_bytes as f64) / fsize as f64) * 100.0;
+    
+    say!("\n{}", "=== Performance Metrics ===".bold().white().on_blue());
+    say!("{:<15} : {:?}", "Mode", mode);
+    say!("{:<15} : {:.2?}", "Latency", duration);
+    say!("{:<15} : {:.2} MB/s", "Throughput", mb_s);
+    say!("{:<15} : {}", "Total Wire", format_bytes(wire_bytes));
+    
+    if wire_bytes < fsize {
+        say!("{:<15} : {}", "Efficiency", format!("{:.2}% Saved", savings).green().bold());
+    } else {
+        let overhead = ((wire_bytes as f64 / fsize as f64) - 1.0) * 100.0;
+        say!("{:<15} : {}", "Overhead", format!("{:.2}%", overhead).red());
+    }
+    let blob_fee = BlobFee::estimate(fsize, args.blob_base_fee);
+    say!("{:<15} : {} blob(s), {} blob gas, {:.3} gwei ({:.9} ETH) at {} wei", "Blob Fee", blob_fee.blobs, blob_fee.blob_gas,
+        blob_fee.fee_gwei(), blob_fee.fee_eth(), args.blob_base_fee);
+
+    if faults.is_active() {
+        say!("{:<15} : {} duplicated, {} reordered, {} malformed, {} truncated", "Injected", conn.duplicated(), conn.reordered(),
+            conn.malformed(), conn.truncated());
+    }
+    let compressed = (codec != Compression::None).then(|| conn.get_ref().get_ref().sent().1);
+    if let Some(compressed) = compressed {
+        say!("{:<15} : {}, {} on the wire ({:.2}% of the frames)", "Compressed", codec.name(), format_bytes(compressed),
+            compressed as f64 / conn.get_ref().get_ref().sent().0 as f64 * 100.0);
+    }
+    if let Some(rate) = send_rate && mode != ResearchMode::Naive {
+        say!("{:<15} : {}", "Paced At", rate);
+    }
+    if let Some(matrix) = &matrix {
+        show_sampling_costs(matrix);
+    }
+
+    let mut files = Vec::new();
+    let mut published = Vec::new();
+    let mut anchored = Vec::new();
+    for blob in sent {
+        if let Some(manifest) = blob.manifest {
+            let path = args.manifest.clone().unwrap_or_else(|| format!("{}.manifest.json", blob.filename));
+            std::fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+            say!("{:<15} : {}", "Manifest", path);
+            anchored.push((manifest.root.clone(), manifest.commitment.versioned_hashes()));
+        }
+        published.extend(blob.published);
+        files.push(blob.filename);
+    }
+    let anchors = match &args.anchoring.anchor_rpc {
+        Some(endpoint) if !anchored.is_empty() => anchor_blobs(&args.anchoring, endpoint, anchored).await,
+        Some(_) => {
+            say!("{} naive transfers carry no root to anchor", "➜ Not Anchored:".yellow());
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+
+    // Wait for buffer flush before exit
+    clock().sleep(Duration::from_millis(500)).await;
+    drop(conn);
+
+    if args.serve_for > 0 && !published.is_empty() {
+        serve_published(Arc::new(published), args.port, max_frame, &args.compression, &id, Duration::from_secs(args.serve_for)).await?;
+    }
+
+    let summary = TransferSummary {
+        role: "proposer",
+        mode,
+        file: files.join(","),
+        payload_bytes: fsize,
+        wire_bytes,
+        latency_ms: duration.as_secs_f64() * 1000.0,
+        throughput_mb_s: mb_s,
+        savings_pct: savings,
+        blob_fee,
+        send_rate: send_rate.filter(|_| mode != ResearchMode::Naive).map(|r| r.to_string()),
+        compression: compressed.map(|wire_bytes| CompressionSummary { codec, wire_bytes }),
+        matrix,
+        anchors,
+    };
+    log_event(Event::Transfer { peer: peer_id, summary: summary.clone() });
+    print_summary(&summary)?;
+    
+    Ok(ExitStatus::Success)
+}
+
+/// What a light client fetches to reach the target confidence, sampling each blob on its own
+/// against sampling the matrix.
+fn show_sampling_costs(matrix: &MatrixSummary) {
+    say!("\n{}", "=== Bandwidth per Confidence ===".bold().white().on_blue());
+    say!("For {:.2}% confidence that all {} blobs are available:", matrix.target_confidence * 100.0, matrix.shape.blobs);
+    say!("{:<12} {:>10} {:>8} {:>12} {:>9}", "sampling", "per sample", "samples", "bytes", "fooled");
+    for cost in &matrix.costs {
+        say!("{:<12} {:>10} {:>8} {:>12} {:>8.2}%", cost.unit, format_bytes(cost.sample_bytes), cost.samples, format_bytes(cost.bytes), cost.fooled * 100.0);
+    }
+}
+
+/// The proposer's connection, shared by the transfers multiplexed onto it.
+type SharedConn = tokio::sync::Mutex<FaultyConnection<PeerConnection<TcpConnection>>>;
+
+/// What one transfer put on the wire, and what it leaves the proposer owing.
+struct SentBlob {
+    filename: String,
+    wire_bytes: usize,
+    manifest: Option<Manifest>,
+    published: Option<Published>,
+}
+
+/// Sends one frame, then yields so the other streams' frames can go next.
+async fn send_on(conn: &SharedConn, frame: String, limit: usize) -> Result<(), TransportError> {
+    conn.lock().await.send_frame(frame, limit).await?;
+    tokio::task::yield_now().await;
+    Ok(())
+}
+
+/// Sends one `--file` on `stream`: a whole payload as one blob, a windowed file as one blob per
+/// window, each read once the window before it is sent. `None` if the connection failed first.
+async fn send_payload(args: &SendArgs, id: &Identity, conn: &SharedConn, peer_id: &str, peer_max_frame: usize, stream: u32, payload: Payload) -> Result<Option<Vec<SentBlob>>> {
+    let (name, path, len, window) = match payload {
+        Payload::Whole(name, data) => return Ok(send_blob(args, id, conn, peer_id, peer_max_frame, stream, (name, data)).await?.map(|sent| vec![sent])),
+        Payload::Windowed { name, path, len, window } => (name, path, len, window),
+    };
+    let windows = Windows::open(&path, &name, len, window)?;
+    let mut sent = Vec::with_capacity(windows.total());
+    for w in windows {
+        let (name, data) = w?;
+        say!("{} {} ({})", "➜ Window:".blue().bold(), name, format_bytes(data.len()));
+        let Some(blob) = send_blob(args, id, conn, peer_id, peer_max_frame, stream, (name, data)).await? else { return Ok(None) };
+        sent.push(blob);
+    }
+    Ok(Some(sent))
+}
+
+/// Sends one payload on `stream` in the chosen mode. `None` if the connection failed before
+/// the transfer was complete.
+async fn send_blob(args: &SendArgs, id: &Identity, conn: &SharedConn, peer_id: &str, peer_max_frame: usize, stream: u32, (filename, data): (String, Vec<u8>)) -> Result<Option<SentBlob>> {
+    let SendArgs { mode, mut layout, permute, permutation_seed, ref withhold, ref withhold_rows, commitment, ref sampling, disclose_limit, send_rate, gossip_ttl, .. } = *args;
+    let checksum = calculate_sha256(&data);
+    let fsize = data.len();
+    let peer_id = peer_id.to_string();
+    let mut wire_bytes = 0;
+
+    match mode {
+        ResearchMode::Naive => {
+            let msg = P2PMessage::NaiveTransfer { filename: filename.clone(), data, checksum: checksum.clone() };
+            let json = encode_frame(stream, &msg)?;
+            wire_bytes += json.len();
+            log_event(Event::BlobSent { peer: peer_id, file: filename.clone(), root: checksum, bytes: json.len() });
+            if let Err(e) = send_on(conn, json, peer_max_frame).await {
+                say!("{} {}", "❌ Transfer Incomplete:".red(), e);
+                return Ok(None);
+            }
+            Ok(Some(SentBlob { filename, wire_bytes, manifest: None, published: None }))
+        }
+        ResearchMode::DasFull | ResearchMode::DasSample => {
+            if permute {
+                layout.permutation_seed = Some(match permutation_seed {
+                    Some(seed) => {
+                        let digest = Sha256::digest([&seed.to_be_bytes()[..], checksum.as_bytes()].concat());
+                        u64::from_be_bytes(digest[..8].try_into()?)
+                    }
+                    None => rand::thread_rng().r#gen(),
+                });
+            }
+            let blob = DasEncoder::new(DATA_SHARDS, PARITY_SHARDS)?.layout(layout).shard_align(commitment.shard_align()).encode(&data);
+            let shards = &blob.shards;
+            let committed = clock().now();
+            let mut m = Manifest::new(&filename, &blob, commitment.commitment(), &id.public)?;
+            if args.matrix {
+                m.matrix = Some(MatrixCells::new(&blob, matrix_shape(&data)?.rows));
+            }
+            say!("Commitment: {:?} in {:.2?}", commitment, clock().elapsed(committed));
+            show_versioned_hashes(&m.commitment);
+            let root = m.root.clone();
+
+            m.published_ms = Some(clock().unix().as_millis() as u64);
+            let json = encode_frame(stream, &P2PMessage::Manifest(m.clone()))?;
+            wire_bytes += json.len();
+            let msg_id = event_msg_id(&json);
+            if let Err(e) = send_on(conn, json, peer_max_frame).await {
+                say!("{} {}", "❌ Transfer Incomplete:".red(), e);
+                return Ok(None);
+            }
+            log_event(Event::ManifestSent { peer: peer_id.clone(), root: root.clone(), scheme: commitment, msg_id });
+            let published = Published {
+                root: root.clone(),
+                blob: ServedBlob { filename: filename.clone(), layout, original_len: fsize, checksum: checksum.clone(), shards: blob.shards.clone() },
+                withhold: withhold.clone(),
+                withhold_rows: withhold_rows.clone(),
+                cell_len: m.matrix.as_ref().map_or(0, |cells| cells.cell_len),
+                disclose_limit,
+                disclosed: Mutex::new(Vec::new()),
+            };
+            
+            // Full nodes get k random shards; light clients the first round of their strategy.
+            let wire_indices = if mode == ResearchMode::DasSample {
+                Sampler::new(sampling.strategy(), TOTAL_SHARDS).next_round()
+            } else {
+                let mut indices: Vec<usize> = (0..TOTAL_SHARDS).collect();
+                indices.shuffle(&mut rand::thread_rng());
+                indices.truncate(DATA_SHARDS);
+                indices
+            };
+            let count = wire_indices.len();
+            if mode == ResearchMode::DasSample {
+                say!("Sampling: {:?} ({} samples)", sampling.kind, count);
+            }
+
+            // A late shard pushes the schedule back rather than letting the next ones burst.
+            let mut due = clock().now();
+            for &wire in &wire_indices {
+                let i = layout.shard_index(wire).context("wire index outside the codeword")?;
+                if withhold.contains(&wire) {
+                    say!("{} wire index {} (shard {})", "➜ Withholding:".yellow(), wire, i);
+                    log_event(Event::ShardWithheld { peer: peer_id.clone(), root: root.clone(), index: wire });
+                    continue;
+                }
+                // Fresh per shard, so its hops can be followed through relays in the event logs.
+                let trace = format!("{:016x}", rand::random::<u64>());
+                let msg = P2PMessage::DasShard {
+                    filename: filename.clone(),
+                    root: root.clone(),
+                    layout,
+                    original_len: fsize,
+                    index: wire,
+                    data: shards[i].clone(),
+                    full_file_checksum: checksum.clone(),
+                    ttl: gossip_ttl,
+                    trace: Some(trace.clone()),
+                };
+                let json = encode_frame(stream, &msg)?;
+                wire_bytes += json.len();
+                let msg_id = event_msg_id(&json);
+                if let Some(rate) = send_rate {
+                    clock().sleep(due.saturating_sub(clock().now())).await;
+                    due = due.max(clock().now()) + rate.interval(json.len());
+                }
+                if let Err(e) = send_on(conn, json, peer_max_frame).await {
+                    say!("{} {}", "❌ Transfer Incomplete:".red(), e);
+                    return Ok(None);
+                }
+                log_event(Event::ShardSent { peer: peer_id.clone(), root: root.clone(), index: wire, bytes: shards[i].len(), msg_id, trace: Some(trace) });
+            }
+            let end = encode_frame(stream, &P2PMessage::TransferEnd { filename: filename.clone(), root: root.clone(), shards_sent: count })?;
+            log_event(Event::TransferEndSent { peer: peer_id.clone(), root, shards_sent: count, msg_id: event_msg_id(&end) });
+            if let Err(e) = send_on(conn, end, peer_max_frame).await {
+                say!("{} {}", "❌ Transfer Incomplete:".red(), e);
+                return Ok(None);
+            }
+            Ok(Some(SentBlob { filename, wire_bytes, manifest: Some(m), published: Some(published) }))
+        }
+    }
+}
+
+/// A blob the proposer published and still owes to samplers.
+struct Published {
+    root: String,
+    blob: ServedBlob,
+    /// Wire indices the proposer keeps refusing (`--withhold`)
+    withhold: Vec<usize>,
+    /// Rows whose cells alone are refused at the withheld indices (`--withhold-rows`)
+    withhold_rows: Vec<usize>,
+    /// Cell length of a block matrix, to find rows by offset; 0 for other blobs
+    cell_len: usize,
+    disclose_limit: Option<usize>,
+    /// Distinct indices answered so far, across every session
+    disclosed: Mutex<Vec<usize>>,
+}
+
+impl Published {
+    /// Whether `index` may be answered; under a disclosure limit this counts it as given away.
+    fn disclose(&self, index: usize) -> bool {
+        !self.withhold.contains(&index) && self.within_limit(index)
+    }
+
+    /// Like [`disclose`](Self::disclose), for `len` bytes from `offset` of a shard: under
+    /// `--withhold-rows`, a withheld index only loses the cells of those rows.
+    fn disclose_range(&self, index: usize, offset: usize, len: usize) -> bool {
+        if self.withhold.contains(&index) {
+            if self.withhold_rows.is_empty() || self.cell_len == 0 {
+                return false;
+            }
+            let rows = offset / self.cell_len..=(offset + len - 1) / self.cell_len;
+            if self.withhold_rows.iter().any(|r| rows.contains(r)) {
+                return false;
+            }
+        }
+        self.within_limit(index)
+    }
+
+    /// Whether the disclosure limit still lets `index` out, counting it as given away if so.
+    fn within_limit(&self, index: usize) -> bool {
+        let Some(limit) = self.disclose_limit else { return true };
+        let mut disclosed = self.disclosed.locked();
+        if disclosed.contains(&index) {
+            return true;
+        }
+        if disclosed.len() < limit {
+            disclosed.push(index);
+            return true;
+        }
+        false
+    }
+}
+
+/// Serving obligation: answers sample requests for the published roots on `port`, from anyone,
+/// until `deadline` has passed.
+async fn serve_published(published: Arc<Vec<Published>>, port: u16, max_frame: usize, compression: &[Compression], id: &Identity, deadline: Duration) -> Result<()> {
+    let mut listener = TcpTransport::new(max_frame).listen(&format!("0.0.0.0:{}", port)).await?;
+    for blob in published.iter() {
+        say!("\n{} {} on :{} for {:.0?}", "➜ Serving:".green().bold(), blob.root, port, deadline);
+    }
+    let mut sessions = tokio::task::JoinSet::new();
+    let accept = async {
+        while let Ok(mut conn) = listener.accept().await {
+            let (published, id, compression) = (published.clone(), id.clone(), compression.to_vec());
+            sessions.spawn(async move {
+                let addr = conn.peer_addr();
+                let hs = match handshake(&mut conn, &id, max_frame, &compression).await {
+                    Ok(hs) => hs,
+                    Err(e) => {
+                        log_event(Event::Handshake { peer: addr, ok: false, clock_offset_ms: skew_offset(&e) });
+                        return;
+                    }
+                };
+                let peer_id = hex::encode(hs.key.as_bytes());
+                log_event(Event::Handshake { peer: peer_id.clone(), ok: true, clock_offset_ms: Some(hs.clock_offset_ms) });
+                let conn = peer_connection(conn, &id, &hs, max_frame);
+                if let Err(e) = answer_samples(conn, &peer_id, hs.max_frame, &published).await {
+                    say!("{} {}: {}", "❌ Session Error:".red(), addr, e);
+                }
+            });
+        }
+    };
+    tokio::select! {
+        _ = accept => {}
+        _ = clock().sleep(deadline) => say!("{} deadline reached", "➜ Serving:".green().bold()),
+    }
+    sessions.abort_all();
+    Ok(())
+}
+
+async fn answer_samples<C: Connection>(mut conn: C, peer_id: &str, limit: usize, published: &[Published]) -> Result<()> {
+    while let Some(frame) = conn.recv_frame().await {
+        let (stream, reply) = match decode_frame(&frame?) {
+            Ok((stream, P2PMessage::GetShard { root, index })) => {
+                log_event(Event::SampleRequest { peer: peer_id.to_string(), root: root.clone(), index });
+                let reply = published.iter().find(|p| p.root == root)
+                    .filter(|p| p.disclose(index))
+                    .and_then(|p| p.blob.shard_message(&root, index));
+                log_event(Event::SampleResponse { peer: peer_id.to_string(), root: root.clone(), index, found: reply.is_some() });
+                (stream, reply.unwrap_or(P2PMessage::NotFound { root, index }))
+            }
+            Ok((stream, P2PMessage::GetRange { root, index, offset, len })) => {
+                let reply = published.iter().find(|p| p.root == root)
+                    .filter(|p| p.disclose_range(index, offset, len))
+                    .and_then(|p| range_message(&root, index, p.blob.shard(index)?, offset, len));
+                (stream, reply.unwrap_or(P2PMessage::NotFound { root, index }))
+            }
+            Ok((stream, P2PMessage::GetRoots {})) => (stream, P2PMessage::Roots { roots: published.iter().map(|p| p.root.clone()).collect() }),
+            Ok(_) => continue,
+            Err(e) => {
+                log_event(Event::ProtocolError { peer: peer_id.to_string(), reason: e.to_string() });
+                let _ = conn.send(serde_json::to_string(&P2PMessage::ProtocolError { reason: e.to_string() })?).await;
+                break;
+            }
+        };
+        conn.send_frame(encode_frame(stream, &reply)?, limit).await?;
+    }
+    Ok(())
+}
+
+// ANCHORING
+/// How long one JSON-RPC call to the anchoring endpoint may take.
+const ANCHOR_RPC_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a sent anchor transaction's receipt is polled while waiting for it to be mined.
+const ANCHOR_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One blob's anchor transaction, in the proposer summary.
+#[derive(Serialize, Debug, Clone)]
+struct AnchorSummary {
+    root: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx: Option<String>,
+    /// Block the transaction was mined in, when it was within `--anchor-wait`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn parse_selector(selector: &str) -> Result<[u8; 4], String> {
+    hex::decode(selector.trim_start_matches("0x")).ok().and_then(|b| b.try_into().ok())
+        .ok_or_else(|| format!("expected 4 hex bytes, got {:?}", selector))
+}
+
+async fn rpc_call<T>(call: impl std::future::Future<Output = Result<T, AnchorError>>) -> Result<T> {
+    tokio::select! {
+        result = call => Ok(result?),
+        _ = clock().sleep(ANCHOR_RPC_TIMEOUT) => bail!("no answer within {}s", ANCHOR_RPC_TIMEOUT.as_secs()),
+    }
+}
+
+/// Sends one transaction per `(root, versioned hashes)` blob through `--anchor-rpc`, and waits
+/// up to `--anchor-wait` for each to be mined. A failed anchor is reported, not fatal: the blobs
+/// were delivered all the same.
+async fn anchor_blobs(anchoring: &Anchoring, endpoint: &RpcEndpoint, blobs: Vec<(String, Vec<String>)>) -> Vec<AnchorSummary> {
+    say!("\n{}", "=== On-Chain Anchors ===".bold().white().on_blue());
+    let rpc = EthRpc::new(endpoint.clone());
+    let from = match &anchoring.anchor_from {
+        Some(from) => Ok(from.clone()),
+        None => rpc_call(rpc.first_account()).await,
+    };
+    let mut anchors = Vec::new();
+    for (root, versioned_hashes) in blobs {
+        let result = match &from {
+            Ok(from) => anchor_blob(anchoring, &rpc, from, &root, &versioned_hashes).await,
+            Err(e) => Err(anyhow!("{}", e)),
+        };
+        let anchor = match result {
+            Ok((tx, block)) => {
+                say!("{} {}… in {}{}", "✓ Anchored:".green().bold(), &root[..16], tx,
+                    block.map(|b| format!(", block {}", b)).unwrap_or_else(|| ", not mined yet".into()));
+                log_event(Event::Anchored { root: root.clone(), tx: tx.clone(), block });
+                AnchorSummary { root, tx: Some(tx), block, error: None }
+            }
+            Err(e) => {
+                say!("{} {}…: {}", "❌ Anchor Failed:".red(), &root[..16], e);
+                AnchorSummary { root, tx: None, block: None, error: Some(e.to_string()) }
+            }
+        };
+        anchors.push(anchor);
+    }
+    anchors
+}
+
+async fn anchor_blob(anchoring: &Anchoring, rpc: &EthRpc, from: &str, root: &str, versioned_hashes: &[String]) -> Result<(String, Option<u64>)> {
+    let data = anchor_calldata(anchoring.anchor_selector, root, versioned_hashes)?;
+    let to = anchoring.anchor_to.as_deref().unwrap_or(from);
+    let tx = rpc_call(rpc.send_transaction(from, to, &data)).await?;
+    if anchoring.anchor_wait == 0 {
+        return Ok((tx, None));
+    }
+    let deadline = clock().now() + Duration::from_secs(anchoring.anchor_wait);
+    loop {
+        if let Some(receipt) = rpc_call(rpc.receipt(&tx)).await? {
+            if !receipt.success {
+                bail!("{} reverted in block {}", tx, receipt.block);
+            }
+            return Ok((tx, Some(receipt.block)));
+        }
+        if clock().now() >= deadline {
+            return Ok((tx, None));
+        }
+        clock().sleep(ANCHOR_POLL_INTERVAL).await;
+    }
+}
+
+// LIGHT CLIENT
+/// Result of a `sample` run, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug)]
+struct SampleSummary {
+    role: &'static str,
+    peer: String,
+    root: String,
+    sampling: SamplingKind,
+    samples: Vec<Sample>,
+    #[serde(flatten)]
+    run: SamplingRun,
+    /// Microseconds to re-check every returned shard in one batch, next to the samples' own times
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_verify_us: Option<u64>,
+    verdict: Verdict,
+    /// From the manifest's publication to the passing verdict, when the manifest carries its send time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence_latency_ms: Option<f64>,
+    /// The signed declaration of unavailability, when `--deadline` passed without the root
+    /// found available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    declared: Option<Attestation>,
+    /// Where the evidence of unavailability was written (`--evidence-dir`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    evidence: Option<String>,
+    latency_ms: f64,
+}
+
+/// What a light client saw while finding a root unavailable, kept so the claim can be audited
+/// later: what it checked against, what it asked for, and what came back, as signed.
+#[derive(Serialize, Debug)]
+struct UnavailabilityEvidence<'a> {
+    root: &'a str,
+    /// The manifest sampled against: header fields, commitment and proposer signature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest: Option<&'a Manifest>,
+    /// The client's pubkey, and the address it sampled
+    client: String,
+    addr: &'a str,
+    sampling: SamplingKind,
+    /// Each sample's index and round, whether it passed, and whether the deadline cut it off
+    samples: &'a [Sample],
+    /// `--deadline` in seconds, and the declaration made at it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deadline: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    declared: Option<&'a Attestation>,
+    /// The session's signed envelopes, or why no session was held: the connection failed,
+    /// the handshake failed, or neither finished by the deadline
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transcript: Option<&'a Transcript>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unreached: Option<&'a str>,
+    /// Unix seconds
+    written_at: u64,
+}
+
+impl UnavailabilityEvidence<'_> {
+    /// Writes the evidence to `<dir>/<root>.evidence.json` and returns the path.
+    fn write(&self, dir: &str) -> Result<String> {
+        std::fs::create_dir_all(dir)?;
+        let path = format!("{}/{}.evidence.json", dir, self.root);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        say!("{} {}", "➜ Evidence:".blue().bold(), path);
+        log_event(Event::Evidence { root: self.root.to_string(), path: path.clone() });
+        Ok(path)
+    }
+}
+
+/// One light client's sampling of a root: what it fetched, and what that could catch.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SamplingRun {
+    unit: SampleUnit,
+    /// Samples taken: `unit`s of a block matrix, shards of any other blob
+    sampled: usize,
+    /// Sample data received
+    bytes: usize,
+    /// Wire bytes of the requests sent after the handshake
+    #[serde(default)]
+    wire_sent: usize,
+    /// Wire bytes of the replies received after the handshake, signatures and framing included
+    #[serde(default)]
+    wire_received: usize,
+    /// Chance these samples catch the smallest withholding that leaves a block matrix
+    /// unrecoverable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    detection: Option<f64>,
+    available: bool,
+}
+
+impl SamplingRun {
+    fn new(unit: SampleUnit, shape: Option<MatrixShape>, sampler: &Sampler, bytes: usize, (wire_sent, wire_received): (usize, usize)) -> Self {
+        let sampled = sampler.history().len();
+        let detection = shape.map(|shape| shape.detection(unit, PARITY_SHARDS, sampled));
+        Self { unit, sampled, bytes, wire_sent, wire_received, detection, available: sampler.is_available() }
+    }
+}
+
+async fn run_light_client(args: SampleArgs, id: Identity) -> Result<ExitStatus> {
+    let SampleArgs { peer, root, manifest: manifest_path, max_frame, compression, sampling, unit, clients, proxy, relay, deadline, evidence_dir } = args;
+    let manifest: Option<Manifest> = match &manifest_path {
+        Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?).context("Invalid manifest")?),
+        None => None,
+    };
+    let root = root.or_else(|| manifest.as_ref().map(|m| m.root.clone())).context("No root to sample")?;
+    let shape = manifest.as_ref().and_then(matrix_of);
+    if unit != SampleUnit::Columns && shape.is_none() {
+        bail!("--unit {} needs the --manifest of a block matrix (send --matrix)", format!("{:?}", unit).to_lowercase());
+    }
+
+    say!("Target: {}", peer);
+    say!("Root: {}", root);
+    say!("Strategy: {:?}", sampling.kind);
+    if let Some(shape) = shape {
+        say!("Unit: {} ({} in the matrix, {} each)", unit.name(), shape.units(unit, PARITY_SHARDS), format_bytes(shape.unit_len(unit, PARITY_SHARDS)));
+    }
+    if let Some(proxy) = proxy {
+        say!("Via: {}", proxy);
+    }
+    if let Some(relay) = &relay {
+        say!("Relay: {}", relay);
+    }
+    let route = Route::new(max_frame, proxy, relay);
+    if let Some(secs) = deadline {
+        say!("Deadline: {}s", secs);
+    }
+    let target = SampleTarget { root: root.clone(), manifest, unit, deadline: deadline.map(|secs| clock().now() + Duration::from_secs(secs)) };
+    if clients > 1 {
+        return run_sybil_clients(clients, peer, target, sampling, route, compression).await;
+    }
+
+    let start = clock().now();
+    let (sampler, returned, bytes, wire, transcript) = match light_client_session(&peer, &id, &target, &sampling, &route, &compression, true).await {
+        Ok(session) => session,
+        Err(e) => {
+            // No session, so nothing was sampled; the evidence records why.
+            if let Some(dir) = &evidence_dir {
+                let reason = format!("{:#}", e);
+                UnavailabilityEvidence {
+                    root: &root, manifest: target.manifest.as_ref(), client: hex::encode(id.public.as_bytes()), addr: &peer, sampling: sampling.kind,
+                    samples: &[], deadline, declared: None, transcript: None, unreached: Some(&reason), written_at: unix_now(),
+                }.write(dir)?;
+            }
+            if e.is::<HandshakeError>() {
+                say!("{} {}", "❌ Auth Failed:".red(), e);
+                return Ok(ExitStatus::AuthFailure);
+            }
+            return Err(e);
+        }
+    };
+
+    // Taken before the batch re-check below, which is only there for comparison.
+    let confidence_latency_ms = target.manifest.as_ref().and_then(|m| m.published_ms).filter(|_| sampler.is_available()).map(since_published);
+    let samples = sampler.history().to_vec();
+    for sample in &samples {
+        let verify = match sample.verify_us {
+            Some(us) => format!(" verified in {:.3} ms", us as f64 / 1000.0),
+            None if sample.late => " unanswered by the deadline".to_string(),
+            None => String::new(),
+        };
+        say!("  {} {:>3} {}{}", if shape.is_some() { unit.name() } else { "index" }, sample.index, if sample.ok { "✓".green() } else { "✗".red() }, verify);
+    }
+    let failed = samples.iter().filter(|s| !s.ok).count();
+    let run = SamplingRun::new(unit, shape, &sampler, bytes, wire);
+    log_event(Event::Sampled { peer: peer.clone(), root: root.clone(), run: run.clone() });
+    say!("\n{}", "=== Light Client Validation ===".bold().blue());
+    say!("Sampled {} {}s ({} failed), {} of data.", samples.len(), if shape.is_some() { unit.name() } else { "shard" }, failed, format_bytes(bytes));
+    say!("Bandwidth: {} on the wire ({} sent, {} received).", format_bytes(run.wire_sent + run.wire_received),
+        format_bytes(run.wire_sent), format_bytes(run.wire_received));
+    if let Some(detection) = run.detection {
+        say!("Detection: {:.2}% against the smallest unrecoverable withholding.", detection * 100.0);
+    }
+    let verify_us: Vec<u64> = samples.iter().filter_map(|s| s.verify_us).collect();
+    if !verify_us.is_empty() {
+        let mean_ms = verify_us.iter().sum::<u64>() as f64 / verify_us.len() as f64 / 1000.0;
+        say!("Verification: {:.3} ms per sample ({} checked against the manifest).", mean_ms, verify_us.len());
+    }
+    let batch_verify_us = target.manifest.as_ref().and_then(|m| {
+        let shards: Vec<(usize, &[u8])> = returned.iter()
+            .filter(|r| r.row.is_none())
+            .filter_map(|r| Some((m.layout.shard_index(r.index)?, r.data.as_slice())))
+            .collect();
+        if shards.is_empty() { return None; }
+        let start = clock().now();
+        m.commitment.verify_shards(&shards)?;
+        let us = clock().elapsed(start).as_micros() as u64;
+        say!("Batched: {:.3} ms for all {} ({:.3} ms per sample, one by one took {:.3} ms).",
+            us as f64 / 1000.0, shards.len(), us as f64 / 1000.0 / shards.len() as f64, verify_us.iter().sum::<u64>() as f64 / 1000.0);
+        Some(us)
+    });
+    let verdict = if sampler.is_available() {
+        say!("{}", "✓ Data Availability Verified".green());
+        Verdict::Sampled
+    } else {
+        say!("{}", "❌ Samples Missing: Data Unavailable".red());
+        Verdict::Unavailable
+    };
+    if let Some(ms) = confidence_latency_ms {
+        say!("Confident {:.1} ms after the header", ms);
+    }
+    // Past a deadline, unavailability is a formal, signed declaration.
+    let declared = deadline.filter(|_| verdict == Verdict::Unavailable).map(|secs| {
+        let late = samples.iter().filter(|s| s.late).count();
+        say!("{} {} after {}s ({} sample(s) failed, {} unanswered)", "❌ Declared Unavailable:".red().bold(), root, secs, failed - late, late);
+        target.declare(&id, &peer, &samples)
+    });
+    let evidence = match &evidence_dir {
+        Some(dir) if verdict == Verdict::Unavailable => Some(UnavailabilityEvidence {
+            root: &root,
+            manifest: target.manifest.as_ref(),
+            client: hex::encode(id.public.as_bytes()),
+            addr: &peer,
+            sampling: sampling.kind,
+            samples: &samples,
+            deadline,
+            declared: declared.as_ref(),
+            transcript: transcript.as_ref().ok(),
+            unreached: transcript.as_ref().err().map(String::as_str),
+            written_at: unix_now(),
+        }.write(dir)?),
+        _ => None,
+    };
+
+    print_summary(&SampleSummary {
+        role: "light-client",
+        peer,
+        root,
+        sampling: sampling.kind,
+        samples,
+        run,
+        batch_verify_us,
+        verdict,
+        confidence_latency_ms,
+        declared: declared.clone(),
+        evidence,
+        latency_ms: clock().elapsed(start).as_secs_f64() * 1000.0,
+    })?;
+    Ok(if declared.is_some() { ExitStatus::DeclaredUnavailable } else { verdict.exit_status() })
+}
+
+/// What a light client samples: a root, checked against its manifest when there is one, in
+/// `unit`s when that manifest describes a block matrix.
+#[derive(Clone)]
+struct SampleTarget {
+    root: String,
+    manifest: Option<Manifest>,
+    unit: SampleUnit,
+    /// When sampling must be done by, on the clock
+    deadline: Option<Duration>,
+}
+
+impl SampleTarget {
+    /// The block matrix being sampled by `unit`, if it is one; how many units there are to
+    /// sample from; and the smallest withholding that leaves the root unrecoverable, which
+    /// confidence is against.
+    fn space(&self) -> (Option<MatrixShape>, usize, usize) {
+        let manifest = self.manifest.as_ref();
+        let shape = manifest.and_then(matrix_of).filter(|_| self.unit != SampleUnit::Columns);
+        let n = match shape {
+            Some(shape) => shape.units(self.unit, PARITY_SHARDS),
+            None => manifest.map_or(TOTAL_SHARDS, |m| m.k + m.m),
+        };
+        let withheld = match shape {
+            Some(shape) => shape.withheld(self.unit, PARITY_SHARDS),
+            None => manifest.map_or(PARITY_SHARDS, |m| m.m) + 1,
+        };
+        (shape, n, withheld)
+    }
+
+    /// A sampling that never reached the peer: the strategy's first round, every sample of it
+    /// cut off by the deadline.
+    fn unreached(&self, sampling: &SamplingArgs) -> Sampler {
+        let mut sampler = Sampler::new(sampling.strategy(), self.space().1);
+        for index in sampler.next_round() {
+            sampler.record_late(index);
+        }
+        sampler
+    }
+
+    /// Signs and logs the declaration that `root` is unavailable, made as `id` when the
+    /// deadline passed with `samples` taken from `peer`.
+    fn declare(&self, id: &Identity, peer: &str, samples: &[Sample]) -> Attestation {
+        let failed = samples.iter().filter(|s| !s.ok).count();
+        let late = samples.iter().filter(|s| s.late).count();
+        let filename = self.manifest.as_ref().map(|m| m.filename.clone()).unwrap_or_default();
+        let outcome = FileOutcome { file: filename, root: self.root.clone(), shards: samples.len() - failed, verdict: Verdict::Unavailable, threshold_ms: None, reconstruct_ms: None, confidence_latency_ms: None, reconstruction_latency_ms: None, senders: BTreeMap::new() };
+        let attestation = Attestation::new(id, &outcome);
+        log_event(Event::NegativeAttestation { peer: peer.to_string(), failed: failed - late, unanswered: late, attestation: attestation.clone() });
+        attestation
+    }
+}
+
+/// One light client: connect over `route`, authenticate as `id`, and run the strategy against
+/// the target, printing its confidence as it goes when `live`. Also returns the session's wire
+/// bytes, as `(sent, received)`, and its transcript, or why no session was held. With a
+/// deadline, a peer that can't be reached, or that doesn't complete the handshake by then,
+/// leaves every sample of the first round unanswered.
+async fn light_client_session(peer: &str, id: &Identity, target: &SampleTarget, sampling: &SamplingArgs, route: &Route, compression: &[Compression], live: bool) -> Result<(Sampler, Vec<Returned>, usize, (usize, usize), std::result::Result<Transcript, String>)> {
+    let open = async {
+        let mut conn = route.connect(peer).await.context("Connection Failed")?;
+        match route.handshake(&mut conn, id, peer, compression).await {
+            Ok(hs) => Ok((conn, hs)),
+            Err(e) => {
+                log_event(Event::Handshake { peer: peer.to_string(), ok: false, clock_offset_ms: skew_offset(&e) });
+                Err(anyhow::Error::from(e))
+            }
+        }
+    };
+    let opened = match target.deadline {
+        Some(deadline) => tokio::select! {
+            opened = open => Some(opened),
+            _ = clock().sleep(deadline.saturating_sub(clock().now())) => None,
+        },
+        None => Some(open.await),
+    };
+    let unreached = |reason: String| {
+        if live {
+            say!("{} {}", "❌ Peer Unreachable:".red(), reason);
+        }
+        (target.unreached(sampling), Vec::new(), 0, (0, 0), Err(reason))
+    };
+    let (conn, hs) = match opened {
+        Some(Ok(opened)) => opened,
+        Some(Err(e)) if target.deadline.is_none() || e.is::<HandshakeError>() => return Err(e),
+        Some(Err(e)) => return Ok(unreached(format!("{:#}", e))),
+        None => return Ok(unreached(format!("no session with {} by the deadline", peer))),
+    };
+    let peer_id = hex::encode(hs.key.as_bytes());
+    log_event(Event::Handshake { peer: peer_id.clone(), ok: true, clock_offset_ms: Some(hs.clock_offset_ms) });
+    let mut conn = peer_connection(conn, id, &hs, route.max_frame()).keep_transcript();
+    let (sampler, returned, bytes) = sample_root(&mut conn, &peer_id, hs.max_frame, target, sampling, live).await;
+    let transcript = Transcript { peer: peer_id, session: hex::encode(hs.session), envelopes: conn.transcript().to_vec() };
+    let wire = conn.get_ref();
+    Ok((sampler, returned, bytes, (wire.sent().1, wire.received()), Ok(transcript)))
+}
+
+/// A light client's session as both sides signed it: the peer's key, the handshake's session
+/// ID that every envelope signature covers, and the envelopes both ways in order.
+#[derive(Serialize, Debug)]
+struct Transcript {
+    peer: String,
+    session: String,
+    envelopes: Vec<Envelope>,
+}
+
+/// One light client of a `--clients` run.
+#[derive(Serialize, Debug)]
+struct SybilClient {
+    client: usize,
+    pubkey: String,
+    verdict: Verdict,
+    samples: Vec<Sample>,
+    /// The client's signed declaration, when `--deadline` passed without it convinced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    declared: Option<Attestation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Result of a `sample --clients N` run, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug)]
+struct SybilSummary {
+    role: &'static str,
+    peer: String,
+    root: String,
+    sampling: SamplingKind,
+    unit: SampleUnit,
+    clients: usize,
+    /// Clients that concluded the blob is available
+    convinced: usize,
+    /// Distinct indices (of `unit`s, for a block matrix) answered to anyone in the population
+    revealed: Vec<usize>,
+    /// Whether the population together received enough to rebuild the blob
+    reconstructable: bool,
+    /// How decoding a block matrix from every cell the population received went
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decoding: Option<MatrixDecodeReport>,
+    latency_ms: f64,
+    results: Vec<SybilClient>,
+}
+
+/// Many light clients from one process, each with a fresh identity and its own session and
+/// random choices. Shows how far a proposer that answers selectively can get: how many clients
+/// it convinces against how many distinct shards it had to give away.
+async fn run_sybil_clients(clients: usize, peer: String, target: SampleTarget, sampling: SamplingArgs, route: Route, compression: Vec<Compression>) -> Result<ExitStatus> {
+    let k = target.manifest.as_ref().map_or(DATA_SHARDS, |m| m.k);
+    let shape = target.manifest.as_ref().and_then(matrix_of);
+    let target = Arc::new(target);
+    let (root, unit) = (target.root.clone(), target.unit);
+    let start = clock().now();
+    let mut tasks = tokio::task::JoinSet::new();
+    for client in 0..clients {
+        let (peer, target, route, compression) = (peer.clone(), target.clone(), route.clone(), compression.clone());
+        tasks.spawn(async move {
+            let id = Identity::generate();
+            // A `coordinated` population splits the indices between its members.
+            let sampling = sampling.for_member(sampling.member + client);
+            let session = light_client_session(&peer, &id, &target, &sampling, &route, &compression, false).await;
+            (client, id, session)
+        });
+    }
+
+    let mut results = Vec::with_capacity(clients);
+    let mut received = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (client, id, session) = joined?;
+        let pubkey = hex::encode(id.public.as_bytes());
+        results.push(match session {
+            Ok((sampler, returned, bytes, wire, _)) => {
+                received.extend(returned);
+                log_event(Event::Sampled { peer: peer.clone(), root: root.clone(), run: SamplingRun::new(unit, shape, &sampler, bytes, wire) });
+                let verdict = if sampler.is_available() { Verdict::Sampled } else { Verdict::Unavailable };
+                let samples = sampler.history().to_vec();
+                // Each client past the deadline declares for itself, under its own key.
+                let declared = target.deadline.filter(|_| verdict == Verdict::Unavailable).map(|_| target.declare(&id, &peer, &samples));
+                SybilClient { client, pubkey, verdict, samples, declared, error: None }
+            }
+            Err(e) => SybilClient { client, pubkey, verdict: Verdict::Incomplete, samples: Vec::new(), declared: None, error: Some(e.to_string()) },
+        });
+    }
+    results.sort_by_key(|r| r.client);
+
+    for r in &results {
+        let mark = if r.verdict == Verdict::Sampled { "✓".green() } else { "✗".red() };
+        let answered = r.samples.iter().filter(|s| s.ok).count();
+        say!("  client {:>4} {} {}/{} answered {}", r.client, mark, answered, r.samples.len(), r.error.as_deref().unwrap_or(""));
+    }
+    let convinced = results.iter().filter(|r| r.verdict == Verdict::Sampled).count();
+    let mut revealed: Vec<usize> = results.iter().flat_map(|r| r.samples.iter().filter(|s| s.ok).map(|s| s.index)).collect();
+    revealed.sort();
+    revealed.dedup();
+    let decoding = match (shape, target.manifest.as_ref()) {
+        (Some(shape), Some(m)) => Some(decode_population(shape, m, &received)?),
+        _ => None,
+    };
+    let reconstructable = decoding.as_ref().map_or(revealed.len() >= k, |d| d.complete);
+
+    say!("\n{}", "=== Sybil Sampling ===".bold().blue());
+    say!("{:<15} : {}/{}", "Convinced", convinced, clients);
+    match shape {
+        Some(shape) => say!("{:<15} : {} distinct {}s of {}", "Revealed", revealed.len(), unit.name(), shape.units(unit, PARITY_SHARDS)),
+        None => say!("{:<15} : {} distinct indices {:?} (k = {})", "Revealed", revealed.len(), revealed, k),
+    }
+    if let (Some(d), Some(shape)) = (&decoding, shape) {
+        let cells = shape.units(SampleUnit::Cells, PARITY_SHARDS);
+        say!("{:<15} : {} of {} cells held, {} recovered in {} iteration{} ({} rows, {} columns decoded)",
+            "Decoding", d.known, cells, d.recovered, d.iterations, if d.iterations == 1 { "" } else { "s" }, d.rows_decoded, d.columns_decoded);
+    }
+    if convinced > 0 && !reconstructable {
+        say!("{}", "❌ Clients convinced while the answers cannot rebuild the blob".red().bold());
+    }
+
+    let declared = results.iter().filter(|r| r.declared.is_some()).count();
+    if declared > 0 {
+        say!("{} {} by {} of {} clients at the deadline", "❌ Declared Unavailable:".red().bold(), root, declared, clients);
+    }
+    let status = if convinced == clients {
+        ExitStatus::Success
+    } else if declared > 0 {
+        ExitStatus::DeclaredUnavailable
+    } else {
+        ExitStatus::SamplingUnavailable
+    };
+    print_summary(&SybilSummary {
+        role: "light-client",
+        peer,
+        root,
+        sampling: sampling.kind,
+        unit,
+        clients,
+        convinced,
+        revealed,
+        reconstructable,
+        decoding,
+        latency_ms: clock().elapsed(start).as_secs_f64() * 1000.0,
+        results,
+    })?;
+    Ok(status)
+}
+
+/// Decodes a block matrix from every cell and shard a population of light clients received,
+/// across both axes, and checks each cell this recovered against the manifest's cell hash.
+fn decode_population(shape: MatrixShape, manifest: &Manifest, received: &[Returned]) -> Result<MatrixDecodeReport> {
+    let matrix = manifest.matrix.as_ref().context("manifest is not a block matrix")?;
+    let mut decoder = MatrixDecoder::new(shape, manifest.m, manifest.layout)?;
+    for r in received {
+        let column = manifest.layout.shard_index(r.index).context("wire index out of range")?;
+        match r.row {
+            Some(row) => decoder.insert(row, column, r.data.clone())?,
+            None => decoder.insert_column(column, &r.data)?,
+        }
+    }
+    let mut report = decoder.decode()?;
+    let wrong = (0..shape.rows)
+        .flat_map(|row| (0..manifest.k + manifest.m).map(move |column| (row, column)))
+        .filter(|&(row, column)| decoder.cell(row, column).is_some_and(|cell| !matrix.verify(row, column, cell)))
+        .count();
+    if wrong > 0 {
+        // Rows and columns that decode to cells the proposer never committed to: the two codes disagree.
+        say!("{} {} decoded cells do not match the manifest", "❌ Bad Extension:".red(), wrong);
+        report.complete = false;
+    }
+    Ok(report)
+}
+
+/// A shard, or one cell of it, that came back for a sample and passed the manifest's check, if
+/// there was one.
+struct Returned {
+    filename: String,
+    /// Wire index
+    index: usize,
+    /// The block matrix row, when only that cell of the shard came back
+    row: Option<usize>,
+    data: Vec<u8>,
+}
+
+/// Runs a sampling strategy against `root` over an open session. Returns the finished sampler
+/// and every shard that came back. The running confidence is logged after each answered
+/// sample, and with `live` printed as well.
+async fn sample_root<C: Connection>(conn: &mut C, peer_id: &str, limit: usize, target: &SampleTarget, sampling: &SamplingArgs, live: bool) -> (Sampler, Vec<Returned>, usize) {
+    let SampleTarget { ref root, ref manifest, unit, deadline } = *target;
+    let manifest = manifest.as_ref();
+    let (shape, n, withheld) = target.space();
+    let mut sampler = Sampler::new(sampling.strategy(), n);
+    let mut returned = Vec::new();
+    let mut bytes = 0;
+    let mut connected = true;
+    // Set once the deadline passes: what is left unasked fails, and no further round is asked.
+    let mut late = false;
+    loop {
+        let round = if late { Vec::new() } else { sampler.next_round() };
+        if round.is_empty() { break; }
+        for index in round {
+            if late || deadline.is_some_and(|d| clock().now() >= d) {
+                late = true;
+                sampler.record_late(index);
+                continue;
+            }
+            log_event(Event::SampleRequest { peer: peer_id.to_string(), root: root.to_string(), index });
+            let answer = if !connected { Some((false, None)) } else {
+                let fetch = async { match (shape, manifest) {
+                    (Some(shape), Some(m)) => fetch_cells(conn, root, &shape.unit_cells(unit, m.m, index), limit, m).await.map(|(cells, complete, verify)| {
+                        // Cells of a sample that failed still go towards the population's decoding.
+                        bytes += cells.iter().map(|c| c.data.len()).sum::<usize>();
+                        returned.extend(cells);
+                        (complete, verify)
+                    }),
+                    _ => fetch_sample(conn, root, index, limit, manifest).await.map(|(shard, verify)| {
+                        let ok = shard.is_some();
+                        bytes += shard.as_ref().map_or(0, |s| s.data.len());
+                        returned.extend(shard);
+                        (ok, verify)
+                    }),
+                } };
+                let fetched = match deadline {
+                    Some(deadline) => tokio::select! {
+                        fetched = fetch => Some(fetched),
+                        _ = clock().sleep(deadline.saturating_sub(clock().now())) => None,
+                    },
+                    None => Some(fetch.await),
+                };
+                match fetched {
+                    Some(Ok(answer)) => Some(answer),
+                    Some(Err(e)) => {
+                        say!("{} {}", "❌ Peer Lost:".red(), e);
+                        connected = false;
+                        Some((false, None))
+                    }
+                    None => None,
+                }
+            };
+            let Some((ok, verify)) = answer else {
+                log_event(Event::SampleResponse { peer: peer_id.to_string(), root: root.to_string(), index, found: false });
+                late = true;
+                sampler.record_late(index);
+                continue;
+            };
+            log_event(Event::SampleResponse { peer: peer_id.to_string(), root: root.to_string(), index, found: ok });
+            match verify {
+                Some(verify) => sampler.record_verified(index, ok, verify),
+                None => sampler.record(index, ok),
+            }
+            if ok {
+                let (confidence, answered) = (sampler.confidence(withheld), sampler.history().iter().filter(|s| s.ok).count());
+                log_event(Event::Confidence { peer: peer_id.to_string(), root: root.to_string(), index, answered, confidence });
+                if live {
+                    say!("{} {:.2}% after {} answered sample(s)", "➜ Confidence:".blue().bold(), confidence * 100.0, answered);
+                }
+            }
+        }
+    }
+    (sampler, returned, bytes)
+}
+
+/// The shape of the block matrix a manifest describes, if it is one.
+fn matrix_of(manifest: &Manifest) -> Option<MatrixShape> {
+    manifest.matrix.as_ref().map(|cells| cells.shape(manifest.k))
+}
+
+/// Requests `(row, wire index)` cells of a block matrix one by one, each checked against the
+/// manifest's cell hash, until one does not come back or fails. The cells that passed, whether
+/// that was all of them, and how long checking them took, if any came back.
+async fn fetch_cells<C: Connection>(conn: &mut C, root: &str, cells: &[(usize, usize)], limit: usize, manifest: &Manifest) -> Result<(Vec<Returned>, bool, Option<Duration>)> {
+    let matrix = manifest.matrix.as_ref().context("manifest is not a block matrix")?;
+    let (mut passed, mut verify) = (Vec::new(), None);
+    'cells: for &(row, index) in cells {
+        let offset = row * matrix.cell_len;
+        let request = P2PMessage::GetRange { root: root.to_string(), index, offset, len: matrix.cell_len };
+        conn.send_frame(serde_json::to_string(&request)?, limit).await?;
+        while let Some(frame) = conn.recv_frame().await {
+            match decode_message(&frame?)? {
+                P2PMessage::Range { root: r, index: i, offset: o, data } if r == root && i == index && o == offset => {
+                    let start = clock().now();
+                    let ok = manifest.layout.shard_index(index).is_some_and(|s| matrix.verify(row, s, &data));
+                    verify = Some(verify.unwrap_or_default() + clock().elapsed(start));
+                    if !ok { return Ok((passed, false, verify)); }
+                    passed.push(Returned { filename: manifest.filename.clone(), index, row: Some(row), data });
+                    continue 'cells;
+                }
+                P2PMessage::NotFound { root: r, index: i } if r == root && i == index => return Ok((passed, false, verify)),
+                P2PMessage::ProtocolError { reason } => bail!("peer rejected request: {}", reason),
+                _ => {}
+            }
+        }
+        bail!("peer closed the connection")
+    }
+    Ok((passed, true, verify))
+}
+
+/// Requests one shard and waits for its answer: the shard if it came back and passed the
+/// manifest's commitment, `None` for `NotFound` or a failing shard. With a manifest, also how
+/// long checking the shard took.
+async fn fetch_sample<C: Connection>(conn: &mut C, root: &str, index: usize, limit: usize, manifest: Option<&Manifest>) -> Result<(Option<Returned>, Option<Duration>)> {
+    let request = P2PMessage::GetShard { root: root.to_string(), index };
+    conn.send_frame(serde_json::to_string(&request)?, limit).await?;
+    while let Some(frame) = conn.recv_frame().await {
+        match decode_message(&frame?)? {
+            P2PMessage::DasShard { filename, root: r, index: i, data, .. } if r == root && i == index => {
+                let returned = Returned { filename, index, row: None, data };
+                let Some(m) = manifest else { return Ok((Some(returned), None)) };
+                let start = clock().now();
+                let ok = m.layout.shard_index(index).is_some_and(|s| m.verify_shard(s, &returned.data) != Some(false));
+                return Ok((ok.then_some(returned), Some(clock().elapsed(start))));
+            }
+            P2PMessage::NotFound { root: r, index: i } if r == root && i == index => return Ok((None, None)),
+            P2PMessage::ProtocolError { reason } => bail!("peer rejected request: {}", reason),
+            _ => {}
+        }
+    }
+    bail!("peer closed the connection")
+}
+
+// FETCH
+/// Result of a `fetch` run, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug)]
+struct FetchSummary {
+    role: &'static str,
+    peers: Vec<String>,
+    #[serde(flatten)]
+    outcome: FileOutcome,
+    /// Shard data received, including shards that were turned away
+    bytes: usize,
+    latency_ms: f64,
+}
+
+/// A shard as a serving node returned it.
+struct FetchedShard {
+    layout: Layout,
+    original_len: usize,
+    data: Vec<u8>,
+    checksum: String,
+}
+
+/// A blob being pulled together from serving nodes.
+struct Fetch {
+    root: String,
+    manifest: Option<Manifest>,
+    /// Where the blob goes, reserved as an empty file until it is rebuilt and checked
+    out: String,
+    /// Opened with the first shard that passes, on the manifest's layout or else that shard's
+    decoder: Option<StreamingDecoder>,
+    /// The blob's checksum, from the manifest or the first shard
+    checksum: String,
+    bytes: usize,
+    /// From the first shard to the k-th
+    threshold: Option<Duration>,
+}
+
+impl Fetch {
+    fn complete(&self) -> bool {
+        self.decoder.as_ref().is_some_and(|d| d.len() >= DATA_SHARDS)
+    }
+
+    /// Wire indices still missing, data shards first so a systematic blob is written out as they
+    /// arrive.
+    fn wanted(&self) -> Vec<usize> {
+        let layout = self.decoder.as_ref().map(|d| d.layout()).or(self.manifest.as_ref().map(|m| m.layout));
+        let held = |i: usize| self.decoder.as_ref().is_some_and(|d| d.layout().shard_index(i).is_some_and(|s| d.has(s)));
+        let mut wanted: Vec<usize> = (0..TOTAL_SHARDS).filter(|&i| !held(i)).collect();
+        if let Some(layout) = layout {
+            wanted.sort_by_key(|&i| layout.shard_index(i).is_none_or(|s| s >= DATA_SHARDS));
+        }
+        wanted
+    }
+
+    /// Requests the missing shards from `peer` until k have passed or it has no more.
+    async fn pull_from(&mut self, peer: &str, id: &Identity, route: &Route, compression: &[Compression]) -> Result<()> {
+        let mut conn = route.connect(peer).await.context("Connection Failed")?;
+        let hs = match route.handshake(&mut conn, id, peer, compression).await {
+            Ok(hs) => hs,
+            Err(e) => {
+                log_event(Event::Handshake { peer: peer.to_string(), ok: false, clock_offset_ms: skew_offset(&e) });
+                return Err(e.into());
+            }
+        };
+        let peer_id = hex::encode(hs.key.as_bytes());
+        log_event(Event::Handshake { peer: peer_id.clone(), ok: true, clock_offset_ms: Some(hs.clock_offset_ms) });
+        say!("\n{} {}", "➜ Fetching from:".blue().bold(), peer);
+        let mut conn = peer_connection(conn, id, &hs, route.max_frame());
+        for index in self.wanted() {
+            if self.complete() { break; }
+            log_event(Event::SampleRequest { peer: peer_id.clone(), root: self.root.clone(), index });
+            let verdict = match fetch_shard(&mut conn, &self.root, index, hs.max_frame).await? {
+                Some(shard) => {
+                    self.bytes += shard.data.len();
+                    self.accept(&peer_id, index, shard)
+                }
+                None => Err("not found".to_string()),
+            };
+            log_event(Event::SampleResponse { peer: peer_id.clone(), root: self.root.clone(), index, found: verdict.is_ok() });
+            match verdict {
+                Ok(()) => say!("  index {:>3} {}", index, "✓".green()),
+                Err(reason) => say!("  index {:>3} {} {}", index, "✗".red(), reason),
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a returned shard against the manifest, or against the shards already taken, and
+    /// buffers it. Says why when it is turned away.
+    fn accept(&mut self, peer_id: &str, index: usize, shard: FetchedShard) -> std::result::Result<(), String> {
+        if let Some(m) = &self.manifest
+            && m.layout.shard_index(index).is_none_or(|s| m.verify_shard(s, &shard.data) == Some(false)) {
+            return Err("fails the manifest's commitment".into());
+        }
+        let decoder = match &mut self.decoder {
+            Some(decoder) => {
+                if (shard.layout, shard.original_len, shard.data.len()) != (decoder.layout(), decoder.original_len(), decoder.shard_len())
+                    || (self.manifest.is_none() && shard.checksum != self.checksum) {
+                    return Err("does not match the shards already received".into());
+                }
+                decoder
+            }
+            None => {
+                let (layout, original_len, shard_len, checksum) = match &self.manifest {
+                    Some(m) => (m.layout, m.original_len, m.shard_len, m.checksum.clone()),
+                    None => (shard.layout, shard.original_len, shard.data.len(), shard.checksum),
+                };
+                self.checksum = checksum;
+                self.decoder.insert(StreamingDecoder::new(self.out.clone(), layout, original_len, shard_len, clock().clone()))
+            }
+        };
+        // The decoder counts shards in codeword order, not by the wire index asked for.
+        let index = decoder.layout().shard_index(index).ok_or("maps to no shard of the layout")?;
+        decoder.push(index, shard.data).map_err(|e| e.to_string())?;
+        decoder.senders.insert(index, peer_id.to_string());
+        if decoder.len() >= DATA_SHARDS {
+            self.threshold.get_or_insert(decoder.elapsed());
+        }
+        Ok(())
+    }
+}
+
+/// Requests one shard and waits for its answer: `None` for `NotFound`.
+async fn fetch_shard<C: Connection>(conn: &mut C, root: &str, index: usize, limit: usize) -> Result<Option<FetchedShard>> {
+    let request = P2PMessage::GetShard { root: root.to_string(), index };
+    conn.send_frame(serde_json::to_string(&request)?, limit).await?;
+    while let Some(frame) = conn.recv_frame().await {
+        match decode_message(&frame?)? {
+            P2PMessage::DasShard { root: r, layout, original_len, index: i, data, full_file_checksum, .. } if r == root && i == index => {
+                return Ok(Some(FetchedShard { layout, original_len, data, checksum: full_file_checksum }));
+            }
+            P2PMessage::NotFound { root: r, index: i } if r == root && i == index => return Ok(None),
+            P2PMessage::ProtocolError { reason } => bail!("peer rejected request: {}", reason),
+            _ => {}
+        }
+    }
+    bail!("peer closed the connection")
+}
+
+async fn run_fetch(args: FetchArgs, id: Identity) -> Result<ExitStatus> {
+    let FetchArgs { peers, root, manifest: manifest_path, out, max_frame, compression, proxy, relay, on_reconstruct } = args;
+    let manifest: Option<Manifest> = match &manifest_path {
+        Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?).context("Invalid manifest")?),
+        None => None,
+    };
+    if let Some(m) = &manifest {
+        if (m.k, m.m) != (DATA_SHARDS, PARITY_SHARDS) {
+            bail!("{} is RS({}, {}); fetch rebuilds RS({}, {}) blobs only", manifest_path.unwrap_or_default(), m.k, m.k + m.m, DATA_SHARDS, TOTAL_SHARDS);
+        }
+        m.validate().context("Invalid manifest")?;
+        if root.as_ref().is_some_and(|root| *root != m.root) {
+            bail!("--root {} is not the manifest's root {}", root.unwrap_or_default(), m.root);
+        }
+    }
+    let root = root.or_else(|| manifest.as_ref().map(|m| m.root.clone())).context("No root to fetch")?;
+    if !is_digest(&root) {
+        bail!("{:?} is not a root (64 lowercase hex characters)", root);
+    }
+    // Named by us, never by a peer, and reserved up front so nothing already there is replaced.
+    let out = out.unwrap_or_else(|| manifest.as_ref().map_or_else(|| root.clone(), |m| m.filename.clone()));
+    std::fs::OpenOptions::new().write(true).create_new(true).open(&out)
+        .with_context(|| format!("{} already exists or cannot be created; pass --out to write elsewhere", out))?;
+    say!("Root: {}", root);
+    say!("Peers: {}", peers.join(", "));
+    let route = Route::new(max_frame, proxy, relay);
+
+    let start = clock().now();
+    let mut fetch = Fetch { root: root.clone(), manifest, out, decoder: None, checksum: String::new(), bytes: 0, threshold: None };
+    for peer in &peers {
+        if fetch.complete() { break; }
+        if let Err(e) = fetch.pull_from(peer, &id, &route, &compression).await {
+            say!("{} {}: {}", "❌ Peer Failed:".red(), peer, e);
+        }
+    }
+
+    say!("\n{}", "=== Fetch ===".bold().blue());
+    let shards = fetch.decoder.as_ref().map_or(0, |d| d.len());
+    let mut reconstruct_ms = None;
+    let verdict = match &mut fetch.decoder {
+        Some(decoder) if shards >= DATA_SHARDS => {
+            let start = clock().now();
+            // The checksum may be the peers' word; the root is ours, so the shards must hash to it.
+            let hashes: Vec<String> = decoder.extended_shards()?.iter().map(|s| calculate_sha256(s)).collect();
+            let rooted = merkle_root(&hashes) == fetch.root;
+            if !rooted {
+                decoder.discard_output();
+            }
+            let ok = rooted && decoder.finish(&fetch.checksum)?;
+            reconstruct_ms = Some(clock().elapsed(start).as_secs_f64() * 1000.0);
+            if !rooted {
+                say!("{}", "❌ Root Mismatch: shards do not hash to the root; blob discarded".red());
+                Verdict::Corrupted
+            } else if ok {
+                say!("{} {} ({}) from {} shards", "✓ Reconstructed:".green(), decoder.path, format_bytes(decoder.original_len()), shards);
+                if let Some(command) = &on_reconstruct {
+                    let _ = run_reconstruct_hook(command, &decoder.path, &root).await;
+                }
+                Verdict::Reconstructed
+            } else {
+                say!("{}", "❌ Checksum Mismatch: blob discarded".red());
+                Verdict::Corrupted
+            }
+        }
+        decoder => {
+            if let Some(decoder) = decoder {
+                decoder.discard_output();
+            }
+            say!("{} {} of {} shards", "❌ Incomplete:".red(), shards, DATA_SHARDS);
+            Verdict::Incomplete
+        }
+    };
+    if verdict != Verdict::Reconstructed {
+        let _ = std::fs::remove_file(&fetch.out);
+    }
+    say!("Received {} of shard data.", format_bytes(fetch.bytes));
+
+    let reconstruction_latency_ms = fetch.manifest.as_ref().and_then(|m| m.published_ms)
+        .filter(|_| verdict == Verdict::Reconstructed).map(since_published);
+    let outcome = FileOutcome {
+        file: fetch.decoder.as_ref().map_or_else(String::new, |d| d.path.clone()),
+        root,
+        shards,
+        verdict,
+        threshold_ms: fetch.threshold.map(|t| t.as_secs_f64() * 1000.0),
+        reconstruct_ms,
+        confidence_latency_ms: None,
+        reconstruction_latency_ms,
+        senders: fetch.decoder.as_ref().map(|d| d.shards_by_sender()).unwrap_or_default(),
+    };
+    log_event(Event::Outcome { peer: peers.join(","), outcome: outcome.clone() });
+    print_summary(&FetchSummary {
+        role: "fetcher",
+        peers,
+        outcome,
+        bytes: fetch.bytes,
+        latency_ms: clock().elapsed(start).as_secs_f64() * 1000.0,
+    })?;
+    Ok(verdict.exit_status())
+}
+
+// DUAL ROLE
+impl Validator {
+    /// Light-client side of a validator: every `--sample-interval`, asks each of `--sample-peers`
+    /// which roots it serves and samples the ones not yet found available here.
+    async fn sample_peers(self: Arc<Self>) {
+        loop {
+            for peer in &self.args.sample_peers {
+                if let Err(e) = self.sample_peer(peer).await {
+                    say!("{} {}: {}", "❌ Sampling Failed:".red(), peer, e);
+                }
+            }
+            clock().sleep(Duration::from_secs(self.args.sample_interval)).await;
+        }
+    }
+
+    /// Connects to another node as this validator: handshake, then signed envelopes over the
+    /// negotiated compression.
+    /// Returns the connection, the peer's pubkey and its frame limit.
+    async fn dial(&self, addr: &str) -> Result<(PeerConnection<TcpConnection>, String, usize)> {
+        let max_frame = self.args.max_frame;
+        let (mut conn, expected) = self.connect_peer(addr).await?;
+        let hs = match self.handshake(&mut conn).await.and_then(|hs| {
+            let got = hex::encode(hs.key.as_bytes());
+            match expected {
+                Some(expected) if !got.eq_ignore_ascii_case(expected) => Err(HandshakeError::UnexpectedPeer { expected: expected.to_string(), got }),
+                _ => Ok(hs),
+            }
+        }) {
+            Ok(hs) => hs,
+            Err(e) => {
+                log_event(Event::Handshake { peer: addr.to_string(), ok: false, clock_offset_ms: skew_offset(&e) });
+                return Err(e.into());
+            }
+        };
+        let peer_id = hex::encode(hs.key.as_bytes());
+        self.traffic(&peer_id, |t| t.clock_offset_ms = Some(hs.clock_offset_ms));
+        log_event(Event::Handshake { peer: peer_id.clone(), ok: true, clock_offset_ms: Some(hs.clock_offset_ms) });
+        if let Some(ban) = self.ban_of(&peer_id) {
+            bail!("{} is banned ({})", peer_id, ban.reason);
+        }
+        self.learn_record(hs.record.as_ref());
+        Ok((peer_connection(conn, &self.id, &hs, max_frame), peer_id, hs.max_frame))
+    }
+
+    async fn sample_peer(&self, peer: &str) -> Result<()> {
+        let (mut conn, peer_id, limit) = self.dial(peer).await?;
+
+        conn.send_frame(serde_json::to_string(&P2PMessage::GetRoots {})?, limit).await?;
+        let roots = loop {
+            match decode_message(&conn.recv_frame().await.context("peer closed before listing roots")??)? {
+                P2PMessage::Roots { roots } => break roots,
+                P2PMessage::ProtocolError { reason } => bail!("peer rejected request: {}", reason),
+                _ => {}
+            }
+        };
+        let fresh: Vec<String> = {
+            let state = self.state.locked();
+            roots.into_iter()
+                .filter(|r| !state.serving.contains_key(r) && state.sampled.get(r) != Some(&Verdict::Sampled))
+                .collect()
+        };
+
+        for root in fresh {
+            say!("\n{} {} from {}", "➜ Sampling:".blue().bold(), root, peer);
+            let target = SampleTarget { root: root.clone(), manifest: None, unit: SampleUnit::Columns, deadline: None };
+            let (sampler, returned, _) = sample_root(&mut conn, &peer_id, limit, &target, &self.args.sampling, true).await;
+            for sample in sampler.history() {
+                say!("  index {:>3} {}", sample.index, if sample.ok { "✓".green() } else { "✗".red() });
+            }
+            let verdict = if sampler.is_available() {
+                say!("{}", "✓ Data Availability Verified".green());
+                Verdict::Sampled
+            } else {
+                say!("{}", "❌ Samples Missing: Data Unavailable".red());
+                Verdict::Unavailable
+            };
+            let shards = sampler.history().iter().filter(|s| s.ok).count();
+            let outcome = FileOutcome { file: returned.first().map(|r| r.filename.clone()).unwrap_or_default(), root: root.clone(), shards, verdict, threshold_ms: None, reconstruct_ms: None, confidence_latency_ms: None, reconstruction_latency_ms: None, senders: BTreeMap::new() };
+            log_event(Event::Outcome { peer: peer_id.clone(), outcome: outcome.clone() });
+            if verdict == Verdict::Sampled && let Some(dir) = &self.args.attest_dir {
+                let path = write_attestation(dir, &self.id, &outcome)?;
+                say!("{} {}", "➜ Attestation:".blue().bold(), path);
+                log_event(Event::Attestation { root: root.clone(), path });
+            }
+            self.state.locked().sampled.insert(root, verdict);
+        }
+        Ok(())
+    }
+}
+
+// NODE RECORDS
+/// Serializes peers-file updates from the validators of one process.
+static PEERS_FILE: Mutex<()> = Mutex::new(());
+
+/// A validator's signed record: where it listens, the columns it keeps and what it answers.
+/// Its sequence number is the start time, so a restarted node's record replaces the old one.
+fn node_record(args: &ListenArgs, port: u16, id: &Identity) -> NodeRecord {
+    let addrs = if args.advertise.is_empty() { vec![format!("127.0.0.1:{}", port)] } else { args.advertise.clone() };
+    let custody = custody_columns(id, args.sampling.subnets, args.custody);
+    NodeRecord::new(id, clock().unix().as_millis() as u64, addrs, custody, validator_caps(&args.compression))
+}
+
+/// The columns on `custody` of `subnets` subnets, picked from the node's ID; all of them when
+/// `custody` is unset.
+fn custody_columns(id: &Identity, subnets: usize, custody: Option<usize>) -> Vec<usize> {
+    match custody {
+        Some(custody) => {
+            let picked = node_subnets(id.public.as_bytes(), subnets, custody);
+            (0..TOTAL_SHARDS).filter(|&c| picked.contains(&column_subnet(c, subnets))).collect()
+        }
+        None => (0..TOTAL_SHARDS).collect(),
+    }
+}
+
+/// What a validator answers, and the codecs it speaks.
+fn validator_caps(compression: &[Compression]) -> Vec<String> {
+    let mut caps: Vec<String> = ["receive", "serve", "sync", "audit"].map(String::from).to_vec();
+    caps.extend(compression.iter().filter(|c| **c != Compression::None).map(|c| c.name().to_string()));
+    caps
+}
+
+/// Result of `enr encode` or `enr decode`, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug)]
+struct EnrSummary {
+    role: &'static str,
+    records: Vec<DecodedRecord>,
+}
+
+/// One record of an `enr` run: its text form and fields, or why it did not check out.
+#[derive(Serialize, Debug)]
+struct DecodedRecord {
+    text: String,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    record: Option<NodeRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn print_record(record: &NodeRecord) {
+    say!("{} {}", "✓ Valid Record:".green(), record.pubkey);
+    say!("  {:<8}: {}", "seq", record.seq);
+    say!("  {:<8}: {}", "addrs", record.addrs.join(", "));
+    say!("  {:<8}: {}", "custody", record.custody.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", "));
+    say!("  {:<8}: {}", "caps", record.caps.join(", "));
+}
+
+fn run_enr_encode(record: NodeRecord, peers_file: Option<&str>) -> Result<ExitStatus> {
+    let text = record.to_string();
+    print_record(&record);
+    say!("{}", text);
+    if let Some(path) = peers_file {
+        let added = merge_peers_file(path, &record)?;
+        say!("{} {}", if added { "➜ Added to:" } else { "➜ Already newer in:" }.blue().bold(), path);
+    }
+    print_summary(&EnrSummary { role: "enr", records: vec![DecodedRecord { text, record: Some(record), error: None }] })?;
+    Ok(ExitStatus::Success)
+}
+
+/// Checks each record, given directly or as a line of a peers file. Any bad one fails the run.
+fn run_enr_decode(mut texts: Vec<String>, from: Option<&str>) -> Result<ExitStatus> {
+    if let Some(path) = from {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Cannot read {}", path))?;
+        texts.extend(text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).map(String::from));
+    }
+    if texts.is_empty() {
+        bail!("No records to decode; give them as arguments or with --from");
+    }
+    let mut records = Vec::with_capacity(texts.len());
+    for text in texts {
+        match text.parse::<NodeRecord>() {
+            Ok(record) => {
+                print_record(&record);
+                records.push(DecodedRecord { text, record: Some(record), error: None });
+            }
+            Err(e) => {
+                say!("{} {}", "❌ Invalid Record:".red(), e);
+                records.push(DecodedRecord { text, record: None, error: Some(e.to_string()) });
+            }
+        }
+    }
+    let valid = records.iter().all(|r| r.error.is_none());
+    print_summary(&EnrSummary { role: "enr", records })?;
+    Ok(if valid { ExitStatus::Success } else { ExitStatus::AuthFailure })
+}
+
+/// Whether a peer list entry names a node by its hex pubkey rather than by address.
+fn is_pubkey(peer: &str) -> bool {
+    peer.len() == 64 && peer.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+impl Validator {
+    /// Keeps a peer's record if it is newer than the one held, and adds it to `--peers-file`.
+    fn learn_record(&self, record: Option<&NodeRecord>) {
+        let Some(record) = record else { return };
+        {
+            let mut state = self.state.locked();
+            if state.records.get(&record.pubkey).is_some_and(|held| held.seq >= record.seq) {
+                return;
+            }
+            state.records.insert(record.pubkey.clone(), record.clone());
+        }
+        say!("{} {} at {} (seq {})", "➜ Peer Record:".blue().bold(), record.pubkey, record.addrs.join(", "), record.seq);
+        log_event(Event::PeerRecord { peer: record.pubkey.clone(), seq: record.seq, addrs: record.addrs.clone(), custody: record.custody.clone(), caps: record.caps.clone() });
+        if let Some(path) = &self.args.peers_file {
+            let _lock = PEERS_FILE.locked();
+            if let Err(e) = merge_peers_file(path, record) {
+                say!("{} {}: {}", "❌ Peers File:".red(), path, e);
+            }
+        }
+    }
+
+    /// Connects to a peer list entry. A pubkey is looked up in the known records and tried at
+    /// each address its record lists, and returned so the handshake can be held to it.
+    async fn connect_peer<'a>(&self, peer: &'a str) -> Result<(TcpConnection, Option<&'a str>)> {
+        let transport = TcpTransport::new(self.args.max_frame);
+        if !is_pubkey(peer) {
+            return Ok((transport.connect(peer).await?, None));
+        }
+        let addrs = self.state.locked().records.get(&peer.to_ascii_lowercase()).map(|r| r.addrs.clone())
+            .with_context(|| format!("no record of {}; add it to --peers-file", peer))?;
+        let mut last = anyhow!("the record of {} lists no addresses", peer);
+        for addr in addrs {
+            match transport.connect(&addr).await {
+                Ok(conn) => return Ok((conn, Some(peer))),
+                Err(e) => last = anyhow!("{}: {}", addr, e),
+            }
+        }
+        Err(last)
+    }
+}
+
+// WATCHDOG
+/// Pause before restarting a task that died. It doubles with each death up to
+/// [`WATCHDOG_MAX_BACKOFF`], and resets once a task has run that long.
+const WATCHDOG_BACKOFF: Duration = Duration::from_secs(1);
+const WATCHDOG_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Why a task ended, unless it finished cleanly.
+fn task_failure(ended: std::result::Result<Result<()>, tokio::task::JoinError>) -> Option<String> {
+    match ended {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            let message = panic.downcast_ref::<&str>().map(|s| s.to_string()).or_else(|| panic.downcast_ref::<String>().cloned());
+            Some(format!("panicked: {}", message.unwrap_or_else(|| "no message".into())))
+        }
+        Err(e) => Some(e.to_string()),
+    }
+}
+
+impl Validator {
+    /// Records a task that died, on the console, in the event log and in `/status`.
+    fn incident(&self, task: &str, reason: &str) {
+        let restarts = {
+            let mut state = self.state.locked();
+            let restarts = state.restarts.entry(task.to_string()).or_default();
+            *restarts += 1;
+            *restarts
+        };
+        say!("\n{} {}: {} (restart {})", "❌ Task Died:".red(), task, reason, restarts);
+        log_event(Event::TaskDied { task: task.to_string(), reason: reason.to_string(), restarts });
+    }
+
+    /// Runs the task `start` makes, and makes and runs a fresh one whenever it returns, fails or
+    /// panics, so a long run never silently loses it. Never returns.
+    async fn supervise<F, Fut>(self: Arc<Self>, task: &'static str, mut start: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let mut backoff = WATCHDOG_BACKOFF;
+        loop {
+            let began = clock().now();
+            let reason = task_failure(spawn_in_node(start()).await).unwrap_or_else(|| "stopped".to_string());
+            self.incident(task, &reason);
+            if clock().elapsed(began) >= WATCHDOG_MAX_BACKOFF {
+                backoff = WATCHDOG_BACKOFF;
+            }
+            clock().sleep(backoff).await;
+            backoff = (backoff * 2).min(WATCHDOG_MAX_BACKOFF);
+        }
+    }
+}
+
+/// A session's `--shard-dir` writer, off the async runtime. One that dies is replaced and the
+/// incident recorded; shards still queued to it are lost.
+struct ShardWriter {
+    dir: String,
+    depth: usize,
+    tx: tokio::sync::mpsc::Sender<ShardFile>,
+    handle: tokio::task::JoinHandle<Result<()>>,
+}
+
+impl ShardWriter {
+    fn start(dir: String, depth: usize) -> Self {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<ShardFile>(depth);
+        let out = dir.clone();
+        let handle = tokio::task::spawn_blocking(move || -> Result<()> {
+            while let Some(file) = rx.blocking_recv() {
+                file.write(&out)?;
+            }
+            Ok(())
+        });
+        Self { dir, depth, tx, handle }
+    }
+
+    async fn write(&mut self, node: &Validator, file: ShardFile) -> Result<()> {
+        let Err(tokio::sync::mpsc::error::SendError(file)) = self.tx.send(file).await else { return Ok(()) };
+        let dead = std::mem::replace(self, Self::start(self.dir.clone(), self.depth));
+        dead.finish(node).await;
+        self.tx.send(file).await.map_err(|_| anyhow!("Shard writer stopped"))
+    }
+
+    /// Waits for the queued shards to be written, recording the incident if the writer died.
+    async fn finish(self, node: &Validator) {
+        drop(self.tx);
+        if let Some(reason) = task_failure(self.handle.await) {
+            node.incident("shard-writer", &reason);
+        }
+    }
+}
+
+// CUSTODY AUDITS
+/// Score lost per failed custody challenge; a pass earns back one point.
+const AUDIT_PENALTY: i64 = 10;
+/// Bytes of a shard each challenge asks to be hashed, from a random offset.
+const AUDIT_SLICE: usize = 64;
+/// How long a challenged peer has to answer before the challenge counts as failed.
+const AUDIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Validator {
+    /// Every `--audit-interval`, challenges each of `--audit-peers` on shards it claims to hold.
+    async fn audit_peers(self: Arc<Self>) {
+        loop {
+            for peer in &self.args.audit_peers {
+                if let Err(e) = self.audit_peer(peer).await {
+                    say!("{} {}: {}", "❌ Audit Failed:".red(), peer, e);
+                }
+            }
+            clock().sleep(Duration::from_secs(self.args.audit_interval)).await;
+        }
+    }
+
+    /// Asks the peer which roots it serves, and for each one this node also serves puts
+    /// `--audit-challenges` random shards to it: a random slice and a fresh nonce, whose digest
+    /// only a node holding the shard can compute. Listing a root claims every index of it.
+    async fn audit_peer(&self, peer: &str) -> Result<()> {
+        let (mut conn, peer_id, limit) = self.dial(peer).await?;
+        conn.send_frame(serde_json::to_string(&P2PMessage::GetRoots {})?, limit).await?;
+        let roots = loop {
+            match decode_message(&conn.recv_frame().await.context("peer closed before listing roots")??)? {
+                P2PMessage::Roots { roots } => break roots,
+                P2PMessage::ProtocolError { reason } => bail!("peer rejected request: {}", reason),
+                _ => {}
+            }
+        };
+        let challenges: Vec<(P2PMessage, String)> = {
+            let state = self.state.locked();
+            let mut rng = rand::thread_rng();
+            let mut challenges = Vec::new();
+            for root in roots.iter().filter(|r| state.serving.contains_key(*r)) {
+                let held: Vec<(usize, Cow<'_, [u8]>)> = (0..TOTAL_SHARDS).filter_map(|i| Some((i, state.shard_data(root, i)?))).collect();
+                for (index, shard) in held.choose_multiple(&mut rng, self.args.audit_challenges) {
+                    let (index, shard) = (*index, &**shard);
+                    let offset = rng.gen_range(0..shard.len());
+                    let nonce = hex::encode(rng.r#gen::<[u8; 16]>());
+                    let expected = custody_digest(&nonce, shard, offset, AUDIT_SLICE);
+                    challenges.push((P2PMessage::CustodyChallenge { root: root.clone(), index, offset, len: AUDIT_SLICE, nonce }, expected));
+                }
+            }
+            challenges
+        };
+        if challenges.is_empty() {
+            return Ok(());
+        }
+
+        say!("\n{} {} challenge(s) to {}", "➜ Custody Audit:".blue().bold(), challenges.len(), peer);
+        let mut connected = true;
+        for (challenge, expected) in challenges {
+            let P2PMessage::CustodyChallenge { root, index, .. } = &challenge else { unreachable!() };
+            let (root, index) = (root.clone(), *index);
+            let failure = if !connected {
+                Some("connection lost".to_string())
+            } else {
+                match challenge_custody(&mut conn, limit, &challenge, &expected).await {
+                    Ok(failure) => failure,
+                    Err(e) => {
+                        connected = false;
+                        Some(e.to_string())
+                    }
+                }
+            };
+            let passed = failure.is_none();
+            say!("  {}… index {:>3} {}{}", &root[..16], index, if passed { "✓".green() } else { "✗".red() },
+                failure.as_ref().map(|f| format!(" ({})", f)).unwrap_or_default());
+            self.traffic(&peer_id, |t| if passed {
+                t.custody_passed += 1;
+                t.score += 1;
+            } else {
+                t.custody_failed += 1;
+                t.score -= AUDIT_PENALTY;
+            });
+            self.enforce_ban_score(&peer_id);
+            log_event(Event::CustodyAudit { peer: peer_id.clone(), root, index, passed, reason: failure });
+        }
+        Ok(())
+    }
+}
+
+/// Sends one custody challenge and judges the answer: `None` if it proves custody, else why
+/// not. Errors once the connection is no longer usable, including after a timeout.
+async fn challenge_custody<C: Connection>(conn: &mut C, limit: usize, challenge: &P2PMessage, expected: &str) -> Result<Option<String>> {
+    let P2PMessage::CustodyChallenge { root, index, nonce, .. } = challenge else { unreachable!() };
+    conn.send_frame(serde_json::to_string(challenge)?, limit).await?;
+    let answer = async {
+        while let Some(frame) = conn.recv_frame().await {
+            match decode_message(&frame?)? {
+                P2PMessage::CustodyProof { root: r, index: i, nonce: n, digest } if r == *root && i == *index && n == *nonce => {
+                    return Ok(Some(digest));
+                }
+                P2PMessage::NotFound { root: r, index: i } if r == *root && i == *index => return Ok(None),
+                P2PMessage::ProtocolError { reason } => bail!("peer rejected challenge: {}", reason),
+                _ => {}
+            }
+        }
+        bail!("peer closed the connection")
+    };
+    tokio::select! {
+        answer = answer => Ok(match answer? {
+            Some(digest) if digest == expected => None,
+            Some(_) => Some("wrong digest".to_string()),
+            None => Some("shard not held".to_string()),
+        }),
+        _ = clock().sleep(AUDIT_TIMEOUT) => bail!("no answer within {}s", AUDIT_TIMEOUT.as_secs()),
+    }
+}
+
+// REPUTATION
+/// What a validator knows about its peers, kept across restarts as `<data-dir>/peers.json`:
+/// their history and scores, and the peers it refuses to talk to.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Reputation {
+    /// Unix seconds when it was saved
+    saved_at: u64,
+    peers: BTreeMap<String, PeerTraffic>,
+    banned: BTreeMap<String, Ban>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Ban {
+    /// Unix seconds when the ban began
+    since: u64,
+    reason: String,
+}
+
+/// How often a validator saves its peers' reputation; bans are saved as they happen.
+const REPUTATION_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+impl Reputation {
+    const FILE: &'static str = "peers.json";
+
+    fn path(data_dir: &str) -> String {
+        format!("{}/{}", data_dir, Self::FILE)
+    }
+
+    /// The saved reputation, or an empty one before the first save.
+    fn read(data_dir: &str) -> Result<Self> {
+        match std::fs::read_to_string(Self::path(data_dir)) {
+            Ok(json) => serde_json::from_str(&json).with_context(|| format!("Invalid {}", Self::path(data_dir))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the file in one rename, so a crash mid-write leaves the previous one.
+    fn write(&mut self, data_dir: &str) -> Result<()> {
+        self.saved_at = unix_now();
+        let tmp = format!("{}.tmp", Self::path(data_dir));
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp, Self::path(data_dir))?;
+        Ok(())
+    }
+}
+
+impl Validator {
+    fn save_reputation(&self) -> Result<()> {
+        let mut reputation = {
+            let state = self.state.locked();
+            Reputation { saved_at: 0, peers: state.peers.clone().into_iter().collect(), banned: state.banned.clone() }
+        };
+        reputation.write(&self.args.data_dir)
+    }
+
+    /// Saves the reputation every [`REPUTATION_SAVE_INTERVAL`].
+    async fn keep_reputation(self: Arc<Self>) {
+        loop {
+            clock().sleep(REPUTATION_SAVE_INTERVAL).await;
+            if let Err(e) = self.save_reputation() {
+                say!("{} {}", "❌ Reputation Not Saved:".red(), e);
+            }
+        }
+    }
+
+    fn ban_of(&self, peer: &str) -> Option<Ban> {
+        self.state.locked().banned.get(peer).cloned()
+    }
+
+    /// Bans `peer` once its score has fallen to `--ban-score`, and saves the ban at once.
+    fn enforce_ban_score(&self, peer: &str) {
+        let Some(threshold) = self.args.ban_score else { return };
+        let reason = {
+            let mut state = self.state.locked();
+            let score = state.peers.get(peer).map_or(0, |t| t.score);
+            if score > threshold || state.banned.contains_key(peer) {
+                return;
+            }
+            let reason = format!("score {} at or below {}", score, threshold);
+            state.banned.insert(peer.to_string(), Ban { since: unix_now(), reason: reason.clone() });
+            reason
+        };
+        say!("\n{} {} ({})", "❌ Peer Banned:".red(), peer, reason);
+        log_event(Event::PeerBanned { peer: peer.to_string(), reason });
+        if let Err(e) = self.save_reputation() {
+            say!("{} {}", "❌ Reputation Not Saved:".red(), e);
+        }
+    }
+}
+
+/// Result of a `peers` run, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Debug)]
+struct PeersSummary {
+    role: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    saved_at: Option<u64>,
+    banned: BTreeMap<String, Ban>,
+    /// With `--show-scores`, lowest score first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    scores: Vec<PeerScore>,
+}
+
+#[derive(Serialize, Debug)]
+struct PeerScore {
+    peer: String,
+    #[serde(flatten)]
+    traffic: PeerTraffic,
+}
+
+/// Shows a validator's saved reputation, after applying `--ban` and `--unban` to it.
+fn run_peers(data_dir: &str, show_scores: bool, ban: Vec<String>, reason: &str, unban: Vec<String>) -> Result<ExitStatus> {
+    if let Some(peer) = ban.iter().chain(&unban).find(|p| !is_pubkey(p)) {
+        bail!("{} is not a hex pubkey", peer);
+    }
+    let mut reputation = Reputation::read(data_dir)?;
+    if !ban.is_empty() || !unban.is_empty() {
+        for peer in ban {
+            say!("{} {}", "➜ Banned:".blue().bold(), peer);
+            reputation.banned.insert(peer.to_ascii_lowercase(), Ban { since: unix_now(), reason: reason.to_string() });
+        }
+        for peer in unban {
+            match reputation.banned.remove(&peer.to_ascii_lowercase()) {
+                Some(_) => say!("{} {}", "➜ Unbanned:".blue().bold(), peer),
+                None => say!("{} {} was not banned", "➜ Unbanned:".blue().bold(), peer),
+            }
+        }
+        std::fs::create_dir_all(data_dir)?;
+        reputation.write(data_dir)?;
+    }
+
+    say!("{} {} ({} peers, {} banned)", "➜ Reputation:".green().bold(), Reputation::path(data_dir),
+        reputation.peers.len(), reputation.banned.len());
+    for (peer, ban) in &reputation.banned {
+        say!("  {} {} since {}: {}", "✗".red(), peer, ban.since, ban.reason);
+    }
+    let mut scores: Vec<PeerScore> = Vec::new();
+    if show_scores {
+        scores = reputation.peers.iter().map(|(peer, traffic)| PeerScore { peer: peer.clone(), traffic: traffic.clone() }).collect();
+        scores.sort_by_key(|s| s.traffic.score);
+        say!("\n{:<18} {:>6} {:>8} {:>8} {:>8} {:>9} {:>8} {:>7}", "Peer", "Score", "Sessions", "Shards", "Rebuilt", "Corrupted", "Custody", "Errors");
+        for s in &scores {
+            let t = &s.traffic;
+            let mark = if reputation.banned.contains_key(&s.peer) { " banned".red().to_string() } else { String::new() };
+            say!("{:<18} {:>6} {:>8} {:>8} {:>8} {:>9} {:>8} {:>7}{}", format!("{}…", &s.peer[..s.peer.len().min(16)]), t.score, t.sessions,
+                t.shards_received, t.shards_rebuilt, t.shards_corrupted, format!("{}/{}", t.custody_passed, t.custody_passed + t.custody_failed),
+                t.protocol_errors, mark);
+        }
+    }
+    print_summary(&PeersSummary {
+        role: "peers",
+        saved_at: (reputation.saved_at > 0).then_some(reputation.saved_at),
+        banned: reputation.banned,
+        scores,
+    })?;
+    Ok(ExitStatus::Success)
+}
+
+// CHECKPOINTS
+/// Transfers a validator had buffered at its last checkpoint, as `<data-dir>/checkpoint/state.json`.
+/// The shards themselves are shard files beside it.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Checkpoint {
+    /// Unix seconds when it was taken
+    taken_at: u64,
+    transfers: Vec<CheckpointedTransfer>,
+}
+
+/// One buffered root: enough to recreate its decoder and re-attribute its shards.
+#[derive(Serialize, Deserialize, Debug)]
+struct CheckpointedTransfer {
+    root: String,
+    layout: Layout,
+    original_len: usize,
+    shard_len: usize,
+    spilled: bool,
+    /// Peer that supplied each buffered shard, by shard index
+    senders: BTreeMap<usize, String>,
+    /// Bytes each peer has counted against its quota
+    charged: BTreeMap<String, usize>,
+    manifest: Option<Manifest>,
+}
+
+impl Checkpoint {
+    const STATE: &'static str = "state.json";
+
+    fn dir(data_dir: &str) -> String {
+        format!("{}/checkpoint", data_dir)
+    }
+
+    fn read(data_dir: &str) -> Result<Option<Self>> {
+        match std::fs::read_to_string(format!("{}/{}", Self::dir(data_dir), Self::STATE)) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces `state.json` in one rename, so a crash mid-write leaves the previous one.
+    fn write(&self, data_dir: &str) -> Result<()> {
+        let dir = Self::dir(data_dir);
+        let tmp = format!("{}/{}.tmp", dir, Self::STATE);
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp, format!("{}/{}", dir, Self::STATE))?;
+        Ok(())
+    }
+}
+
+impl Validator {
+    /// Drops abandoned roots past `--abandon-after`, checking a few times per period.
+    async fn expire_abandoned(self: Arc<Self>) {
+        let age = Duration::from_secs(self.args.abandon_after);
+        loop {
+            clock().sleep(age / 4).await;
+            let expired = self.state.locked().expire_abandoned(age);
+            if expired > 0 {
+                say!("{} {} abandoned transfer(s) dropped", "➜ Expired:".blue().bold(), expired);
+            }
+        }
+    }
+
+    /// Every `--checkpoint-interval`, records the buffered transfers for [`restore`](Self::restore).
+    async fn checkpoint_transfers(self: Arc<Self>, interval: u64) {
+        let mut last = (0, 0);
+        let mut written = HashMap::new();
+        loop {
+            clock().sleep(Duration::from_secs(interval)).await;
+            match self.checkpoint(&mut written).await {
+                Ok(taken) if taken != last => {
+                    say!("{} {} transfer(s), {} shard(s)", "➜ Checkpoint:".blue().bold(), taken.0, taken.1);
+                    last = taken;
+                }
+                Ok(_) => {}
+                Err(e) => say!("{} {}", "❌ Checkpoint Failed:".red(), e),
+            }
+        }
+    }
+
+    /// Writes the shards that are new or replaced since they were last checkpointed, deletes
+    /// those whose root is no longer buffered, then swaps in the new `state.json`. `written`
+    /// holds the [`revision`](StreamingDecoder::revision) each shard file was written from.
+    /// Only the snapshot is taken under the state lock; the files are written off the runtime.
+    /// Returns the transfers and shards the checkpoint holds.
+    async fn checkpoint(&self, written: &mut HashMap<(String, usize), u64>) -> Result<(usize, usize)> {
+        let (data_dir, dir) = (self.args.data_dir.clone(), Checkpoint::dir(&self.args.data_dir));
+        let mut checkpoint = Checkpoint { taken_at: unix_now(), transfers: Vec::new() };
+        let mut keep = HashSet::new();
+        // Shards to write, with the revision they were copied at; a spilled one by the path to
+        // read it from, since reading it here would hold the lock on disk I/O.
+        let mut pending = Vec::new();
+        {
+            let state = self.state.locked();
+            for (root, decoder) in &state.shards {
+                for index in decoder.indices() {
+                    let Some(revision) = decoder.revision(index) else { continue };
+                    let key = (root.clone(), index);
+                    if written.get(&key) != Some(&revision) {
+                        let spilled = decoder.spilled_path(index);
+                        let data = match spilled {
+                            Some(_) => Vec::new(),
+                            None => decoder.shard(index).map(Cow::into_owned).unwrap_or_default(),
+                        };
+                        let file = ShardFile { root: root.clone(), index, k: DATA_SHARDS, m: PARITY_SHARDS, layout: decoder.layout(), original_len: decoder.original_len(), data };
+                        pending.push((file, spilled, revision));
+                    }
+                    keep.insert(key);
+                }
+                checkpoint.transfers.push(CheckpointedTransfer {
+                    root: root.clone(),
+                    layout: decoder.layout(),
+                    original_len: decoder.original_len(),
+                    shard_len: decoder.shard_len(),
+                    spilled: decoder.is_spilled(),
+                    senders: decoder.senders.clone(),
+                    charged: decoder.charged.iter().map(|(peer, &n)| (peer.clone(), n)).collect(),
+                    manifest: state.manifests.get(root).cloned(),
+                });
+            }
+        }
+        let transfers = checkpoint.transfers.len();
+        let paths: HashSet<String> = keep.iter().map(|(root, index)| format!("{}/{}_{}.shard", dir, root, index)).collect();
+        let done = tokio::task::spawn_blocking(move || -> Result<Vec<((String, usize), u64)>> {
+            std::fs::create_dir_all(&dir)?;
+            let mut done = Vec::new();
+            for (mut file, spilled, revision) in pending {
+                // A spilled shard gone by now went with its root; the checkpoint drops it below.
+                if let Some(path) = spilled {
+                    let Ok(data) = std::fs::read(path) else { continue };
+                    file.data = data;
+                }
+                file.write(&dir)?;
+                done.push(((file.root, file.index), revision));
+            }
+            checkpoint.write(&data_dir)?;
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path().to_string_lossy().into_owned();
+                if path.ends_with(".shard") && !paths.contains(&path) {
+                    std::fs::remove_file(path)?;
+                }
+            }
+            Ok(done)
+        }).await??;
+        written.retain(|key, _| keep.contains(key));
+        written.extend(done);
+        Ok((transfers, keep.len()))
+    }
+
+    /// Rebuilds the buffered transfers of the last checkpoint, so shards received before a crash
+    /// or restart count towards their roots as if the node had never stopped.
+    fn restore(&self) -> Result<()> {
+        let Some(checkpoint) = Checkpoint::read(&self.args.data_dir)? else { return Ok(()) };
+        let dir = Checkpoint::dir(&self.args.data_dir);
+        let mut state = self.state.locked();
+        let mut shards = 0;
+        for transfer in &checkpoint.transfers {
+            let staged = match self.store.transfer_path(&transfer.root) {
+                Ok(staged) => staged,
+                Err(e) => {
+                    say!("{} {:#}", "❌ Checkpointed Transfer Lost:".red(), e);
+                    continue;
+                }
+            };
+            let mut decoder = StreamingDecoder::new(staged, transfer.layout, transfer.original_len, transfer.shard_len, clock().clone());
+            if transfer.spilled {
+                decoder = decoder.spill_to_disk();
+            }
+            for &index in transfer.senders.keys() {
+                // A shard file that can't be read or doesn't fit its transfer (truncated,
+                // corrupted) is dropped, so it can't hold up every later start.
+                let path = format!("{}/{}_{}.shard", dir, transfer.root, index);
+                let pushed = ShardFile::read(&path).and_then(|file| Ok(decoder.push(index, file.data)?));
+                if let Err(e) = pushed {
+                    say!("{} {:#}", "❌ Checkpointed Shard Lost:".red(), e);
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                }
+                shards += 1;
+            }
+            decoder.senders = transfer.senders.iter().filter(|&(&index, _)| decoder.has(index)).map(|(&i, peer)| (i, peer.clone())).collect();
+            for (peer, &bytes) in &transfer.charged {
+                decoder.charged.insert(peer.clone(), bytes);
+                *state.quota_usage.entry(peer.clone()).or_default() += bytes;
+            }
+            if let Some(manifest) = &transfer.manifest {
+                state.manifests.insert(transfer.root.clone(), manifest.clone());
+            }
+            state.shards.insert(transfer.root.clone(), decoder);
+        }
+        say!("{} {} transfer(s), {} shard(s) from a checkpoint {}s old", "➜ Resumed:".green().bold(),
+            checkpoint.transfers.len(), shards, unix_now().saturating_sub(checkpoint.taken_at));
+        Ok(())
+    }
+}
+
+// GOSSIP
+/// A shard to re-gossip, with the TTL it leaves with already taken off.
+#[derive(Clone)]
+struct Relay {
+    /// Pubkey it came from; never sent back there
+    from: String,
+    root: String,
+    shard: P2PMessage,
+}
+
+struct GossipPeer {
+    addr: String,
+    tx: tokio::sync::mpsc::Sender<Relay>,
+}
+
+/// An open connection to a gossip peer.
+struct GossipLink {
+    conn: PeerConnection<TcpConnection>,
+    peer_id: String,
+    limit: usize,
+    /// Roots whose manifest already went over this connection
+    announced: HashSet<String>,
+}
+
+/// Recently accepted gossip shards, keyed by (root, wire index, SHA-256 of the data). The hash
+/// keeps a different shard under the same index from passing as a copy. Entries expire in the
+/// order they were added, so the cache stays bounded by the gossip rate over the TTL.
+#[derive(Default)]
+struct SeenCache {
+    ttl: Duration,
+    entries: HashSet<(String, usize, String)>,
+    order: std::collections::VecDeque<(Duration, (String, usize, String))>,
+}
+
+impl SeenCache {
+    fn new(ttl: Duration) -> Self {
+        Self { ttl, ..Default::default() }
+    }
+
+    fn expire(&mut self) {
+        let now = clock().now();
+        while let Some((at, _)) = self.order.front() && now.saturating_sub(*at) >= self.ttl {
+            let (_, key) = self.order.pop_front().expect("front exists");
+            self.entries.remove(&key);
+        }
+    }
+
+    fn contains(&mut self, root: &str, index: usize, digest: &str) -> bool {
+        self.expire();
+        self.entries.contains(&(root.to_string(), index, digest.to_string()))
+    }
+
+    /// Remembers a shard; `false` if it was already remembered.
+    fn insert(&mut self, root: &str, index: usize, digest: &str) -> bool {
+        self.expire();
+        let key = (root.to_string(), index, digest.to_string());
+        if !self.entries.insert(key.clone()) {
+            return false;
+        }
+        self.order.push_back((clock().now(), key));
+        true
+    }
+}
+
+/// How long a gossip peer that could not be reached is left alone; its relays are dropped meanwhile.
+const GOSSIP_RETRY: Duration = Duration::from_secs(1);
+
+impl Validator {
+    /// Hands a shard to `--gossip-fanout` random gossip peers. Never waits: a peer whose outbox
+    /// is full loses the shard, so a slow peer can't stall the session that received it.
+    fn relay(&self, relay: Relay) {
+        let fanout = self.args.gossip_fanout.unwrap_or(self.gossip.len());
+        let mut dropped = 0;
+        for peer in self.gossip.choose_multiple(&mut rand::thread_rng(), fanout) {
+            if peer.tx.try_send(relay.clone()).is_err() {
+                dropped += 1;
+            }
+        }
+        self.state.locked().relay_drops += dropped;
+    }
+
+    /// Sends one gossip peer its relays, connecting on first use and again after a failure.
+    async fn gossip_to(self: Arc<Self>, addr: String, mut outbox: tokio::sync::mpsc::Receiver<Relay>) {
+        let mut link: Option<GossipLink> = None;
+        let mut retry_at = None;
+        while let Some(relay) = outbox.recv().await {
+            if link.is_none() && retry_at.is_none_or(|at| clock().now() >= at) {
+                match self.gossip_connect(&addr).await {
+                    Ok(connected) => link = Some(connected),
+                    Err(e) => {
+                        say!("{} {}: {}", "❌ Gossip Peer Unreachable:".red(), addr, e);
+                        retry_at = Some(clock().now() + GOSSIP_RETRY);
+                    }
+                }
+            }
+            let Some(open) = &mut link else {
+                self.state.locked().relay_drops += 1;
+                continue;
+            };
+            if open.peer_id == relay.from {
+                continue;
+            }
+            if let Err(e) = self.gossip_send(open, relay).await {
+                say!("{} {}: {}", "❌ Gossip Peer Lost:".red(), addr, e);
+                self.state.locked().relay_drops += 1;
+                link = None;
+                retry_at = Some(clock().now() + GOSSIP_RETRY);
+            }
+        }
+    }
+
+    async fn gossip_connect(&self, addr: &str) -> Result<GossipLink> {
+        let (conn, peer_id, limit) = self.dial(addr).await?;
+        self.traffic(&peer_id, |t| t.sessions += 1);
+        Ok(GossipLink { conn, peer_id, limit, announced: HashSet::new() })
+    }
+
+    /// Sends the root's manifest ahead of its first shard on this connection, so the peer can
+    /// check what it is relayed.
+    async fn gossip_send(&self, link: &mut GossipLink, relay: Relay) -> Result<()> {
+        let Relay { root, shard, .. } = relay;
+        let P2PMessage::DasShard { index, ttl, trace, .. } = &shard else { bail!("only shards are relayed") };
+        let (index, ttl, trace) = (*index, ttl.unwrap_or(0), trace.clone());
+        if !link.announced.contains(&root) {
+            let manifest = self.state.locked().manifests.get(&root).cloned();
+            if let Some(manifest) = manifest {
+                let json = serde_json::to_string(&P2PMessage::Manifest(manifest))?;
+                let len = json.len();
+                link.conn.send_frame(json, link.limit).await?;
+                self.traffic(&link.peer_id, |t| t.bytes_sent += len);
+                link.announced.insert(root.clone());
+            }
+        }
+        let json = serde_json::to_string(&shard)?;
+        let (len, msg_id) = (json.len(), event_msg_id(&json));
+        link.conn.send_frame(json, link.limit).await?;
+        self.traffic(&link.peer_id, |t| {
+            t.bytes_sent += len;
+            t.shards_relayed += 1;
+        });
+        log_event(Event::ShardRelayed { peer: link.peer_id.clone(), root, index, ttl, msg_id, trace });
+        Ok(())
+    }
+}
+
+// ANTI-ENTROPY
+impl Validator {
+    /// Every `--sync-interval`, reconciles shard holdings with each of `--sync-peers`.
+    async fn sync_peers(self: Arc<Self>) {
+        loop {
+            for peer in &self.args.sync_peers {
+                if let Err(e) = self.sync_with(peer).await {
+                    say!("{} {}: {}", "❌ Sync Failed:".red(), peer, e);
+                }
+            }
+            clock().sleep(Duration::from_secs(self.args.sync_interval)).await;
+        }
+    }
+
+    /// Advertises what this node holds of every root either side knows, as `HaveShards`. The
+    /// peer pulls what it lacks and advertises its own holdings back when it has something we
+    /// lack; the session then pulls those. A trailing `GetRoots` is answered only after every
+    /// offer, and its `Roots` ends the session once our pulls are answered.
+    async fn sync_with(&self, peer: &str) -> Result<()> {
+        let (mut conn, peer_id, limit) = self.dial(peer).await?;
+        conn.send_frame(serde_json::to_string(&P2PMessage::GetRoots {})?, limit).await?;
+        let theirs = loop {
+            match decode_message(&conn.recv_frame().await.context("peer closed before listing roots")??)? {
+                P2PMessage::Roots { roots } => break roots,
+                P2PMessage::ProtocolError { reason } => bail!("peer rejected request: {}", reason),
+                _ => {}
+            }
+        };
+        let (offers, advertised) = {
+            let state = self.state.locked();
+            let mut roots: Vec<&String> = state.serving.keys().chain(state.shards.keys()).chain(&theirs).collect();
+            roots.sort();
+            roots.dedup();
+            let mut offers = Vec::new();
+            for root in &roots {
+                let ours = state.holdings(root);
+                // A peer that doesn't serve the root may not have its manifest to check our shards.
+                if !ours.is_empty() && !theirs.contains(root) && let Some(manifest) = state.manifests.get(*root) {
+                    offers.push(P2PMessage::Manifest(manifest.clone()));
+                }
+                offers.push(P2PMessage::HaveShards { root: root.to_string(), bitfield: encode_bitfield(ours) });
+            }
+            (offers, roots.into_iter().cloned().collect::<HashSet<String>>())
+        };
+        say!("\n{} {} root(s) with {}", "➜ Sync:".blue().bold(), advertised.len(), peer);
+        for offer in offers.iter().chain([&P2PMessage::GetRoots {}]) {
+            conn.send_frame(serde_json::to_string(offer)?, limit).await?;
+        }
+
+        let (sink, stream) = conn.split();
+        let (frame_tx, frames) = tokio::sync::mpsc::channel(self.args.pipeline_depth);
+        let reader = self.read_frames(stream, frame_tx, None, peer);
+        let status = self.session(&peer_id, limit, peer, frames, sink, advertised).await;
+        reader.abort();
+        status.map(|_| ())
+    }
+}
+
+// SIMULATION
+fn run_simulate(topology: Option<&str>, compare: Option<SamplingKind>, config: SimConfig) -> Result<ExitStatus> {
+    let config: SimConfig = match topology {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?).with_context(|| format!("Invalid topology {}", path))?,
+        None => config,
+    };
+    check_params(config.k, config.m)?;
+    if config.shard_bytes == 0 { bail!("shard_bytes must be at least 1"); }
+    if !(0.0..=1.0).contains(&config.lazy_fraction) { bail!("lazy_fraction must be within [0, 1]"); }
+    if config.committees > config.n() { bail!("{} committees leave some without indices to custody (n={})", config.committees, config.n()); }
+    let nodes = 1 + config.full_nodes + config.validators + config.light_clients;
+    let validators = 1 + config.full_nodes..1 + config.full_nodes + config.validators;
+    if !config.stakes.is_empty() && config.committees == 0 { bail!("stakes set custody, which needs committees"); }
+    if let Some((node, stake)) = config.stakes.iter().find(|(node, stake)| !validators.contains(node) || !stake.is_finite() || **stake < 0.0) {
+        bail!("stake {} for node {}: stakes go to validators ({}..{}) and must be non-negative", stake, node, validators.start, validators.end);
+    }
+    if let Some(node) = config.partitions.iter().flat_map(|p| &p.nodes).find(|&&node| node >= nodes) {
+        bail!("partition names node {}, but there are only {} nodes (0..{})", node, nodes, nodes);
+    }
+
+    say!("Simulating {} slots: {} full nodes, {} validators, {} light clients, RS({}, {}), {} withheld per slot",
+        config.slots, config.full_nodes, config.validators, config.light_clients, config.k, config.n(), config.withhold);
+    say!("Sampling: {:?} ({} samples)", config.sampling.kind, config.sampling.samples);
+    for p in &config.partitions {
+        say!("Partition: nodes {:?} cut off from {} ms until {} ms", p.nodes, p.at_ms, p.heal_ms);
+    }
+    let report = Simulation::new(config.clone()).run();
+
+    say!("\n{:>5} {:>9} {:>10} {:>7} {:>8} {:>9} {:>6} {:>8} {:>8}", "slot", "withheld", "available", "rebuilt", "attested", "accepted", "lc-ok", "samples", "dropped");
+    for r in &report.slots {
+        let accepted = if r.accepted == r.available { format!("{:>9}", r.accepted).normal() } else { format!("{:>9}", r.accepted).red().bold() };
+        let slot = if r.partitioned { format!("{:>5}", format!("{}*", r.slot)).yellow() } else { format!("{:>5}", r.slot).normal() };
+        say!("{} {:>9} {:>10} {:>7} {:>8} {} {:>6} {:>8} {:>8}", slot, r.withheld, r.available, r.reconstructed,
+            format!("{}/{}", r.attestations, report.config.validators), accepted, r.convinced_light_clients, r.samples, r.dropped);
+    }
+    if !report.epochs.is_empty() {
+        say!("\n{:>5} {:>5} {:>10} {:>7} {:>10}", "epoch", "slot", "reassigned", "churn", "bytes");
+        for e in &report.epochs {
+            say!("{:>5} {:>5} {:>10} {:>7} {:>10}  sizes {:?}", e.epoch, e.first_slot, e.reassigned, e.churn_shards, format_bytes(e.churn_bytes), e.committee_sizes);
+        }
+    }
+    let s = &report.summary;
+    say!("\n{}", "=== Simulation Summary ===".bold().white().on_blue());
+    say!("{:<22} : {}", "Lazy validators", s.lazy_validators);
+    say!("{:<22} : {}/{}", "Unavailable slots", s.unavailable_slots, s.slots);
+    say!("{:<22} : {}", "False accepts", s.false_accepts);
+    say!("{:<22} : {}", "False rejects", s.false_rejects);
+    say!("{:<22} : {:.1}%", "Mean attestation rate", s.mean_attestation_rate * 100.0);
+    match (s.light_client_fooled_rate, &s.confidence) {
+        (Some(rate), Some(c)) => {
+            let percent = |p: Option<f64>| p.map_or_else(|| "n/a".to_string(), |p| format!("{:.3}%", p * 100.0));
+            say!("{:<22} : {} measured, {} analytic (client confidence {})", "Light clients fooled", percent(Some(rate)),
+                percent(c.analytic_fooled), percent(c.client_confidence));
+            say!("{:<22} : {} measured, {} analytic", format!("Undetected by all {}", c.clients), percent(Some(c.measured_undetected)),
+                percent(c.analytic_undetected));
+        }
+        (Some(rate), None) => say!("{:<22} : {:.1}%", "Light clients fooled", rate * 100.0),
+        _ => {}
+    }
+    say!("{:<22} : {} ({})", "Samples", s.samples, format_bytes(s.bytes));
+    let after = |ms: Option<f64>| ms.map_or_else(|| "n/a".to_string(), |ms| format!("{:.0} ms", ms));
+    say!("{:<22} : confident after {}, rebuilt after {}", "Time to availability", after(s.mean_confidence_ms), after(s.mean_reconstruction_ms));
+    if let Some(c) = &s.committee {
+        say!("{:<22} : {} over {} epochs ({} reassignments)", "Committees", c.committees, c.epochs, c.reassigned);
+        say!("{:<22} : {} shards, {} ({} per rotation, {:.1}% of traffic)", "Custody churn", c.churn_shards, format_bytes(c.churn_bytes),
+            format_bytes(c.churn_bytes_per_rotation as usize), c.churn_share * 100.0);
+    }
+    if !s.stake_custody.is_empty() {
+        say!("\n{:>6} {:>6} {:>8} {:>12} {:>12}", "stake", "nodes", "indices", "storage", "bandwidth");
+        for c in &s.stake_custody {
+            say!("{:>6} {:>6} {:>8.1} {:>12} {:>12}", c.stake, c.nodes, c.custody_indices, format_bytes(c.storage_bytes as usize),
+                format_bytes(c.bandwidth_bytes as usize));
+        }
+    }
+    if let Some(p) = &s.partition {
+        say!("{:<22} : {}", "Dropped messages", s.dropped);
+        for (phase, stats) in [("before", &p.before), ("during", &p.during), ("after", &p.after)] {
+            say!("{:<22} : {} slots, {:.1}% rebuilt, {:.1}% accepted", format!("Partition ({})", phase), stats.slots,
+                stats.rebuilt_rate * 100.0, stats.accepted_rate * 100.0);
+        }
+        let recovered = if p.recovered { "yes".green().bold() } else { "no".red().bold() };
+        say!("{:<22} : {}", "Recovered after heal", recovered);
+    }
+
+    say!("\n{}", "=== Scoreboard ===".bold().white().on_blue());
+    say!("{:>5} {:<16} {:>7} {:>8} {:>8} {:>9} {:>10}", "node", "role", "served", "refused", "attest", "rewards", "balance");
+    for n in &report.scoreboard {
+        let role = format!("{:?}{}", n.role, if n.lazy { " (lazy)" } else { "" });
+        let balance = if n.balance < 0.0 { format!("{:>10.1}", n.balance).red() } else { format!("{:>10.1}", n.balance).normal() };
+        say!("{:>5} {:<16} {:>7} {:>8} {:>8} {:>9.1} {}", n.node, role, n.served, n.refused,
+            format!("{}/{}", n.correct_attestations, n.correct_attestations + n.wrong_attestations), n.rewards, balance);
+    }
+
+    if let Some(kind) = compare {
+        let other = Simulation::new(SimConfig { sampling: SamplingArgs { kind, ..config.sampling }, ..config }).run();
+        let percent = |p: Option<f64>| p.map_or_else(|| "n/a".to_string(), |p| format!("{:.2}%", p * 100.0));
+        say!("\n{}", "=== Sampling Comparison ===".bold().white().on_blue());
+        say!("{:<12} {:>10} {:>11} {:>9} {:>8} {:>12}", "strategy", "lc-fooled", "undetected", "coverage", "samples", "bytes");
+        for r in [&report, &other] {
+            let s = &r.summary;
+            say!("{:<12} {:>10} {:>11} {:>9.1} {:>8} {:>12}", format!("{:?}", r.config.sampling.kind), percent(s.light_client_fooled_rate),
+                percent(s.confidence.as_ref().map(|c| c.measured_undetected)), s.mean_coverage, s.samples, format_bytes(s.bytes));
+        }
+        print_summary(&[&report, &other])?;
+        return Ok(ExitStatus::Success);
+    }
+
+    print_summary(&report)?;
+    Ok(ExitStatus::Success)
+}
+
+// REPORT
+/// Aggregate of an event log, printed as a single JSON line in `--quiet` mode.
+#[derive(Serialize, Default)]
+struct Report {
+    role: &'static str,
+    events: usize,
+    /// Lines that were not events
+    malformed: usize,
+    /// Verdicts reached by receivers
+    outcomes: usize,
+    /// Outcomes that were verified, reconstructed or sampled
+    succeeded: usize,
+    success_rate: f64,
+    verdicts: BTreeMap<String, usize>,
+    modes: Vec<ModeReport>,
+    /// Light-client runs by sample unit
+    units: Vec<UnitReport>,
+    /// Failure reasons, most frequent first
+    failures: Vec<FailureCount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_confidence_latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_reconstruction_latency_ms: Option<f64>,
+}
+
+/// Completed proposer runs of one mode.
+#[derive(Serialize)]
+struct ModeReport {
+    mode: ResearchMode,
+    transfers: usize,
+    payload_bytes: usize,
+    wire_bytes: usize,
+    /// Mean of the runs' own savings (negative: overhead)
+    mean_savings_pct: f64,
+    mean_throughput_mb_s: f64,
+    mean_latency_ms: f64,
+}
+
+/// Light-client runs of one sample unit: what they fetched against what they caught.
+#[derive(Serialize)]
+struct UnitReport {
+    unit: SampleUnit,
+    runs: usize,
+    /// Runs that found the root unavailable
+    unavailable: usize,
+    mean_samples: f64,
+    mean_bytes: f64,
+    /// Mean chance of catching the smallest unrecoverable withholding, over block matrix runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_detection: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct FailureCount {
+    reason: String,
+    count: usize,
+}
+
+/// The slice of an event line the report reads; every other field is ignored.
+#[derive(Deserialize)]
+struct ReportEvent {
+    event: String,
+    #[serde(default)]
+    ok: Option<bool>,
+    #[serde(default)]
+    accepted: Option<bool>,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    verdict: Option<Verdict>,
+    #[serde(default)]
+    confidence_latency_ms: Option<f64>,
+    #[serde(default)]
+    reconstruction_latency_ms: Option<f64>,
+    /// Present on `transfer` events
+    #[serde(flatten)]
+    transfer: Option<TransferRun>,
+    /// Present on `sampled` events
+    #[serde(flatten)]
+    sampling: Option<SamplingRun>,
+}
+
+/// The metrics of a `transfer` event that the report averages.
+#[derive(Deserialize)]
+struct TransferRun {
+    mode: ResearchMode,
+    payload_bytes: usize,
+    wire_bytes: usize,
+    latency_ms: f64,
+    throughput_mb_s: f64,
+    savings_pct: f64,
+}
+
+fn run_report(from: &str) -> Result<ExitStatus> {
+    let text = std::fs::read_to_string(from).with_context(|| format!("Cannot read {}", from))?;
+    let mut report = Report { role: "report", ..Default::default() };
+    let mut transfers: BTreeMap<ResearchMode, Vec<TransferRun>> = BTreeMap::new();
+    let mut sampling: BTreeMap<SampleUnit, Vec<SamplingRun>> = BTreeMap::new();
+    let mut failures: BTreeMap<String, usize> = BTreeMap::new();
+    let (mut confidence, mut reconstruction) = (Vec::new(), Vec::new());
+
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let Ok(event) = serde_json::from_str::<ReportEvent>(line) else {
+            report.malformed += 1;
+            continue;
+        };
+        report.events += 1;
+        match event.event.as_str() {
+            "transfer" => match event.transfer {
+                Some(run) => transfers.entry(run.mode).or_default().push(run),
+                None => report.malformed += 1,
+            },
+            "sampled" => match event.sampling {
+                Some(run) => sampling.entry(run.unit).or_default().push(run),
+                None => report.malformed += 1,
+            },
+            "outcome" => {
+                let Some(verdict) = event.verdict else { continue };
+                report.outcomes += 1;
+                *report.verdicts.entry(format!("{:?}", verdict).to_lowercase()).or_default() += 1;
+                if matches!(verdict, Verdict::Verified | Verdict::Reconstructed | Verdict::Sampled) {
+                    report.succeeded += 1;
+                } else {
+                    *failures.entry(format!("verdict {}", format!("{:?}", verdict).to_lowercase())).or_default() += 1;
+                }
+                confidence.extend(event.confidence_latency_ms);
+                reconstruction.extend(event.reconstruction_latency_ms);
+            }
+            "shard_received" if event.accepted == Some(false) => {
+                *failures.entry(format!("shard rejected: {}", event.reason.unwrap_or_default())).or_default() += 1;
+            }
+            "protocol_error" => *failures.entry(format!("protocol error: {}", event.reason.unwrap_or_default())).or_default() += 1,
+            "handshake" if event.ok == Some(false) => *failures.entry("handshake failed".to_string()).or_default() += 1,
+            _ => {}
+        }
+    }
+
+    let mean = |values: &[f64]| (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64);
+    report.success_rate = if report.outcomes == 0 { 0.0 } else { report.succeeded as f64 / report.outcomes as f64 };
+    report.mean_confidence_latency_ms = mean(&confidence);
+    report.mean_reconstruction_latency_ms = mean(&reconstruction);
+    report.modes = transfers.into_iter()
+        .map(|(mode, runs)| {
+            let avg = |f: fn(&TransferRun) -> f64| runs.iter().map(f).sum::<f64>() / runs.len() as f64;
+            ModeReport {
+                mode,
+                transfers: runs.len(),
+                payload_bytes: runs.iter().map(|r| r.payload_bytes).sum(),
+                wire_bytes: runs.iter().map(|r| r.wire_bytes).sum(),
+                mean_savings_pct: avg(|r| r.savings_pct),
+                mean_throughput_mb_s: avg(|r| r.throughput_mb_s),
+                mean_latency_ms: avg(|r| r.latency_ms),
+            }
+        })
+        .collect();
+    report.units = sampling.into_iter()
+        .map(|(unit, runs)| {
+            let avg = |f: fn(&SamplingRun) -> f64| runs.iter().map(f).sum::<f64>() / runs.len() as f64;
+            let detection: Vec<f64> = runs.iter().filter_map(|r| r.detection).collect();
+            UnitReport {
+                unit,
+                runs: runs.len(),
+                unavailable: runs.iter().filter(|r| !r.available).count(),
+                mean_samples: avg(|r| r.sampled as f64),
+                mean_bytes: avg(|r| r.bytes as f64),
+                mean_detection: mean(&detection),
+            }
+        })
+        .collect();
+    report.failures = failures.into_iter().map(|(reason, count)| FailureCount { reason, count }).collect();
+    report.failures.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.reason.cmp(&b.reason)));
+
+    say!("{} {} ({} events, {} malformed lines)", "➜ Report:".blue().bold(), from, report.events, report.malformed);
+    say!("\n{}", "=== Outcomes ===".bold().white().on_blue());
+    say!("{:<22} : {}/{} ({:.1}%)", "Succeeded", report.succeeded, report.outcomes, report.success_rate * 100.0);
+    for (verdict, count) in &report.verdicts {
+        say!("{:<22} : {}", format!("  {}", verdict), count);
+    }
+    let after = |ms: Option<f64>| ms.map_or_else(|| "n/a".to_string(), |ms| format!("{:.1} ms", ms));
+    say!("{:<22} : confident after {}, rebuilt after {}", "Time to availability", after(report.mean_confidence_latency_ms),
+        after(report.mean_reconstruction_latency_ms));
+    if !report.modes.is_empty() {
+        say!("\n{:<11} {:>9} {:>12} {:>12} {:>10} {:>11} {:>11}", "mode", "transfers", "payload", "wire", "savings", "throughput", "latency");
+        for m in &report.modes {
+            let savings = format!("{:>9.2}%", m.mean_savings_pct);
+            let savings = if m.mean_savings_pct >= 0.0 { savings.green() } else { savings.red() };
+            say!("{:<11} {:>9} {:>12} {:>12} {} {:>11} {:>11}", format!("{:?}", m.mode), m.transfers, format_bytes(m.payload_bytes),
+                format_bytes(m.wire_bytes), savings, format!("{:.2} MB/s", m.mean_throughput_mb_s), format!("{:.1} ms", m.mean_latency_ms));
+        }
+    }
+    if !report.units.is_empty() {
+        say!("\n{}", "=== Sampling Units ===".bold().white().on_blue());
+        say!("{:<8} {:>6} {:>8} {:>12} {:>10} {:>12}", "unit", "runs", "samples", "bytes", "detection", "unavailable");
+        for u in &report.units {
+            say!("{:<8} {:>6} {:>8.1} {:>12} {:>10} {:>12}", u.unit.name(), u.runs, u.mean_samples, format_bytes(u.mean_bytes as usize),
+                u.mean_detection.map_or_else(|| "n/a".to_string(), |d| format!("{:.2}%", d * 100.0)),
+                format!("{}/{}", u.unavailable, u.runs));
+        }
+    }
+    if !report.failures.is_empty() {
+        say!("\n{}", "=== Failure Reasons ===".bold().white().on_blue());
+        for f in &report.failures {
+            say!("{:>6}  {}", f.count, f.reason);
+        }
+    }
+
+    print_summary(&report)?;
+    Ok(ExitStatus::Success)
+}
+
+// RESULTS
+// `--results <file>` appends every run's summary as a JSON line under `--experiment-id`, so runs
+// across code or parameter changes can be set side by side with `compare-runs`.
+static RESULTS: OnceLock<String> = OnceLock::new();
+
+/// One run in a `--results` file.
+#[derive(Serialize, Deserialize, Debug)]
+struct RunRecord {
+    /// `--experiment-id`, or `default`
+    experiment: String,
+    /// `--tag`s of the run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    /// Unix seconds when the run finished
+    finished_at: u64,
+    /// Version of the binary that ran it
+    version: String,
+    /// The command line, without the binary
+    args: Vec<String>,
+    /// `role` of the summary: proposer, validator, light-client, ...
+    role: String,
+    /// Every number in the summary, by its dotted path (`roots.0.bytes`)
+    metrics: BTreeMap<String, f64>,
+    summary: serde_json::Value,
+}
+
+/// Appends the run to `--results`, if set. Unlike the event log, a failed write fails the run:
+/// a comparison missing it would be silently wrong.
+fn record_run<T: Serialize>(summary: &T) -> Result<()> {
+    let Some(path) = RESULTS.get() else { return Ok(()) };
+    let summary = serde_json::to_value(summary)?;
+    let role = match &summary {
+        serde_json::Value::Array(items) => items.first().and_then(|s| s["role"].as_str()),
+        summary => summary["role"].as_str(),
+    };
+    let mut metrics = BTreeMap::new();
+    numeric_fields(&summary, "", &mut metrics);
+    let record = RunRecord {
+        experiment: run_tags().experiment.clone().unwrap_or_else(|| "default".to_string()),
+        tags: run_tags().tags.clone(),
+        finished_at: unix_now(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        args: std::env::args().skip(1).collect(),
+        role: role.unwrap_or("run").to_string(),
+        metrics,
+        summary,
+    };
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("Cannot open {}", path))?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)?;
     Ok(())
-}
\ No newline at end of file
+}
+
+fn numeric_fields(value: &serde_json::Value, path: &str, out: &mut BTreeMap<String, f64>) {
+    let child = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+    match value {
+        serde_json::Value::Number(n) => if let Some(n) = n.as_f64() {
+            out.insert(path.to_string(), n);
+        },
+        serde_json::Value::Array(items) => for (i, item) in items.iter().enumerate() {
+            numeric_fields(item, &child(&i.to_string()), out);
+        },
+        serde_json::Value::Object(fields) => for (key, field) in fields {
+            numeric_fields(field, &child(key), out);
+        },
+        _ => {}
+    }
+}
+
+/// The runs in a `--results` file. Lines that are bare summaries (`--quiet` output) count as
+/// runs of an experiment named after the file.
+fn read_results(path: &str) -> Result<Vec<RunRecord>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Cannot read {}", path))?;
+    text.lines().filter(|l| !l.trim().is_empty()).enumerate()
+        .map(|(i, line)| {
+            let value: serde_json::Value = serde_json::from_str(line).with_context(|| format!("{} line {} is not JSON", path, i + 1))?;
+            if value.get("metrics").is_some() && value.get("experiment").is_some() {
+                return Ok(serde_json::from_value(value)?);
+            }
+            let mut metrics = BTreeMap::new();
+            numeric_fields(&value, "", &mut metrics);
+            Ok(RunRecord {
+                experiment: path.to_string(),
+                tags: BTreeMap::new(),
+                finished_at: 0,
+                version: String::new(),
+                args: Vec::new(),
+                role: value["role"].as_str().unwrap_or("run").to_string(),
+                metrics,
+                summary: value,
+            })
+        })
+        .collect()
+}
+
+/// Every value each metric took over some runs, keyed by role and metric.
+fn metric_values<'a>(runs: impl Iterator<Item = &'a RunRecord>) -> BTreeMap<(String, String), Vec<f64>> {
+    let mut values: BTreeMap<(String, String), Vec<f64>> = BTreeMap::new();
+    for run in runs {
+        for (metric, &value) in &run.metrics {
+            values.entry((run.role.clone(), metric.clone())).or_default().push(value);
+        }
+    }
+    values
+}
+
+/// Whether a lower value of a metric is the better one, judged by its name; `None` for metrics
+/// that are neither, such as payload sizes or counts of what was asked for.
+fn lower_is_better(metric: &str) -> Option<bool> {
+    let name = metric.rsplit('.').next().unwrap_or(metric);
+    const NEUTRAL: [&str; 4] = ["payload", "original_len", "shards_sent", "seed"];
+    const LOWER: [&str; 11] = ["latency", "_ms", "bytes", "overhead", "rejected", "drops", "fail", "corrupt", "malformed", "unavailable", "fee"];
+    const HIGHER: [&str; 8] = ["throughput", "mb_s", "savings", "succe", "confidence", "detection", "verified", "recovered"];
+    if NEUTRAL.iter().any(|w| name.contains(w)) {
+        None
+    } else if LOWER.iter().any(|w| name.contains(w)) {
+        Some(true)
+    } else if HIGHER.iter().any(|w| name.contains(w)) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Mean of each metric over one experiment's runs, keyed by role and metric.
+#[derive(Serialize)]
+struct ExperimentMeans {
+    experiment: String,
+    runs: usize,
+    #[serde(skip)]
+    means: BTreeMap<(String, String), f64>,
+}
+
+impl ExperimentMeans {
+    fn new(experiment: &str, runs: &[&RunRecord]) -> Self {
+        let means = metric_values(runs.iter().copied()).into_iter().map(|(key, v)| (key, v.iter().sum::<f64>() / v.len() as f64)).collect();
+        Self { experiment: experiment.to_string(), runs: runs.len(), means }
+    }
+}
+
+/// One metric of an experiment against the baseline.
+#[derive(Serialize)]
+struct MetricChange {
+    experiment: String,
+    role: String,
+    metric: String,
+    baseline: f64,
+    value: f64,
+    /// Relative to the baseline; absent when the baseline is zero
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change_pct: Option<f64>,
+    /// improved, regressed, changed (no better direction) or unchanged
+    verdict: &'static str,
+}
+
+/// `compare-runs` in `--quiet` mode.
+#[derive(Serialize)]
+struct CompareSummary {
+    role: &'static str,
+    baseline: String,
+    experiments: Vec<ExperimentMeans>,
+    changes: Vec<MetricChange>,
+    regressions: usize,
+    improvements: usize,
+}
+
+fn run_compare_runs(from: &str, experiments: Vec<String>, role: Option<String>, group_by: Option<String>, threshold: f64) -> Result<ExitStatus> {
+    let mut runs: Vec<RunRecord> = read_results(from)?.into_iter().filter(|r| role.as_ref().is_none_or(|role| &r.role == role)).collect();
+    // Grouping by a tag makes each of its values an experiment; untagged runs drop out.
+    if let Some(key) = &group_by {
+        runs = runs.into_iter().filter_map(|mut r| {
+            r.experiment = r.tags.get(key)?.clone();
+            Some(r)
+        }).collect();
+    }
+    let experiments = if experiments.is_empty() {
+        let mut seen = Vec::new();
+        for run in &runs {
+            if !seen.contains(&run.experiment) { seen.push(run.experiment.clone()); }
+        }
+        seen
+    } else {
+        experiments
+    };
+    if experiments.len() < 2 {
+        bail!("{} holds {} experiment(s){}; need a baseline and at least one to compare", from, experiments.len(),
+            role.map_or(String::new(), |r| format!(" with role {}", r)));
+    }
+    let experiments: Vec<ExperimentMeans> = experiments.iter()
+        .map(|e| ExperimentMeans::new(e, &runs.iter().filter(|r| &r.experiment == e).collect::<Vec<_>>()))
+        .collect();
+    if let Some(empty) = experiments.iter().find(|e| e.runs == 0) {
+        bail!("No runs of experiment {} in {}", empty.experiment, from);
+    }
+
+    let (baseline, others) = experiments.split_first().expect("at least two experiments");
+    say!("{} {} ({} runs), baseline {}", "➜ Compare:".blue().bold(), from, runs.len(), baseline.experiment);
+    let mut changes = Vec::new();
+    for other in others {
+        say!("\n{}", format!("=== {} ({} runs) vs {} ({} runs) ===", other.experiment, other.runs, baseline.experiment, baseline.runs).bold().white().on_blue());
+        say!("{:<44} {:>14} {:>14} {:>10}", "metric", baseline.experiment, other.experiment, "change");
+        for ((role, metric), &value) in &other.means {
+            let Some(&base) = baseline.means.get(&(role.clone(), metric.clone())) else { continue };
+            let change_pct = (base != 0.0).then(|| (value - base) / base.abs() * 100.0);
+            let moved = match change_pct {
+                Some(pct) => pct.abs() > threshold,
+                None => value != 0.0,
+            };
+            let verdict = match (moved, lower_is_better(metric)) {
+                (false, _) => "unchanged",
+                (true, None) => "changed",
+                (true, Some(lower)) if (value < base) == lower => "improved",
+                (true, Some(_)) => "regressed",
+            };
+            let change = change_pct.map_or_else(|| "n/a".to_string(), |pct| format!("{:+.1}%", pct));
+            let line = format!("{:<44} {:>14.3} {:>14.3} {:>10}  {}", format!("{} {}", role, metric), base, value, change, verdict);
+            match verdict {
+                "improved" => say!("{}", line.green()),
+                "regressed" => say!("{}", line.red()),
+                _ => say!("{}", line),
+            }
+            changes.push(MetricChange {
+                experiment: other.experiment.clone(), role: role.clone(), metric: metric.clone(), baseline: base, value, change_pct, verdict,
+            });
+        }
+    }
+    let count = |verdict: &str| changes.iter().filter(|c| c.verdict == verdict).count();
+    let (regressions, improvements) = (count("regressed"), count("improved"));
+    say!("\n{} {} regression(s), {} improvement(s) beyond {}%", "➜ Result:".blue().bold(), regressions, improvements, threshold);
+
+    print_summary(&CompareSummary { role: "compare-runs", baseline: baseline.experiment.clone(), experiments, changes, regressions, improvements })?;
+    Ok(ExitStatus::Success)
+}
+
+/// Two-sided 5% critical values of Student's t for 1 to 30 degrees of freedom; beyond that the
+/// normal value, 1.96, is close enough.
+const T_CRITICAL_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+    2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+    2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+
+/// Mean, sample standard deviation and count of one metric on one side of a diff.
+#[derive(Serialize, Clone, Copy)]
+struct Trials {
+    mean: f64,
+    std_dev: f64,
+    n: usize,
+}
+
+impl Trials {
+    fn new(values: &[f64]) -> Self {
+        let n = values.len();
+        let mean = values.iter().sum::<f64>() / n as f64;
+        let var = if n > 1 { values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64 } else { 0.0 };
+        Self { mean, std_dev: var.sqrt(), n }
+    }
+
+    /// Welch's t statistic for `other` against `self`, and whether it clears the 95% level.
+    /// `None` unless both sides have at least two trials.
+    fn welch(&self, other: &Trials) -> Option<(f64, bool)> {
+        if self.n < 2 || other.n < 2 {
+            return None;
+        }
+        let (va, vb) = (self.std_dev.powi(2) / self.n as f64, other.std_dev.powi(2) / other.n as f64);
+        let delta = other.mean - self.mean;
+        if va + vb == 0.0 {
+            // No spread on either side: any difference at all is real.
+            return Some((if delta == 0.0 { 0.0 } else { f64::INFINITY.copysign(delta) }, delta != 0.0));
+        }
+        let t = delta / (va + vb).sqrt();
+        let df = (va + vb).powi(2) / (va.powi(2) / (self.n - 1) as f64 + vb.powi(2) / (other.n - 1) as f64);
+        let critical = T_CRITICAL_95.get((df.floor() as usize).saturating_sub(1)).copied().unwrap_or(1.96);
+        Some((t, t.abs() > critical))
+    }
+}
+
+/// One metric in `diff-metrics`.
+#[derive(Serialize)]
+struct MetricDiff {
+    role: String,
+    metric: String,
+    a: Trials,
+    b: Trials,
+    delta: f64,
+    /// Relative to A; absent when A's mean is zero
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change_pct: Option<f64>,
+    /// Welch's t of B against A, when both sides have two or more trials
+    #[serde(skip_serializing_if = "Option::is_none")]
+    t: Option<f64>,
+    significant: Option<bool>,
+}
+
+/// `diff-metrics` in `--quiet` mode.
+#[derive(Serialize)]
+struct DiffSummary {
+    role: &'static str,
+    a: String,
+    b: String,
+    metrics: Vec<MetricDiff>,
+    significant: usize,
+}
+
+/// The runs one side of a diff names: a metrics file, or an experiment in `--from`.
+fn diff_side<'a>(spec: &str, from: Option<&'a [RunRecord]>, loaded: &'a mut Vec<RunRecord>) -> Result<Vec<&'a RunRecord>> {
+    if std::path::Path::new(spec).is_file() {
+        *loaded = read_results(spec)?;
+        return Ok(loaded.iter().collect());
+    }
+    let Some(runs) = from else { bail!("{} is neither a metrics file nor, without --from, a run ID", spec) };
+    let runs: Vec<&RunRecord> = runs.iter().filter(|r| r.experiment == spec).collect();
+    if runs.is_empty() {
+        bail!("No runs of experiment {} in --from", spec);
+    }
+    Ok(runs)
+}
+
+fn run_diff_metrics(a: &str, b: &str, from: Option<&str>, role: Option<String>) -> Result<ExitStatus> {
+    let results = from.map(read_results).transpose()?;
+    let (mut loaded_a, mut loaded_b) = (Vec::new(), Vec::new());
+    let keep = |r: &&RunRecord| role.as_ref().is_none_or(|role| &r.role == role);
+    let runs_a: Vec<&RunRecord> = diff_side(a, results.as_deref(), &mut loaded_a)?.into_iter().filter(keep).collect();
+    let runs_b: Vec<&RunRecord> = diff_side(b, results.as_deref(), &mut loaded_b)?.into_iter().filter(keep).collect();
+    let (values_a, values_b) = (metric_values(runs_a.iter().copied()), metric_values(runs_b.iter().copied()));
+
+    say!("{} A = {} ({} runs), B = {} ({} runs)", "➜ Diff:".blue().bold(), a, runs_a.len(), b, runs_b.len());
+    say!("\n{:<40} {:>22} {:>22} {:>9} {:>8}  {}", "metric", "A (mean ± sd, n)", "B (mean ± sd, n)", "change", "t", "significant");
+    let trials = |t: &Trials| format!("{:.3} ± {:.3}, {}", t.mean, t.std_dev, t.n);
+    let mut metrics = Vec::new();
+    for ((role, metric), va) in &values_a {
+        let Some(vb) = values_b.get(&(role.clone(), metric.clone())) else { continue };
+        let (ta, tb) = (Trials::new(va), Trials::new(vb));
+        let delta = tb.mean - ta.mean;
+        let change_pct = (ta.mean != 0.0).then(|| delta / ta.mean.abs() * 100.0);
+        let welch = ta.welch(&tb);
+        let line = format!("{:<40} {:>22} {:>22} {:>9} {:>8}  {}", format!("{} {}", role, metric), trials(&ta), trials(&tb),
+            change_pct.map_or_else(|| "n/a".to_string(), |pct| format!("{:+.1}%", pct)),
+            welch.map_or_else(|| "n/a".to_string(), |(t, _)| format!("{:.2}", t)),
+            match welch {
+                Some((_, true)) => "yes",
+                Some((_, false)) => "no",
+                None => "n/a (one trial)",
+            });
+        if welch.is_some_and(|(_, significant)| significant) { say!("{}", line.bold()); } else { say!("{}", line); }
+        metrics.push(MetricDiff {
+            role: role.clone(), metric: metric.clone(), a: ta, b: tb, delta, change_pct,
+            t: welch.map(|(t, _)| t).filter(|t| t.is_finite()), significant: welch.map(|(_, s)| s),
+        });
+    }
+    if metrics.is_empty() {
+        bail!("{} and {} share no metrics{}", a, b, role.map_or(String::new(), |r| format!(" with role {}", r)));
+    }
+    let significant = metrics.iter().filter(|m| m.significant == Some(true)).count();
+    say!("\n{} {} of {} metrics differ significantly (Welch's t-test, 95%)", "➜ Result:".blue().bold(), significant, metrics.len());
+
+    print_summary(&DiffSummary { role: "diff-metrics", a: a.to_string(), b: b.to_string(), metrics, significant })?;
+    Ok(ExitStatus::Success)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_round_trip() {
+        let files = vec![("a.txt".to_string(), b"alpha".to_vec()), ("empty".to_string(), Vec::new()), ("b.bin".to_string(), vec![0xff; 300])];
+        let packed = pack_files(&files).unwrap();
+        let unpacked = unpack_files(&packed).unwrap();
+        assert_eq!(unpacked.len(), files.len());
+        for ((name, data), (got_name, got)) in files.iter().zip(&unpacked) {
+            assert_eq!((name, data.as_slice()), (got_name, *got));
+        }
+        assert!(unpack_files(&pack_files(&[]).unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_packs_are_refused() {
+        let packed = pack_files(&[("a.txt".to_string(), b"alpha".to_vec())]).unwrap();
+        // Every truncation, down to nothing.
+        for len in 0..packed.len() {
+            assert!(unpack_files(&packed[..len]).is_err(), "truncated to {}", len);
+        }
+        let mut bad_magic = packed.clone();
+        bad_magic[0] ^= 1;
+        assert!(unpack_files(&bad_magic).is_err());
+        let mut bad_version = packed.clone();
+        bad_version[4] = PACK_VERSION + 1;
+        assert!(unpack_files(&bad_version).is_err());
+
+        // A count or length far past the bytes there are, and one that overflows the offset.
+        let mut huge_count = packed[..5].to_vec();
+        huge_count.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(unpack_files(&huge_count).is_err());
+        for len in [u64::MAX, u64::MAX - 20, 6] {
+            let mut huge_len = packed.clone();
+            huge_len[16..24].copy_from_slice(&len.to_be_bytes());
+            assert!(unpack_files(&huge_len).is_err(), "entry length {}", len);
+        }
+
+        // Entry names that would escape the output directory, or aren't UTF-8.
+        for name in ["../evil", "a/b", "..", ""] {
+            assert!(unpack_files(&pack_files(&[(name.to_string(), b"x".to_vec())]).unwrap()).is_err(), "{:?}", name);
+        }
+        let mut not_utf8 = packed;
+        not_utf8[11] = 0xff;
+        assert!(unpack_files(&not_utf8).is_err());
+    }
+
+    #[test]
+    fn window_names_round_trip() {
+        assert_eq!(window_of(&window_name("big.iso", 3, 12)), Some(("big.iso", 3, 12)));
+        assert_eq!(window_of(&window_name("a.window-x", 1, 1)), Some(("a.window-x", 1, 1)));
+        for name in ["big.iso", "big.iso.window-0000-of-0002", "big.iso.window-0003-of-0002", "big.iso.window-1-of-x", "big.iso.window-99999999999999999999-of-1"] {
+            assert_eq!(window_of(name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn attestations_verify_and_catch_tampering() {
+        let id = Identity::generate();
+        let mut att = Attestation {
+            root: calculate_sha256(b"blob"),
+            filename: "blob.bin".into(),
+            verdict: Verdict::Verified,
+            samples: 4,
+            verified_at: unix_now(),
+            validator: hex::encode(id.public.as_bytes()),
+            signature: String::new(),
+        };
+        att.signature = hex::encode(id.key.sign(&att.signing_bytes()).to_bytes());
+        assert_eq!(att.verify().unwrap(), id.public);
+
+        let tampered = [
+            Attestation { filename: "other.bin".into(), ..att.clone() },
+            Attestation { verdict: Verdict::Corrupted, ..att.clone() },
+            Attestation { samples: 5, ..att.clone() },
+            Attestation { verified_at: att.verified_at + 1, ..att.clone() },
+            Attestation { validator: hex::encode(Identity::generate().public.as_bytes()), ..att.clone() },
+            Attestation { signature: "00".into(), ..att.clone() },
+        ];
+        for att in tampered {
+            assert!(att.verify().is_err());
+        }
+        // Moving bytes between the root and the filename changes what is signed.
+        let shifted = Attestation { root: format!("{}b", att.root), filename: "lob.bin".into(), ..att.clone() };
+        assert_ne!(shifted.signing_bytes(), att.signing_bytes());
+    }
+
+    fn speculation(held: &[usize], tried: Vec<Vec<usize>>) -> Speculation {
+        Speculation {
+            state: Arc::default(),
+            peer: "peer".into(),
+            stream: BARE_STREAM,
+            filename: "blob.bin".into(),
+            layout: Layout::default(),
+            original_len: 0,
+            checksum: String::new(),
+            started: Duration::ZERO,
+            shards: held.iter().map(|&i| (i, Vec::new())).collect(),
+            charged: 0,
+            tried,
+            running: false,
+            failed: None,
+        }
+    }
+
+    #[test]
+    fn speculation_tries_each_subset_once() {
+        assert_eq!(speculation(&[0, 1], Vec::new()).next_subset(), None);
+        let held: Vec<usize> = (0..TOTAL_SHARDS).collect();
+        let mut spec = speculation(&held, Vec::new());
+        assert_eq!(spec.next_subset(), Some((0..DATA_SHARDS).collect()));
+        while let Some(subset) = spec.next_subset() {
+            assert_eq!(subset.len(), DATA_SHARDS);
+            assert!(subset.windows(2).all(|w| w[0] < w[1]));
+            assert!(!spec.tried.contains(&subset));
+            spec.tried.push(subset);
+        }
+        // Every k-subset of the held shards, then none.
+        let choose = (0..DATA_SHARDS).fold(1, |c, i| c * (TOTAL_SHARDS - i) / (i + 1));
+        assert_eq!(spec.tried.len(), choose);
+    }
+
+    #[test]
+    fn speculation_returns_its_quota_charge() {
+        let mut spec = speculation(&[], Vec::new());
+        spec.state.locked().quota_usage.insert("peer".into(), 100);
+        spec.charged = 40;
+        let state = spec.state.clone();
+        drop(spec);
+        assert_eq!(state.locked().quota_usage["peer"], 60);
+    }
+
+    #[test]
+    fn welch_flags_only_real_differences() {
+        let a = Trials::new(&[10.0, 11.0, 9.0, 10.5, 9.5]);
+        assert_eq!(a.welch(&a), Some((0.0, false)));
+        let (t, significant) = a.welch(&Trials::new(&[20.0, 21.0, 19.0, 20.5, 19.5])).unwrap();
+        assert!(t > 0.0 && significant);
+        let (t, significant) = a.welch(&Trials::new(&[10.2, 10.8, 9.1, 10.0, 9.9])).unwrap();
+        assert!(t.is_finite() && !significant);
+        // Too few trials to say, and no spread at all.
+        assert_eq!(a.welch(&Trials::new(&[10.0])), None);
+        let flat = Trials::new(&[5.0, 5.0]);
+        assert_eq!(flat.welch(&Trials::new(&[4.0, 4.0])), Some((f64::NEG_INFINITY, true)));
+        assert_eq!(flat.welch(&flat), Some((0.0, false)));
+    }
+}
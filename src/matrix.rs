@@ -0,0 +1,428 @@
+//! Danksharding-style 2D batching across the blobs of one block. Each blob is a row; the row
+//! code extends every row across columns, and a column code extends every column down across
+//! the rows, doubling them. Laid out column-major, the data columns form an ordinary payload
+//! whose k data shards are exactly those columns, so any [`DasEncoder`](crate::coding::DasEncoder)
+//! adds the parity columns, and one sampled shard is one column covering every blob at once.
+//!
+//! Row 0 indexes the block, so the payload describes itself:
+//! magic(4) | version(1) | rows(4) | cell_len(8) | count(4) | count x [name_len(2) | name | len(8)].
+//! The fixed fields sit in the first cell, which leads the payload, so the shape can be read
+//! before the rest of the row is gathered from the other columns.
+
+use clap::ValueEnum;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+
+use crate::coding::{calculate_sha256, DasDecoder, EncodedBlob};
+use crate::error::MatrixError;
+use crate::protocol::Layout;
+use crate::sampling::hit_probability;
+
+pub const MATRIX_MAGIC: &[u8; 4] = b"DASM";
+pub const MATRIX_VERSION: u8 = 1;
+/// The column code is RS(d, d) over the d data rows (the index row and the blobs), and
+/// GF(2^8) allows at most 256 shards.
+pub const MAX_MATRIX_BLOBS: usize = 127;
+/// magic, version, rows and cell_len: what must fit in the first cell.
+const FIXED_HEADER: usize = 17;
+
+/// What one sample of a block matrix fetches.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SampleUnit {
+    /// One cell: one row's share of one column
+    Cells,
+    /// A whole row, every cell of one blob (or parity row)
+    Rows,
+    /// A whole column, one cell of every row: the shard itself
+    #[default]
+    Columns,
+}
+
+impl SampleUnit {
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleUnit::Cells => "cell",
+            SampleUnit::Rows => "row",
+            SampleUnit::Columns => "column",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct MatrixShape {
+    /// Blobs in the block, one row each
+    pub blobs: usize,
+    /// Rows of the extended matrix: the index row and the blobs, then as many parity rows
+    pub rows: usize,
+    /// Data columns; the row code adds the parity columns
+    pub k: usize,
+    /// Bytes per cell
+    pub cell_len: usize,
+}
+
+impl MatrixShape {
+    pub fn data_rows(&self) -> usize {
+        self.rows / 2
+    }
+
+    /// Bytes in one column, a cell from every row.
+    pub fn column_len(&self) -> usize {
+        self.rows * self.cell_len
+    }
+
+    /// Units of this kind in the extended matrix (k + m columns), which samples are drawn from.
+    pub fn units(&self, unit: SampleUnit, m: usize) -> usize {
+        match unit {
+            SampleUnit::Cells => self.rows * (self.k + m),
+            SampleUnit::Rows => self.rows,
+            SampleUnit::Columns => self.k + m,
+        }
+    }
+
+    /// Bytes one sample of this kind fetches.
+    pub fn unit_len(&self, unit: SampleUnit, m: usize) -> usize {
+        match unit {
+            SampleUnit::Cells => self.cell_len,
+            SampleUnit::Rows => (self.k + m) * self.cell_len,
+            SampleUnit::Columns => self.column_len(),
+        }
+    }
+
+    /// Units of this kind touched by the cheapest withholding that leaves the block
+    /// unrecoverable: d + 1 rows by m + 1 columns of cells. Each of those rows is then short of
+    /// k cells and each of those columns short of d, so neither code can bring them back.
+    pub fn withheld(&self, unit: SampleUnit, m: usize) -> usize {
+        match unit {
+            SampleUnit::Cells => (self.data_rows() + 1) * (m + 1),
+            SampleUnit::Rows => self.data_rows() + 1,
+            SampleUnit::Columns => m + 1,
+        }
+    }
+
+    /// The `(row, column)` cells that unit `index` of this kind covers, indices being row-major
+    /// for cells.
+    pub fn unit_cells(&self, unit: SampleUnit, m: usize, index: usize) -> Vec<(usize, usize)> {
+        let columns = self.k + m;
+        match unit {
+            SampleUnit::Cells => vec![(index / columns, index % columns)],
+            SampleUnit::Rows => (0..columns).map(|c| (index, c)).collect(),
+            SampleUnit::Columns => (0..self.rows).map(|r| (r, index)).collect(),
+        }
+    }
+
+    /// Chance that `samples` distinct units of this kind hit that withholding.
+    pub fn detection(&self, unit: SampleUnit, m: usize, samples: usize) -> f64 {
+        1.0 - hit_probability(self.units(unit, m), self.withheld(unit, m), samples, 0)
+    }
+
+    /// Sampling units of this kind until `target` sure; each covers every blob it crosses.
+    pub fn cost(&self, unit: SampleUnit, m: usize, target: f64) -> SamplingCost {
+        let (units, withheld) = (self.units(unit, m), self.withheld(unit, m));
+        let samples = samples_for(units, withheld, target);
+        SamplingCost {
+            unit: unit.name(),
+            sample_bytes: self.unit_len(unit, m),
+            samples,
+            bytes: samples * self.unit_len(unit, m),
+            fooled: hit_probability(units, withheld, samples, 0),
+        }
+    }
+}
+
+/// What one light client fetches to be `target` sure that every blob of a block is available,
+/// against the cheapest withholding that leaves some blob unrecoverable.
+#[derive(Clone, Debug, Serialize)]
+pub struct SamplingCost {
+    /// What one sample fetches
+    pub unit: &'static str,
+    /// Bytes per sample; for blob shards, the mean over the blobs
+    pub sample_bytes: usize,
+    /// Queries, over every blob they are needed for
+    pub samples: usize,
+    pub bytes: usize,
+    /// Chance the client is still fooled after `samples`
+    pub fooled: f64,
+}
+
+/// Sampling each blob of `lens` bytes on its own, as 1D RS(k, m) blobs: every blob needs its
+/// own samples, since withholding m + 1 shards of any one of them is enough.
+pub fn per_blob_cost(lens: &[usize], k: usize, m: usize, target: f64) -> SamplingCost {
+    let n = k + m;
+    let samples = samples_for(n, m + 1, target);
+    let shard_bytes = lens.iter().map(|len| len.div_ceil(k)).sum::<usize>();
+    SamplingCost {
+        unit: "blob shard",
+        sample_bytes: shard_bytes / lens.len().max(1),
+        samples: samples * lens.len(),
+        bytes: samples * shard_bytes,
+        fooled: hit_probability(n, m + 1, samples, 0),
+    }
+}
+
+/// Fewest distinct samples out of `n` that miss all `withheld` with chance at most `1 - target`.
+fn samples_for(n: usize, withheld: usize, target: f64) -> usize {
+    (1..=n).find(|&s| hit_probability(n, withheld, s, 0) <= 1.0 - target).unwrap_or(n)
+}
+
+/// Commits to every cell of an encoded block matrix, so a light client can check a single cell
+/// without fetching its whole column.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatrixCells {
+    pub rows: usize,
+    pub cell_len: usize,
+    /// SHA-256 of every cell, hex, row by row; along a row, in shard (not wire) order
+    pub cell_hashes: Vec<String>,
+}
+
+impl MatrixCells {
+    /// Cell hashes of `blob`, the encoded payload of a block matrix with `rows` rows.
+    pub fn new(blob: &EncodedBlob, rows: usize) -> Self {
+        let cell_len = blob.shard_len / rows;
+        let cell_hashes = (0..rows)
+            .flat_map(|r| blob.shards.iter().map(move |shard| calculate_sha256(&shard[r * cell_len..(r + 1) * cell_len])))
+            .collect();
+        Self { rows, cell_len, cell_hashes }
+    }
+
+    /// The shape of the block, given the manifest's k.
+    pub fn shape(&self, k: usize) -> MatrixShape {
+        MatrixShape { blobs: (self.rows / 2).saturating_sub(1), rows: self.rows, k, cell_len: self.cell_len }
+    }
+
+    /// Whether `data` is the cell at `row` of shard `shard`.
+    pub fn verify(&self, row: usize, shard: usize, data: &[u8]) -> bool {
+        let columns = self.cell_hashes.len() / self.rows.max(1);
+        shard < columns && self.cell_hashes.get(row * columns + shard).is_some_and(|h| *h == calculate_sha256(data))
+    }
+}
+
+/// Batches `blobs` into one block matrix with `k` data columns: its shape, and its data
+/// columns one after another, ready to encode as a single payload. Cells are a multiple of
+/// `align` bytes, so columns come out as whole field elements when the commitment needs them.
+pub fn encode_matrix(blobs: &[(String, Vec<u8>)], k: usize, align: usize) -> Result<(MatrixShape, Vec<u8>), MatrixError> {
+    if blobs.is_empty() || blobs.len() > MAX_MATRIX_BLOBS {
+        return Err(MatrixError::BlobCount { count: blobs.len(), max: MAX_MATRIX_BLOBS });
+    }
+    let data_rows = blobs.len() + 1;
+    let rows = 2 * data_rows;
+    let mut index = (blobs.len() as u32).to_be_bytes().to_vec();
+    for (name, data) in blobs {
+        let name_len = u16::try_from(name.len()).map_err(|_| MatrixError::Malformed(format!("name {:?} too long", name)))?;
+        index.extend_from_slice(&name_len.to_be_bytes());
+        index.extend_from_slice(name.as_bytes());
+        index.extend_from_slice(&(data.len() as u64).to_be_bytes());
+    }
+    let longest = blobs.iter().map(|(_, data)| data.len()).max().unwrap_or(0).max(FIXED_HEADER + index.len());
+    let cell_len = longest.div_ceil(k).max(FIXED_HEADER).next_multiple_of(align.max(1));
+
+    let mut header = MATRIX_MAGIC.to_vec();
+    header.push(MATRIX_VERSION);
+    header.extend_from_slice(&(rows as u32).to_be_bytes());
+    header.extend_from_slice(&(cell_len as u64).to_be_bytes());
+    header.extend_from_slice(&index);
+    let row_bytes: Vec<&[u8]> = std::iter::once(&header[..]).chain(blobs.iter().map(|(_, data)| &data[..])).collect();
+
+    let code = ReedSolomon::new(data_rows, data_rows)?;
+    let mut payload = Vec::with_capacity(k * rows * cell_len);
+    for c in 0..k {
+        let mut column: Vec<Vec<u8>> = row_bytes.iter().map(|row| {
+            let mut cell = row.get(c * cell_len..).unwrap_or_default().to_vec();
+            cell.resize(cell_len, 0);
+            cell
+        }).collect();
+        column.resize(rows, vec![0; cell_len]);
+        code.encode(&mut column)?;
+        payload.extend(column.concat());
+    }
+    Ok((MatrixShape { blobs: blobs.len(), rows, k, cell_len }, payload))
+}
+
+/// Reads the shape of a block matrix payload from its first cell.
+pub fn matrix_shape(payload: &[u8]) -> Result<MatrixShape, MatrixError> {
+    let head = payload.get(..FIXED_HEADER).ok_or(MatrixError::NotAMatrix)?;
+    if &head[..4] != MATRIX_MAGIC {
+        return Err(MatrixError::NotAMatrix);
+    }
+    if head[4] != MATRIX_VERSION {
+        return Err(MatrixError::Version(head[4]));
+    }
+    let rows = u32::from_be_bytes(head[5..9].try_into().expect("4 bytes")) as usize;
+    let cell_len = usize::try_from(u64::from_be_bytes(head[9..17].try_into().expect("8 bytes")))
+        .map_err(|_| MatrixError::Malformed("cell length overflows".into()))?;
+    if rows < 4 || !rows.is_multiple_of(2) || rows / 2 > MAX_MATRIX_BLOBS + 1 {
+        return Err(MatrixError::Malformed(format!("{} rows", rows)));
+    }
+    let column_len = rows.checked_mul(cell_len).filter(|_| cell_len >= FIXED_HEADER)
+        .ok_or_else(|| MatrixError::Malformed(format!("{} byte cells", cell_len)))?;
+    if !payload.len().is_multiple_of(column_len) {
+        return Err(MatrixError::Malformed(format!("{} bytes is not a whole number of {} byte columns", payload.len(), column_len)));
+    }
+    Ok(MatrixShape { blobs: rows / 2 - 1, rows, k: payload.len() / column_len, cell_len })
+}
+
+/// Checks every column of a block matrix payload against the column code, then splits the
+/// payload back into its `(name, bytes)` blobs.
+pub fn decode_matrix(payload: &[u8]) -> Result<Vec<(String, Vec<u8>)>, MatrixError> {
+    let shape = matrix_shape(payload)?;
+    let (rows, cell_len) = (shape.rows, shape.cell_len);
+    let code = ReedSolomon::new(shape.data_rows(), shape.data_rows())?;
+    for (column, cells) in payload.chunks(shape.column_len()).enumerate() {
+        let cells: Vec<&[u8]> = cells.chunks(cell_len).collect();
+        if !code.verify(&cells)? {
+            return Err(MatrixError::BadExtension { column });
+        }
+    }
+    let row = |r: usize| -> Vec<u8> {
+        (0..shape.k).flat_map(|c| &payload[(c * rows + r) * cell_len..][..cell_len]).copied().collect()
+    };
+
+    let index = row(0);
+    let mut at = FIXED_HEADER;
+    let mut take = |n: usize| -> Result<&[u8], MatrixError> {
+        let slice = at.checked_add(n).and_then(|end| index.get(at..end)).ok_or_else(|| MatrixError::Malformed("index truncated".into()))?;
+        at += n;
+        Ok(slice)
+    };
+    let count = u32::from_be_bytes(take(4)?.try_into().expect("4 bytes")) as usize;
+    if count != shape.blobs {
+        return Err(MatrixError::Malformed(format!("index lists {} blobs for {} rows", count, rows)));
+    }
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let name_len = u16::from_be_bytes(take(2)?.try_into().expect("2 bytes")) as usize;
+        let name = String::from_utf8(take(name_len)?.to_vec()).map_err(|e| MatrixError::Malformed(e.to_string()))?;
+        let len = u64::from_be_bytes(take(8)?.try_into().expect("8 bytes"));
+        if len > (shape.k * cell_len) as u64 {
+            return Err(MatrixError::Malformed(format!("{} is {} bytes, longer than a row", name, len)));
+        }
+        entries.push((name, len as usize));
+    }
+    Ok(entries.into_iter().enumerate().map(|(i, (name, len))| {
+        let mut data = row(i + 1);
+        data.truncate(len);
+        (name, data)
+    }).collect())
+}
+
+/// Rebuilds a block matrix from whichever cells are at hand by iterative decoding. A row with
+/// k cells is completed by the row code and a column with d cells by the column code, and what
+/// one axis recovers can lift lines of the other axis to their threshold. Cells scattered over
+/// partial rows and columns may so be enough where no single axis has k complete lines.
+pub struct MatrixDecoder {
+    shape: MatrixShape,
+    m: usize,
+    layout: Layout,
+    column_code: ReedSolomon,
+    /// Row by row, k + m to a row, in shard order
+    cells: Vec<Option<Vec<u8>>>,
+}
+
+/// How a [`MatrixDecoder::decode`] went.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MatrixDecodeReport {
+    /// Passes that recovered cells; each decodes every row it can, then every column it can
+    pub iterations: usize,
+    pub rows_decoded: usize,
+    pub columns_decoded: usize,
+    /// Cells held before decoding
+    pub known: usize,
+    pub recovered: usize,
+    pub complete: bool,
+}
+
+impl MatrixDecoder {
+    /// `layout` is the row code's, as the block's manifest gives it.
+    pub fn new(shape: MatrixShape, m: usize, layout: Layout) -> Result<Self, MatrixError> {
+        DasDecoder::new(shape.k, m)?;
+        Ok(Self {
+            shape,
+            m,
+            layout,
+            column_code: ReedSolomon::new(shape.data_rows(), shape.data_rows())?,
+            cells: vec![None; shape.rows * (shape.k + m)],
+        })
+    }
+
+    fn columns(&self) -> usize {
+        self.shape.k + self.m
+    }
+
+    /// Adds the cell at `row` of shard `column` (shard order, not wire order).
+    pub fn insert(&mut self, row: usize, column: usize, data: Vec<u8>) -> Result<(), MatrixError> {
+        if row >= self.shape.rows || column >= self.columns() {
+            return Err(MatrixError::Malformed(format!("no cell ({}, {}) in {} x {}", row, column, self.shape.rows, self.columns())));
+        }
+        if data.len() != self.shape.cell_len {
+            return Err(MatrixError::Malformed(format!("{} byte cell, expected {}", data.len(), self.shape.cell_len)));
+        }
+        let columns = self.columns();
+        self.cells[row * columns + column] = Some(data);
+        Ok(())
+    }
+
+    /// Adds a whole shard: one cell of every row.
+    pub fn insert_column(&mut self, column: usize, shard: &[u8]) -> Result<(), MatrixError> {
+        if shard.len() != self.shape.column_len() {
+            return Err(MatrixError::Malformed(format!("{} byte shard, expected {}", shard.len(), self.shape.column_len())));
+        }
+        for (row, cell) in shard.chunks(self.shape.cell_len).enumerate() {
+            self.insert(row, column, cell.to_vec())?;
+        }
+        Ok(())
+    }
+
+    pub fn cell(&self, row: usize, column: usize) -> Option<&[u8]> {
+        self.cells.get(row * self.columns() + column)?.as_deref()
+    }
+
+    pub fn known(&self) -> usize {
+        self.cells.iter().filter(|c| c.is_some()).count()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.cells.iter().all(Option::is_some)
+    }
+
+    /// Alternates between the axes until every cell is known or a pass recovers nothing.
+    pub fn decode(&mut self) -> Result<MatrixDecodeReport, MatrixError> {
+        let (rows, columns, k, d) = (self.shape.rows, self.columns(), self.shape.k, self.shape.data_rows());
+        let mut report = MatrixDecodeReport { known: self.known(), ..Default::default() };
+        while !self.is_complete() {
+            let before = self.known();
+            for r in 0..rows {
+                let held: Vec<usize> = (0..columns).filter(|&c| self.cells[r * columns + c].is_some()).collect();
+                if held.len() < k || held.len() == columns {
+                    continue;
+                }
+                let mut row = DasDecoder::new(k, self.m)?.layout(self.layout);
+                for &c in &held[..k] {
+                    row.push(c, self.cells[r * columns + c].clone().expect("held"))?;
+                }
+                for (c, cell) in row.extended_shards()?.into_iter().enumerate() {
+                    self.cells[r * columns + c].get_or_insert(cell);
+                }
+                report.rows_decoded += 1;
+            }
+            for c in 0..columns {
+                let mut column: Vec<Option<Vec<u8>>> = (0..rows).map(|r| self.cells[r * columns + c].clone()).collect();
+                let held = column.iter().filter(|cell| cell.is_some()).count();
+                if held < d || held == rows {
+                    continue;
+                }
+                self.column_code.reconstruct(&mut column)?;
+                for (r, cell) in column.into_iter().enumerate() {
+                    self.cells[r * columns + c] = cell;
+                }
+                report.columns_decoded += 1;
+            }
+            if self.known() == before {
+                break;
+            }
+            report.iterations += 1;
+        }
+        report.recovered = self.known() - report.known;
+        report.complete = self.is_complete();
+        Ok(report)
+    }
+}
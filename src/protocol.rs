@@ -0,0 +1,779 @@
+//! Wire protocol: the JSON-lines messages peers exchange, and the checks every inbound frame
+//! passes before a node acts on it.
+//!
+//! Every frame from a peer goes through [`decode_message`]: parse, then check each field against
+//! what this node can safely act on. Failures are typed ([`CodecError`]), reported back to the
+//! peer as `ProtocolError`, and cost the sender only the offending frame.
+//!
+//! A frame may also carry a stream ID ([`encode_frame`] / [`decode_frame`]), so several
+//! transfers and sample exchanges can interleave on one connection and replies find their
+//! request. Stream 0 is the connection itself and travels as a bare message.
+
+use clap::{Args, ValueEnum};
+use ed25519_dalek::VerifyingKey;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+use crate::coding::{calculate_sha256, EncodedBlob};
+use crate::commitment::{BlobCommitment, Commitment, CommitmentScheme, FieldLayout, MerkleCommitment};
+use crate::error::{CodecError, CommitmentError};
+use crate::kzg;
+use crate::matrix::MatrixCells;
+use crate::{DATA_SHARDS, PARITY_SHARDS, TOTAL_SHARDS};
+
+/// Largest JSON line accepted by default. Shard bytes travel as JSON arrays (~3.5x expansion),
+/// so this leaves room for a naive transfer of a ~16 MiB blob.
+pub const DEFAULT_MAX_FRAME: usize = 64 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum P2PMessage {
+    Handshake {
+        pubkey: Vec<u8>,
+        sig: Vec<u8>,
+        /// Sender's Unix time in milliseconds; `sig` signs it along with `max_frame` and
+        /// `compression`.
+        ts: u64,
+        /// Largest frame this node will read; peers must not send anything bigger.
+        /// Absent from peers that predate the limit.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_frame: Option<usize>,
+        /// Frame compression this node can use, by name. Unknown names are ignored, and peers
+        /// that send none get uncompressed frames.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        compression: Vec<String>,
+        /// The node's signed record in text form (`enr:...`), from nodes that have one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        record: Option<String>,
+    },
+    NaiveTransfer {
+        filename: String,
+        data: Vec<u8>,
+        checksum: String,
+    },
+    /// Sent ahead of the shards so the receiver can check each one against its hash.
+    Manifest(Manifest),
+    DasShard {
+        filename: String,
+        root: String,
+        #[serde(flatten)]
+        layout: Layout,
+        original_len: usize,
+        index: usize,
+        data: Vec<u8>,
+        full_file_checksum: String,
+        /// Relays this shard may still make between validators. Absent when the sender doesn't
+        /// want it re-gossiped.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl: Option<u8>,
+        /// Correlates every hop of this shard in the event logs; relays pass it on unchanged.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trace: Option<String>,
+    },
+    /// Announces the wire indices a node can now serve for a root (e.g. after reconstruction).
+    CanServe {
+        root: String,
+        indices: Vec<usize>,
+    },
+    /// Requests one shard by wire index; answered with `DasShard` or `NotFound`.
+    GetShard {
+        root: String,
+        index: usize,
+    },
+    NotFound {
+        root: String,
+        index: usize,
+    },
+    /// Requests `len` bytes from `offset` of the shard at wire `index`, such as one cell of a
+    /// block matrix column; answered with `Range` or `NotFound`.
+    GetRange {
+        root: String,
+        index: usize,
+        offset: usize,
+        len: usize,
+    },
+    Range {
+        root: String,
+        index: usize,
+        offset: usize,
+        data: Vec<u8>,
+    },
+    /// Asks which roots a node can serve; answered with `Roots`.
+    GetRoots {},
+    Roots {
+        roots: Vec<String>,
+    },
+    /// Advertises which wire indices of a root a node holds, without their data. See
+    /// [`encode_bitfield`].
+    HaveShards {
+        root: String,
+        bitfield: String,
+    },
+    /// Marks the end of a DAS transfer so the receiver can tell sampling from an aborted send.
+    TransferEnd {
+        filename: String,
+        root: String,
+        shards_sent: usize,
+    },
+    /// Proof-of-custody challenge for the shard at wire `index`: answered with `CustodyProof`
+    /// over `len` bytes from `offset` (see [`custody_digest`]), or `NotFound` if the shard is
+    /// not held.
+    CustodyChallenge {
+        root: String,
+        index: usize,
+        offset: usize,
+        len: usize,
+        /// Fresh per challenge, hex, so an answer can't be computed ahead or replayed
+        nonce: String,
+    },
+    CustodyProof {
+        root: String,
+        index: usize,
+        nonce: String,
+        digest: String,
+    },
+    /// Sent just before a node drops a session for violating the protocol.
+    ProtocolError {
+        reason: String,
+    },
+}
+
+/// Describes one encoded blob: enough to verify any subset of its shards offline.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Manifest {
+    pub filename: String,
+    /// Merkle root over the shard hashes; identifies the encoded blob.
+    pub root: String,
+    pub k: usize,
+    pub m: usize,
+    #[serde(flatten)]
+    pub layout: Layout,
+    pub original_len: usize,
+    pub shard_len: usize,
+    /// SHA-256 of the original (unpadded) blob.
+    pub checksum: String,
+    /// What shards are checked against, in the proposer's chosen scheme.
+    pub commitment: BlobCommitment,
+    pub proposer: String,
+    /// Unix milliseconds when the proposer sent the manifest; availability latency counts from
+    /// here. Absent from manifests only written to disk, and from older proposers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published_ms: Option<u64>,
+    /// Cell hashes, when the blob is a block matrix (`send --matrix`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<MatrixCells>,
+    /// How the shards map onto field elements, under KZG. Absent from older proposers, whose
+    /// shards need not be aligned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field_layout: Option<FieldLayout>,
+}
+
+impl Manifest {
+    pub fn new(filename: &str, blob: &EncodedBlob, scheme: &dyn Commitment, proposer: &VerifyingKey) -> Result<Self, CommitmentError> {
+        Ok(Self {
+            filename: filename.to_string(),
+            root: blob.root.clone(),
+            k: blob.k,
+            m: blob.m,
+            layout: blob.layout,
+            original_len: blob.original_len,
+            shard_len: blob.shard_len,
+            checksum: blob.checksum.clone(),
+            commitment: scheme.commit(blob)?,
+            proposer: hex::encode(proposer.as_bytes()),
+            published_ms: None,
+            matrix: None,
+            field_layout: (scheme.scheme() == CommitmentScheme::Kzg).then(|| FieldLayout::new(blob)),
+        })
+    }
+
+    /// What `shard_len` must be a multiple of.
+    pub fn shard_align(&self) -> usize {
+        self.field_layout.map_or(1, |f| f.bytes_per_element)
+    }
+
+    /// Whether `root` follows from the commitment. Only Merkle commitments carry what is needed
+    /// to recompute it; for other schemes the root is just an identifier.
+    pub fn root_matches(&self) -> bool {
+        MerkleCommitment::root(&self.commitment).is_none_or(|r| r == self.root)
+    }
+
+    /// Checks shard `index` against the commitment; `None` if the scheme has no per-shard check.
+    pub fn verify_shard(&self, index: usize, data: &[u8]) -> Option<bool> {
+        self.commitment.verify_shard(index, data)
+    }
+}
+
+/// How a blob is spread over its shards. Travels with every shard so receivers can decode it.
+#[derive(Args, Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize, Default)]
+pub struct Layout {
+    /// Whether the original data appears verbatim in the first k shards
+    #[arg(long, value_enum, default_value_t = Encoding::Systematic)]
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// How bytes are assigned to data shards before coding
+    #[arg(long, value_enum, default_value_t = Striping::Contiguous)]
+    #[serde(default)]
+    pub striping: Striping,
+    /// Per-blob seed mapping shard index to wire index; `None` sends shards in codeword order
+    #[arg(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permutation_seed: Option<u64>,
+}
+
+impl Layout {
+    /// `perm[shard_index] == wire_index`. Deterministic for a given seed.
+    pub fn permutation(&self) -> Vec<usize> {
+        let mut perm: Vec<usize> = (0..TOTAL_SHARDS).collect();
+        if let Some(seed) = self.permutation_seed {
+            perm.shuffle(&mut StdRng::seed_from_u64(seed));
+        }
+        perm
+    }
+
+    pub fn wire_index(&self, shard_index: usize) -> usize {
+        self.permutation()[shard_index]
+    }
+
+    pub fn shard_index(&self, wire_index: usize) -> Option<usize> {
+        self.permutation().iter().position(|&w| w == wire_index)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Encoding {
+    /// Original data appears verbatim in the first k shards
+    #[default]
+    Systematic,
+    /// Every shard is a parity combination; no single shard leaks plaintext
+    NonSystematic,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Striping {
+    /// Data shard i holds the i-th contiguous chunk of the blob
+    #[default]
+    Contiguous,
+    /// Byte j of the blob goes to data shard j mod k (row-wise striping)
+    Interleaved,
+}
+
+pub const MAX_FILENAME_LEN: usize = 255;
+pub const MAX_REASON_LEN: usize = 1024;
+pub const MAX_COMPRESSION_OFFERS: usize = 16;
+pub const MAX_CODEC_NAME_LEN: usize = 32;
+/// Highest shard TTL accepted, so a sender can't ask for an unbounded flood.
+pub const MAX_GOSSIP_TTL: u8 = 16;
+pub const MAX_TRACE_LEN: usize = 32;
+pub const MAX_NONCE_LEN: usize = 64;
+/// Latest handshake timestamp accepted: the start of the year 3000, in Unix milliseconds. Keeps
+/// the skew arithmetic far from overflow whatever a peer signs.
+pub const MAX_HANDSHAKE_TS: u64 = 32_503_680_000_000;
+/// Longest node record text accepted in a handshake.
+pub const MAX_RECORD_LEN: usize = 4096;
+/// Largest slice a custody challenge may ask to be hashed.
+pub const MAX_CUSTODY_SLICE: usize = 64 * 1024;
+
+fn invalid(field: &'static str, reason: impl Into<String>) -> CodecError {
+    CodecError::InvalidField { field, reason: reason.into() }
+}
+
+/// Parses and validates one frame from a peer.
+pub fn decode_message(line: &str) -> Result<P2PMessage, CodecError> {
+    let msg: P2PMessage = serde_json::from_str(line).map_err(|e| CodecError::Malformed(e.to_string()))?;
+    msg.validate()?;
+    Ok(msg)
+}
+
+/// Stream 0: frames that belong to the connection rather than to one of several transfers.
+pub const BARE_STREAM: u32 = 0;
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    stream: u32,
+    msg: &'a P2PMessage,
+}
+
+/// Serializes `msg` for `stream`: bare on stream 0, so single-transfer peers see the frames
+/// they always have, otherwise wrapped as `{"stream":n,"msg":{...}}`.
+pub fn encode_frame(stream: u32, msg: &P2PMessage) -> serde_json::Result<String> {
+    if stream == BARE_STREAM { serde_json::to_string(msg) } else { serde_json::to_string(&Envelope { stream, msg }) }
+}
+
+/// The answer to a custody challenge: SHA-256 over the nonce (its hex text) followed by `len`
+/// bytes of the shard from `offset`, cut short at the shard's end.
+pub fn custody_digest(nonce: &str, shard: &[u8], offset: usize, len: usize) -> String {
+    let start = offset.min(shard.len());
+    let end = offset.saturating_add(len).min(shard.len());
+    let mut input = nonce.as_bytes().to_vec();
+    input.extend_from_slice(&shard[start..end]);
+    calculate_sha256(&input)
+}
+
+/// Identifies one frame as sent: the first 16 hex characters of the SHA-256 of its line. Sender
+/// and receiver derive the same ID without it going over the wire; byte-identical frames (a
+/// duplicate, a repeated request) share one.
+pub fn message_id(frame: &str) -> String {
+    calculate_sha256(frame.as_bytes())[..16].to_string()
+}
+
+/// Parses and validates one frame that may belong to a stream, returning the stream ID with
+/// the message. Bare messages are on stream 0.
+pub fn decode_frame(line: &str) -> Result<(u32, P2PMessage), CodecError> {
+    // A bare message's only key is its variant name, which this ignores.
+    #[derive(Deserialize)]
+    struct Wrapped {
+        stream: Option<u32>,
+        msg: Option<P2PMessage>,
+    }
+    let wrapped: Wrapped = serde_json::from_str(line).map_err(|e| CodecError::Malformed(e.to_string()))?;
+    match wrapped {
+        Wrapped { stream, msg: Some(msg) } => {
+            msg.validate()?;
+            Ok((stream.unwrap_or(BARE_STREAM), msg))
+        }
+        Wrapped { stream: Some(_), msg: None } => Err(CodecError::Malformed("stream frame without a message".into())),
+        Wrapped { stream: None, msg: None } => Ok((BARE_STREAM, decode_message(line)?)),
+    }
+}
+
+/// A bare file name: no path components, so it can be joined onto a directory safely.
+pub fn is_safe_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_FILENAME_LEN && name != "." && name != ".."
+        && !name.contains(['/', '\\', '\0'])
+}
+
+fn check_name(field: &'static str, name: &str) -> Result<(), CodecError> {
+    if is_safe_name(name) { Ok(()) } else { Err(invalid(field, format!("unsafe file name {:?}", name))) }
+}
+
+/// A challenge nonce: short, non-empty hex.
+fn check_nonce(field: &'static str, value: &str) -> Result<(), CodecError> {
+    if value.is_empty() || value.len() > MAX_NONCE_LEN || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid(field, format!("expected 1 to {} hex characters", MAX_NONCE_LEN)));
+    }
+    Ok(())
+}
+
+/// A lowercase hex SHA-256 digest, as roots and checksums are written.
+pub fn is_digest(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn check_digest(field: &'static str, value: &str) -> Result<(), CodecError> {
+    if is_digest(value) {
+        Ok(())
+    } else {
+        Err(invalid(field, "expected 64 lowercase hex characters"))
+    }
+}
+
+fn check_index(field: &'static str, index: usize) -> Result<(), CodecError> {
+    if index < TOTAL_SHARDS { Ok(()) } else { Err(invalid(field, format!("{} is not below {}", index, TOTAL_SHARDS))) }
+}
+
+/// Bytes in a bitfield over the extended shard set.
+const BITFIELD_LEN: usize = TOTAL_SHARDS.div_ceil(8);
+
+/// A set of wire indices as lowercase hex: bit `i % 8` of byte `i / 8` is set when index `i`
+/// is held, so the extended set fits in a few bytes whatever its size.
+pub fn encode_bitfield(indices: impl IntoIterator<Item = usize>) -> String {
+    let mut bytes = [0u8; BITFIELD_LEN];
+    for i in indices.into_iter().filter(|&i| i < TOTAL_SHARDS) {
+        bytes[i / 8] |= 1 << (i % 8);
+    }
+    hex::encode(bytes)
+}
+
+/// The wire indices set in a bitfield that passed validation, in ascending order.
+pub fn bitfield_indices(bitfield: &str) -> Vec<usize> {
+    let bytes = hex::decode(bitfield).unwrap_or_default();
+    (0..TOTAL_SHARDS.min(bytes.len() * 8)).filter(|i| bytes[i / 8] & (1 << (i % 8)) != 0).collect()
+}
+
+fn check_bitfield(field: &'static str, bitfield: &str) -> Result<(), CodecError> {
+    let Ok(bytes) = hex::decode(bitfield) else { return Err(invalid(field, "expected hex")) };
+    if bytes.len() != BITFIELD_LEN {
+        return Err(invalid(field, format!("{} bytes, expected {}", bytes.len(), BITFIELD_LEN)));
+    }
+    if (TOTAL_SHARDS..BITFIELD_LEN * 8).any(|i| bytes[i / 8] & (1 << (i % 8)) != 0) {
+        return Err(invalid(field, format!("bits set at or past index {}", TOTAL_SHARDS)));
+    }
+    Ok(())
+}
+
+impl P2PMessage {
+    /// The message's variant name, as it appears on the wire.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            P2PMessage::Handshake { .. } => "Handshake",
+            P2PMessage::NaiveTransfer { .. } => "NaiveTransfer",
+            P2PMessage::Manifest(_) => "Manifest",
+            P2PMessage::DasShard { .. } => "DasShard",
+            P2PMessage::CanServe { .. } => "CanServe",
+            P2PMessage::GetShard { .. } => "GetShard",
+            P2PMessage::NotFound { .. } => "NotFound",
+            P2PMessage::GetRange { .. } => "GetRange",
+            P2PMessage::Range { .. } => "Range",
+            P2PMessage::GetRoots {} => "GetRoots",
+            P2PMessage::Roots { .. } => "Roots",
+            P2PMessage::HaveShards { .. } => "HaveShards",
+            P2PMessage::TransferEnd { .. } => "TransferEnd",
+            P2PMessage::CustodyChallenge { .. } => "CustodyChallenge",
+            P2PMessage::CustodyProof { .. } => "CustodyProof",
+            P2PMessage::ProtocolError { .. } => "ProtocolError",
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), CodecError> {
+        match self {
+            P2PMessage::Handshake { pubkey, sig, ts, compression, record, .. } => {
+                if pubkey.len() != 32 { return Err(invalid("pubkey", "expected 32 bytes")); }
+                if sig.len() != 64 { return Err(invalid("sig", "expected 64 bytes")); }
+                if *ts > MAX_HANDSHAKE_TS { return Err(invalid("ts", format!("after {} ms", MAX_HANDSHAKE_TS))); }
+                if record.as_ref().is_some_and(|r| r.len() > MAX_RECORD_LEN) {
+                    return Err(invalid("record", format!("over {} bytes", MAX_RECORD_LEN)));
+                }
+                if compression.len() > MAX_COMPRESSION_OFFERS || compression.iter().any(|c| c.len() > MAX_CODEC_NAME_LEN) {
+                    return Err(invalid("compression", "too many or too long codec names"));
+                }
+            }
+            P2PMessage::NaiveTransfer { filename, checksum, .. } => {
+                check_name("filename", filename)?;
+                check_digest("checksum", checksum)?;
+            }
+            P2PMessage::Manifest(m) => m.validate()?,
+            P2PMessage::DasShard { filename, root, original_len, index, data, full_file_checksum, ttl, trace, .. } => {
+                check_name("filename", filename)?;
+                check_digest("root", root)?;
+                check_digest("full_file_checksum", full_file_checksum)?;
+                check_index("index", *index)?;
+                if ttl.is_some_and(|t| t > MAX_GOSSIP_TTL) {
+                    return Err(invalid("ttl", format!("above the maximum of {}", MAX_GOSSIP_TTL)));
+                }
+                if let Some(trace) = trace && (trace.is_empty() || trace.len() > MAX_TRACE_LEN || !trace.bytes().all(|b| b.is_ascii_hexdigit())) {
+                    return Err(invalid("trace", format!("expected 1 to {} hex characters", MAX_TRACE_LEN)));
+                }
+                if data.is_empty() { return Err(invalid("data", "empty shard")); }
+                if *original_len > data.len() * DATA_SHARDS {
+                    return Err(invalid("original_len", format!("{} exceeds k x shard length", original_len)));
+                }
+            }
+            P2PMessage::CanServe { root, indices } => {
+                check_digest("root", root)?;
+                indices.iter().try_for_each(|&i| check_index("indices", i))?;
+            }
+            P2PMessage::GetShard { root, index } | P2PMessage::NotFound { root, index } => {
+                check_digest("root", root)?;
+                check_index("index", *index)?;
+            }
+            P2PMessage::GetRange { root, index, offset, len } => {
+                check_digest("root", root)?;
+                check_index("index", *index)?;
+                if *len == 0 || offset.checked_add(*len).is_none() {
+                    return Err(invalid("len", "empty, or past the end of any shard"));
+                }
+            }
+            P2PMessage::Range { root, index, data, .. } => {
+                check_digest("root", root)?;
+                check_index("index", *index)?;
+                if data.is_empty() { return Err(invalid("data", "empty range")); }
+            }
+            P2PMessage::GetRoots {} => {}
+            P2PMessage::Roots { roots } => roots.iter().try_for_each(|r| check_digest("roots", r))?,
+            P2PMessage::HaveShards { root, bitfield } => {
+                check_digest("root", root)?;
+                check_bitfield("bitfield", bitfield)?;
+            }
+            P2PMessage::TransferEnd { filename, root, shards_sent } => {
+                check_name("filename", filename)?;
+                check_digest("root", root)?;
+                if *shards_sent > TOTAL_SHARDS { return Err(invalid("shards_sent", "more than the extended set")); }
+            }
+            P2PMessage::CustodyChallenge { root, index, len, nonce, .. } => {
+                check_digest("root", root)?;
+                check_index("index", *index)?;
+                check_nonce("nonce", nonce)?;
+                if *len > MAX_CUSTODY_SLICE { return Err(invalid("len", format!("above the maximum of {}", MAX_CUSTODY_SLICE))); }
+            }
+            P2PMessage::CustodyProof { root, index, nonce, digest } => {
+                check_digest("root", root)?;
+                check_index("index", *index)?;
+                check_nonce("nonce", nonce)?;
+                check_digest("digest", digest)?;
+            }
+            P2PMessage::ProtocolError { reason } => {
+                if reason.len() > MAX_REASON_LEN { return Err(invalid("reason", "too long")); }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Manifest {
+    /// Strict check of one shard against this manifest. Every field the shard repeats must
+    /// agree with it. The wire index must map into the codeword, and the data must be
+    /// `shard_len` long. `shard_len` must also be the least that holds `original_len` bytes in k
+    /// shards (of whole field elements, under a field layout), so no more than the minimum is
+    /// padding.
+    pub fn check_shard(&self, filename: &str, layout: &Layout, original_len: usize, index: usize, data: &[u8], checksum: &str) -> Result<(), CodecError> {
+        if filename != self.filename {
+            return Err(invalid("filename", format!("{:?}, but the manifest names {:?}", filename, self.filename)));
+        }
+        if *layout != self.layout {
+            return Err(invalid("layout", format!("{:?}, but the manifest has {:?}", layout, self.layout)));
+        }
+        if original_len != self.original_len {
+            return Err(invalid("original_len", format!("{}, but the manifest has {}", original_len, self.original_len)));
+        }
+        if checksum != self.checksum {
+            return Err(invalid("full_file_checksum", format!("{}, but the manifest has {}", checksum, self.checksum)));
+        }
+        if layout.shard_index(index).is_none() {
+            return Err(invalid("index", format!("wire index {} maps to no shard of {}", index, self.k + self.m)));
+        }
+        if data.len() != self.shard_len {
+            return Err(invalid("data", format!("{} bytes, but the manifest's shard_len is {}", data.len(), self.shard_len)));
+        }
+        let least = self.original_len.div_ceil(self.k).max(1).next_multiple_of(self.shard_align());
+        if self.shard_len != least {
+            return Err(invalid("shard_len", format!("{} bytes, but {} bytes in {} shards need {}", self.shard_len, self.original_len, self.k, least)));
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), CodecError> {
+        check_name("filename", &self.filename)?;
+        check_digest("root", &self.root)?;
+        check_digest("checksum", &self.checksum)?;
+        check_digest("proposer", &self.proposer)?;
+        if (self.k, self.m) != (DATA_SHARDS, PARITY_SHARDS) {
+            return Err(invalid("k/m", format!("({}, {}) does not match this node's ({}, {})", self.k, self.m, DATA_SHARDS, PARITY_SHARDS)));
+        }
+        match &self.commitment {
+            BlobCommitment::Sha256 => {}
+            BlobCommitment::Merkle { shard_hashes } => {
+                if shard_hashes.len() != TOTAL_SHARDS {
+                    return Err(invalid("shard_hashes", format!("expected {} hashes", TOTAL_SHARDS)));
+                }
+                shard_hashes.iter().try_for_each(|h| check_digest("shard_hashes", h))?;
+            }
+            BlobCommitment::Kzg { commitments, proofs } => {
+                if commitments.len() != TOTAL_SHARDS {
+                    return Err(invalid("commitments", format!("expected {} commitments", TOTAL_SHARDS)));
+                }
+                if commitments.iter().any(|c| kzg::decode_point(c).is_err()) {
+                    return Err(invalid("commitments", "expected compressed G1 points"));
+                }
+                if !proofs.is_empty() && proofs.len() != TOTAL_SHARDS {
+                    return Err(invalid("proofs", format!("expected none or {} proofs", TOTAL_SHARDS)));
+                }
+                if proofs.iter().any(|p| kzg::decode_point(p).is_err()) {
+                    return Err(invalid("proofs", "expected compressed G1 points"));
+                }
+            }
+        }
+        if self.original_len > self.shard_len.saturating_mul(DATA_SHARDS) {
+            return Err(invalid("original_len", "exceeds k x shard_len"));
+        }
+        if let Some(f) = &self.field_layout {
+            if !matches!(self.commitment, BlobCommitment::Kzg { .. }) {
+                return Err(invalid("field_layout", "only KZG commitments pack shards into field elements"));
+            }
+            if f.bytes_per_element != kzg::BYTES_PER_FIELD_ELEMENT || f.elements_per_shard.checked_mul(f.bytes_per_element) != Some(self.shard_len) {
+                return Err(invalid("field_layout", format!("{} elements of {} bytes do not make a {} byte shard", f.elements_per_shard, f.bytes_per_element, self.shard_len)));
+            }
+            if Some(f.padding) != self.shard_len.saturating_mul(DATA_SHARDS).checked_sub(self.original_len) {
+                return Err(invalid("field_layout", format!("{} bytes of padding, but {} bytes fill {} shards of {}", f.padding, self.original_len, DATA_SHARDS, self.shard_len)));
+            }
+        }
+        if let Some(cells) = &self.matrix {
+            if self.layout.striping != Striping::Contiguous {
+                return Err(invalid("matrix", "a block matrix needs contiguous striping"));
+            }
+            if cells.rows < 4 || !cells.rows.is_multiple_of(2) || cells.rows.checked_mul(cells.cell_len) != Some(self.shard_len) {
+                return Err(invalid("matrix", format!("{} rows of {} byte cells do not make a {} byte shard", cells.rows, cells.cell_len, self.shard_len)));
+            }
+            if cells.cell_hashes.len() != cells.rows * TOTAL_SHARDS {
+                return Err(invalid("cell_hashes", format!("expected {} hashes", cells.rows * TOTAL_SHARDS)));
+            }
+            cells.cell_hashes.iter().try_for_each(|h| check_digest("cell_hashes", h))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root() -> String {
+        calculate_sha256(b"blob")
+    }
+
+    fn shard(index: usize) -> P2PMessage {
+        P2PMessage::DasShard {
+            filename: "blob.bin".into(),
+            root: root(),
+            layout: Layout::default(),
+            original_len: 10,
+            index,
+            data: vec![7; 4],
+            full_file_checksum: root(),
+            ttl: Some(2),
+            trace: Some("00ff".into()),
+        }
+    }
+
+    fn invalid_field(msg: &P2PMessage) -> &'static str {
+        match msg.validate() {
+            Err(CodecError::InvalidField { field, .. }) => field,
+            other => panic!("expected an invalid field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn frames_round_trip_bare_and_on_a_stream() {
+        let msg = shard(3);
+        let bare = encode_frame(BARE_STREAM, &msg).unwrap();
+        assert!(bare.starts_with("{\"DasShard\""));
+        let (stream, decoded) = decode_frame(&bare).unwrap();
+        assert_eq!(stream, BARE_STREAM);
+        assert_eq!(encode_frame(BARE_STREAM, &decoded).unwrap(), bare);
+
+        let wrapped = encode_frame(9, &msg).unwrap();
+        let (stream, decoded) = decode_frame(&wrapped).unwrap();
+        assert_eq!(stream, 9);
+        assert_eq!(encode_frame(9, &decoded).unwrap(), wrapped);
+        assert_eq!(decode_message(&bare).unwrap().kind(), "DasShard");
+    }
+
+    #[test]
+    fn malformed_frames_are_refused() {
+        for line in ["", "not json", "{\"Bogus\":{}}", "{\"stream\":1}", "{\"DasShard\":{\"filename\":\"x\"}}"] {
+            assert!(matches!(decode_frame(line), Err(CodecError::Malformed(_))), "{:?}", line);
+        }
+        // A wrapped message is validated like a bare one.
+        let wrapped = encode_frame(4, &shard(TOTAL_SHARDS)).unwrap();
+        assert!(matches!(decode_frame(&wrapped), Err(CodecError::InvalidField { field: "index", .. })));
+    }
+
+    #[test]
+    fn shard_fields_are_checked() {
+        assert!(shard(TOTAL_SHARDS - 1).validate().is_ok());
+        assert_eq!(invalid_field(&shard(TOTAL_SHARDS)), "index");
+        let edited = |edit: &dyn Fn(&mut String, &mut String)| {
+            let mut msg = shard(0);
+            let P2PMessage::DasShard { filename, root, .. } = &mut msg else { unreachable!() };
+            edit(filename, root);
+            invalid_field(&msg)
+        };
+        for name in ["../x", "a/b", ".."] {
+            assert_eq!(edited(&|f, _| *f = name.into()), "filename");
+        }
+        assert_eq!(edited(&|_, r| *r = r.to_uppercase()), "root");
+        assert_eq!(edited(&|_, r| r.truncate(63)), "root");
+
+        let mut msg = shard(0);
+        let P2PMessage::DasShard { original_len, .. } = &mut msg else { unreachable!() };
+        *original_len = 4 * DATA_SHARDS + 1;
+        assert_eq!(invalid_field(&msg), "original_len");
+    }
+
+    #[test]
+    fn trace_ids_are_short_hex() {
+        let traced = |id: &str| {
+            let mut msg = shard(0);
+            let P2PMessage::DasShard { trace, .. } = &mut msg else { unreachable!() };
+            *trace = Some(id.into());
+            msg
+        };
+        assert!(traced(&"a".repeat(MAX_TRACE_LEN)).validate().is_ok());
+        for id in ["", "xyz", &"a".repeat(MAX_TRACE_LEN + 1)] {
+            assert_eq!(invalid_field(&traced(id)), "trace", "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn handshake_timestamps_past_the_limit_are_refused() {
+        let handshake = |ts| P2PMessage::Handshake {
+            pubkey: vec![0; 32],
+            sig: vec![0; 64],
+            ts,
+            max_frame: None,
+            compression: Vec::new(),
+            record: None,
+        };
+        assert!(handshake(MAX_HANDSHAKE_TS).validate().is_ok());
+        assert_eq!(invalid_field(&handshake(MAX_HANDSHAKE_TS + 1)), "ts");
+        assert_eq!(invalid_field(&handshake(u64::MAX)), "ts");
+        // A u64 timestamp written past u64::MAX doesn't parse at all.
+        let line = serde_json::to_string(&handshake(0)).unwrap().replace("\"ts\":0", "\"ts\":18446744073709551616");
+        assert!(matches!(decode_message(&line), Err(CodecError::Malformed(_))));
+    }
+
+    #[test]
+    fn ranges_that_overflow_are_refused() {
+        let range = |offset, len| P2PMessage::GetRange { root: root(), index: 0, offset, len };
+        assert!(range(0, 1).validate().is_ok());
+        assert_eq!(invalid_field(&range(usize::MAX, 1)), "len");
+        assert_eq!(invalid_field(&range(0, 0)), "len");
+    }
+
+    #[test]
+    fn bitfields_round_trip_and_refuse_stray_bits() {
+        let held = [0, TOTAL_SHARDS / 2, TOTAL_SHARDS - 1];
+        let bitfield = encode_bitfield(held.iter().copied().chain([TOTAL_SHARDS]));
+        assert_eq!(bitfield_indices(&bitfield), held);
+        assert!(P2PMessage::HaveShards { root: root(), bitfield: bitfield.clone() }.validate().is_ok());
+
+        let mut bytes = hex::decode(&bitfield).unwrap();
+        if !TOTAL_SHARDS.is_multiple_of(8) {
+            bytes[BITFIELD_LEN - 1] |= 0x80;
+            assert_eq!(invalid_field(&P2PMessage::HaveShards { root: root(), bitfield: hex::encode(&bytes) }), "bitfield");
+        }
+        bytes.push(0);
+        assert_eq!(invalid_field(&P2PMessage::HaveShards { root: root(), bitfield: hex::encode(&bytes) }), "bitfield");
+    }
+
+    #[test]
+    fn custody_digest_binds_nonce_and_slice() {
+        let shard: Vec<u8> = (0..=255).collect();
+        let digest = custody_digest("ab", &shard, 10, 20);
+        assert_eq!(digest, custody_digest("ab", &shard, 10, 20));
+        assert_eq!(digest, calculate_sha256(&[b"ab".as_slice(), &shard[10..30]].concat()));
+        assert_ne!(digest, custody_digest("ac", &shard, 10, 20));
+        assert_ne!(digest, custody_digest("ab", &shard, 11, 20));
+        assert_ne!(digest, custody_digest("ab", &shard, 10, 21));
+        // Slices are cut at the shard's end, even when offset + len overflows.
+        assert_eq!(custody_digest("ab", &shard, 250, usize::MAX), calculate_sha256(&[b"ab".as_slice(), &shard[250..]].concat()));
+        assert_eq!(custody_digest("ab", &shard, usize::MAX, 5), calculate_sha256(b"ab"));
+    }
+
+    #[test]
+    fn custody_frames_are_checked() {
+        let challenge = |nonce: &str, len| P2PMessage::CustodyChallenge { root: root(), index: 1, offset: 0, len, nonce: nonce.into() };
+        assert!(challenge("0a1b", MAX_CUSTODY_SLICE).validate().is_ok());
+        assert_eq!(invalid_field(&challenge("0a1b", MAX_CUSTODY_SLICE + 1)), "len");
+        assert_eq!(invalid_field(&challenge("", 1)), "nonce");
+        assert_eq!(invalid_field(&challenge("zz", 1)), "nonce");
+        assert_eq!(invalid_field(&challenge(&"a".repeat(MAX_NONCE_LEN + 1), 1)), "nonce");
+        let proof = P2PMessage::CustodyProof { root: root(), index: 1, nonce: "0a".into(), digest: "0a".into() };
+        assert_eq!(invalid_field(&proof), "digest");
+    }
+
+    #[test]
+    fn names_and_digests() {
+        assert!(is_safe_name("blob.bin"));
+        for name in ["", ".", "..", "a/b", "a\\b", "a\0b", &"x".repeat(MAX_FILENAME_LEN + 1)] {
+            assert!(!is_safe_name(name), "{:?}", name);
+        }
+        assert!(is_digest(&root()));
+        assert!(!is_digest(&root().to_uppercase()));
+        assert!(!is_digest(&root()[1..]));
+        assert!(!is_digest(&format!("{}g", &root()[1..])));
+    }
+}
@@ -0,0 +1,399 @@
+//! Streaming reconstruction: rebuilds a blob on disk from any k of its shards as they arrive.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use crate::clock::Clock;
+use crate::coding::DasDecoder;
+use crate::error::ReconstructError;
+use crate::protocol::{Encoding, Layout, Striping};
+use crate::{DATA_SHARDS, PARITY_SHARDS};
+
+type Result<T> = std::result::Result<T, ReconstructError>;
+
+/// Rows of interleaved data written per chunk when finishing an interleaved blob.
+pub const UNSTRIPE_CHUNK_ROWS: usize = 64 * 1024;
+
+/// Bytes of each shard read per chunk when rebuilding from spilled shards.
+pub const SPILL_CHUNK: usize = 1024 * 1024;
+
+/// Source of [`StreamingDecoder::revision`] stamps, shared by every decoder in the process.
+static REVISIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Incremental decoder for one root, writing to disk on top of a [`DasDecoder`]. Systematic,
+/// contiguous data shards go straight to `<path>.part` as they arrive; at threshold only the
+/// still-missing data rows are computed, one at a time, and the blob is hashed while it is
+/// completed. The file is renamed into place only if the checksum matches.
+///
+/// With [`spill_to_disk`](Self::spill_to_disk), received shards wait in `<path>.shard-<index>`
+/// files instead of memory, and the blob is rebuilt [`SPILL_CHUNK`] bytes of each shard at a
+/// time, so memory stays bounded by the chunk size rather than the blob's.
+pub struct StreamingDecoder {
+    pub path: String,
+    layout: Layout,
+    original_len: usize,
+    shard_len: usize,
+    shards: DasDecoder,
+    /// Indices of the shards spilled to disk, when spilling; `shards` then stays empty
+    spilled: Option<BTreeSet<usize>>,
+    /// Data shards already flushed to the `.part` file
+    written: Vec<bool>,
+    out: Option<File>,
+    clock: Arc<dyn Clock>,
+    /// When the decoder was created, on its clock
+    pub started: Duration,
+    pub first_byte: Option<Duration>,
+    /// Bytes each peer contributed, counted against its quota until a verdict is reached
+    pub charged: HashMap<String, usize>,
+    /// Authenticated peer that supplied each buffered shard, by index
+    pub senders: BTreeMap<usize, String>,
+    /// Stamp of the data each index holds, by index
+    revisions: HashMap<usize, u64>,
+}
+
+impl StreamingDecoder {
+    pub fn new(path: String, layout: Layout, original_len: usize, shard_len: usize, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            path,
+            layout,
+            original_len,
+            shard_len,
+            shards: DasDecoder::new(DATA_SHARDS, PARITY_SHARDS)
+                .expect("default code is valid")
+                .layout(layout)
+                .original_len(original_len)
+                .shard_len(shard_len),
+            spilled: None,
+            written: vec![false; DATA_SHARDS],
+            out: None,
+            started: clock.now(),
+            clock,
+            first_byte: None,
+            charged: HashMap::new(),
+            senders: BTreeMap::new(),
+            revisions: HashMap::new(),
+        }
+    }
+
+    /// Buffers shards on disk from here on rather than in memory.
+    pub fn spill_to_disk(mut self) -> Self {
+        self.spilled = Some(BTreeSet::new());
+        self
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        self.spilled.is_some()
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn original_len(&self) -> usize {
+        self.original_len
+    }
+
+    pub fn shard_len(&self) -> usize {
+        self.shard_len
+    }
+
+    /// Time since the decoder was created.
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed(self.started)
+    }
+
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.shards.indices().chain(self.spilled.iter().flatten().copied())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.len() + self.spilled.as_ref().map_or(0, BTreeSet::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn has(&self, index: usize) -> bool {
+        self.shards.shard(index).is_some() || self.spilled.as_ref().is_some_and(|s| s.contains(&index))
+    }
+
+    /// A buffered shard; a spilled one is read back from disk.
+    pub fn shard(&self, index: usize) -> Option<Cow<'_, [u8]>> {
+        match &self.spilled {
+            Some(spilled) => spilled.contains(&index).then(|| std::fs::read(self.spill_path(index)).ok()).flatten().map(Cow::Owned),
+            None => self.shards.shard(index).map(Cow::Borrowed),
+        }
+    }
+
+    /// A stamp for the data `index` holds, unique across every decoder in the process, so a
+    /// copy taken of a shard can tell whether the shard has since been replaced.
+    pub fn revision(&self, index: usize) -> Option<u64> {
+        self.revisions.get(&index).copied()
+    }
+
+    /// Where a spilled shard waits on disk; `None` for one held in memory or not held at all.
+    pub fn spilled_path(&self, index: usize) -> Option<String> {
+        self.spilled.as_ref().is_some_and(|s| s.contains(&index)).then(|| self.spill_path(index))
+    }
+
+    /// How many of the buffered shards each peer supplied.
+    pub fn shards_by_sender(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for peer in self.senders.values() {
+            *counts.entry(peer.clone()).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn part_path(&self) -> String {
+        format!("{}.part", self.path)
+    }
+
+    fn spill_path(&self, index: usize) -> String {
+        format!("{}.shard-{}", self.path, index)
+    }
+
+    fn streams_data_shards(&self) -> bool {
+        self.layout.encoding == Encoding::Systematic && self.layout.striping == Striping::Contiguous
+    }
+
+    pub fn push(&mut self, index: usize, data: Vec<u8>) -> Result<()> {
+        // A wrong-length shard is not written; `DasDecoder::push` rejects it below.
+        if self.streams_data_shards() && index < DATA_SHARDS && !self.written[index] && data.len() == self.shard_len {
+            self.write_data_shard(index, &data)?;
+        }
+        let Some(spilled) = &self.spilled else {
+            self.shards.push(index, data)?;
+            self.revisions.insert(index, REVISIONS.fetch_add(1, Ordering::Relaxed));
+            return Ok(());
+        };
+        let n = DATA_SHARDS + PARITY_SHARDS;
+        if index >= n {
+            return Err(ReconstructError::IndexOutOfRange { index, n });
+        }
+        if data.len() != self.shard_len {
+            return Err(ReconstructError::ShardLength { index, len: data.len(), expected: self.shard_len });
+        }
+        if !spilled.contains(&index) {
+            std::fs::write(self.spill_path(index), &data)?;
+            self.revisions.insert(index, REVISIONS.fetch_add(1, Ordering::Relaxed));
+        }
+        self.spilled.as_mut().expect("spilling").insert(index);
+        Ok(())
+    }
+
+    /// Rebuilds the data shards from the spilled ones, [`SPILL_CHUNK`] bytes of each at a time:
+    /// calls `each` with every chunk's offset into the shards and the k data shards' bytes there.
+    /// Reed-Solomon works byte by byte, so each chunk decodes on its own.
+    fn spilled_chunks(&self, mut each: impl FnMut(usize, &[Cow<'_, [u8]>]) -> Result<()>) -> Result<()> {
+        let held: Vec<usize> = self.spilled.iter().flatten().copied().take(DATA_SHARDS).collect();
+        if held.len() < DATA_SHARDS {
+            return Err(ReconstructError::NotEnoughShards { have: held.len(), need: DATA_SHARDS });
+        }
+        let mut files = held.iter().map(|&i| File::open(self.spill_path(i))).collect::<std::io::Result<Vec<_>>>()?;
+        let mut offset = 0;
+        while offset < self.shard_len {
+            let len = SPILL_CHUNK.min(self.shard_len - offset);
+            let mut chunk = DasDecoder::new(DATA_SHARDS, PARITY_SHARDS)?.layout(self.layout).shard_len(len);
+            for (&index, file) in held.iter().zip(&mut files) {
+                let mut data = vec![0; len];
+                file.read_exact(&mut data)?;
+                chunk.push(index, data)?;
+            }
+            let data = (0..DATA_SHARDS).map(|j| chunk.data_shard(j)).collect::<Result<Vec<_>>>()?;
+            each(offset, &data)?;
+            offset += len;
+        }
+        Ok(())
+    }
+
+    /// [`finish`](Self::finish) for spilled shards: every data chunk goes to its place in the
+    /// output, which is hashed by reading it back once complete.
+    fn finish_spilled(&mut self, checksum: &str) -> Result<bool> {
+        let (shard_len, original_len, striping) = (self.shard_len, self.original_len, self.layout.striping);
+        let mut out = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(self.part_path())?;
+        let mut first_byte = None;
+        self.spilled_chunks(|offset, data| {
+            match striping {
+                Striping::Contiguous => {
+                    for (j, shard) in data.iter().enumerate() {
+                        let start = j * shard_len + offset;
+                        let n = shard.len().min(original_len.saturating_sub(start));
+                        out.seek(SeekFrom::Start(start as u64))?;
+                        out.write_all(&shard[..n])?;
+                    }
+                }
+                Striping::Interleaved => {
+                    let mut chunk: Vec<u8> = (0..data[0].len()).flat_map(|r| data.iter().map(move |s| s[r])).collect();
+                    chunk.truncate(original_len.saturating_sub(offset * DATA_SHARDS));
+                    out.seek(SeekFrom::Start((offset * DATA_SHARDS) as u64))?;
+                    out.write_all(&chunk)?;
+                }
+            }
+            first_byte.get_or_insert(self.elapsed());
+            Ok(())
+        })?;
+        self.first_byte = self.first_byte.or(first_byte);
+        out.set_len(original_len as u64)?;
+        out.flush()?;
+
+        let mut hasher = Sha256::new();
+        let mut part = File::open(self.part_path())?;
+        let mut buf = vec![0; SPILL_CHUNK];
+        loop {
+            let n = part.read(&mut buf)?;
+            if n == 0 { break; }
+            hasher.update(&buf[..n]);
+        }
+        if hex::encode(hasher.finalize()) == checksum {
+            std::fs::rename(self.part_path(), &self.path)?;
+            Ok(true)
+        } else {
+            std::fs::remove_file(self.part_path())?;
+            Ok(false)
+        }
+    }
+
+    fn output(&mut self) -> Result<&mut File> {
+        if self.out.is_none() {
+            let f = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(self.part_path())?;
+            self.out = Some(f);
+            self.written.iter_mut().for_each(|w| *w = false);
+        }
+        Ok(self.out.as_mut().unwrap())
+    }
+
+    /// Bytes of contiguous data shard `j` that belong to the original (unpadded) blob.
+    fn data_extent(&self, j: usize) -> usize {
+        self.shard_len.min(self.original_len.saturating_sub(j * self.shard_len))
+    }
+
+    fn write_data_shard(&mut self, j: usize, data: &[u8]) -> Result<()> {
+        let (offset, n) = ((j * self.shard_len) as u64, self.data_extent(j));
+        let out = self.output()?;
+        out.seek(SeekFrom::Start(offset))?;
+        out.write_all(&data[..n])?;
+        self.written[j] = true;
+        self.first_byte.get_or_insert(self.elapsed());
+        Ok(())
+    }
+
+    /// Completes the output and checks it. Returns whether the checksum matched.
+    pub fn finish(&mut self, checksum: &str) -> Result<bool> {
+        if self.is_spilled() {
+            // Data shards streamed to the part file so far are rewritten along with the rest.
+            self.out = None;
+            return self.finish_spilled(checksum);
+        }
+        let mut hasher = Sha256::new();
+        match self.layout.striping {
+            Striping::Contiguous => {
+                for j in 0..DATA_SHARDS {
+                    let shard = self.shards.data_shard(j)?.into_owned();
+                    hasher.update(&shard[..self.data_extent(j)]);
+                    if !self.written[j] {
+                        self.write_data_shard(j, &shard)?;
+                    }
+                }
+            }
+            Striping::Interleaved => {
+                // Decoded and de-interleaved UNSTRIPE_CHUNK_ROWS rows at a time from k of the
+                // held shards, so finishing takes a chunk's worth of memory on top of them.
+                let mut held: Vec<usize> = self.shards.indices().collect();
+                held.sort_unstable();
+                held.truncate(DATA_SHARDS);
+                let (shard_len, original_len) = (self.shard_len, self.original_len);
+                let mut chunk = Vec::with_capacity(UNSTRIPE_CHUNK_ROWS.min(shard_len) * DATA_SHARDS);
+                let (mut start, mut pos) = (0, 0);
+                while start < shard_len && pos < original_len {
+                    let rows = UNSTRIPE_CHUNK_ROWS.min(shard_len - start);
+                    let mut rows_of = DasDecoder::new(DATA_SHARDS, PARITY_SHARDS)?.layout(self.layout).shard_len(rows);
+                    for &i in &held {
+                        let shard = self.shards.shard(i).expect("held index");
+                        rows_of.push(i, shard[start..start + rows].to_vec())?;
+                    }
+                    let data = (0..DATA_SHARDS).map(|j| rows_of.data_shard(j)).collect::<Result<Vec<_>>>()?;
+                    chunk.clear();
+                    chunk.extend((0..rows).flat_map(|r| data.iter().map(move |s| s[r])));
+                    chunk.truncate(original_len - pos);
+                    pos += chunk.len();
+                    start += rows;
+                    hasher.update(&chunk);
+                    self.output()?.write_all(&chunk)?;
+                    self.first_byte.get_or_insert(self.elapsed());
+                }
+            }
+        }
+        let original_len = self.original_len as u64;
+        let out = self.output()?;
+        out.set_len(original_len)?;
+        out.flush()?;
+        self.out = None;
+
+        if hex::encode(hasher.finalize()) == checksum {
+            std::fs::rename(self.part_path(), &self.path)?;
+            Ok(true)
+        } else {
+            std::fs::remove_file(self.part_path())?;
+            Ok(false)
+        }
+    }
+
+    /// Drops any partial output (e.g. after a light-client verdict); received shards are kept.
+    pub fn discard_output(&mut self) {
+        if self.out.take().is_some() {
+            let _ = std::fs::remove_file(self.part_path());
+        }
+        self.written.iter_mut().for_each(|w| *w = false);
+    }
+
+    /// See [`DasDecoder::nonzero_padding`]. Spilled shards are checked chunk by chunk.
+    pub fn nonzero_padding(&self) -> Result<Option<usize>> {
+        if !self.is_spilled() {
+            return self.shards.nonzero_padding();
+        }
+        let (shard_len, original_len, striping) = (self.shard_len, self.original_len, self.layout.striping);
+        let mut first = None;
+        self.spilled_chunks(|offset, data| {
+            for (j, shard) in data.iter().enumerate() {
+                let nonzero = shard.iter().enumerate().filter(|&(_, &b)| b != 0).map(|(r, _)| match striping {
+                    Striping::Contiguous => j * shard_len + offset + r,
+                    Striping::Interleaved => (offset + r) * DATA_SHARDS + j,
+                });
+                if let Some(pos) = nonzero.filter(|&pos| pos >= original_len).min() {
+                    first = Some(first.map_or(pos, |f: usize| f.min(pos)));
+                }
+            }
+            Ok(())
+        })?;
+        Ok(first)
+    }
+
+    /// The full extended shard set, regenerating any index that was not received. Spilled
+    /// shards are read back into memory for this.
+    pub fn extended_shards(&self) -> Result<Vec<Vec<u8>>> {
+        let Some(spilled) = &self.spilled else { return self.shards.extended_shards() };
+        let mut decoder = self.shards.clone();
+        for &index in spilled {
+            decoder.push(index, std::fs::read(self.spill_path(index))?)?;
+        }
+        decoder.extended_shards()
+    }
+}
+
+impl Drop for StreamingDecoder {
+    /// Spilled shards only live as long as the decoder.
+    fn drop(&mut self) {
+        for index in self.spilled.iter().flatten() {
+            let _ = std::fs::remove_file(self.spill_path(*index));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Signed node records, after Ethereum's ENRs: a node's public key, the addresses it takes
+//! connections on, the columns it custodies and what it can do, signed with that key. Anyone
+//! can pass a record on, and anyone can check it without asking the node.
+
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::error::RecordError;
+use crate::handshake::Identity;
+
+/// Starts the text form of a record: `enr:` and the record's JSON, base64url without padding.
+pub const RECORD_PREFIX: &str = "enr:";
+
+/// Signed with the record, so a signature over some other message never passes as one.
+const RECORD_DOMAIN: &[u8] = b"das-node-record-v1";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeRecord {
+    /// Raised whenever the node changes its record; a higher one replaces a lower one
+    pub seq: u64,
+    /// Hex Ed25519 public key, the node's ID
+    #[serde(rename = "id")]
+    pub pubkey: String,
+    /// `host:port` addresses the node accepts connections on, preferred first
+    pub addrs: Vec<String>,
+    /// Wire indices (columns) the node keeps
+    pub custody: Vec<usize>,
+    /// What the node answers or speaks, such as `serve` or `zstd`
+    pub caps: Vec<String>,
+    /// Hex signature over everything above
+    pub sig: String,
+}
+
+impl NodeRecord {
+    pub fn new(id: &Identity, seq: u64, addrs: Vec<String>, custody: Vec<usize>, caps: Vec<String>) -> Self {
+        let mut record = Self { seq, pubkey: hex::encode(id.public.as_bytes()), addrs, custody, caps, sig: String::new() };
+        record.sig = hex::encode(id.key.sign(&record.signed_bytes()).to_bytes());
+        record
+    }
+
+    /// Length-prefixed fields, so no two records sign the same bytes.
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = RECORD_DOMAIN.to_vec();
+        bytes.extend(self.seq.to_be_bytes());
+        let mut field = |data: &[u8]| {
+            bytes.extend((data.len() as u64).to_be_bytes());
+            bytes.extend(data);
+        };
+        field(self.pubkey.as_bytes());
+        for addr in &self.addrs {
+            field(addr.as_bytes());
+        }
+        field(&[]);
+        field(&self.custody.iter().flat_map(|c| (*c as u64).to_be_bytes()).collect::<Vec<_>>());
+        for cap in &self.caps {
+            field(cap.as_bytes());
+        }
+        bytes
+    }
+
+    /// Checks the signature against the record's own key, and returns that key.
+    pub fn verify(&self) -> Result<VerifyingKey, RecordError> {
+        let pubkey: [u8; 32] = hex::decode(&self.pubkey).ok().and_then(|b| b.try_into().ok())
+            .ok_or_else(|| RecordError::Malformed("id is not a 32-byte hex key".into()))?;
+        let sig: [u8; 64] = hex::decode(&self.sig).ok().and_then(|b| b.try_into().ok())
+            .ok_or_else(|| RecordError::Malformed("sig is not a 64-byte hex signature".into()))?;
+        let key = VerifyingKey::from_bytes(&pubkey)?;
+        key.verify(&self.signed_bytes(), &Signature::from_bytes(&sig))?;
+        Ok(key)
+    }
+
+    pub fn has_cap(&self, cap: &str) -> bool {
+        self.caps.iter().any(|c| c == cap)
+    }
+}
+
+impl fmt::Display for NodeRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_vec(self).expect("record serializes");
+        write!(f, "{}{}", RECORD_PREFIX, URL_SAFE_NO_PAD.encode(json))
+    }
+}
+
+/// Parses the text form. Only a record whose signature checks out parses.
+impl FromStr for NodeRecord {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<Self, RecordError> {
+        let body = s.trim().strip_prefix(RECORD_PREFIX).ok_or_else(|| RecordError::Malformed(format!("missing {} prefix", RECORD_PREFIX)))?;
+        let json = URL_SAFE_NO_PAD.decode(body).map_err(|e| RecordError::Malformed(e.to_string()))?;
+        let record: Self = serde_json::from_slice(&json).map_err(|e| RecordError::Malformed(e.to_string()))?;
+        record.verify()?;
+        Ok(record)
+    }
+}
+
+/// Reads a peers file: one record per line in text form. Blank lines and `#` comments are
+/// skipped; any other line must be a valid record. A missing file holds no records.
+pub fn read_peers_file(path: &str) -> Result<Vec<NodeRecord>, RecordError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| line.parse().map_err(|e| RecordError::Line { line: i + 1, source: Box::new(e) }))
+        .collect()
+}
+
+/// Adds `record` to the peers file at `path`, replacing an older record of the same node.
+/// Returns whether the file changed; a record no newer than the one held leaves it alone.
+/// The file is replaced whole, through a temporary file, so readers never see half of it.
+pub fn merge_peers_file(path: &str, record: &NodeRecord) -> Result<bool, RecordError> {
+    let mut records = read_peers_file(path)?;
+    match records.iter_mut().find(|r| r.pubkey == record.pubkey) {
+        Some(held) if held.seq >= record.seq => return Ok(false),
+        Some(held) => *held = record.clone(),
+        None => records.push(record.clone()),
+    }
+    let tmp = format!("{}.tmp", path);
+    let mut file = std::fs::File::create(&tmp)?;
+    for record in &records {
+        writeln!(file, "{}", record)?;
+    }
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(true)
+}
@@ -0,0 +1,380 @@
+//! Sampling strategies for light clients. A strategy only decides which wire indices to query
+//! and what the answers mean; [`Sampler`] drives it round by round, so the networking code that
+//! fetches shards never changes when a strategy is added.
+
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::subnets::column_subnet;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SamplingKind {
+    /// `--samples` distinct indices, uniformly at random
+    #[default]
+    Uniform,
+    /// Samples spread evenly over `--column-subnets` (index mod subnets), random within each subnet
+    Stratified,
+    /// Uniform, but available with up to `--tolerate` failed samples (LossyDAS)
+    Lossy,
+    /// Uniform, then two fresh samples for every failure until a round is clean or `--max-samples`
+    Adaptive,
+    /// Slice `--member` of a permutation shared through `--cohort-seed`, so a cohort covers
+    /// disjoint indices
+    Coordinated,
+}
+
+/// Strategy flags shared by every command that samples.
+#[derive(Args, Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[command(about = None, long_about = None)]
+#[serde(default)]
+pub struct SamplingArgs {
+    /// How a light client chooses the shards it samples
+    #[arg(long = "sampling", value_enum, default_value_t = SamplingKind::Uniform)]
+    #[serde(rename = "sampling")]
+    pub kind: SamplingKind,
+    /// Samples in the first round
+    #[arg(long, default_value_t = 2)]
+    pub samples: usize,
+    /// Column subnets indices are spread over, index `i` on subnet `i % N` (`stratified`; see
+    /// the `mapping` command)
+    #[arg(long = "column-subnets", visible_alias = "subnets", value_name = "N", default_value_t = 2, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub subnets: usize,
+    /// Failed samples still accepted as available (`lossy`)
+    #[arg(long, default_value_t = 1)]
+    pub tolerate: usize,
+    /// Upper bound on samples over all rounds (`adaptive`)
+    #[arg(long, default_value_t = 8)]
+    pub max_samples: usize,
+    /// This client's position in its cohort (`coordinated`)
+    #[arg(long, default_value_t = 0)]
+    pub member: usize,
+    /// Seed the whole cohort agrees on (`coordinated`)
+    #[arg(long, default_value_t = 0)]
+    pub cohort_seed: u64,
+}
+
+impl Default for SamplingArgs {
+    fn default() -> Self {
+        Self { kind: SamplingKind::Uniform, samples: 2, subnets: 2, tolerate: 1, max_samples: 8, member: 0, cohort_seed: 0 }
+    }
+}
+
+impl SamplingArgs {
+    pub fn strategy(&self) -> Box<dyn SamplingStrategy> {
+        match self.kind {
+            SamplingKind::Uniform => Box::new(UniformSampling { samples: self.samples }),
+            SamplingKind::Stratified => Box::new(StratifiedSampling { samples: self.samples, subnets: self.subnets }),
+            SamplingKind::Lossy => Box::new(LossySampling { samples: self.samples, tolerate: self.tolerate }),
+            SamplingKind::Adaptive => Box::new(AdaptiveSampling { samples: self.samples, max_samples: self.max_samples }),
+            SamplingKind::Coordinated => Box::new(CoordinatedSampling { samples: self.samples, member: self.member, cohort_seed: self.cohort_seed }),
+        }
+    }
+
+    /// The same settings for the `member`th client of a cohort.
+    pub fn for_member(self, member: usize) -> Self {
+        Self { member, ..self }
+    }
+}
+
+/// The answer to one sample query.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Sample {
+    /// Wire index queried
+    pub index: usize,
+    /// The round (from 0) the query was made in
+    pub round: usize,
+    /// Whether a shard came back and passed its checks
+    pub ok: bool,
+    /// Microseconds spent checking the shard against the manifest's commitment, if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_us: Option<u64>,
+    /// Whether the sampling deadline passed before an answer came; such a sample is failed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub late: bool,
+}
+
+pub trait SamplingStrategy: Send + Sync {
+    fn kind(&self) -> SamplingKind;
+
+    /// Wire indices (out of `n`) to query next, given every answer so far. Never repeats an
+    /// index already in `history`; empty once the strategy has seen enough.
+    fn next_round(&self, n: usize, history: &[Sample], rng: &mut dyn RngCore) -> Vec<usize>;
+
+    /// Whether `history` counts as the blob being available.
+    fn is_available(&self, history: &[Sample]) -> bool;
+
+    /// Chance that a client using this strategy judges available a blob with `withheld` of its
+    /// `n` indices missing, when every other query succeeds. `None` if there is no closed form.
+    fn fooled_probability(&self, n: usize, withheld: usize) -> Option<f64> {
+        let _ = (n, withheld);
+        None
+    }
+
+    /// Chance that every one of `clients` such clients is fooled. Assumes they choose
+    /// independently unless the strategy says otherwise.
+    fn population_fooled_probability(&self, n: usize, withheld: usize, clients: usize) -> Option<f64> {
+        self.fooled_probability(n, withheld).map(|p| p.powi(clients as i32))
+    }
+}
+
+/// Indices out of `n` not yet queried in `history`.
+fn unsampled(n: usize, history: &[Sample]) -> Vec<usize> {
+    (0..n).filter(|i| !history.iter().any(|s| s.index == *i)).collect()
+}
+
+fn failures(history: &[Sample]) -> usize {
+    history.iter().filter(|s| !s.ok).count()
+}
+
+fn ln_choose(n: usize, k: usize) -> f64 {
+    if k > n {
+        return f64::NEG_INFINITY;
+    }
+    (0..k).map(|i| ((n - i) as f64).ln() - ((i + 1) as f64).ln()).sum()
+}
+
+/// Hypergeometric: chance that `samples` distinct indices out of `n` hit exactly `hits` of the
+/// `withheld` ones.
+pub(crate) fn hit_probability(n: usize, withheld: usize, samples: usize, hits: usize) -> f64 {
+    let samples = samples.min(n);
+    if hits > samples {
+        return 0.0;
+    }
+    (ln_choose(withheld, hits) + ln_choose(n - withheld, samples - hits) - ln_choose(n, samples)).exp()
+}
+
+pub struct UniformSampling {
+    pub samples: usize,
+}
+
+impl SamplingStrategy for UniformSampling {
+    fn kind(&self) -> SamplingKind {
+        SamplingKind::Uniform
+    }
+
+    fn next_round(&self, n: usize, history: &[Sample], rng: &mut dyn RngCore) -> Vec<usize> {
+        if !history.is_empty() {
+            return Vec::new();
+        }
+        unsampled(n, history).choose_multiple(rng, self.samples).copied().collect()
+    }
+
+    fn is_available(&self, history: &[Sample]) -> bool {
+        failures(history) == 0
+    }
+
+    fn fooled_probability(&self, n: usize, withheld: usize) -> Option<f64> {
+        Some(hit_probability(n, withheld.min(n), self.samples, 0))
+    }
+}
+
+/// PeerDAS-style column subnets: index `i` lives on subnet `i % subnets`. Visiting the subnets
+/// in a random order, one sample each, keeps a withholder from hiding behind a single subnet.
+pub struct StratifiedSampling {
+    pub samples: usize,
+    pub subnets: usize,
+}
+
+impl SamplingStrategy for StratifiedSampling {
+    fn kind(&self) -> SamplingKind {
+        SamplingKind::Stratified
+    }
+
+    fn next_round(&self, n: usize, history: &[Sample], rng: &mut dyn RngCore) -> Vec<usize> {
+        if !history.is_empty() {
+            return Vec::new();
+        }
+        let mut order: Vec<usize> = (0..self.subnets).collect();
+        order.shuffle(rng);
+        let mut subnets: Vec<Vec<usize>> = order.iter()
+            .map(|&s| (0..n).filter(|&i| column_subnet(i, self.subnets) == s).collect())
+            .filter(|members: &Vec<usize>| !members.is_empty())
+            .collect();
+        let mut picked = Vec::with_capacity(self.samples);
+        while picked.len() < self.samples && subnets.iter().any(|m| !m.is_empty()) {
+            for members in subnets.iter_mut().filter(|m| !m.is_empty()) {
+                if picked.len() == self.samples {
+                    break;
+                }
+                let i = rng.gen_range(0..members.len());
+                picked.push(members.swap_remove(i));
+            }
+        }
+        picked
+    }
+
+    fn is_available(&self, history: &[Sample]) -> bool {
+        failures(history) == 0
+    }
+}
+
+/// LossyDAS: a few failed samples are put down to network loss rather than withholding. Pair a
+/// larger `tolerate` with more samples to keep the same false-positive bound.
+pub struct LossySampling {
+    pub samples: usize,
+    pub tolerate: usize,
+}
+
+impl SamplingStrategy for LossySampling {
+    fn kind(&self) -> SamplingKind {
+        SamplingKind::Lossy
+    }
+
+    fn next_round(&self, n: usize, history: &[Sample], rng: &mut dyn RngCore) -> Vec<usize> {
+        if !history.is_empty() {
+            return Vec::new();
+        }
+        unsampled(n, history).choose_multiple(rng, self.samples).copied().collect()
+    }
+
+    fn is_available(&self, history: &[Sample]) -> bool {
+        failures(history) <= self.tolerate
+    }
+
+    fn fooled_probability(&self, n: usize, withheld: usize) -> Option<f64> {
+        Some((0..=self.tolerate).map(|hits| hit_probability(n, withheld.min(n), self.samples, hits)).sum())
+    }
+}
+
+/// Starts small and escalates only when something fails: each failed sample in a round buys
+/// two fresh ones in the next, until a round comes back clean or the budget runs out.
+pub struct AdaptiveSampling {
+    pub samples: usize,
+    pub max_samples: usize,
+}
+
+impl AdaptiveSampling {
+    fn last_round(history: &[Sample]) -> impl Iterator<Item = &Sample> {
+        let round = history.iter().map(|s| s.round).max();
+        history.iter().filter(move |s| Some(s.round) == round)
+    }
+}
+
+impl SamplingStrategy for AdaptiveSampling {
+    fn kind(&self) -> SamplingKind {
+        SamplingKind::Adaptive
+    }
+
+    fn next_round(&self, n: usize, history: &[Sample], rng: &mut dyn RngCore) -> Vec<usize> {
+        let wanted = if history.is_empty() {
+            self.samples
+        } else {
+            2 * Self::last_round(history).filter(|s| !s.ok).count()
+        };
+        let budget = self.max_samples.saturating_sub(history.len());
+        unsampled(n, history).choose_multiple(rng, wanted.min(budget)).copied().collect()
+    }
+
+    fn is_available(&self, history: &[Sample]) -> bool {
+        Self::last_round(history).all(|s| s.ok)
+    }
+}
+
+/// Uniform for each client on its own, but a cohort shares one random permutation of the
+/// indices and member `i` takes the `i`th run of `samples` from it. Members cover disjoint
+/// indices until the cohort has sampled all `n`, then wrap around.
+pub struct CoordinatedSampling {
+    pub samples: usize,
+    pub member: usize,
+    pub cohort_seed: u64,
+}
+
+impl SamplingStrategy for CoordinatedSampling {
+    fn kind(&self) -> SamplingKind {
+        SamplingKind::Coordinated
+    }
+
+    fn next_round(&self, n: usize, history: &[Sample], _rng: &mut dyn RngCore) -> Vec<usize> {
+        if !history.is_empty() || n == 0 {
+            return Vec::new();
+        }
+        let mut order: Vec<usize> = (0..n).collect();
+        order.shuffle(&mut StdRng::seed_from_u64(self.cohort_seed));
+        let samples = self.samples.min(n);
+        (0..samples).map(|j| order[(self.member * samples + j) % n]).collect()
+    }
+
+    fn is_available(&self, history: &[Sample]) -> bool {
+        failures(history) == 0
+    }
+
+    fn fooled_probability(&self, n: usize, withheld: usize) -> Option<f64> {
+        Some(hit_probability(n, withheld.min(n), self.samples, 0))
+    }
+
+    /// The cohort misses only if all of its distinct indices do.
+    fn population_fooled_probability(&self, n: usize, withheld: usize, clients: usize) -> Option<f64> {
+        Some(hit_probability(n, withheld.min(n), clients * self.samples.min(n), 0))
+    }
+}
+
+/// Runs a strategy over a blob of `n` wire indices. Callers alternate [`Sampler::next_round`]
+/// with [`Sampler::record`] for each answer until a round comes back empty.
+pub struct Sampler {
+    strategy: Box<dyn SamplingStrategy>,
+    n: usize,
+    round: usize,
+    history: Vec<Sample>,
+    rng: StdRng,
+}
+
+impl Sampler {
+    pub fn new(strategy: Box<dyn SamplingStrategy>, n: usize) -> Self {
+        Self { strategy, n, round: 0, history: Vec::new(), rng: StdRng::from_entropy() }
+    }
+
+    /// Makes index choices reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn kind(&self) -> SamplingKind {
+        self.strategy.kind()
+    }
+
+    /// The next indices to query; empty once sampling is over.
+    pub fn next_round(&mut self) -> Vec<usize> {
+        if !self.history.is_empty() {
+            self.round += 1;
+        }
+        self.strategy.next_round(self.n, &self.history, &mut self.rng)
+    }
+
+    pub fn record(&mut self, index: usize, ok: bool) {
+        self.history.push(Sample { index, round: self.round, ok, verify_us: None, late: false });
+    }
+
+    /// Like [`record`](Self::record), for an answer whose check against the commitment took `verify`.
+    pub fn record_verified(&mut self, index: usize, ok: bool, verify: Duration) {
+        self.history.push(Sample { index, round: self.round, ok, verify_us: Some(verify.as_micros() as u64), late: false });
+    }
+
+    /// Records a query the deadline cut off, as failed.
+    pub fn record_late(&mut self, index: usize) {
+        self.history.push(Sample { index, round: self.round, ok: false, verify_us: None, late: true });
+    }
+
+    pub fn history(&self) -> &[Sample] {
+        &self.history
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.strategy.is_available(&self.history)
+    }
+
+    /// Chance that the distinct indices answered so far would have hit a withholding of
+    /// `withheld` of the `n`: how sure the client can be, at this point, that none is going on.
+    pub fn confidence(&self, withheld: usize) -> f64 {
+        let mut answered: Vec<usize> = self.history.iter().filter(|s| s.ok).map(|s| s.index).collect();
+        answered.sort_unstable();
+        answered.dedup();
+        1.0 - hit_probability(self.n, withheld.min(self.n), answered.len(), 0)
+    }
+}
@@ -0,0 +1,249 @@
+//! Blob sidecars as consensus clients store and serve them: the SSZ `BlobSidecar` of Deneb and
+//! Electra, alone or as the `List[BlobSidecar]` the beacon API returns for a block. Only
+//! fixed-size fields, so a list is the sidecars back to back.
+//!
+//! What can be checked without the chain is checked: that the blob is made of canonical field
+//! elements, and that the commitment is included in the block body its signed header names.
+//! The KZG proof needs the mainnet trusted setup and the header signature the proposer's key,
+//! so neither is verified here.
+
+use bls12_381::Scalar;
+use sha2::{Digest, Sha256};
+
+use crate::error::SidecarError;
+use crate::kzg;
+
+/// Bytes in one blob: 4096 field elements of 32 bytes.
+pub const BYTES_PER_BLOB: usize = 131_072;
+/// Depth of the branch from a commitment up to the block body root.
+pub const INCLUSION_PROOF_DEPTH: usize = 17;
+/// Bytes in one SSZ-encoded sidecar.
+pub const SIDECAR_LEN: usize = 8 + BYTES_PER_BLOB + 48 + 48 + HEADER_LEN + 96 + INCLUSION_PROOF_DEPTH * 32;
+/// Bytes in an SSZ `BeaconBlockHeader`.
+const HEADER_LEN: usize = 8 + 8 + 32 + 32 + 32;
+/// Position of `blob_kzg_commitments[0]` below the body root, as the low bits of its
+/// generalized index: body field 11 of 16, the list's data root (left of its length), then
+/// the first of 4096 commitments.
+const COMMITMENTS_SUBTREE_INDEX: u64 = (11 * 2) << 12;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BeaconBlockHeader {
+    pub slot: u64,
+    pub proposer_index: u64,
+    pub parent_root: [u8; 32],
+    pub state_root: [u8; 32],
+    pub body_root: [u8; 32],
+}
+
+impl BeaconBlockHeader {
+    /// The block root: the header's SSZ hash tree root.
+    pub fn root(&self) -> [u8; 32] {
+        let mut leaves = [[0u8; 32]; 8];
+        leaves[0][..8].copy_from_slice(&self.slot.to_le_bytes());
+        leaves[1][..8].copy_from_slice(&self.proposer_index.to_le_bytes());
+        leaves[2] = self.parent_root;
+        leaves[3] = self.state_root;
+        leaves[4] = self.body_root;
+        let mut level = leaves.to_vec();
+        while level.len() > 1 {
+            level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+        }
+        level[0]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobSidecar {
+    /// Position of the blob in its block
+    pub index: u64,
+    pub blob: Vec<u8>,
+    pub kzg_commitment: [u8; 48],
+    pub kzg_proof: [u8; 48],
+    pub header: BeaconBlockHeader,
+    pub signature: [u8; 96],
+    pub inclusion_proof: [[u8; 32]; INCLUSION_PROOF_DEPTH],
+}
+
+impl BlobSidecar {
+    /// Parses one SSZ sidecar, which must fill `bytes` exactly.
+    pub fn from_ssz(bytes: &[u8]) -> Result<Self, SidecarError> {
+        if bytes.len() != SIDECAR_LEN {
+            return Err(SidecarError::Length { len: bytes.len() });
+        }
+        let mut at = 0;
+        let mut take = |len: usize| {
+            let field = &bytes[at..at + len];
+            at += len;
+            field
+        };
+        let u64_le = |field: &[u8]| u64::from_le_bytes(field.try_into().expect("8 bytes"));
+        let index = u64_le(take(8));
+        let blob = take(BYTES_PER_BLOB).to_vec();
+        let kzg_commitment = take(48).try_into().expect("48 bytes");
+        let kzg_proof = take(48).try_into().expect("48 bytes");
+        let header = BeaconBlockHeader {
+            slot: u64_le(take(8)),
+            proposer_index: u64_le(take(8)),
+            parent_root: take(32).try_into().expect("32 bytes"),
+            state_root: take(32).try_into().expect("32 bytes"),
+            body_root: take(32).try_into().expect("32 bytes"),
+        };
+        let signature = take(96).try_into().expect("96 bytes");
+        let mut inclusion_proof = [[0u8; 32]; INCLUSION_PROOF_DEPTH];
+        for node in &mut inclusion_proof {
+            *node = take(32).try_into().expect("32 bytes");
+        }
+        Ok(Self { index, blob, kzg_commitment, kzg_proof, header, signature, inclusion_proof })
+    }
+
+    /// Parses a file of sidecars: one, or an SSZ list of them.
+    pub fn read_list(bytes: &[u8]) -> Result<Vec<Self>, SidecarError> {
+        if bytes.is_empty() || !bytes.len().is_multiple_of(SIDECAR_LEN) {
+            return Err(SidecarError::Length { len: bytes.len() });
+        }
+        bytes.chunks(SIDECAR_LEN).map(Self::from_ssz).collect()
+    }
+
+    /// The EIP-4844 versioned hash of the commitment, as the execution layer refers to the blob.
+    pub fn versioned_hash(&self) -> [u8; 32] {
+        kzg::versioned_hash(&self.kzg_commitment)
+    }
+
+    /// Checks that every 32-byte element of the blob is below the BLS12-381 scalar modulus, as
+    /// a blob's must be, and names the first that is not.
+    pub fn check_canonical(&self) -> Result<(), SidecarError> {
+        for (element, bytes) in self.blob.chunks(32).enumerate() {
+            let mut le: [u8; 32] = bytes.try_into().expect("32 bytes");
+            le.reverse();
+            if Scalar::from_bytes(&le).is_none().into() {
+                return Err(SidecarError::NonCanonical { element });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the inclusion proof: the commitment at `index` of the body's blob commitments
+    /// hashes up the branch to the header's body root.
+    pub fn verify_inclusion(&self) -> Result<(), SidecarError> {
+        if self.index >= 1 << 12 {
+            return Err(SidecarError::Inclusion { index: self.index });
+        }
+        let mut chunk = [0u8; 64];
+        chunk[..48].copy_from_slice(&self.kzg_commitment);
+        let mut node: [u8; 32] = Sha256::digest(chunk).into();
+        let position = COMMITMENTS_SUBTREE_INDEX + self.index;
+        for (depth, sibling) in self.inclusion_proof.iter().enumerate() {
+            node = if position >> depth & 1 == 1 { hash_pair(sibling, &node) } else { hash_pair(&node, sibling) };
+        }
+        if node != self.header.body_root {
+            return Err(SidecarError::Inclusion { index: self.index });
+        }
+        Ok(())
+    }
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_ssz(sidecar: &BlobSidecar) -> Vec<u8> {
+        let h = &sidecar.header;
+        let mut out = sidecar.index.to_le_bytes().to_vec();
+        out.extend(&sidecar.blob);
+        out.extend(sidecar.kzg_commitment);
+        out.extend(sidecar.kzg_proof);
+        out.extend(h.slot.to_le_bytes());
+        out.extend(h.proposer_index.to_le_bytes());
+        out.extend(h.parent_root.into_iter().chain(h.state_root).chain(h.body_root));
+        out.extend(sidecar.signature);
+        out.extend(sidecar.inclusion_proof.as_flattened());
+        out
+    }
+
+    /// A sidecar for blob `index` whose proof leads to its header's body root, walking the
+    /// generalized index up from the commitment's leaf.
+    fn sidecar(index: u64) -> BlobSidecar {
+        let kzg_commitment = [0xc0; 48];
+        let mut inclusion_proof = [[0u8; 32]; INCLUSION_PROOF_DEPTH];
+        for (depth, sibling) in inclusion_proof.iter_mut().enumerate() {
+            sibling.fill(depth as u8 + 1);
+        }
+        let mut chunk = [0u8; 64];
+        chunk[..48].copy_from_slice(&kzg_commitment);
+        let mut node: [u8; 32] = Sha256::digest(chunk).into();
+        let mut gindex = (1 << INCLUSION_PROOF_DEPTH) | (COMMITMENTS_SUBTREE_INDEX + index);
+        for sibling in &inclusion_proof {
+            node = if gindex & 1 == 1 { hash_pair(sibling, &node) } else { hash_pair(&node, sibling) };
+            gindex >>= 1;
+        }
+        BlobSidecar {
+            index,
+            blob: vec![0; BYTES_PER_BLOB],
+            kzg_commitment,
+            kzg_proof: [0; 48],
+            header: BeaconBlockHeader { slot: 7, proposer_index: 3, parent_root: [1; 32], state_root: [2; 32], body_root: node },
+            signature: [9; 96],
+            inclusion_proof,
+        }
+    }
+
+    #[test]
+    fn header_root_is_its_hash_tree_root() {
+        let zero = BeaconBlockHeader { slot: 0, proposer_index: 0, parent_root: [0; 32], state_root: [0; 32], body_root: [0; 32] };
+        assert_eq!(hex::encode(zero.root()), "c78009fdf07fc56a11f122370658a353aaa542ed63e44c4bc15ff4cd105ab33c");
+    }
+
+    #[test]
+    fn ssz_round_trips() {
+        let one = sidecar(2);
+        assert_eq!(BlobSidecar::from_ssz(&to_ssz(&one)).unwrap(), one);
+        let list = [to_ssz(&one), to_ssz(&sidecar(5))].concat();
+        let read = BlobSidecar::read_list(&list).unwrap();
+        assert_eq!(read.iter().map(|s| s.index).collect::<Vec<_>>(), [2, 5]);
+    }
+
+    #[test]
+    fn wrong_lengths_are_refused() {
+        let bytes = to_ssz(&sidecar(0));
+        for len in [0, 1, SIDECAR_LEN - 1, SIDECAR_LEN + 1] {
+            let bytes: Vec<u8> = bytes.iter().copied().cycle().take(len).collect();
+            assert!(matches!(BlobSidecar::read_list(&bytes), Err(SidecarError::Length { len: l }) if l == len));
+        }
+        assert!(matches!(BlobSidecar::from_ssz(&[bytes.clone(), bytes].concat()), Err(SidecarError::Length { .. })));
+    }
+
+    #[test]
+    fn inclusion_proof_is_checked() {
+        let good = sidecar(3);
+        good.verify_inclusion().unwrap();
+
+        let mut moved = good.clone();
+        moved.index = 4;
+        assert!(matches!(moved.verify_inclusion(), Err(SidecarError::Inclusion { index: 4 })));
+        let mut swapped = good.clone();
+        swapped.kzg_commitment[0] ^= 1;
+        assert!(swapped.verify_inclusion().is_err());
+        let mut out_of_range = good;
+        out_of_range.index = 1 << 12;
+        assert!(out_of_range.verify_inclusion().is_err());
+    }
+
+    #[test]
+    fn non_canonical_elements_are_named() {
+        let mut sidecar = sidecar(0);
+        sidecar.check_canonical().unwrap();
+        // The modulus itself, big-endian, in the third element.
+        let modulus = hex::decode("73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001").unwrap();
+        sidecar.blob[64..96].copy_from_slice(&modulus);
+        assert!(matches!(sidecar.check_canonical(), Err(SidecarError::NonCanonical { element: 2 })));
+        sidecar.blob[95] -= 1;
+        sidecar.check_canonical().unwrap();
+    }
+}
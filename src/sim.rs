@@ -0,0 +1,1070 @@
+//! Deterministic network simulator. Nodes exchange shard messages over simulated links on a
+//! [`SimulatedClock`]; one seed fixes every choice, so a run can be repeated exactly and
+//! configurations compared on identical randomness.
+//!
+//! Each slot the proposer publishes an extended blob of `k + m` shards, withholding some. It
+//! pushes the rest to every full node, which reconstructs once it holds `k` and then serves every
+//! index. Validators and light clients sample from random providers (the proposer or a full
+//! node) with the configured [`SamplingStrategy`](crate::sampling::SamplingStrategy). Validators
+//! attest to what they found; the blob is accepted when enough of them attest.
+//!
+//! Nodes are numbered: the proposer is 0, then the full nodes, validators and light clients in
+//! that order. Partitions cut every link between a group of nodes and the rest for a while.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::Args;
+use rand::rngs::StdRng;
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::{Clock, SimulatedClock};
+use crate::sampling::{Sampler, SamplingArgs, SamplingKind};
+use crate::{DATA_SHARDS, PARITY_SHARDS};
+
+/// Bytes charged for a message that carries no shard.
+const CONTROL_BYTES: usize = 64;
+
+/// Everything a run depends on. Set by flags, or read from a JSON topology file with the same
+/// field names (missing fields take the flag defaults).
+#[derive(Args, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SimConfig {
+    /// Seed for every random choice in the run
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+    #[arg(long, default_value_t = 10)]
+    pub slots: usize,
+    /// Data shards per blob
+    #[arg(long, default_value_t = DATA_SHARDS)]
+    pub k: usize,
+    /// Parity shards per blob
+    #[arg(long, default_value_t = PARITY_SHARDS)]
+    pub m: usize,
+    #[arg(long, default_value_t = 128 * 1024)]
+    pub shard_bytes: usize,
+    /// Nodes that take every pushed shard, reconstruct, and serve the extended blob
+    #[arg(long, default_value_t = 2)]
+    pub full_nodes: usize,
+    /// Sampling nodes whose attestations decide acceptance
+    #[arg(long, default_value_t = 16)]
+    pub validators: usize,
+    /// Sampling nodes that do not attest
+    #[arg(long, default_value_t = 0)]
+    pub light_clients: usize,
+    /// One-way delay of every link
+    #[arg(long, default_value_t = 50)]
+    pub latency_ms: u64,
+    #[arg(long, default_value_t = 12_000)]
+    pub slot_ms: u64,
+    /// When samplers start, from the beginning of the slot
+    #[arg(long, default_value_t = 1_000)]
+    pub sample_delay_ms: u64,
+    /// How long a sampling round waits for answers before counting the rest as failed
+    #[arg(long, default_value_t = 1_000)]
+    pub sample_timeout_ms: u64,
+    /// Shards the proposer withholds each slot, chosen at random
+    #[arg(long, default_value_t = 0)]
+    pub withhold: usize,
+    /// Fraction of validators that are lazy
+    #[arg(long, default_value_t = 0.0)]
+    pub lazy_fraction: f64,
+    /// Chance a lazy validator samples in a slot; otherwise it attests without looking
+    #[arg(long, default_value_t = 0.0)]
+    pub lazy_sample_probability: f64,
+    /// Fraction of validators that must attest for the blob to be accepted
+    #[arg(long, default_value_t = 2.0 / 3.0)]
+    pub attestation_threshold: f64,
+    /// How often a full node that has not reconstructed asks other providers for missing shards
+    /// (0 disables)
+    #[arg(long, default_value_t = 1_000)]
+    pub repair_interval_ms: u64,
+    /// Cut a group of nodes off from the rest: `NODES@FROM..UNTIL`, e.g. `1,2@12000..36000`
+    /// (node ids, then milliseconds of simulated time). Repeatable
+    #[arg(long = "partition")]
+    pub partitions: Vec<Partition>,
+    /// Validator committees; committee `c` custodies the indices `i` with `i % committees == c`
+    /// (0 disables the committee layer)
+    #[arg(long, default_value_t = 0)]
+    pub committees: usize,
+    /// Slots per epoch; committee assignments rotate at each epoch boundary
+    #[arg(long, default_value_t = 4, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub epoch_slots: usize,
+    /// Fraction of validators reassigned to a random committee each epoch
+    #[arg(long, default_value_t = 0.25)]
+    pub rotation_fraction: f64,
+    /// Stake weight by validator node id (topology file only). Each unit of stake custodies one
+    /// committee, rounded up, from one up to all of them; unlisted validators weigh 1
+    #[arg(skip)]
+    pub stakes: BTreeMap<usize, f64>,
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub sampling: SamplingArgs,
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub rewards: Rewards,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            slots: 10,
+            k: DATA_SHARDS,
+            m: PARITY_SHARDS,
+            shard_bytes: 128 * 1024,
+            full_nodes: 2,
+            validators: 16,
+            light_clients: 0,
+            latency_ms: 50,
+            slot_ms: 12_000,
+            sample_delay_ms: 1_000,
+            sample_timeout_ms: 1_000,
+            withhold: 0,
+            lazy_fraction: 0.0,
+            lazy_sample_probability: 0.0,
+            attestation_threshold: 2.0 / 3.0,
+            repair_interval_ms: 1_000,
+            partitions: Vec::new(),
+            committees: 0,
+            epoch_slots: 4,
+            rotation_fraction: 0.25,
+            stakes: BTreeMap::new(),
+            sampling: SamplingArgs::default(),
+            rewards: Rewards::default(),
+        }
+    }
+}
+
+impl SimConfig {
+    pub fn n(&self) -> usize {
+        self.k + self.m
+    }
+
+    pub fn stake(&self, node: usize) -> f64 {
+        self.stakes.get(&node).copied().unwrap_or(1.0)
+    }
+
+    /// Committees a validator with this much stake custodies.
+    pub fn custody_committees(&self, stake: f64) -> usize {
+        (stake.ceil().max(1.0) as usize).min(self.committees)
+    }
+}
+
+/// The incentive scheme scored after a run.
+#[derive(Args, Serialize, Deserialize, Copy, Clone, Debug)]
+#[serde(default)]
+pub struct Rewards {
+    /// Paid to a node for each shard it serves a sampler within the slot
+    #[arg(long, default_value_t = 1.0)]
+    pub serve_reward: f64,
+    /// Charged to a node for each sampler's request it answers with `NotFound`
+    #[arg(long, default_value_t = 1.0)]
+    pub serve_penalty: f64,
+    /// Paid to a validator whose attestation matches the blob's availability
+    #[arg(long, default_value_t = 10.0)]
+    pub attest_reward: f64,
+    /// Charged to a validator that attests to an unavailable blob or fails to attest to an
+    /// available one
+    #[arg(long, default_value_t = 20.0)]
+    pub attest_penalty: f64,
+}
+
+impl Default for Rewards {
+    fn default() -> Self {
+        Self { serve_reward: 1.0, serve_penalty: 1.0, attest_reward: 10.0, attest_penalty: 20.0 }
+    }
+}
+
+/// Every link between `nodes` and the other nodes is down from `at_ms` until `heal_ms`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Partition {
+    pub nodes: Vec<usize>,
+    pub at_ms: u64,
+    pub heal_ms: u64,
+}
+
+impl Partition {
+    fn cuts(&self, a: usize, b: usize, now: Duration) -> bool {
+        let now_ms = now.as_millis() as u64;
+        (self.at_ms..self.heal_ms).contains(&now_ms) && self.nodes.contains(&a) != self.nodes.contains(&b)
+    }
+
+    fn overlaps(&self, from_ms: u64, until_ms: u64) -> bool {
+        self.at_ms < until_ms && from_ms < self.heal_ms
+    }
+}
+
+impl FromStr for Partition {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let usage = || format!("expected NODES@FROM..UNTIL, got {:?}", spec);
+        let (nodes, window) = spec.split_once('@').ok_or_else(usage)?;
+        let (at, heal) = window.split_once("..").ok_or_else(usage)?;
+        let nodes = nodes.split(',').map(|n| n.trim().parse()).collect::<Result<Vec<usize>, _>>().map_err(|_| usage())?;
+        let (at_ms, heal_ms) = (at.trim().parse().map_err(|_| usage())?, heal.trim().parse().map_err(|_| usage())?);
+        if heal_ms <= at_ms {
+            return Err(format!("partition heals at {} ms, before it starts at {} ms", heal_ms, at_ms));
+        }
+        Ok(Self { nodes, at_ms, heal_ms })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    Proposer,
+    FullNode,
+    Validator,
+    LightClient,
+}
+
+/// What a sampling node concluded in one slot.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SampleVerdict {
+    Available,
+    Unavailable,
+    /// A lazy validator that did not sample (and attests anyway)
+    Skipped,
+    /// Sampling had not finished by the end of the slot
+    Unfinished,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SlotReport {
+    pub slot: usize,
+    /// Whether any partition was in force during the slot
+    pub partitioned: bool,
+    pub withheld: usize,
+    /// Whether enough shards were published to rebuild the blob
+    pub available: bool,
+    /// Full nodes that reconstructed
+    pub reconstructed: usize,
+    pub attestations: usize,
+    /// Validators that sampled this slot (lazy ones that skipped are not counted)
+    pub sampling_validators: usize,
+    pub accepted: bool,
+    /// Light clients that concluded the blob was available
+    pub convinced_light_clients: usize,
+    /// Distinct indices the light clients sampled between them
+    pub coverage: usize,
+    pub samples: usize,
+    pub bytes: usize,
+    /// Messages lost to partitions
+    pub dropped: usize,
+    /// Mean time from the slot's start, when the header goes out, to a sampler concluding the
+    /// blob is available (over the samplers that did)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence_ms: Option<f64>,
+    /// Mean time from the slot's start to a full node having rebuilt the blob
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reconstruction_ms: Option<f64>,
+}
+
+/// Outcomes over the slots of one phase of a partitioned run.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct PhaseStats {
+    pub slots: usize,
+    /// Share of full-node slots that ended with the blob rebuilt (available slots only)
+    pub rebuilt_rate: f64,
+    /// Share of available slots that were accepted
+    pub accepted_rate: f64,
+}
+
+impl PhaseStats {
+    fn over(slots: &[&SlotReport], full_nodes: usize) -> Self {
+        let available: Vec<&&SlotReport> = slots.iter().filter(|r| r.available).collect();
+        let rate = |hits: usize, total: usize| if total == 0 { 1.0 } else { hits as f64 / total as f64 };
+        Self {
+            slots: slots.len(),
+            rebuilt_rate: rate(available.iter().map(|r| r.reconstructed).sum(), available.len() * full_nodes),
+            accepted_rate: rate(available.iter().filter(|r| r.accepted).count(), available.len()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PartitionSummary {
+    pub before: PhaseStats,
+    pub during: PhaseStats,
+    pub after: PhaseStats,
+    /// Whether, once every partition healed, reconstruction and acceptance were back to how
+    /// they were before (or complete, with no slots before)
+    pub recovered: bool,
+}
+
+/// How well the light-client population detects withholding: the sampling strategy's closed
+/// form, to set against `light_client_fooled_rate` and `measured_undetected`. The population
+/// figure assumes clients sample independently unless the strategy coordinates them.
+#[derive(Serialize, Debug, Clone)]
+pub struct ConfidenceSummary {
+    pub clients: usize,
+    /// Chance one client is fooled by this run's withholding (`None` without a closed form)
+    pub analytic_fooled: Option<f64>,
+    /// One client's confidence that an accepted blob is available: `1 - analytic_fooled`
+    pub client_confidence: Option<f64>,
+    /// Chance no client at all notices: `analytic_fooled ^ clients` for independent clients
+    pub analytic_undetected: Option<f64>,
+    /// Share of unavailable slots in which every client was fooled
+    pub measured_undetected: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SimSummary {
+    pub slots: usize,
+    pub lazy_validators: usize,
+    pub unavailable_slots: usize,
+    /// Slots accepted although the blob could not be rebuilt
+    pub false_accepts: usize,
+    /// Slots rejected although the blob was available
+    pub false_rejects: usize,
+    /// Over unavailable slots, the share of light clients that were fooled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub light_client_fooled_rate: Option<f64>,
+    pub mean_attestation_rate: f64,
+    /// Distinct indices the light clients sampled per slot, on average
+    pub mean_coverage: f64,
+    pub samples: usize,
+    pub bytes: usize,
+    pub dropped: usize,
+    /// Availability latency: slot means of `confidence_ms` and `reconstruction_ms`, averaged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_confidence_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_reconstruction_ms: Option<f64>,
+    /// Present when light clients faced unavailable slots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<ConfidenceSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub committee: Option<CommitteeSummary>,
+    /// Custody cost as a function of stake, lowest stake first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stake_custody: Vec<StakeCustody>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<PartitionSummary>,
+}
+
+/// Committee assignments at the start of an epoch, and what the rotation into them cost.
+#[derive(Serialize, Debug, Clone)]
+pub struct EpochReport {
+    pub epoch: usize,
+    pub first_slot: usize,
+    /// Validators per committee
+    pub committee_sizes: Vec<usize>,
+    /// Validators that moved to a different committee
+    pub reassigned: usize,
+    /// Shards moved validators had to fetch: their new custody indices, for every blob of the
+    /// previous epoch they must keep
+    pub churn_shards: usize,
+    pub churn_bytes: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CommitteeSummary {
+    pub committees: usize,
+    pub epochs: usize,
+    pub reassigned: usize,
+    pub churn_shards: usize,
+    pub churn_bytes: usize,
+    /// Churn per epoch boundary (the initial assignment is free)
+    pub churn_bytes_per_rotation: f64,
+    /// Share of all shard traffic spent on custody churn
+    pub churn_share: f64,
+}
+
+/// What custody cost one validator over the run.
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeCustody {
+    pub node: usize,
+    pub stake: f64,
+    /// Committees held at the end of the run
+    pub committees: usize,
+    pub custody_indices: usize,
+    /// Custody shards kept for one epoch of available blobs
+    pub storage_bytes: usize,
+    /// Everything the node sent and received, custody fetches and churn included
+    pub bandwidth_bytes: usize,
+}
+
+/// Per-validator custody costs averaged over the validators with the same stake.
+#[derive(Serialize, Debug, Clone)]
+pub struct StakeCustody {
+    pub stake: f64,
+    pub nodes: usize,
+    pub custody_indices: f64,
+    pub storage_bytes: f64,
+    pub bandwidth_bytes: f64,
+}
+
+/// One node's line on the scoreboard.
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeScore {
+    pub node: usize,
+    pub role: Role,
+    pub lazy: bool,
+    pub served: usize,
+    pub refused: usize,
+    pub correct_attestations: usize,
+    pub wrong_attestations: usize,
+    pub rewards: f64,
+    pub penalties: f64,
+    pub balance: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SimReport {
+    pub config: SimConfig,
+    pub slots: Vec<SlotReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub epochs: Vec<EpochReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub custody: Vec<NodeCustody>,
+    /// Every node but the light clients, which neither serve nor attest
+    pub scoreboard: Vec<NodeScore>,
+    pub summary: SimSummary,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Message {
+    Push { index: usize },
+    GetShard { index: usize },
+    Shard { index: usize },
+    NotFound { index: usize },
+}
+
+impl Message {
+    fn bytes(&self, shard_bytes: usize) -> usize {
+        match self {
+            Message::Push { .. } | Message::Shard { .. } => shard_bytes,
+            Message::GetShard { .. } | Message::NotFound { .. } => CONTROL_BYTES,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Event {
+    SlotStart { slot: usize },
+    StartSampling { node: usize },
+    Deliver { slot: usize, from: usize, to: usize, msg: Message },
+    RoundTimeout { slot: usize, node: usize, round: usize },
+    Repair { slot: usize, node: usize },
+    SlotEnd { slot: usize },
+}
+
+/// An event queued for `at`; `seq` keeps same-time events in scheduling order.
+struct Scheduled {
+    at: Duration,
+    seq: u64,
+    event: Event,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.seq) == (other.at, other.seq)
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+struct Node {
+    role: Role,
+    lazy: bool,
+    /// Shards of the current slot's blob this node holds
+    held: Vec<bool>,
+    reconstructed: bool,
+    /// When this slot's blob was rebuilt (full nodes) or judged available (samplers)
+    reconstructed_at: Option<Duration>,
+    confident_at: Option<Duration>,
+    sampler: Option<Sampler>,
+    /// Indices asked for in the current round and not yet answered
+    pending: Vec<usize>,
+    round: usize,
+    verdict: Option<SampleVerdict>,
+    /// Committees a validator belongs to this epoch
+    committees: Vec<usize>,
+}
+
+impl Node {
+    fn new(role: Role, n: usize) -> Self {
+        Self {
+            role,
+            lazy: false,
+            held: vec![false; n],
+            reconstructed: false,
+            reconstructed_at: None,
+            confident_at: None,
+            sampler: None,
+            pending: Vec::new(),
+            round: 0,
+            verdict: None,
+            committees: Vec::new(),
+        }
+    }
+
+    fn samples(&self) -> bool {
+        matches!(self.role, Role::Validator | Role::LightClient)
+    }
+}
+
+pub struct Simulation {
+    config: SimConfig,
+    clock: SimulatedClock,
+    rng: StdRng,
+    nodes: Vec<Node>,
+    queue: BinaryHeap<Reverse<Scheduled>>,
+    seq: u64,
+    /// Nodes serving the current slot: the proposer and every full node
+    providers: Vec<usize>,
+    withheld: Vec<usize>,
+    /// What `coordinated` cohorts agree on this slot; each role forms its own cohort
+    cohort_seed: u64,
+    samples: usize,
+    bytes: usize,
+    dropped: usize,
+    reports: Vec<SlotReport>,
+    epochs: Vec<EpochReport>,
+    /// Bytes each node sent and received over the whole run
+    traffic: Vec<usize>,
+    /// Per-node counts the scoreboard is priced from
+    scores: Vec<NodeScore>,
+}
+
+/// The proposer is always node 0.
+const PROPOSER: usize = 0;
+
+impl Simulation {
+    pub fn new(config: SimConfig) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let n = config.n();
+        let mut nodes = vec![Node::new(Role::Proposer, n)];
+        nodes.extend((0..config.full_nodes).map(|_| Node::new(Role::FullNode, n)));
+        nodes.extend((0..config.validators).map(|_| Node::new(Role::Validator, n)));
+        nodes.extend((0..config.light_clients).map(|_| Node::new(Role::LightClient, n)));
+
+        let lazy = (config.validators as f64 * config.lazy_fraction).round() as usize;
+        let validators: Vec<usize> = (0..nodes.len()).filter(|&i| nodes[i].role == Role::Validator).collect();
+        for i in validators.choose_multiple(&mut rng, lazy.min(validators.len())) {
+            nodes[*i].lazy = true;
+        }
+        let providers = (0..nodes.len()).filter(|&i| matches!(nodes[i].role, Role::Proposer | Role::FullNode)).collect();
+
+        let mut sim = Self {
+            config,
+            clock: SimulatedClock::default(),
+            rng,
+            nodes,
+            queue: BinaryHeap::new(),
+            seq: 0,
+            providers,
+            withheld: Vec::new(),
+            samples: 0,
+            bytes: 0,
+            cohort_seed: 0,
+            dropped: 0,
+            reports: Vec::new(),
+            epochs: Vec::new(),
+            traffic: Vec::new(),
+            scores: Vec::new(),
+        };
+        sim.traffic = vec![0; sim.nodes.len()];
+        sim.scores = sim.nodes.iter().enumerate()
+            .map(|(node, n)| NodeScore {
+                node,
+                role: n.role,
+                lazy: n.lazy,
+                served: 0,
+                refused: 0,
+                correct_attestations: 0,
+                wrong_attestations: 0,
+                rewards: 0.0,
+                penalties: 0.0,
+                balance: 0.0,
+            })
+            .collect();
+        // A slot's end is queued ahead of the next slot's start, which falls at the same instant.
+        for slot in 0..sim.config.slots {
+            sim.schedule(sim.slot_start(slot), Event::SlotStart { slot });
+            sim.schedule(sim.slot_start(slot + 1), Event::SlotEnd { slot });
+        }
+        sim
+    }
+
+    pub fn clock(&self) -> &SimulatedClock {
+        &self.clock
+    }
+
+    /// Runs every slot to completion.
+    pub fn run(mut self) -> SimReport {
+        while let Some(Reverse(Scheduled { at, event, .. })) = self.queue.pop() {
+            self.clock.advance_to(at);
+            self.handle(event);
+        }
+        let custody = self.custody();
+        let summary = self.summary(&custody);
+        let scoreboard = self.scoreboard();
+        SimReport { config: self.config, slots: self.reports, epochs: self.epochs, custody, scoreboard, summary }
+    }
+
+    fn slot_start(&self, slot: usize) -> Duration {
+        Duration::from_millis(self.config.slot_ms * slot as u64)
+    }
+
+    fn schedule(&mut self, at: Duration, event: Event) {
+        self.seq += 1;
+        self.queue.push(Reverse(Scheduled { at, seq: self.seq, event }));
+    }
+
+    fn send(&mut self, slot: usize, from: usize, to: usize, msg: Message) {
+        let bytes = msg.bytes(self.config.shard_bytes);
+        self.bytes += bytes;
+        self.traffic[from] += bytes;
+        self.traffic[to] += bytes;
+        let now = self.clock.now();
+        if self.config.partitions.iter().any(|p| p.cuts(from, to, now)) {
+            self.dropped += 1;
+            return;
+        }
+        let at = self.clock.now() + Duration::from_millis(self.config.latency_ms);
+        self.schedule(at, Event::Deliver { slot, from, to, msg });
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::SlotStart { slot } => self.start_slot(slot),
+            Event::StartSampling { node } => {
+                self.fetch_custody(node);
+                self.start_sampling(node);
+            }
+            Event::Deliver { slot, from, to, msg } => {
+                if self.reports.len() == slot {
+                    self.deliver(slot, from, to, msg);
+                }
+            }
+            Event::RoundTimeout { slot, node, round } => {
+                if self.reports.len() == slot && self.nodes[node].round == round && !self.nodes[node].pending.is_empty() {
+                    for index in std::mem::take(&mut self.nodes[node].pending) {
+                        self.nodes[node].sampler.as_mut().expect("sampling node").record(index, false);
+                    }
+                    self.next_round(slot, node);
+                }
+            }
+            Event::Repair { slot, node } => {
+                if self.reports.len() == slot {
+                    self.repair(slot, node);
+                }
+            }
+            Event::SlotEnd { slot } => self.end_slot(slot),
+        }
+    }
+
+    fn start_slot(&mut self, slot: usize) {
+        let n = self.config.n();
+        if self.config.committees > 0 && slot.is_multiple_of(self.config.epoch_slots) {
+            self.rotate_committees(slot);
+        }
+        self.withheld = (0..n).choose_multiple(&mut self.rng, self.config.withhold.min(n));
+        if self.config.sampling.kind == SamplingKind::Coordinated {
+            self.cohort_seed = self.rng.r#gen();
+        }
+        for node in &mut self.nodes {
+            *node = Node { lazy: node.lazy, committees: std::mem::take(&mut node.committees), ..Node::new(node.role, n) };
+        }
+        for index in 0..n {
+            self.nodes[PROPOSER].held[index] = !self.withheld.contains(&index);
+        }
+        let published: Vec<usize> = (0..n).filter(|i| !self.withheld.contains(i)).collect();
+        for to in self.providers.clone().into_iter().filter(|&p| p != PROPOSER) {
+            for &index in &published {
+                self.send(slot, PROPOSER, to, Message::Push { index });
+            }
+        }
+        let start = self.slot_start(slot);
+        if self.config.repair_interval_ms > 0 {
+            let at = start + Duration::from_millis(self.config.repair_interval_ms);
+            for node in self.providers.clone().into_iter().filter(|&p| p != PROPOSER) {
+                self.schedule(at, Event::Repair { slot, node });
+            }
+        }
+        let samplers: Vec<usize> = (0..self.nodes.len()).filter(|&i| self.nodes[i].samples()).collect();
+        for node in samplers {
+            self.schedule(start + Duration::from_millis(self.config.sample_delay_ms), Event::StartSampling { node });
+        }
+    }
+
+    /// Indices the given committees custody.
+    fn custody_indices(&self, committees: &[usize]) -> Vec<usize> {
+        if committees.is_empty() {
+            return Vec::new();
+        }
+        (0..self.config.n()).filter(|i| committees.contains(&(i % self.config.committees))).collect()
+    }
+
+    /// Deals validators round-robin into committees in the first epoch, as many as their stake
+    /// buys; afterwards moves a `rotation_fraction` of them to random committees and charges
+    /// each mover for the custody indices it did not already hold, over the blobs of the epoch
+    /// just ended.
+    fn rotate_committees(&mut self, slot: usize) {
+        let committees = self.config.committees;
+        let mut validators: Vec<usize> = (0..self.nodes.len()).filter(|&i| self.nodes[i].role == Role::Validator).collect();
+        let (mut reassigned, mut churn_shards) = (0, 0);
+        if slot == 0 {
+            validators.shuffle(&mut self.rng);
+            let mut next = 0;
+            for &v in &validators {
+                let count = self.config.custody_committees(self.config.stake(v));
+                let mut assigned: Vec<usize> = (next..next + count).map(|c| c % committees).collect();
+                assigned.sort_unstable();
+                self.nodes[v].committees = assigned;
+                next += count;
+            }
+        } else {
+            let moving = (validators.len() as f64 * self.config.rotation_fraction.clamp(0.0, 1.0)).round() as usize;
+            let retained = self.reports[slot.saturating_sub(self.config.epoch_slots)..].iter().filter(|r| r.available).count();
+            for v in validators.choose_multiple(&mut self.rng, moving).copied().collect::<Vec<_>>() {
+                let count = self.nodes[v].committees.len();
+                let mut next = (0..committees).choose_multiple(&mut self.rng, count);
+                next.sort_unstable();
+                if next == self.nodes[v].committees {
+                    continue;
+                }
+                let held = self.custody_indices(&self.nodes[v].committees);
+                let fetched = retained * self.custody_indices(&next).iter().filter(|i| !held.contains(i)).count();
+                reassigned += 1;
+                churn_shards += fetched;
+                self.traffic[v] += fetched * self.config.shard_bytes;
+                self.nodes[v].committees = next;
+            }
+        }
+        let mut committee_sizes = vec![0; committees];
+        for c in self.nodes.iter().flat_map(|n| &n.committees) {
+            committee_sizes[*c] += 1;
+        }
+        self.bytes += churn_shards * self.config.shard_bytes;
+        self.epochs.push(EpochReport {
+            epoch: slot / self.config.epoch_slots,
+            first_slot: slot,
+            committee_sizes,
+            reassigned,
+            churn_shards,
+            churn_bytes: churn_shards * self.config.shard_bytes,
+        });
+    }
+
+    /// A validator fetches this slot's shards for its custody indices from random providers.
+    fn fetch_custody(&mut self, node: usize) {
+        let slot = self.reports.len();
+        for index in self.custody_indices(&self.nodes[node].committees) {
+            let provider = *self.providers.choose(&mut self.rng).expect("the proposer always provides");
+            self.send(slot, node, provider, Message::GetShard { index });
+        }
+    }
+
+    fn start_sampling(&mut self, node: usize) {
+        let lazy = self.nodes[node].lazy;
+        if lazy && !self.rng.gen_bool(self.config.lazy_sample_probability.clamp(0.0, 1.0)) {
+            self.nodes[node].verdict = Some(SampleVerdict::Skipped);
+            return;
+        }
+        let role = self.nodes[node].role;
+        let member = self.nodes[..node].iter().filter(|n| n.role == role).count();
+        let sampling = SamplingArgs { cohort_seed: self.cohort_seed.wrapping_add(role as u64), ..self.config.sampling }.for_member(member);
+        let sampler = Sampler::new(sampling.strategy(), self.config.n()).seed(self.rng.r#gen());
+        self.nodes[node].sampler = Some(sampler);
+        self.next_round(self.reports.len(), node);
+    }
+
+    /// Sends the sampler's next round, one query per index to a random provider, or settles the
+    /// verdict when the strategy is done.
+    fn next_round(&mut self, slot: usize, node: usize) {
+        let sampler = self.nodes[node].sampler.as_mut().expect("sampling node");
+        let round = sampler.next_round();
+        if round.is_empty() {
+            let available = sampler.is_available();
+            self.nodes[node].verdict = Some(if available { SampleVerdict::Available } else { SampleVerdict::Unavailable });
+            if available {
+                self.nodes[node].confident_at = Some(self.clock.now());
+            }
+            return;
+        }
+        self.nodes[node].round += 1;
+        self.nodes[node].pending = round.clone();
+        for index in round {
+            let provider = *self.providers.choose(&mut self.rng).expect("the proposer always provides");
+            self.samples += 1;
+            self.send(slot, node, provider, Message::GetShard { index });
+        }
+        let at = self.clock.now() + Duration::from_millis(self.config.sample_timeout_ms);
+        let round = self.nodes[node].round;
+        self.schedule(at, Event::RoundTimeout { slot, node, round });
+    }
+
+    /// A full node still short of `k` shards asks a random other provider for each missing one.
+    fn repair(&mut self, slot: usize, node: usize) {
+        if self.nodes[node].reconstructed {
+            return;
+        }
+        let others: Vec<usize> = self.providers.iter().copied().filter(|&p| p != node).collect();
+        let missing: Vec<usize> = (0..self.config.n()).filter(|&i| !self.nodes[node].held[i]).collect();
+        for index in missing {
+            if let Some(&provider) = others.choose(&mut self.rng) {
+                self.send(slot, node, provider, Message::GetShard { index });
+            }
+        }
+        let at = self.clock.now() + Duration::from_millis(self.config.repair_interval_ms);
+        self.schedule(at, Event::Repair { slot, node });
+    }
+
+    fn deliver(&mut self, slot: usize, from: usize, to: usize, msg: Message) {
+        // Serving is scored towards samplers only; repair traffic between full nodes is not.
+        if self.nodes[to].samples() {
+            match msg {
+                Message::Shard { .. } => self.scores[from].served += 1,
+                Message::NotFound { .. } => self.scores[from].refused += 1,
+                _ => {}
+            }
+        }
+        match msg {
+            Message::Shard { index } if self.nodes[to].role == Role::FullNode => self.deliver(slot, from, to, Message::Push { index }),
+            Message::NotFound { .. } if self.nodes[to].role == Role::FullNode => {}
+            Message::Push { index } => {
+                let (k, node) = (self.config.k, &mut self.nodes[to]);
+                node.held[index] = true;
+                if !node.reconstructed && node.held.iter().filter(|h| **h).count() >= k {
+                    node.reconstructed = true;
+                    node.reconstructed_at = Some(self.clock.now());
+                    node.held.fill(true);
+                }
+            }
+            Message::GetShard { index } => {
+                let reply = if self.nodes[to].held[index] { Message::Shard { index } } else { Message::NotFound { index } };
+                self.send(slot, to, from, reply);
+            }
+            Message::Shard { index } | Message::NotFound { index } => {
+                let node = &mut self.nodes[to];
+                let Some(pos) = node.pending.iter().position(|&i| i == index) else { return };
+                node.pending.swap_remove(pos);
+                node.sampler.as_mut().expect("sampling node").record(index, matches!(msg, Message::Shard { .. }));
+                if node.pending.is_empty() {
+                    self.next_round(slot, to);
+                }
+            }
+        }
+    }
+
+    fn end_slot(&mut self, slot: usize) {
+        let verdict = |node: &Node| node.verdict.unwrap_or(SampleVerdict::Unfinished);
+        let validators: Vec<&Node> = self.nodes.iter().filter(|n| n.role == Role::Validator).collect();
+        // Lazy validators that skipped sampling attest anyway; that is what makes them lazy.
+        let attestations = validators.iter().filter(|n| matches!(verdict(n), SampleVerdict::Available | SampleVerdict::Skipped)).count();
+        let sampling_validators = validators.iter().filter(|n| verdict(n) != SampleVerdict::Skipped).count();
+        let accepted = !validators.is_empty() && attestations as f64 >= self.config.attestation_threshold * validators.len() as f64;
+        let available = self.config.n() - self.withheld.len() >= self.config.k;
+        for (node, n) in self.nodes.iter().enumerate().filter(|(_, n)| n.role == Role::Validator) {
+            let attested = matches!(verdict(n), SampleVerdict::Available | SampleVerdict::Skipped);
+            let score = &mut self.scores[node];
+            if attested == available {
+                score.correct_attestations += 1;
+            } else {
+                score.wrong_attestations += 1;
+            }
+        }
+        let convinced_light_clients = self.nodes.iter()
+            .filter(|n| n.role == Role::LightClient && verdict(n) == SampleVerdict::Available)
+            .count();
+        let mut covered: Vec<usize> = self.nodes.iter()
+            .filter(|n| n.role == Role::LightClient)
+            .filter_map(|n| n.sampler.as_ref())
+            .flat_map(|s| s.history().iter().map(|sample| sample.index))
+            .collect();
+        covered.sort_unstable();
+        covered.dedup();
+        let samples = self.samples;
+        let bytes = self.bytes;
+        let start = self.slot_start(slot);
+        let since_start = |at: Duration| at.saturating_sub(start).as_secs_f64() * 1000.0;
+        let confidence_ms = mean(self.nodes.iter().filter_map(|n| n.confident_at).map(since_start));
+        let reconstruction_ms = mean(self.nodes.iter().filter(|n| n.role == Role::FullNode).filter_map(|n| n.reconstructed_at).map(since_start));
+        let (from_ms, until_ms) = (self.config.slot_ms * slot as u64, self.config.slot_ms * (slot as u64 + 1));
+        self.reports.push(SlotReport {
+            slot,
+            partitioned: self.config.partitions.iter().any(|p| p.overlaps(from_ms, until_ms)),
+            withheld: self.withheld.len(),
+            available,
+            reconstructed: self.nodes.iter().filter(|n| n.role == Role::FullNode && n.reconstructed).count(),
+            attestations,
+            sampling_validators,
+            accepted,
+            convinced_light_clients,
+            coverage: covered.len(),
+            samples,
+            bytes,
+            dropped: self.dropped,
+            confidence_ms,
+            reconstruction_ms,
+        });
+        self.samples = 0;
+        self.bytes = 0;
+        self.dropped = 0;
+    }
+
+    /// Prices the counts with the configured rewards, best balance first.
+    fn scoreboard(&self) -> Vec<NodeScore> {
+        let r = self.config.rewards;
+        let mut board: Vec<NodeScore> = self.scores.iter()
+            .filter(|s| s.role != Role::LightClient)
+            .map(|s| {
+                let rewards = s.served as f64 * r.serve_reward + s.correct_attestations as f64 * r.attest_reward;
+                let penalties = s.refused as f64 * r.serve_penalty + s.wrong_attestations as f64 * r.attest_penalty;
+                NodeScore { rewards, penalties, balance: rewards - penalties, ..s.clone() }
+            })
+            .collect();
+        board.sort_by(|a, b| b.balance.total_cmp(&a.balance).then(a.node.cmp(&b.node)));
+        board
+    }
+
+    fn custody(&self) -> Vec<NodeCustody> {
+        if self.config.committees == 0 {
+            return Vec::new();
+        }
+        let retained = self.reports.iter().filter(|r| r.available).count().min(self.config.epoch_slots);
+        (0..self.nodes.len()).filter(|&i| self.nodes[i].role == Role::Validator)
+            .map(|node| {
+                let custody_indices = self.custody_indices(&self.nodes[node].committees).len();
+                NodeCustody {
+                    node,
+                    stake: self.config.stake(node),
+                    committees: self.nodes[node].committees.len(),
+                    custody_indices,
+                    storage_bytes: custody_indices * retained * self.config.shard_bytes,
+                    bandwidth_bytes: self.traffic[node],
+                }
+            })
+            .collect()
+    }
+
+    fn summary(&self, custody: &[NodeCustody]) -> SimSummary {
+        let unavailable: Vec<&SlotReport> = self.reports.iter().filter(|r| !r.available).collect();
+        let light_client_fooled_rate = (self.config.light_clients > 0 && !unavailable.is_empty()).then(|| {
+            let fooled: usize = unavailable.iter().map(|r| r.convinced_light_clients).sum();
+            fooled as f64 / (unavailable.len() * self.config.light_clients) as f64
+        });
+        let mean_attestation_rate = if self.reports.is_empty() || self.config.validators == 0 {
+            0.0
+        } else {
+            self.reports.iter().map(|r| r.attestations as f64 / self.config.validators as f64).sum::<f64>() / self.reports.len() as f64
+        };
+        SimSummary {
+            slots: self.reports.len(),
+            lazy_validators: self.nodes.iter().filter(|n| n.lazy).count(),
+            unavailable_slots: unavailable.len(),
+            false_accepts: unavailable.iter().filter(|r| r.accepted).count(),
+            false_rejects: self.reports.iter().filter(|r| r.available && !r.accepted).count(),
+            light_client_fooled_rate,
+            mean_attestation_rate,
+            mean_coverage: self.reports.iter().map(|r| r.coverage as f64).sum::<f64>() / self.reports.len().max(1) as f64,
+            samples: self.reports.iter().map(|r| r.samples).sum(),
+            bytes: self.reports.iter().map(|r| r.bytes).sum(),
+            dropped: self.reports.iter().map(|r| r.dropped).sum(),
+            mean_confidence_ms: mean(self.reports.iter().filter_map(|r| r.confidence_ms)),
+            mean_reconstruction_ms: mean(self.reports.iter().filter_map(|r| r.reconstruction_ms)),
+            confidence: self.confidence_summary(&unavailable),
+            committee: self.committee_summary(),
+            stake_custody: stake_custody(custody),
+            partition: self.partition_summary(),
+        }
+    }
+
+    fn confidence_summary(&self, unavailable: &[&SlotReport]) -> Option<ConfidenceSummary> {
+        let clients = self.config.light_clients;
+        if clients == 0 || unavailable.is_empty() {
+            return None;
+        }
+        let n = self.config.n();
+        let strategy = self.config.sampling.strategy();
+        let withheld = self.config.withhold.min(n);
+        let analytic_fooled = strategy.fooled_probability(n, withheld);
+        Some(ConfidenceSummary {
+            clients,
+            analytic_fooled,
+            client_confidence: analytic_fooled.map(|p| 1.0 - p),
+            analytic_undetected: strategy.population_fooled_probability(n, withheld, clients),
+            measured_undetected: unavailable.iter().filter(|r| r.convinced_light_clients == clients).count() as f64 / unavailable.len() as f64,
+        })
+    }
+
+    fn committee_summary(&self) -> Option<CommitteeSummary> {
+        if self.config.committees == 0 {
+            return None;
+        }
+        let churn_bytes: usize = self.epochs.iter().map(|e| e.churn_bytes).sum();
+        let total_bytes: usize = self.reports.iter().map(|r| r.bytes).sum();
+        Some(CommitteeSummary {
+            committees: self.config.committees,
+            epochs: self.epochs.len(),
+            reassigned: self.epochs.iter().map(|e| e.reassigned).sum(),
+            churn_shards: self.epochs.iter().map(|e| e.churn_shards).sum(),
+            churn_bytes,
+            churn_bytes_per_rotation: churn_bytes as f64 / self.epochs.len().saturating_sub(1).max(1) as f64,
+            churn_share: if total_bytes == 0 { 0.0 } else { churn_bytes as f64 / total_bytes as f64 },
+        })
+    }
+
+    /// Splits the slots into before the first partition, during any, and after the last heals.
+    fn partition_summary(&self) -> Option<PartitionSummary> {
+        let first = self.config.partitions.iter().map(|p| p.at_ms).min()?;
+        let healed = self.config.partitions.iter().map(|p| p.heal_ms).max()?;
+        let slot_ms = self.config.slot_ms;
+        let before: Vec<&SlotReport> = self.reports.iter().filter(|r| (r.slot as u64 + 1) * slot_ms <= first).collect();
+        let during: Vec<&SlotReport> = self.reports.iter().filter(|r| r.partitioned).collect();
+        let after: Vec<&SlotReport> = self.reports.iter().filter(|r| r.slot as u64 * slot_ms >= healed).collect();
+        let full_nodes = self.config.full_nodes;
+        let (before, during, after) = (PhaseStats::over(&before, full_nodes), PhaseStats::over(&during, full_nodes), PhaseStats::over(&after, full_nodes));
+        let recovered = after.slots > 0
+            && after.rebuilt_rate >= before.rebuilt_rate.min(1.0)
+            && after.accepted_rate >= before.accepted_rate.min(1.0);
+        Some(PartitionSummary { before, during, after, recovered })
+    }
+}
+
+/// Mean of `values`, `None` if there are none.
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+fn stake_custody(custody: &[NodeCustody]) -> Vec<StakeCustody> {
+    let mut stakes: Vec<f64> = custody.iter().map(|c| c.stake).collect();
+    stakes.sort_by(f64::total_cmp);
+    stakes.dedup();
+    stakes.into_iter()
+        .map(|stake| {
+            let group: Vec<&NodeCustody> = custody.iter().filter(|c| c.stake == stake).collect();
+            let mean = |f: fn(&NodeCustody) -> usize| group.iter().map(|c| f(c) as f64).sum::<f64>() / group.len() as f64;
+            StakeCustody {
+                stake,
+                nodes: group.len(),
+                custody_indices: mean(|c| c.custody_indices),
+                storage_bytes: mean(|c| c.storage_bytes),
+                bandwidth_bytes: mean(|c| c.bandwidth_bytes),
+            }
+        })
+        .collect()
+}
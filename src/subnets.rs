@@ -0,0 +1,35 @@
+//! PeerDAS-style column subnets. Each column (wire index) is carried on one subnet, and each
+//! node subscribes to a few subnets picked from its ID alone, so anyone holding the list of node
+//! IDs can work out who should hear which columns without asking them.
+
+use sha2::{Digest, Sha256};
+
+/// The subnet column `column` is carried on, out of `subnets`.
+pub fn column_subnet(column: usize, subnets: usize) -> usize {
+    column % subnets
+}
+
+/// The columns out of `columns` carried on `subnet`.
+pub fn subnet_columns(subnet: usize, subnets: usize, columns: usize) -> Vec<usize> {
+    (0..columns).filter(|&c| column_subnet(c, subnets) == subnet).collect()
+}
+
+/// The `custody` subnets (at most `subnets`) a node with this ID subscribes to, ascending.
+/// Hashes the ID with a counter (0, 1, ...) and takes each hash modulo `subnets`, skipping
+/// repeats, like the PeerDAS custody group derivation.
+pub fn node_subnets(node_id: &[u8], subnets: usize, custody: usize) -> Vec<usize> {
+    let custody = custody.min(subnets);
+    let mut picked = Vec::with_capacity(custody);
+    let mut counter: u64 = 0;
+    while picked.len() < custody {
+        let digest = Sha256::new().chain_update(node_id).chain_update(counter.to_be_bytes()).finalize();
+        let value = u64::from_be_bytes(digest[..8].try_into().expect("digest has 8 bytes"));
+        let subnet = (value % subnets as u64) as usize;
+        if !picked.contains(&subnet) {
+            picked.push(subnet);
+        }
+        counter += 1;
+    }
+    picked.sort_unstable();
+    picked
+}
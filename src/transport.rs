@@ -0,0 +1,933 @@
+//! Transports carry protocol frames (one JSON message each) between nodes. Sessions only see a
+//! [`Connection`], so the same protocol code runs over TCP or, for simulations, in memory.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use clap::{Args, ValueEnum};
+use ed25519_dalek::{Signer, SigningKey};
+use futures::channel::mpsc;
+use futures::{ready, Sink, SinkExt, Stream, StreamExt};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::distributions::Alphanumeric;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Framed, LinesCodec, LinesCodecError};
+
+use crate::error::TransportError;
+
+/// Frames buffered in each direction of an in-memory connection before the sender waits.
+const MEMORY_CHANNEL_DEPTH: usize = 16;
+
+/// A bidirectional frame channel to one peer. It is a `Sink` and a `Stream` so it can be split
+/// into a reader and a writer running in separate tasks. Frames over the receiving side's limit
+/// are reported as [`TransportError::OversizeFrame`].
+pub trait Connection:
+    Sink<String, Error = TransportError> + Stream<Item = Result<String, TransportError>> + Send + Unpin + 'static
+{
+    /// Remote address, for logs.
+    fn peer_addr(&self) -> String;
+
+    /// Sends one frame, refusing anything larger than `limit` (the peer's advertised `max_frame`).
+    fn send_frame(&mut self, frame: String, limit: usize) -> impl Future<Output = Result<(), TransportError>> + Send {
+        send_frame(self, frame, limit)
+    }
+
+    /// The next frame, or `None` once the peer has closed.
+    fn recv_frame(&mut self) -> impl Future<Output = Option<Result<String, TransportError>>> + Send {
+        self.next()
+    }
+}
+
+pub trait Listener: Send + 'static {
+    type Conn: Connection;
+
+    fn accept(&mut self) -> impl Future<Output = Result<Self::Conn, TransportError>> + Send;
+}
+
+pub trait Transport: Send + Sync + 'static {
+    type Conn: Connection;
+    type Listener: Listener<Conn = Self::Conn>;
+
+    fn connect(&self, addr: &str) -> impl Future<Output = Result<Self::Conn, TransportError>> + Send;
+    fn listen(&self, addr: &str) -> impl Future<Output = Result<Self::Listener, TransportError>> + Send;
+}
+
+/// Sends one frame on any frame sink (a whole connection or its split write half), refusing
+/// anything larger than the peer said it will read.
+pub async fn send_frame<S>(sink: &mut S, frame: String, limit: usize) -> Result<(), TransportError>
+where
+    S: Sink<String, Error = TransportError> + Unpin + ?Sized,
+{
+    if frame.len() > limit {
+        return Err(TransportError::FrameTooLarge { len: frame.len(), limit });
+    }
+    sink.send(frame).await
+}
+
+// TCP
+/// JSON lines over TCP. `max_frame` caps the length of every line read. Outbound connections
+/// go through `proxy` when one is set.
+#[derive(Copy, Clone, Debug)]
+pub struct TcpTransport {
+    pub max_frame: usize,
+    pub proxy: Option<Socks5Proxy>,
+}
+
+impl TcpTransport {
+    pub fn new(max_frame: usize) -> Self {
+        Self { max_frame, proxy: None }
+    }
+
+    pub fn via(self, proxy: Option<Socks5Proxy>) -> Self {
+        Self { proxy, ..self }
+    }
+
+    fn wrap(&self, socket: TcpStream, peer: String) -> TcpConnection {
+        let framed = Framed::new(socket, LinesCodec::new_with_max_length(self.max_frame));
+        TcpConnection { framed, peer, limit: self.max_frame }
+    }
+}
+
+impl Transport for TcpTransport {
+    type Conn = TcpConnection;
+    type Listener = TcpFrameListener;
+
+    async fn connect(&self, addr: &str) -> Result<TcpConnection, TransportError> {
+        let socket = match self.proxy {
+            Some(proxy) => proxy.connect(addr).await?,
+            None => TcpStream::connect(addr).await?,
+        };
+        Ok(self.wrap(socket, addr.to_string()))
+    }
+
+    async fn listen(&self, addr: &str) -> Result<TcpFrameListener, TransportError> {
+        Ok(TcpFrameListener { listener: TcpListener::bind(addr).await?, transport: *self })
+    }
+}
+
+pub struct TcpFrameListener {
+    listener: TcpListener,
+    transport: TcpTransport,
+}
+
+impl Listener for TcpFrameListener {
+    type Conn = TcpConnection;
+
+    async fn accept(&mut self) -> Result<TcpConnection, TransportError> {
+        let (socket, addr) = self.listener.accept().await?;
+        Ok(self.transport.wrap(socket, addr.to_string()))
+    }
+}
+
+pub struct TcpConnection {
+    framed: Framed<TcpStream, LinesCodec>,
+    peer: String,
+    limit: usize,
+}
+
+impl Connection for TcpConnection {
+    fn peer_addr(&self) -> String {
+        self.peer.clone()
+    }
+}
+
+impl Stream for TcpConnection {
+    type Item = Result<String, TransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let limit = self.limit;
+        Pin::new(&mut self.framed).poll_next(cx).map(|frame| {
+            frame.map(|r| {
+                r.map_err(|e| match e {
+                    LinesCodecError::MaxLineLengthExceeded => TransportError::OversizeFrame { limit },
+                    e => e.into(),
+                })
+            })
+        })
+    }
+}
+
+impl Sink<String> for TcpConnection {
+    type Error = TransportError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Sink::<String>::poll_ready(Pin::new(&mut self.framed), cx).map_err(Into::into)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: String) -> Result<(), TransportError> {
+        Pin::new(&mut self.framed).start_send(frame).map_err(Into::into)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Sink::<String>::poll_flush(Pin::new(&mut self.framed), cx).map_err(Into::into)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Sink::<String>::poll_close(Pin::new(&mut self.framed), cx).map_err(Into::into)
+    }
+}
+
+// IN-MEMORY
+/// An in-process network. Listeners register under an address; `connect` hands them one end of
+/// a pair of bounded channels. Frames are passed as strings, with no sockets or serialization
+/// beyond the JSON itself.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    listeners: Arc<Mutex<HashMap<String, Registration>>>,
+}
+
+/// Where a listener accepts connections, and the frame limit it reads with.
+type Registration = (mpsc::UnboundedSender<MemoryConnection>, usize);
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A transport for the node at `local`, reading frames of up to `max_frame` bytes.
+    pub fn transport(&self, local: &str, max_frame: usize) -> MemoryTransport {
+        MemoryTransport { net: self.clone(), local: local.to_string(), max_frame }
+    }
+}
+
+#[derive(Clone)]
+pub struct MemoryTransport {
+    net: MemoryNetwork,
+    local: String,
+    max_frame: usize,
+}
+
+impl Transport for MemoryTransport {
+    type Conn = MemoryConnection;
+    type Listener = MemoryListener;
+
+    async fn connect(&self, addr: &str) -> Result<MemoryConnection, TransportError> {
+        let refused = || TransportError::Io(std::io::Error::new(std::io::ErrorKind::ConnectionRefused, format!("nothing listening on {}", addr)));
+        let (accept, remote_limit) = self.net.listeners.lock().unwrap().get(addr).cloned().ok_or_else(refused)?;
+        let (to_remote, from_local) = mpsc::channel(MEMORY_CHANNEL_DEPTH);
+        let (to_local, from_remote) = mpsc::channel(MEMORY_CHANNEL_DEPTH);
+        let remote = MemoryConnection { tx: to_local, rx: from_local, peer: self.local.clone(), limit: remote_limit };
+        accept.unbounded_send(remote).map_err(|_| refused())?;
+        Ok(MemoryConnection { tx: to_remote, rx: from_remote, peer: addr.to_string(), limit: self.max_frame })
+    }
+
+    async fn listen(&self, addr: &str) -> Result<MemoryListener, TransportError> {
+        let (tx, rx) = mpsc::unbounded();
+        let mut listeners = self.net.listeners.lock().unwrap();
+        if listeners.get(addr).is_some_and(|(l, _)| !l.is_closed()) {
+            return Err(TransportError::Io(std::io::Error::new(std::io::ErrorKind::AddrInUse, addr.to_string())));
+        }
+        listeners.insert(addr.to_string(), (tx, self.max_frame));
+        Ok(MemoryListener { rx })
+    }
+}
+
+pub struct MemoryListener {
+    rx: mpsc::UnboundedReceiver<MemoryConnection>,
+}
+
+impl Listener for MemoryListener {
+    type Conn = MemoryConnection;
+
+    async fn accept(&mut self) -> Result<MemoryConnection, TransportError> {
+        self.rx.next().await.ok_or(TransportError::Closed)
+    }
+}
+
+pub struct MemoryConnection {
+    tx: mpsc::Sender<String>,
+    rx: mpsc::Receiver<String>,
+    peer: String,
+    limit: usize,
+}
+
+impl Connection for MemoryConnection {
+    fn peer_addr(&self) -> String {
+        self.peer.clone()
+    }
+}
+
+impl Stream for MemoryConnection {
+    type Item = Result<String, TransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let limit = self.limit;
+        self.rx.poll_next_unpin(cx).map(|frame| {
+            frame.map(|f| if f.len() > limit { Err(TransportError::OversizeFrame { limit }) } else { Ok(f) })
+        })
+    }
+}
+
+impl Sink<String> for MemoryConnection {
+    type Error = TransportError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        self.tx.poll_ready(cx).map_err(|_| TransportError::Closed)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: String) -> Result<(), TransportError> {
+        self.tx.start_send(frame).map_err(|_| TransportError::Closed)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        self.tx.poll_flush_unpin(cx).map_err(|_| TransportError::Closed)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        self.tx.poll_close_unpin(cx).map_err(|_| TransportError::Closed)
+    }
+}
+
+// SOCKS5
+/// A SOCKS5 proxy (RFC 1928) to open outbound connections through, given as `socks5://host:port`
+/// (or `socks5h://`). Only the no-authentication method is offered. Target host names go to the
+/// proxy unresolved, so `.onion` and lab-internal names work through Tor or a lab gateway.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Socks5Proxy {
+    pub addr: SocketAddr,
+}
+
+impl Socks5Proxy {
+    /// Connects to the proxy and has it connect on to `target` (`host:port`).
+    pub async fn connect(&self, target: &str) -> Result<TcpStream, TransportError> {
+        let socks = |reason: &str| TransportError::Socks(reason.to_string());
+        let (host, port) = target.rsplit_once(':').ok_or_else(|| socks("target must be host:port"))?;
+        let port: u16 = port.parse().map_err(|_| socks("bad target port"))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        let mut stream = TcpStream::connect(self.addr).await?;
+        // Greeting: version 5, one method, "no authentication".
+        stream.write_all(&[5, 1, 0]).await?;
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await?;
+        if choice != [5, 0] {
+            return Err(socks("proxy requires authentication"));
+        }
+
+        // CONNECT to an IPv4, IPv6 or domain-name address.
+        let mut request = vec![5, 1, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(1);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                let name = u8::try_from(host.len()).map_err(|_| socks("target host name over 255 bytes"))?;
+                request.push(3);
+                request.push(name);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != 5 {
+            return Err(socks("not a SOCKS5 reply"));
+        }
+        if reply[1] != 0 {
+            return Err(socks(Self::failure(reply[1])));
+        }
+        // The address the proxy bound for us; read past it.
+        let bound = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => stream.read_u8().await? as usize,
+            _ => return Err(socks("unknown address type in reply")),
+        };
+        let mut skip = vec![0u8; bound + 2];
+        stream.read_exact(&mut skip).await?;
+        Ok(stream)
+    }
+
+    fn failure(code: u8) -> &'static str {
+        match code {
+            1 => "general failure",
+            2 => "connection not allowed by ruleset",
+            3 => "network unreachable",
+            4 => "host unreachable",
+            5 => "connection refused",
+            6 => "TTL expired",
+            7 => "command not supported",
+            8 => "address type not supported",
+            _ => "unknown failure",
+        }
+    }
+}
+
+impl FromStr for Socks5Proxy {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let hostport = spec.strip_prefix("socks5://").or_else(|| spec.strip_prefix("socks5h://"))
+            .ok_or_else(|| format!("expected socks5://host:port, got {:?}", spec))?;
+        let addr = hostport.trim_end_matches('/').to_socket_addrs()
+            .map_err(|e| format!("can't resolve proxy {:?}: {}", hostport, e))?
+            .next()
+            .ok_or_else(|| format!("proxy {:?} resolves to no address", hostport))?;
+        Ok(Self { addr })
+    }
+}
+
+impl fmt::Display for Socks5Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "socks5://{}", self.addr)
+    }
+}
+
+// RELAY
+/// Control lines between a relay and its clients, one JSON object each, before a connection is
+/// handed over to the protocol. A node behind NAT keeps a `register` connection open, once it
+/// has signed the relay's `challenge` with the key it registers as; for each client that asks
+/// to `connect` to it, the relay sends `incoming` and the node dials back with `accept`. From
+/// then on the relay only forwards frames, so the handshake and envelopes run end to end
+/// between the two peers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "relay", rename_all = "snake_case")]
+pub enum RelayMessage {
+    /// Node -> relay: route clients asking for `node` (its hex pubkey) over this connection
+    Register { node: String },
+    /// Relay -> node: sign `nonce` (hex) to prove you hold `node`'s key
+    Challenge { nonce: String },
+    /// Node -> relay: the hex signature over [`registration_bytes`]
+    Proof { sig: String },
+    /// Client -> relay: put this connection through to `node`
+    Connect { node: String },
+    /// Relay -> node: `from` is waiting under `ticket`
+    Incoming { ticket: u64, from: String },
+    /// Node -> relay, on a new connection: this one is for `ticket`
+    Accept { ticket: u64 },
+    /// Relay -> client or node: registered, or connected to `peer`
+    Ok { peer: String },
+    Error { reason: String },
+}
+
+/// What a node signs to register with a relay: a domain tag, then its name and the relay's
+/// nonce, each length-prefixed. The nonce is fresh per registration, so a captured proof can't
+/// be replayed.
+pub fn registration_bytes(node: &str, nonce: &[u8]) -> Vec<u8> {
+    let mut bytes = b"das-relay-register-v1".to_vec();
+    for field in [node.as_bytes(), nonce] {
+        bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(field);
+    }
+    bytes
+}
+
+/// Reaches nodes through a relay (`relay` is its `host:port`) instead of by address. Addresses
+/// given to [`Transport::connect`] and [`Transport::listen`] are node names (hex pubkeys). The
+/// relay itself is dialed with `tcp`, so it can sit behind a SOCKS5 proxy. Only a transport
+/// with the node's `key` can listen.
+#[derive(Clone, Debug)]
+pub struct RelayTransport {
+    pub tcp: TcpTransport,
+    pub relay: String,
+    pub key: Option<Arc<SigningKey>>,
+}
+
+impl RelayTransport {
+    pub fn new(tcp: TcpTransport, relay: String) -> Self {
+        Self { tcp, relay, key: None }
+    }
+
+    /// Registers with `key`, which must be the key of the node name given to `listen`.
+    pub fn signed_by(mut self, key: Arc<SigningKey>) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    async fn request(&self, message: &RelayMessage) -> Result<(TcpConnection, String), TransportError> {
+        let mut conn = self.tcp.connect(&self.relay).await?;
+        conn.send(serde_json::to_string(message).expect("relay message serializes")).await?;
+        let peer = match read_relay_message(&mut conn).await? {
+            RelayMessage::Ok { peer } => peer,
+            RelayMessage::Error { reason } => return Err(TransportError::Relay(reason)),
+            other => return Err(TransportError::Relay(format!("unexpected {:?}", other))),
+        };
+        Ok((conn, peer))
+    }
+}
+
+/// The next control line on a relay connection.
+pub async fn read_relay_message(conn: &mut TcpConnection) -> Result<RelayMessage, TransportError> {
+    let line = conn.next().await.ok_or(TransportError::Closed)??;
+    serde_json::from_str(&line).map_err(|e| TransportError::Relay(format!("bad control line: {}", e)))
+}
+
+impl Transport for RelayTransport {
+    type Conn = TcpConnection;
+    type Listener = RelayListener;
+
+    async fn connect(&self, node: &str) -> Result<TcpConnection, TransportError> {
+        let (mut conn, _) = self.request(&RelayMessage::Connect { node: node.to_string() }).await?;
+        conn.peer = format!("{} via {}", node, self.relay);
+        Ok(conn)
+    }
+
+    async fn listen(&self, node: &str) -> Result<RelayListener, TransportError> {
+        let key = self.key.as_ref().ok_or_else(|| TransportError::Relay("registering takes the node's key".into()))?;
+        let mut control = self.tcp.connect(&self.relay).await?;
+        control.send(serde_json::to_string(&RelayMessage::Register { node: node.to_string() }).expect("relay message serializes")).await?;
+        let nonce = match read_relay_message(&mut control).await? {
+            RelayMessage::Challenge { nonce } => hex::decode(nonce).map_err(|e| TransportError::Relay(format!("bad challenge: {}", e)))?,
+            RelayMessage::Error { reason } => return Err(TransportError::Relay(reason)),
+            other => return Err(TransportError::Relay(format!("unexpected {:?}", other))),
+        };
+        let sig = hex::encode(key.sign(&registration_bytes(node, &nonce)).to_bytes());
+        control.send(serde_json::to_string(&RelayMessage::Proof { sig }).expect("relay message serializes")).await?;
+        match read_relay_message(&mut control).await? {
+            RelayMessage::Ok { .. } => Ok(RelayListener { control, transport: self.clone() }),
+            RelayMessage::Error { reason } => Err(TransportError::Relay(reason)),
+            other => Err(TransportError::Relay(format!("unexpected {:?}", other))),
+        }
+    }
+}
+
+/// A node's registration with a relay. `accept` fails with [`TransportError::Closed`] once the
+/// relay drops the registration; listen again to re-register.
+pub struct RelayListener {
+    control: TcpConnection,
+    transport: RelayTransport,
+}
+
+impl Listener for RelayListener {
+    type Conn = TcpConnection;
+
+    async fn accept(&mut self) -> Result<TcpConnection, TransportError> {
+        let (ticket, from) = match read_relay_message(&mut self.control).await? {
+            RelayMessage::Incoming { ticket, from } => (ticket, from),
+            other => return Err(TransportError::Relay(format!("unexpected {:?}", other))),
+        };
+        let mut conn = self.transport.tcp.connect(&self.transport.relay).await?;
+        conn.send(serde_json::to_string(&RelayMessage::Accept { ticket }).expect("relay message serializes")).await?;
+        conn.peer = format!("{} via {}", from, self.transport.relay);
+        Ok(conn)
+    }
+}
+
+// FAULT INJECTION
+/// Transport misbehavior to inject on the sending side of a connection.
+#[derive(Args, Copy, Clone, Debug, Default)]
+#[command(about = None, long_about = None)]
+pub struct Faults {
+    /// Hold outgoing frames in windows of this many and release each window shuffled (0 or 1
+    /// keeps the order). A partial window goes out when the connection closes
+    #[arg(long, default_value_t = 0)]
+    pub reorder_window: usize,
+    /// Probability that an outgoing frame is sent twice (the copy lands in the same window)
+    #[arg(long, default_value_t = 0.0)]
+    pub duplicate_rate: f64,
+    /// Make the injected faults reproducible
+    #[arg(long)]
+    pub fault_seed: Option<u64>,
+    /// Probability that an outgoing frame is sabotaged, one of three ways at random: a malformed
+    /// frame is slipped in ahead of it, it is cut short, or the connection drops right there
+    #[arg(long, default_value_t = 0.0)]
+    pub chaos: f64,
+}
+
+impl Faults {
+    pub fn is_active(&self) -> bool {
+        self.reorder_window > 1 || self.duplicate_rate > 0.0 || self.chaos > 0.0
+    }
+}
+
+/// Frames no receiver should accept, as `--chaos` slips them in: JSON that is no message, or
+/// a known message with a missing body. Random text is mixed in as well.
+const MALFORMED_FRAMES: [&str; 4] = ["{\"Unknown\":{}}", "[]", "{\"DasShard\":null}", "{\"GetShard\":{\"root\":\"\",\"index\":0}}"];
+
+/// Wraps a connection and reorders, duplicates and sabotages the frames written to it, as
+/// configured by [`Faults`]. Reading is untouched.
+pub struct FaultyConnection<C> {
+    inner: C,
+    faults: Faults,
+    rng: StdRng,
+    /// Frames of the current window, tagged with their send order
+    held: Vec<(u64, String)>,
+    /// Released frames not yet handed to `inner`
+    out: VecDeque<String>,
+    sent: u64,
+    duplicated: usize,
+    reordered: usize,
+    malformed: usize,
+    truncated: usize,
+    /// Set once `--chaos` has dropped the connection; every later send fails
+    severed: bool,
+}
+
+impl<C: Connection> FaultyConnection<C> {
+    pub fn new(inner: C, faults: Faults) -> Self {
+        let rng = faults.fault_seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+        Self { inner, faults, rng, held: Vec::new(), out: VecDeque::new(), sent: 0, duplicated: 0, reordered: 0, malformed: 0, truncated: 0, severed: false }
+    }
+
+    /// The wrapped connection.
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    /// Frames sent twice so far.
+    pub fn duplicated(&self) -> usize {
+        self.duplicated
+    }
+
+    /// Frames released somewhere other than their place in the send order.
+    pub fn reordered(&self) -> usize {
+        self.reordered
+    }
+
+    /// Malformed frames slipped in so far.
+    pub fn malformed(&self) -> usize {
+        self.malformed
+    }
+
+    /// Frames cut short so far.
+    pub fn truncated(&self) -> usize {
+        self.truncated
+    }
+
+    /// Whether `--chaos` has dropped the connection.
+    pub fn severed(&self) -> bool {
+        self.severed
+    }
+
+    fn hold(&mut self, frame: String) {
+        self.held.push((self.sent, frame));
+        self.sent += 1;
+    }
+
+    /// Releases the current window once it is full.
+    fn release_full(&mut self) {
+        if self.held.len() >= self.faults.reorder_window.max(1) {
+            self.release();
+        }
+    }
+
+    fn malformed_frame(&mut self) -> String {
+        if self.rng.gen_bool(0.5) {
+            return MALFORMED_FRAMES.choose(&mut self.rng).expect("non-empty").to_string();
+        }
+        let len = self.rng.gen_range(1..=64);
+        (&mut self.rng).sample_iter(Alphanumeric).take(len).map(char::from).collect()
+    }
+
+    fn release(&mut self) {
+        // A window of 0 or 1 keeps the order, even when a copy or an injected frame rides along.
+        if self.faults.reorder_window > 1 {
+            self.held.shuffle(&mut self.rng);
+        }
+        let mut order: Vec<u64> = self.held.iter().map(|(seq, _)| *seq).collect();
+        order.sort_unstable();
+        self.reordered += self.held.iter().zip(&order).filter(|((seq, _), expected)| seq != *expected).count();
+        self.out.extend(self.held.drain(..).map(|(_, frame)| frame));
+    }
+
+    /// Hands released frames to the inner connection until it stops accepting them.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        while !self.out.is_empty() {
+            ready!(Pin::new(&mut self.inner).poll_ready(cx))?;
+            let frame = self.out.pop_front().expect("checked non-empty");
+            Pin::new(&mut self.inner).start_send(frame)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<C: Connection> Connection for FaultyConnection<C> {
+    fn peer_addr(&self) -> String {
+        self.inner.peer_addr()
+    }
+}
+
+impl<C: Connection> Stream for FaultyConnection<C> {
+    type Item = Result<String, TransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl<C: Connection> Sink<String> for FaultyConnection<C> {
+    type Error = TransportError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        self.poll_drain(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, mut frame: String) -> Result<(), TransportError> {
+        let this = &mut *self;
+        if this.severed {
+            return Err(TransportError::Closed);
+        }
+        if this.faults.chaos > 0.0 && this.rng.gen_bool(this.faults.chaos.min(1.0)) {
+            match this.rng.gen_range(0..3) {
+                0 => {
+                    let junk = this.malformed_frame();
+                    this.hold(junk);
+                    this.malformed += 1;
+                }
+                1 if frame.len() > 1 => {
+                    let mut cut = this.rng.gen_range(1..frame.len());
+                    while !frame.is_char_boundary(cut) { cut -= 1; }
+                    frame.truncate(cut);
+                    this.truncated += 1;
+                    // Not duplicated too, so each counted truncation is one rejected frame.
+                    this.hold(frame);
+                    this.release_full();
+                    return Ok(());
+                }
+                1 => {}
+                _ => {
+                    // Whatever is still held is lost with the connection.
+                    this.severed = true;
+                    this.held.clear();
+                    return Err(TransportError::Closed);
+                }
+            }
+        }
+        if this.faults.duplicate_rate > 0.0 && this.rng.gen_bool(this.faults.duplicate_rate.min(1.0)) {
+            this.hold(frame.clone());
+            this.duplicated += 1;
+        }
+        this.hold(frame);
+        this.release_full();
+        Ok(())
+    }
+
+    /// Flushes what has been released; a partially filled window stays held, or every send
+    /// would flush it and nothing could be reordered.
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        ready!(self.poll_drain(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        self.release();
+        ready!(self.poll_drain(cx))?;
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+// PACING
+/// A steady rate to emit shards at instead of back to back: `N/s` or `Nshards/s` for shards
+/// per second, `NKB/s` or `NMB/s` for bytes on the wire per second.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SendRate {
+    ShardsPerSec(f64),
+    BytesPerSec(f64),
+}
+
+impl SendRate {
+    /// How long a frame of `bytes` holds the sender before the next may go out.
+    pub fn interval(&self, bytes: usize) -> Duration {
+        match *self {
+            SendRate::ShardsPerSec(rate) => Duration::from_secs_f64(1.0 / rate),
+            SendRate::BytesPerSec(rate) => Duration::from_secs_f64(bytes as f64 / rate),
+        }
+    }
+}
+
+impl FromStr for SendRate {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        let usage = || format!("expected N/s, Nshards/s, NKB/s or NMB/s, got {:?}", spec);
+        let lower = spec.trim().to_ascii_lowercase();
+        let unit_start = lower.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(lower.len());
+        let (number, unit) = lower.split_at(unit_start);
+        let rate: f64 = number.parse().map_err(|_| usage())?;
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(format!("send rate must be positive, got {:?}", spec));
+        }
+        match unit.strip_suffix("/s").unwrap_or(unit).trim() {
+            "" | "shards" => Ok(SendRate::ShardsPerSec(rate)),
+            "kb" => Ok(SendRate::BytesPerSec(rate * 1024.0)),
+            "mb" => Ok(SendRate::BytesPerSec(rate * 1024.0 * 1024.0)),
+            _ => Err(usage()),
+        }
+    }
+}
+
+impl fmt::Display for SendRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SendRate::ShardsPerSec(rate) => write!(f, "{} shards/s", rate),
+            SendRate::BytesPerSec(rate) if rate < 1024.0 * 1024.0 => write!(f, "{:.2} KB/s", rate / 1024.0),
+            SendRate::BytesPerSec(rate) => write!(f, "{:.2} MB/s", rate / 1024.0 / 1024.0),
+        }
+    }
+}
+
+// COMPRESSION
+/// Frame compression a connection can use once the handshake has settled on it.
+#[derive(ValueEnum, Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    /// Codecs in the order negotiation prefers them, better ratio first.
+    const RANKED: [Compression; 2] = [Compression::Zstd, Compression::Lz4];
+
+    /// The name offered in handshakes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    /// The best codec both sides offered, or `None` if they share none. Both peers run the same
+    /// rule on the same two lists, so they agree without another round trip. Names this node
+    /// doesn't know are skipped, so newer peers can offer codecs older ones lack.
+    pub fn negotiate(ours: &[Compression], theirs: &[String]) -> Compression {
+        Self::RANKED.into_iter()
+            .find(|c| ours.contains(c) && theirs.iter().any(|t| t == c.name()))
+            .unwrap_or(Compression::None)
+    }
+
+    /// The codec two peers settle on, worked out from both handshake offers by an observer
+    /// that knows every codec, such as a proxy in the middle.
+    pub fn settled(a: &[String], b: &[String]) -> Compression {
+        let known: Vec<Compression> = Self::RANKED.into_iter().filter(|c| a.iter().any(|n| n == c.name())).collect();
+        Self::negotiate(&known, b)
+    }
+
+    /// Compresses one frame into a line: the codec's output, base64-encoded so it stays free
+    /// of newlines.
+    pub fn compress(self, frame: &str) -> Result<String, TransportError> {
+        let bytes = match self {
+            Compression::None => return Ok(frame.to_string()),
+            Compression::Zstd => zstd::bulk::compress(frame.as_bytes(), zstd::DEFAULT_COMPRESSION_LEVEL)?,
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(frame.as_bytes())?;
+                encoder.finish().map_err(|e| self.corrupt(e))?
+            }
+        };
+        Ok(BASE64.encode(bytes))
+    }
+
+    /// Reverses [`Compression::compress`], refusing to inflate past `limit` bytes.
+    pub fn decompress(self, line: String, limit: usize) -> Result<String, TransportError> {
+        if self == Compression::None {
+            return Ok(line);
+        }
+        let bytes = BASE64.decode(line.trim_end()).map_err(|e| self.corrupt(e))?;
+        let reader: Box<dyn Read + '_> = match self {
+            Compression::None => Box::new(&bytes[..]),
+            Compression::Zstd => Box::new(zstd::stream::Decoder::new(&bytes[..])?),
+            Compression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(&bytes[..])),
+        };
+        let mut frame = Vec::new();
+        reader.take(limit as u64 + 1).read_to_end(&mut frame).map_err(|e| self.corrupt(e))?;
+        if frame.len() > limit {
+            return Err(TransportError::OversizeFrame { limit });
+        }
+        String::from_utf8(frame).map_err(|e| self.corrupt(e))
+    }
+
+    fn corrupt(self, reason: impl fmt::Display) -> TransportError {
+        TransportError::Decompress { codec: self.name(), reason: reason.to_string() }
+    }
+}
+
+/// Compresses every frame written and decompresses every frame read with the codec the
+/// handshake settled on. With [`Compression::None`] frames pass through untouched.
+pub struct CompressedConnection<C> {
+    inner: C,
+    codec: Compression,
+    /// Largest frame accepted once inflated: this node's own `max_frame`
+    max_frame: usize,
+    /// Bytes handed to `send`, before compression
+    frame_bytes: usize,
+    /// Bytes written to `inner`, after compression
+    wire_bytes: usize,
+    /// Bytes read from `inner`, before decompression
+    read_bytes: usize,
+}
+
+impl<C: Connection> CompressedConnection<C> {
+    pub fn new(inner: C, codec: Compression, max_frame: usize) -> Self {
+        Self { inner, codec, max_frame, frame_bytes: 0, wire_bytes: 0, read_bytes: 0 }
+    }
+
+    pub fn codec(&self) -> Compression {
+        self.codec
+    }
+
+    /// Sent so far, as `(frame bytes, wire bytes)`.
+    pub fn sent(&self) -> (usize, usize) {
+        (self.frame_bytes, self.wire_bytes)
+    }
+
+    /// Wire bytes received so far.
+    pub fn received(&self) -> usize {
+        self.read_bytes
+    }
+}
+
+impl<C: Connection> Connection for CompressedConnection<C> {
+    fn peer_addr(&self) -> String {
+        self.inner.peer_addr()
+    }
+}
+
+impl<C: Connection> Stream for CompressedConnection<C> {
+    type Item = Result<String, TransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let (codec, limit) = (self.codec, self.max_frame);
+        let frame = ready!(self.inner.poll_next_unpin(cx));
+        if let Some(Ok(line)) = &frame {
+            self.read_bytes += line.len();
+        }
+        Poll::Ready(frame.map(|frame| frame.and_then(|line| codec.decompress(line, limit))))
+    }
+}
+
+impl<C: Connection> Sink<String> for CompressedConnection<C> {
+    type Error = TransportError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, frame: String) -> Result<(), TransportError> {
+        let line = self.codec.compress(&frame)?;
+        self.frame_bytes += frame.len();
+        self.wire_bytes += line.len();
+        Pin::new(&mut self.inner).start_send(line)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}